@@ -1,15 +1,29 @@
+#[cfg(feature = "std")]
+pub(crate) mod backoff;
 pub(crate) mod deque;
+#[cfg(feature = "std")]
 pub(crate) mod error;
 pub(crate) mod frequency_sketch;
+#[cfg(feature = "std")]
 pub(crate) mod thread_pool;
+#[cfg(feature = "std")]
+pub(crate) mod timer_wheel;
+#[cfg(feature = "std")]
 pub(crate) mod unsafe_weak_pointer;
 
 // targe_has_atomic is more convenient but yet unstable (Rust 1.55)
 // https://github.com/rust-lang/rust/issues/32976
 // #[cfg_attr(target_has_atomic = "64", path = "common/time_atomic64.rs")]
 
-#[cfg_attr(feature = "atomic64", path = "common/time_atomic64.rs")]
-#[cfg_attr(not(feature = "atomic64"), path = "common/time_compat.rs")]
+#[cfg_attr(
+    all(feature = "std", feature = "atomic64"),
+    path = "common/time_atomic64.rs"
+)]
+#[cfg_attr(
+    all(feature = "std", not(feature = "atomic64")),
+    path = "common/time_compat.rs"
+)]
+#[cfg_attr(not(feature = "std"), path = "common/time_no_std.rs")]
 pub(crate) mod time;
 
 use time::Instant;
@@ -0,0 +1,128 @@
+use std::{thread, time::Duration};
+
+/// The number of the steps spinning before yielding the thread.
+const SPIN_LIMIT: u32 = 4;
+/// The number of the steps yielding the thread before sleeping.
+const YIELD_LIMIT: u32 = 8;
+/// The first sleep duration. It is doubled on each step until it reaches the max.
+const MIN_SLEEP_MICROS: u64 = 2;
+
+/// What a retry loop should do before the next attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BackoffStep {
+    /// Spin for the given number of iterations.
+    Spin(u32),
+    /// Yield the thread (or the task).
+    Yield,
+    /// Sleep for the duration.
+    Sleep(Duration),
+}
+
+/// An adaptive backoff for the retry loops writing to a full channel.
+///
+/// It spins a few times first, as the housekeeper may drain the channel soon,
+/// then yields, and then sleeps with exponentially growing durations capped at
+/// `max_sleep`. This keeps the latency low when the channel is only briefly full,
+/// and the CPU usage low when it stays full.
+///
+/// A `Backoff` is created per retry loop, so it starts over after each
+/// successful write.
+pub(crate) struct Backoff {
+    step: u32,
+    max_sleep: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(max_sleep: Duration) -> Self {
+        Self { step: 0, max_sleep }
+    }
+
+    /// Returns what to do before the next attempt, and advances the step.
+    pub(crate) fn next_step(&mut self) -> BackoffStep {
+        let step = self.step;
+        self.step = self.step.saturating_add(1);
+
+        if step < SPIN_LIMIT {
+            BackoffStep::Spin(1 << step)
+        } else if step < YIELD_LIMIT {
+            BackoffStep::Yield
+        } else {
+            let exp = (step - YIELD_LIMIT).min(16);
+            let sleep = Duration::from_micros(MIN_SLEEP_MICROS << exp);
+            BackoffStep::Sleep(sleep.min(self.max_sleep))
+        }
+    }
+
+    /// Blocks the current thread for the next step.
+    pub(crate) fn snooze(&mut self) {
+        match self.next_step() {
+            BackoffStep::Spin(n) => {
+                for _ in 0..n {
+                    // `std::hint::spin_loop` requires Rust 1.49, which is newer
+                    // than our MSRV.
+                    #[allow(deprecated)]
+                    std::sync::atomic::spin_loop_hint();
+                }
+            }
+            BackoffStep::Yield => thread::yield_now(),
+            BackoffStep::Sleep(duration) => thread::sleep(duration),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backoff, BackoffStep};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn steps() {
+        let max = Duration::from_micros(50);
+        let mut backoff = Backoff::new(max);
+
+        for i in 0..4 {
+            assert_eq!(backoff.next_step(), BackoffStep::Spin(1 << i));
+        }
+        for _ in 0..4 {
+            assert_eq!(backoff.next_step(), BackoffStep::Yield);
+        }
+        let sleeps: Vec<_> = (0..8)
+            .map(|_| match backoff.next_step() {
+                BackoffStep::Sleep(d) => d.as_micros(),
+                step => panic!("unexpected step: {:?}", step),
+            })
+            .collect();
+        assert_eq!(sleeps, vec![2, 4, 8, 16, 32, 50, 50, 50]);
+    }
+
+    // A benchmark rather than a test. Run it with:
+    // cargo test --release --lib backoff -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn compare_with_fixed_sleep() {
+        const COUNT: usize = 200_000;
+
+        // A producer writes to a small channel drained by a consumer, like the
+        // writers of a cache and its housekeeper.
+        fn run(mut wait: impl FnMut(&mut Backoff)) -> Duration {
+            let (snd, rcv) = crossbeam_channel::bounded(64);
+            let consumer = std::thread::spawn(move || rcv.iter().count());
+            let started_at = Instant::now();
+            for i in 0..COUNT {
+                let mut backoff = Backoff::new(Duration::from_micros(50));
+                let mut item = i;
+                while let Err(crossbeam_channel::TrySendError::Full(i)) = snd.try_send(item) {
+                    item = i;
+                    wait(&mut backoff);
+                }
+            }
+            drop(snd);
+            assert_eq!(consumer.join().unwrap(), COUNT);
+            started_at.elapsed()
+        }
+
+        let fixed = run(|_| std::thread::sleep(Duration::from_micros(50)));
+        let adaptive = run(Backoff::snooze);
+        println!("fixed sleep: {:?}, adaptive backoff: {:?}", fixed, adaptive);
+    }
+}
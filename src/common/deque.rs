@@ -12,7 +12,11 @@
 // For full authorship information, see the version control history of
 // https://github.com/rust-lang/rust/ or https://thanks.rust-lang.org
 
-use std::{marker::PhantomData, ptr::NonNull};
+// Some of the methods are only used by the thread-safe caches.
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+use alloc::boxed::Box;
+use core::{marker::PhantomData, ptr::NonNull};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum CacheRegion {
@@ -30,8 +34,8 @@ pub(crate) struct DeqNode<T> {
     pub(crate) element: T,
 }
 
-impl<T> std::fmt::Debug for DeqNode<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T> core::fmt::Debug for DeqNode<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("DeqNode")
             .field("region", &self.region)
             .field("next", &self.next)
@@ -81,7 +85,7 @@ impl<T> Drop for Deque<T> {
         while let Some(node) = self.pop_front() {
             let guard = DropGuard(self);
             drop(node);
-            std::mem::forget(guard);
+            core::mem::forget(guard);
         }
     }
 }
@@ -246,6 +250,34 @@ impl<T> Deque<T> {
     pub(crate) fn reset_cursor(&mut self) {
         self.cursor = None;
     }
+
+    /// Returns an iterator over the elements from the front to the back.
+    ///
+    /// Unlike iterating `&mut Deque<T>`, this does not use nor move the cursor.
+    pub(crate) fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head,
+            marker: PhantomData,
+        }
+    }
+}
+
+pub(crate) struct Iter<'a, T> {
+    next: Option<NonNull<DeqNode<T>>>,
+    marker: PhantomData<&'a DeqNode<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| unsafe {
+            // Not creating new mutable (unique!) references overlapping `element`.
+            let node = &*node.as_ptr();
+            self.next = node.next;
+            &node.element
+        })
+    }
 }
 
 impl<'a, T> Iterator for &'a mut Deque<T> {
@@ -271,7 +303,7 @@ impl<'a, T> Iterator for &'a mut Deque<T> {
 impl<T> Deque<T> {
     fn is_head(&self, node: &DeqNode<T>) -> bool {
         if let Some(head) = self.head {
-            std::ptr::eq(unsafe { head.as_ref() }, node)
+            core::ptr::eq(unsafe { head.as_ref() }, node)
         } else {
             false
         }
@@ -279,7 +311,7 @@ impl<T> Deque<T> {
 
     fn is_tail(&self, node: &DeqNode<T>) -> bool {
         if let Some(tail) = self.tail {
-            std::ptr::eq(unsafe { tail.as_ref() }, node)
+            core::ptr::eq(unsafe { tail.as_ref() }, node)
         } else {
             false
         }
@@ -287,7 +319,7 @@ impl<T> Deque<T> {
 
     fn is_at_cursor(&self, node: &DeqNode<T>) -> bool {
         if let Some(DeqCursor::Node(cur_node)) = self.cursor {
-            std::ptr::eq(unsafe { cur_node.as_ref() }, node)
+            core::ptr::eq(unsafe { cur_node.as_ref() }, node)
         } else {
             false
         }
@@ -643,11 +675,20 @@ mod tests {
         assert_eq!((&mut deque).next(), Some(&"c".into()));
         assert!((&mut deque).next().is_none());
 
+        // -------------------------------------------------------
+        // Ensure iter() does not interfere with the cursor.
+        deque.reset_cursor();
+        assert_eq!((&mut deque).next(), Some(&"c".into()));
+        let elems = deque.iter().cloned().collect::<Vec<String>>();
+        assert_eq!(elems, vec!["c".to_string()]);
+        assert!((&mut deque).next().is_none());
+
         // -------------------------------------------------------
         // Check iterating on an empty deque.
         deque.pop_front(); // "c"
         assert!((&mut deque).next().is_none());
         assert!((&mut deque).next().is_none());
+        assert!(deque.iter().next().is_none());
     }
 
     #[test]
@@ -1,8 +1,13 @@
 /// The error type for the functionalities around
 /// [`Cache#invalidate_entries_if`][invalidate-if] method.
 ///
+/// It implements `std::error::Error`, so it can be propagated by `?` into
+/// `Box<dyn std::error::Error>`. More variants may be added in the future, so
+/// `match` on it needs a wildcard arm.
+///
 /// [invalidate-if]: ./sync/struct.Cache.html#method.invalidate_entries_if
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum PredicateError {
     /// This cache does not have a necessary configuration enabled to support
     /// invalidating entries with a closure.
@@ -18,4 +23,218 @@ pub enum PredicateError {
     of the builder at the cache creation time"
     )]
     InvalidationClosuresDisabled,
+
+    /// The number of the predicates that are registered but not applied yet has
+    /// reached the limit set by
+    /// [`CacheBuilder::max_pending_invalidation_closures`][max-pending].
+    ///
+    /// The predicates are removed once they have been applied to the entries by
+    /// the housekeeping tasks, so registering may succeed later.
+    ///
+    /// [max-pending]: ./sync/struct.CacheBuilder.html#method.max_pending_invalidation_closures
+    #[error("Too many pending invalidation closures (max: {max})")]
+    TooManyPending {
+        /// The limit set to the builder.
+        max: usize,
+    },
+}
+
+/// The error type returned by the `try_insert` method of the caches.
+///
+/// More variants may be added in the future, so `match` on it needs a wildcard
+/// arm.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheError {
+    /// The write operation channel of the cache has been disconnected, so the
+    /// write could not be recorded for the housekeeping tasks.
+    ///
+    /// This should not happen in normal operation. The value may have been
+    /// stored in the internal hash table, but it will not be managed by the
+    /// eviction policy.
+    #[error("the write operation channel of the cache is disconnected")]
+    WriteChannelDisconnected,
+}
+
+/// The error type returned by the `try_build` method of the cache builders, when
+/// the builder has an invalid configuration.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BuilderError {
+    /// The number of segments was set to 0 by the `segments` method.
+    #[error("num_segments must be greater than 0")]
+    ZeroSegments,
+
+    /// The capacity of the write operation channel was set to 0 by the
+    /// `write_channel_capacity` method.
+    #[error("write_channel_capacity must be greater than 0")]
+    ZeroWriteChannelCapacity,
+
+    /// The threshold of the read buffer was set to 0 by the
+    /// `read_buffer_flush_threshold` method.
+    #[error("read_buffer_flush_threshold must be greater than 0")]
+    ZeroReadBufferFlushThreshold,
+
+    /// The max number of the pending invalidation closures was set to 0 by the
+    /// `max_pending_invalidation_closures` method.
+    #[error("max_pending_invalidation_closures must be greater than 0")]
+    ZeroMaxPendingInvalidationClosures,
+
+    /// The max number of the entries to evict in a housekeeping pass was set to 0
+    /// by the `eviction_batch_size` method.
+    #[error("eviction_batch_size must be greater than 0")]
+    ZeroEvictionBatchSize,
+
+    /// The max number of the calls to the `init` closure of
+    /// `get_or_insert_with_retrying` was set to 0 by the `max_init_attempts`
+    /// method.
+    #[error("max_init_attempts must be greater than 0")]
+    ZeroMaxInitAttempts,
+
+    /// The max number of the concurrent async eviction listener futures was set
+    /// to 0 by the `eviction_concurrency` method.
+    #[error("eviction_concurrency must be greater than 0")]
+    ZeroEvictionConcurrency,
+
+    /// The max number of the concurrent background refreshes was set to 0 by the
+    /// `refresh_concurrency` method.
+    #[error("refresh_concurrency must be greater than 0")]
+    ZeroRefreshConcurrency,
+
+    /// The max capacity was not set. It must be set by the `max_capacity` method
+    /// when the builder is created by the `builder` function of a cache, or by
+    /// `CacheBuilder::default`.
+    #[error("max_capacity must be set")]
+    MissingMaxCapacity,
+
+    /// The initial capacity is greater than the max capacity. This is only
+    /// checked when no weigher is set, as the max capacity is the total weight of
+    /// the entries otherwise.
+    #[error(
+        "initial_capacity ({initial_capacity}) must not be greater than \
+    max_capacity ({max_capacity})"
+    )]
+    InitialCapacityExceedsMaxCapacity {
+        /// The initial capacity set to the builder.
+        initial_capacity: usize,
+        /// The max capacity set to the builder.
+        max_capacity: usize,
+    },
+
+    /// The refresh period was set by the `refresh_after_write` method of
+    /// `future::CacheBuilder`, but no runtime was set by the `runtime` method to
+    /// run the background reloads on.
+    #[error("refresh_after_write requires a runtime set by the runtime method")]
+    MissingRuntime,
+
+    /// The refresh period is not shorter than the time-to-live, so the entries
+    /// would expire before they are refreshed.
+    #[error(
+        "refresh_after_write ({refresh_after_write:?}) must be shorter than \
+    time_to_live ({time_to_live:?})"
+    )]
+    RefreshAfterWriteNotShorterThanTimeToLive {
+        /// The refresh period set to the builder.
+        refresh_after_write: std::time::Duration,
+        /// The time-to-live set to the builder.
+        time_to_live: std::time::Duration,
+    },
+}
+
+/// The error type returned by the `load_with` and `try_load_with` methods of
+/// `future::Cache`.
+///
+/// `E` is the error type of the init future of `try_load_with`. It is
+/// `Infallible` for `load_with`.
+#[cfg(feature = "future")]
+#[derive(thiserror::Error, Debug)]
+pub enum LoadError<E = std::convert::Infallible> {
+    /// The load did not complete within the
+    /// [`CacheBuilder::load_timeout`][load-timeout].
+    ///
+    /// [load-timeout]: ./future/struct.CacheBuilder.html#method.load_timeout
+    #[error("the load did not complete within the load timeout")]
+    TimedOut,
+
+    /// The init future resolved to an error. The error is shared with the other
+    /// calls that were waiting for the same init future.
+    #[error("the init future failed: {0}")]
+    InitFailed(std::sync::Arc<E>),
+}
+
+/// The error of a background reload by the `get_with_refresh` method of the
+/// caches, which is passed to the listener set by the `refresh_error_listener`
+/// method of [`sync::CacheBuilder`][sync-refresh-error-listener] or
+/// [`future::CacheBuilder`][future-refresh-error-listener].
+///
+/// The current value of the entry is retained in both cases, and the entry will
+/// be reloaded again by the next call of `get_with_refresh`.
+///
+/// [sync-refresh-error-listener]: ./sync/struct.CacheBuilder.html#method.refresh_error_listener
+/// [future-refresh-error-listener]: ./future/struct.CacheBuilder.html#method.refresh_error_listener
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum RefreshError {
+    /// The reload closure (or its future) returned an error.
+    #[error("the reload failed: {0}")]
+    Failed(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    /// The reload closure or its future panicked.
+    #[error("the reload panicked")]
+    Panicked,
+}
+
+/// The panic payload of the calls of `get_or_insert_with` (and its family) that
+/// were waiting for an `init` closure that panicked, when the cache is built with
+/// [`InitPanicPolicy::Fail`][fail].
+///
+/// Such a call unwinds with this payload (without invoking the panic hook), so
+/// that it can be told apart from a panic of the caller's own code:
+///
+/// ```rust
+/// use moka::InitPanicked;
+/// # let result: std::thread::Result<()> = Err(Box::new(InitPanicked));
+/// // let result = std::panic::catch_unwind(|| cache.get_or_insert_with(key, init));
+/// if let Err(payload) = result {
+///     assert!(payload.is::<InitPanicked>());
+/// }
+/// ```
+///
+/// [fail]: ./policy/enum.InitPanicPolicy.html#variant.Fail
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("the init closure evaluated by another call for the same key panicked")]
+pub struct InitPanicked;
+
+#[cfg(test)]
+mod tests {
+    use super::PredicateError;
+    use crate::sync::Cache;
+
+    #[test]
+    fn format_predicate_error() {
+        assert_eq!(
+            PredicateError::InvalidationClosuresDisabled.to_string(),
+            "Support for invalidation closures is disabled in this cache. \
+            Please enable it by calling the support_invalidation_closures method \
+            of the builder at the cache creation time"
+        );
+        assert_eq!(
+            PredicateError::TooManyPending { max: 2 }.to_string(),
+            "Too many pending invalidation closures (max: 2)"
+        );
+    }
+
+    #[test]
+    fn propagate_predicate_error() {
+        fn invalidate(cache: &Cache<u32, u32>) -> Result<(), Box<dyn std::error::Error>> {
+            cache.invalidate_entries_if(|_k, _v| true)?;
+            Ok(())
+        }
+
+        let cache = Cache::new(100);
+        let error = invalidate(&cache).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<PredicateError>(),
+            Some(&PredicateError::InvalidationClosuresDisabled)
+        );
+    }
 }
@@ -11,6 +11,11 @@
 // For full authorship information, see the version control history of
 // https://github.com/ben-manes/caffeine/
 
+// Some of the methods are only used by the thread-safe caches.
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+use alloc::{boxed::Box, vec};
+
 /// A probabilistic multi-set for estimating the popularity of an element within
 /// a time window. The maximum frequency of an element is limited to 15 (4-bits)
 /// and an aging process periodically halves the popularity of all elements.
@@ -110,7 +115,7 @@ impl FrequencySketch {
     /// occurrences of the element, up to the maximum (15).
     pub(crate) fn frequency(&self, hash: u64) -> u8 {
         let start = ((hash & 3) << 2) as u8;
-        let mut frequency = std::u8::MAX;
+        let mut frequency = core::u8::MAX;
         for i in 0..4 {
             let index = self.index_of(hash, i);
             let count = (self.table[index] >> ((start + i) << 2) & 0xF) as u8;
@@ -165,6 +170,18 @@ impl FrequencySketch {
         self.size = (self.size >> 1) - (count >> 2);
     }
 
+    /// Sets every counter to zero.
+    pub(crate) fn clear(&mut self) {
+        for entry in self.table.iter_mut() {
+            *entry = 0;
+        }
+        self.size = 0;
+    }
+
+    pub(crate) fn table_len(&self) -> usize {
+        self.table.len()
+    }
+
     /// Returns the table index for the counter at the specified depth.
     fn index_of(&self, hash: u64, depth: u8) -> usize {
         let i = depth as usize;
@@ -174,14 +191,6 @@ impl FrequencySketch {
     }
 }
 
-// Methods only available for testing.
-#[cfg(test)]
-impl FrequencySketch {
-    pub(crate) fn table_len(&self) -> usize {
-        self.table.len()
-    }
-}
-
 // Some test cases were ported from Caffeine at:
 // https://github.com/ben-manes/caffeine/blob/master/caffeine/src/test/java/com/github/benmanes/caffeine/cache/FrequencySketchTest.java
 //
@@ -265,6 +274,20 @@ mod tests {
         assert!(sketch.size <= sketch.sample_size / 2);
     }
 
+    #[test]
+    fn clear() {
+        let mut sketch = FrequencySketch::with_capacity(64);
+        let hasher = hasher();
+        for i in 0..100 {
+            sketch.increment(hasher(i % 10));
+        }
+        assert!(sketch.frequency(hasher(0)) > 0);
+
+        sketch.clear();
+        assert_eq!(sketch.size, 0);
+        assert!((0..10).all(|i| sketch.frequency(hasher(i)) == 0));
+    }
+
     // This test was ported from Caffeine.
     #[test]
     fn heavy_hitters() {
@@ -9,6 +9,7 @@ static REGISTRY: Lazy<ThreadPoolRegistry> = Lazy::new(ThreadPoolRegistry::defaul
 pub(crate) enum PoolName {
     Housekeeper,
     Invalidator,
+    Refresher,
 }
 
 impl PoolName {
@@ -16,6 +17,7 @@ impl PoolName {
         match self {
             PoolName::Housekeeper => "moka-housekeeper-{}",
             PoolName::Invalidator => "moka-invalidator-{}",
+            PoolName::Refresher => "moka-refresher-{}",
         }
     }
 }
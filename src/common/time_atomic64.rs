@@ -23,6 +23,18 @@ impl AtomicInstant {
         self.instant.store(std::u64::MAX, Ordering::Release);
     }
 
+    /// Resets the instant only when it is the given one. Returns `true` if reset.
+    pub(crate) fn compare_and_reset(&self, instant: Instant) -> bool {
+        self.instant
+            .compare_exchange(
+                instant.as_u64(),
+                u64::MAX,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
+
     pub(crate) fn is_set(&self) -> bool {
         self.instant.load(Ordering::Acquire) != u64::MAX
     }
@@ -23,6 +23,17 @@ impl AtomicInstant {
         *self.instant.write() = None;
     }
 
+    /// Resets the instant only when it is the given one. Returns `true` if reset.
+    pub(crate) fn compare_and_reset(&self, instant: Instant) -> bool {
+        let mut lock = self.instant.write();
+        if *lock == Some(instant) {
+            *lock = None;
+            true
+        } else {
+            false
+        }
+    }
+
     pub(crate) fn is_set(&self) -> bool {
         self.instant.read().is_some()
     }
@@ -0,0 +1,333 @@
+// License and Copyright Notice:
+//
+// The design of the timer wheel in this module was taken from a Java class
+// `com.github.benmanes.caffeine.cache.TimerWheel` of Caffeine.
+// https://github.com/ben-manes/caffeine/blob/master/caffeine/src/main/java/com/github/benmanes/caffeine/cache/TimerWheel.java
+//
+// The original code/comments from Caffeine are licensed under the Apache License,
+// Version 2.0 <https://github.com/ben-manes/caffeine/blob/master/LICENSE>
+//
+// Copyrights of the original code/comments are retained by their contributors.
+// For full authorship information, see the version control history of
+// https://github.com/ben-manes/caffeine/
+
+use std::{collections::HashMap, hash::Hash, mem, sync::Arc};
+
+/// The number of buckets in each level of the wheel.
+const BUCKET_COUNTS: [u64; 5] = [
+    64, // roughly seconds
+    64, // roughly minutes
+    32, // roughly hours
+    4,  // roughly days
+    1,  // overflow (> ~6.5 days)
+];
+
+/// The time span (in nanoseconds) of a bucket in each level of the wheel.
+const SPANS: [u64; 6] = [
+    1 << 30, // 1.07 seconds
+    1 << 36, // 1.14 minutes
+    1 << 42, // 1.22 hours
+    1 << 47, // 1.63 days
+    1 << 49, // 6.5 days
+    1 << 49, // 6.5 days
+];
+
+const SHIFTS: [u32; 5] = [30, 36, 42, 47, 49];
+
+/// The pseudo level of the timers that are due but have not been returned yet.
+const OVERDUE: usize = usize::MAX;
+
+type Bucket<K> = Vec<Timer<K>>;
+
+/// A timer scheduled in the wheel.
+pub(crate) struct Timer<K> {
+    pub(crate) key: Arc<K>,
+    /// The deadline in nanoseconds.
+    pub(crate) deadline: u64,
+}
+
+/// The location of a timer: the level, the bucket index in the level, and the
+/// position in the bucket.
+#[derive(Clone, Copy)]
+struct Location {
+    level: usize,
+    index: usize,
+    pos: usize,
+}
+
+/// A hierarchical timer wheel to find the keys whose deadlines have passed,
+/// without scanning all the keys.
+///
+/// Timers are put into the buckets by their coarse deadlines, and each call to
+/// `advance` only examines the buckets that have become due since the last call.
+/// Timers in a bucket of an upper level are cascaded to the lower levels when the
+/// bucket becomes due.
+///
+/// The wheel has at most one timer per key. Scheduling a timer for a key replaces
+/// the existing one, and `cancel` removes it, so the number of the timers never
+/// exceeds the number of the keys.
+pub(crate) struct TimerWheel<K> {
+    levels: Vec<Vec<Bucket<K>>>,
+    /// The timers that are due but have not been returned by `advance` because of
+    /// its limit.
+    overdue: Bucket<K>,
+    /// The locations of the timers, to cancel them.
+    locations: HashMap<Arc<K>, Location>,
+    /// The time (in nanoseconds) when the wheel was last advanced.
+    nanos: u64,
+}
+
+impl<K> Default for TimerWheel<K> {
+    fn default() -> Self {
+        let levels = BUCKET_COUNTS
+            .iter()
+            .map(|&n| (0..n).map(|_| Bucket::default()).collect())
+            .collect();
+        Self {
+            levels,
+            overdue: Bucket::default(),
+            locations: HashMap::default(),
+            nanos: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq> TimerWheel<K> {
+    /// Returns the number of the scheduled timers.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Schedules a timer for the key, which will be returned by `advance` when the
+    /// time reaches the deadline (in nanoseconds). The existing timer of the key, if
+    /// any, is replaced.
+    pub(crate) fn schedule(&mut self, key: Arc<K>, deadline: u64) {
+        self.cancel(&key);
+        self.push(Timer { key, deadline });
+    }
+
+    /// Removes the timer of the key if any.
+    pub(crate) fn cancel(&mut self, key: &K) {
+        if let Some(loc) = self.locations.remove(key) {
+            let bucket = self.bucket_mut(loc.level, loc.index);
+            bucket.swap_remove(loc.pos);
+            // The last timer of the bucket has been moved to the removed position.
+            if let Some(moved) = bucket.get(loc.pos) {
+                let moved = Arc::clone(&moved.key);
+                if let Some(moved_loc) = self.locations.get_mut(&moved) {
+                    moved_loc.pos = loc.pos;
+                }
+            }
+        }
+    }
+
+    /// Advances the wheel to the current time (in nanoseconds), and returns up to
+    /// `limit` timers whose deadlines have passed. The rest of the due timers stay
+    /// in the wheel, and will be returned by the next calls.
+    pub(crate) fn advance(&mut self, now: u64, limit: usize) -> Vec<Timer<K>> {
+        if now < self.nanos {
+            // The clock has gone backward (e.g. it has been replaced). The timers
+            // will never be returned too early, as they are checked against their
+            // deadlines below.
+            self.nanos = now;
+        }
+
+        let prev = mem::replace(&mut self.nanos, now);
+        let mut pending = Vec::default();
+
+        for (level, &shift) in SHIFTS.iter().enumerate() {
+            let prev_ticks = prev >> shift;
+            let current_ticks = now >> shift;
+            if current_ticks == prev_ticks {
+                // The upper levels cannot have any due buckets either.
+                break;
+            }
+
+            let buckets = &mut self.levels[level];
+            let mask = BUCKET_COUNTS[level] - 1;
+            let steps = (current_ticks - prev_ticks + 1).min(BUCKET_COUNTS[level]);
+            for step in 0..steps {
+                let index = ((prev_ticks + step) & mask) as usize;
+                pending.append(&mut buckets[index]);
+            }
+        }
+
+        // Also examine the current bucket of the lowest level, so that the timers
+        // will not have to wait for the next tick.
+        let index = ((now >> SHIFTS[0]) & (BUCKET_COUNTS[0] - 1)) as usize;
+        pending.append(&mut self.levels[0][index]);
+
+        for timer in pending {
+            if timer.deadline <= now {
+                self.push_overdue(timer);
+            } else {
+                // Not due yet. Cascade it to the appropriate bucket.
+                self.push(timer);
+            }
+        }
+
+        let split_at = self.overdue.len().saturating_sub(limit);
+        let expired = self.overdue.split_off(split_at);
+        for timer in &expired {
+            self.locations.remove(&timer.key);
+        }
+        expired
+    }
+
+    fn push(&mut self, timer: Timer<K>) {
+        let delay = timer.deadline.saturating_sub(self.nanos);
+        let last = BUCKET_COUNTS.len() - 1;
+        let level = (0..last).find(|&i| delay < SPANS[i + 1]).unwrap_or(last);
+        let mask = BUCKET_COUNTS[level] - 1;
+        let index = ((timer.deadline >> SHIFTS[level]) & mask) as usize;
+        let bucket = &mut self.levels[level][index];
+        let pos = bucket.len();
+        self.locations
+            .insert(Arc::clone(&timer.key), Location { level, index, pos });
+        bucket.push(timer);
+    }
+
+    fn push_overdue(&mut self, timer: Timer<K>) {
+        let loc = Location {
+            level: OVERDUE,
+            index: 0,
+            pos: self.overdue.len(),
+        };
+        self.locations.insert(Arc::clone(&timer.key), loc);
+        self.overdue.push(timer);
+    }
+
+    fn bucket_mut(&mut self, level: usize, index: usize) -> &mut Bucket<K> {
+        if level == OVERDUE {
+            &mut self.overdue
+        } else {
+            &mut self.levels[level][index]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimerWheel, SPANS};
+    use std::sync::Arc;
+
+    const SEC: u64 = 1_000_000_000;
+
+    fn advance(wheel: &mut TimerWheel<u32>, now: u64) -> Vec<u32> {
+        let mut keys: Vec<_> = wheel
+            .advance(now, usize::MAX)
+            .into_iter()
+            .map(|t| *t.key)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn advance_returns_due_timers() {
+        let mut wheel = TimerWheel::default();
+        wheel.schedule(Arc::new(1), 10 * SEC);
+        wheel.schedule(Arc::new(2), 20 * SEC);
+        wheel.schedule(Arc::new(3), 20 * SEC);
+        wheel.schedule(Arc::new(4), 90 * SEC);
+        assert_eq!(wheel.len(), 4);
+
+        assert!(advance(&mut wheel, 5 * SEC).is_empty());
+        assert_eq!(advance(&mut wheel, 10 * SEC), vec![1]);
+        assert!(advance(&mut wheel, 19 * SEC).is_empty());
+        assert_eq!(advance(&mut wheel, 21 * SEC), vec![2, 3]);
+        assert_eq!(wheel.len(), 1);
+        assert_eq!(advance(&mut wheel, 100 * SEC), vec![4]);
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn cascade_from_upper_levels() {
+        let mut wheel = TimerWheel::default();
+        let hour = 3600 * SEC;
+        let day = 24 * hour;
+        let deadlines = [3 * hour, 2 * day, 10 * day, 3 * hour + SEC];
+        for (i, &d) in deadlines.iter().enumerate() {
+            wheel.schedule(Arc::new(i as u32), d);
+        }
+
+        // Advance in small steps, and check that every timer is returned exactly
+        // once and never before its deadline.
+        let mut now = 0;
+        let mut fired = vec![None; deadlines.len()];
+        while now < 11 * day {
+            now += 7 * 60 * SEC;
+            for key in advance(&mut wheel, now) {
+                let key = key as usize;
+                assert!(fired[key].is_none());
+                assert!(deadlines[key] <= now);
+                fired[key] = Some(now);
+            }
+        }
+        for (i, f) in fired.iter().enumerate() {
+            let f = f.expect("timer did not fire");
+            // Fired within a step (and a bucket span of the lowest level).
+            assert!(f - deadlines[i] < 7 * 60 * SEC + SPANS[0]);
+        }
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn schedule_replaces_and_cancel_removes_timers() {
+        let mut wheel = TimerWheel::default();
+        let keys: Vec<_> = (0..4).map(Arc::new).collect();
+
+        // Rescheduling a key many times keeps only one timer for it.
+        for i in 0..100 {
+            wheel.schedule(Arc::clone(&keys[0]), (10 + i) * SEC);
+        }
+        // These timers are in the same bucket.
+        for (i, key) in keys.iter().enumerate().skip(1) {
+            wheel.schedule(Arc::clone(key), (i as u64) * SEC / 4);
+        }
+        assert_eq!(wheel.len(), 4);
+
+        // Removing the first timer of the bucket moves the last one to its
+        // position. The moved timer can still be cancelled.
+        wheel.cancel(&1);
+        wheel.cancel(&3);
+        assert_eq!(wheel.len(), 2);
+        wheel.cancel(&3);
+        assert_eq!(wheel.len(), 2);
+
+        assert_eq!(advance(&mut wheel, 100 * SEC), vec![2]);
+        // Only the last deadline of key 0 counts.
+        assert_eq!(advance(&mut wheel, 110 * SEC), vec![0]);
+        assert_eq!(wheel.len(), 0);
+    }
+
+    #[test]
+    fn advance_returns_up_to_limit() {
+        let mut wheel = TimerWheel::default();
+        for i in 0..10 {
+            wheel.schedule(Arc::new(i), SEC);
+        }
+
+        let mut fired = Vec::new();
+        for _ in 0..3 {
+            let timers = wheel.advance(2 * SEC, 4);
+            assert!(timers.len() <= 4);
+            fired.extend(timers.into_iter().map(|t| *t.key));
+        }
+        fired.sort_unstable();
+        assert_eq!(fired, (0..10).collect::<Vec<_>>());
+        assert_eq!(wheel.len(), 0);
+
+        // The due timers left by the limit can be cancelled too.
+        for i in 0..10 {
+            wheel.schedule(Arc::new(i), 3 * SEC);
+        }
+        assert_eq!(wheel.advance(4 * SEC, 2).len(), 2);
+        for i in 0..10 {
+            wheel.cancel(&i);
+        }
+        assert_eq!(wheel.len(), 0);
+        assert!(wheel.advance(5 * SEC, usize::MAX).is_empty());
+    }
+}
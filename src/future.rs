@@ -1,16 +1,72 @@
 //! Provides a thread-safe, asynchronous (futures aware) cache implementation.
 //!
 //! To use this module, enable a crate feature called "future".
+//!
+//! # Async runtimes
+//!
+//! The caches in this module do not depend on any particular async runtime:
+//!
+//! - The housekeeping (e.g. the evictions and the calls of the sync eviction
+//!   listener) runs on a global thread pool shared by all the caches in the
+//!   process, rather than on the async runtime.
+//! - The internal timers (e.g. the backoff on a full write channel) are driven
+//!   by [`async-io`][async-io-crate].
+//! - The async blocks passed to `get_or_insert_with` and its friends, and the
+//!   futures of the async eviction listener, are polled by the task that awaits
+//!   the method of the cache, so they can use the facilities (e.g. I/O and
+//!   timers) of the runtime running that task.
+//!
+//! The exception is the background reloads of `get_with_refresh`. No caller
+//! awaits them, so they are spawned as tasks on the [`Runtime`][runtime-trait]
+//! set by `CacheBuilder::runtime`, and can use the facilities of that runtime.
+//! When the runtime is set, the `load_timeout` is also measured by its timer.
+//!
+//! | Executor     | Crate features of moka      | `Runtime` for `get_with_refresh`      |
+//! |:-------------|:----------------------------|:--------------------------------------|
+//! | Tokio        | `future` and `rt-tokio`     | [`TokioRuntime`][tokio-runtime]       |
+//! | async-std    | `future` and `rt-async-std` | [`AsyncStdRuntime`][async-std-runtime] |
+//! | actix-rt     | `future`                    | Implement it by `actix_rt::spawn`     |
+//! | Others       | `future`                    | Implement it by the `spawn` function of the executor |
+//!
+//! The "rt-tokio" and "rt-async-std" features enable "future" as well. No feature
+//! pulls in an async runtime unless it is enabled; the "future" feature alone does
+//! not depend on Tokio or async-std.
+//!
+//! [async-io-crate]: https://crates.io/crates/async-io
+//! [runtime-trait]: ./trait.Runtime.html
+//! [tokio-runtime]: ./struct.TokioRuntime.html
+//! [async-std-runtime]: ./struct.AsyncStdRuntime.html
 
+mod blocking;
 mod builder;
 mod cache;
+mod invalidation;
+mod local_cache;
+mod refresher;
+mod runtime;
+mod segment;
 mod value_initializer;
 
+pub use blocking::BlockingCache;
 pub use builder::CacheBuilder;
 pub use cache::Cache;
+pub use invalidation::InvalidationHandle;
+pub(crate) use invalidation::WriteOpCompletion;
+pub use local_cache::LocalCache;
+#[cfg(feature = "rt-async-std")]
+pub use runtime::AsyncStdRuntime;
+pub use runtime::Runtime;
+#[cfg(feature = "rt-tokio")]
+pub use runtime::TokioRuntime;
+pub use segment::SegmentedCache;
+
+pub use crate::common::error::{LoadError, RefreshError};
 
 /// Provides extra methods that will be useful for testing.
 pub trait ConcurrentCacheExt<K, V> {
     /// Performs any pending maintenance operations needed by the cache.
+    ///
+    /// This is a deprecated alias of the `run_pending_tasks` method of the cache.
+    #[deprecated(since = "0.6.2", note = "Use `run_pending_tasks` method instead")]
     fn sync(&self);
 }
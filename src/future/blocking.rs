@@ -0,0 +1,92 @@
+use super::Cache;
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+/// A blocking wrapper of [`Cache`][cache-struct], returned by
+/// [`Cache::blocking`][blocking-method].
+///
+/// The methods of this wrapper block the current thread until the underlying
+/// futures of the cache complete, so they can be called from synchronous code
+/// without an async runtime.
+///
+/// # Blocking an async runtime
+///
+/// Do not call the methods of this wrapper from an async context, e.g. in an async
+/// task running on a worker thread of an async runtime. The methods drive the
+/// futures by `async_io::block_on`, which cannot tell whether the current thread
+/// is a worker thread of a runtime, so the call neither panics nor returns an
+/// error. It blocks the worker thread, and the runtime cannot make progress on
+/// the other tasks of the thread meanwhile.
+///
+/// This can deadlock: if the future being blocked on waits for a task scheduled on
+/// the same thread, e.g. `get_or_insert_with` waiting for another task resolving
+/// the init future of the same key on a single-threaded runtime, that task never
+/// runs and the call never returns. Move the blocking calls to a dedicated thread
+/// (e.g. by Tokio's `spawn_blocking`) instead.
+///
+/// [cache-struct]: ./struct.Cache.html
+/// [blocking-method]: ./struct.Cache.html#method.blocking
+pub struct BlockingCache<'a, K, V, S = RandomState> {
+    cache: &'a Cache<K, V, S>,
+}
+
+impl<'a, K, V, S> BlockingCache<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    pub(crate) fn new(cache: &'a Cache<K, V, S>) -> Self {
+        Self { cache }
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key.
+    ///
+    /// This is the same as the `get` method of the cache, which does not block.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get(key)
+    }
+
+    /// Ensures the value of the key exists by inserting the result of the init
+    /// closure if not exist, and returns a _clone_ of the value.
+    ///
+    /// Like the `get_or_insert_with` method of the cache, the init closure is
+    /// evaluated only once even if this method (or `get_or_insert_with` of the
+    /// cache) is concurrently called on the same key. The other callers are
+    /// blocked until the value has been inserted.
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the init closure has been panicked.
+    pub fn get_with(&self, key: K, init: impl FnOnce() -> V) -> V {
+        self.cache.blocking_get_or_insert_with(key, init)
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub fn insert(&self, key: K, value: V) {
+        self.cache.blocking_insert(key, value)
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.blocking_invalidate(key)
+    }
+}
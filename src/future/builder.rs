@@ -1,9 +1,18 @@
-use super::Cache;
+use super::{refresher::Refresher, Cache, RefreshError, Runtime, SegmentedCache};
+use crate::{
+    notification::{AsyncEvictionListener, ListenerFuture, MaintenanceReport, RemovalCause},
+    policy::{EvictionPolicy, Expiry, InitPanicPolicy},
+    sync::{config::CacheConfig, SpillStore},
+    time_source::TimeSource,
+    BuilderError,
+};
 
 use std::{
     collections::hash_map::RandomState,
+    future::Future,
     hash::{BuildHasher, Hash},
     marker::PhantomData,
+    sync::Arc,
     time::Duration,
 };
 
@@ -36,17 +45,31 @@ use std::{
 /// // after 30 minutes (TTL) from the insert().
 /// ```
 ///
-pub struct CacheBuilder<C> {
-    max_capacity: usize,
-    initial_capacity: Option<usize>,
-    // num_segments: Option<usize>,
-    time_to_live: Option<Duration>,
-    time_to_idle: Option<Duration>,
-    invalidator_enabled: bool,
+pub struct CacheBuilder<K, V, C> {
+    max_capacity: Option<usize>,
+    num_segments: Option<usize>,
+    config: CacheConfig<K, V>,
     cache_type: PhantomData<C>,
 }
 
-impl<K, V> CacheBuilder<Cache<K, V, RandomState>>
+impl<K, V> Default for CacheBuilder<K, V, Cache<K, V, RandomState>>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Constructs a `CacheBuilder` without the max capacity. It must be set by the
+    /// `max_capacity` method before building a cache.
+    fn default() -> Self {
+        Self {
+            max_capacity: None,
+            num_segments: None,
+            config: CacheConfig::default(),
+            cache_type: PhantomData::default(),
+        }
+    }
+}
+
+impl<K, V> CacheBuilder<K, V, Cache<K, V, RandomState>>
 where
     K: Eq + Hash + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
@@ -55,27 +78,52 @@ where
     /// up to `max_capacity` entries.
     pub fn new(max_capacity: usize) -> Self {
         Self {
-            max_capacity,
-            initial_capacity: None,
-            // num_segments: None,
-            time_to_live: None,
-            time_to_idle: None,
-            invalidator_enabled: false,
+            max_capacity: Some(max_capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the number of segments of the cache.
+    ///
+    /// A `SegmentedCache` has multiple internal `Cache`s, each having its own
+    /// write operation channel and housekeeper, so that many tasks writing
+    /// concurrently contend less. The keys are distributed across the segments by
+    /// their hashes.
+    ///
+    /// The `num_segments` must be greater than 0; otherwise `build` panics and
+    /// `try_build` returns an error.
+    pub fn segments(
+        self,
+        num_segments: usize,
+    ) -> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>> {
+        CacheBuilder {
+            max_capacity: self.max_capacity,
+            num_segments: Some(num_segments),
+            config: self.config,
             cache_type: PhantomData::default(),
         }
     }
 
     /// Builds a `Cache<K, V>`.
+    ///
+    /// If you want to build a `SegmentedCache<K, V>`, call `segments` method before
+    /// calling this method.
     pub fn build(self) -> Cache<K, V, RandomState> {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Builds a `Cache<K, V>`, or returns an error if the builder has an invalid
+    /// configuration.
+    pub fn try_build(self) -> Result<Cache<K, V, RandomState>, BuilderError> {
+        self.validate()?;
+        let refresher = self.refresher();
         let build_hasher = RandomState::default();
-        Cache::with_everything(
-            self.max_capacity,
-            self.initial_capacity,
+        Ok(Cache::with_everything(
+            self.max_capacity.unwrap(),
             build_hasher,
-            self.time_to_live,
-            self.time_to_idle,
-            self.invalidator_enabled,
-        )
+            self.config,
+            refresher,
+        ))
     }
 
     /// Builds a `Cache<K, V, S>`, with the given `hasher`.
@@ -83,46 +131,564 @@ where
     where
         S: BuildHasher + Clone + Send + Sync + 'static,
     {
-        Cache::with_everything(
-            self.max_capacity,
-            self.initial_capacity,
+        self.validate().unwrap_or_else(|e| panic!("{}", e));
+        let refresher = self.refresher();
+        Cache::with_everything(self.max_capacity.unwrap(), hasher, self.config, refresher)
+    }
+}
+
+impl<K, V> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Builds a `SegmentedCache<K, V>`.
+    ///
+    /// If you want to build a `Cache<K, V>`, do not call `segments` method before
+    /// calling this method.
+    pub fn build(self) -> SegmentedCache<K, V, RandomState> {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Builds a `SegmentedCache<K, V>`, or returns an error if the builder has an
+    /// invalid configuration.
+    pub fn try_build(self) -> Result<SegmentedCache<K, V, RandomState>, BuilderError> {
+        self.validate()?;
+        let refresher = self.refresher();
+        let build_hasher = RandomState::default();
+        Ok(SegmentedCache::with_everything(
+            self.max_capacity.unwrap(),
+            self.num_segments.unwrap(),
+            build_hasher,
+            self.config,
+            refresher,
+        ))
+    }
+
+    /// Builds a `SegmentedCache<K, V, S>`, with the given `hasher`.
+    ///
+    /// If you want to build a `Cache<K, V>`, do not call `segments` method before
+    /// calling this method.
+    pub fn build_with_hasher<S>(self, hasher: S) -> SegmentedCache<K, V, S>
+    where
+        S: BuildHasher + Clone + Send + Sync + 'static,
+    {
+        self.validate().unwrap_or_else(|e| panic!("{}", e));
+        let refresher = self.refresher();
+        SegmentedCache::with_everything(
+            self.max_capacity.unwrap(),
+            self.num_segments.unwrap(),
             hasher,
-            self.time_to_live,
-            self.time_to_idle,
-            self.invalidator_enabled,
+            self.config,
+            refresher,
         )
     }
 }
 
-impl<C> CacheBuilder<C> {
-    /// Sets the initial capacity of the cache.
-    pub fn initial_capacity(self, capacity: usize) -> Self {
-        Self {
-            initial_capacity: Some(capacity),
-            ..self
+impl<K, V, C> CacheBuilder<K, V, C> {
+    fn validate(&self) -> Result<(), BuilderError> {
+        let max_capacity = self.max_capacity.ok_or(BuilderError::MissingMaxCapacity)?;
+        if self.num_segments == Some(0) {
+            return Err(BuilderError::ZeroSegments);
+        }
+        if self.config.write_channel_capacity == Some(0) {
+            return Err(BuilderError::ZeroWriteChannelCapacity);
         }
+        if self.config.read_buffer_flush_threshold == Some(0) {
+            return Err(BuilderError::ZeroReadBufferFlushThreshold);
+        }
+        if self.config.max_pending_invalidation_closures == Some(0) {
+            return Err(BuilderError::ZeroMaxPendingInvalidationClosures);
+        }
+        if self.config.eviction_batch_size == Some(0) {
+            return Err(BuilderError::ZeroEvictionBatchSize);
+        }
+        if self.config.eviction_concurrency == 0 {
+            return Err(BuilderError::ZeroEvictionConcurrency);
+        }
+        if self.config.refresh_concurrency == 0 {
+            return Err(BuilderError::ZeroRefreshConcurrency);
+        }
+        if let Some(initial_capacity) = self.config.initial_capacity {
+            if self.config.weigher.is_none() && initial_capacity > max_capacity {
+                return Err(BuilderError::InitialCapacityExceedsMaxCapacity {
+                    initial_capacity,
+                    max_capacity,
+                });
+            }
+        }
+        if let (Some(refresh_after_write), Some(time_to_live)) =
+            (self.config.refresh_after_write, self.config.time_to_live)
+        {
+            if refresh_after_write >= time_to_live {
+                return Err(BuilderError::RefreshAfterWriteNotShorterThanTimeToLive {
+                    refresh_after_write,
+                    time_to_live,
+                });
+            }
+        }
+        if self.config.refresh_after_write.is_some() && self.config.runtime.is_none() {
+            return Err(BuilderError::MissingRuntime);
+        }
+        Ok(())
+    }
+
+    /// Creates the refresher shared by the segments of the cache, if the refresh
+    /// is enabled.
+    fn refresher(&self) -> Option<Arc<Refresher>> {
+        match (&self.config.refresh_after_write, &self.config.runtime) {
+            (Some(_), Some(runtime)) => Some(Arc::new(Refresher::new(
+                Arc::clone(runtime),
+                self.config.refresh_concurrency,
+            ))),
+            _ => None,
+        }
+    }
+
+    /// Sets the max capacity of the cache.
+    ///
+    /// This is required when the builder is created by the `builder` function of
+    /// a cache.
+    pub fn max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = Some(max_capacity);
+        self
+    }
+
+    /// Sets the initial capacity of the cache.
+    pub fn initial_capacity(mut self, capacity: usize) -> Self {
+        self.config.initial_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets both the initial capacity and the max capacity of the cache to the
+    /// expected number of entries.
+    ///
+    /// The internal hash table and the frequency sketch of the cache will be
+    /// pre-sized for `entries` entries, so they will not be resized while the
+    /// cache is filled up.
+    pub fn with_expected_entries(mut self, entries: usize) -> Self {
+        self.max_capacity = Some(entries);
+        self.config.initial_capacity = Some(entries);
+        self
     }
 
     /// Sets the time to live of the cache.
     ///
     /// A cached entry will be expired after the specified duration past from
     /// `insert`.
-    pub fn time_to_live(self, duration: Duration) -> Self {
-        Self {
-            time_to_live: Some(duration),
-            ..self
-        }
+    pub fn time_to_live(mut self, duration: Duration) -> Self {
+        self.config.time_to_live = Some(duration);
+        self
     }
 
     /// Sets the time to idle of the cache.
     ///
     /// A cached entry will be expired after the specified duration past from `get`
     /// or `insert`.
-    pub fn time_to_idle(self, duration: Duration) -> Self {
-        Self {
-            time_to_idle: Some(duration),
-            ..self
-        }
+    pub fn time_to_idle(mut self, duration: Duration) -> Self {
+        self.config.time_to_idle = Some(duration);
+        self
+    }
+
+    /// Sets the expiry policy of the cache, which calculates the expiration of each
+    /// entry from its key and value.
+    ///
+    /// See [`Expiry`][expiry-trait] for details.
+    ///
+    /// [expiry-trait]: ../policy/trait.Expiry.html
+    pub fn expire_after(mut self, expiry: impl Expiry<K, V> + 'static) -> Self {
+        self.config.expiry = Some(Arc::new(expiry));
+        self
+    }
+
+    /// Makes the time to idle of the cache reset only by reads.
+    ///
+    /// By default, both `get` and `insert` reset the idle timer of an entry. When
+    /// this option is set, updating the value of an existing entry does not reset
+    /// the timer, so an entry that is written but never read will expire after the
+    /// `time_to_idle` past from its first `insert`. Inserting a value after the
+    /// entry has expired or been invalidated starts a new timer.
+    ///
+    /// The updates do not make the entry recently used for the eviction by the
+    /// capacity either.
+    pub fn time_to_idle_reads_only(mut self) -> Self {
+        self.config.time_to_idle_reads_only = true;
+        self
+    }
+
+    /// Sets the capacity of the write operation channel of the cache.
+    ///
+    /// Every `insert` and `invalidate` sends a write operation to a bounded
+    /// channel, which is drained by the housekeeper. When the channel is full, the
+    /// writers will wait until the housekeeper catches up. A larger channel lets
+    /// bursts of writes complete without waiting, at the cost of memory; each slot
+    /// of the channel holds a `WriteOp<K, V>`, which is a few pointers in size.
+    ///
+    /// The default capacity is 3,072. A `SegmentedCache` has one channel of this
+    /// capacity per segment.
+    ///
+    /// The `capacity` must be greater than 0; otherwise `build` panics and
+    /// `try_build` returns an error.
+    pub fn write_channel_capacity(mut self, capacity: usize) -> Self {
+        self.config.write_channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets how many reads are buffered before the housekeeper is scheduled to
+    /// apply them.
+    ///
+    /// Every `get` records a read operation in a buffer, and the recorded accesses
+    /// are applied to the cache policy (the recency and frequency of the entries)
+    /// in batches. Until then, the accesses are not taken into account by the
+    /// eviction. A lower threshold makes the eviction more accurate, at the cost of
+    /// running the housekeeping more often.
+    ///
+    /// The default threshold is 512.
+    ///
+    /// The `threshold` must be greater than 0; otherwise `build` panics and
+    /// `try_build` returns an error.
+    pub fn read_buffer_flush_threshold(mut self, threshold: usize) -> Self {
+        self.config.read_buffer_flush_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the time source of the cache.
+    ///
+    /// By default, the cache reads the current time from a monotonic clock of the
+    /// system to determine the expiration of the entries. Set a custom time source
+    /// to drive the expiration by a virtual clock, e.g. in tests or simulations.
+    /// See [`TimeSource`][time-source] for an example.
+    ///
+    /// [time-source]: ../time_source/trait.TimeSource.html
+    pub fn clock(mut self, clock: impl TimeSource + 'static) -> Self {
+        self.config.time_source = Some(Arc::new(clock));
+        self
+    }
+
+    /// Sets the eviction policy of the cache.
+    ///
+    /// The default is `EvictionPolicy::TinyLfu`, which may reject a new entry with
+    /// a low access frequency. Use `EvictionPolicy::Lru` to always admit new
+    /// entries and evict the least recently used ones.
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.config.eviction_policy = policy;
+        self
+    }
+
+    /// Sets the time limit of the loads by the `load_with` and `try_load_with`
+    /// methods of the cache.
+    ///
+    /// A load that does not complete within the time limit is cancelled, and the
+    /// method returns `LoadError::TimedOut`. The time limit includes the time
+    /// waiting for the init future of another call on the same key. When the
+    /// cancelled call was resolving its init future, the future is dropped, so
+    /// one of the waiting calls (or a later call) will resolve its own init
+    /// future.
+    ///
+    /// The `get_or_insert_with` and `try_get_with` methods (and their families)
+    /// are not affected by the time limit.
+    pub fn load_timeout(mut self, duration: Duration) -> Self {
+        self.config.load_timeout = Some(duration);
+        self
+    }
+
+    /// Sets the refresh period of the cache.
+    ///
+    /// When an entry is read by `get_with_refresh` after the specified duration
+    /// past from `insert`, the current value is returned and the entry is reloaded
+    /// in background. The reloads run on the [`runtime`](#method.runtime), which
+    /// must be set as well.
+    pub fn refresh_after_write(mut self, duration: Duration) -> Self {
+        self.config.refresh_after_write = Some(duration);
+        self
+    }
+
+    /// Sets the max number of the background reloads by `get_with_refresh` that
+    /// run concurrently.
+    ///
+    /// The reloads are run by up to `n` worker tasks spawned on the
+    /// [`runtime`](#method.runtime). When many entries are due for refresh at
+    /// once, the reloads exceeding this limit wait in a queue without spawning
+    /// tasks, and the stale values continue to be served until their reloads
+    /// complete. The default is 1, and `n` must be greater than 0.
+    pub fn refresh_concurrency(mut self, n: usize) -> Self {
+        self.config.refresh_concurrency = n;
+        self
+    }
+
+    /// Sets the async runtime to spawn the background reloads of
+    /// `get_with_refresh` on.
+    ///
+    /// This is required when `refresh_after_write` is set; otherwise `build`
+    /// panics and `try_build` returns
+    /// [`BuilderError::MissingRuntime`][missing-runtime]. See
+    /// [`Runtime`][runtime-trait] for an example.
+    ///
+    /// [missing-runtime]: ../enum.BuilderError.html#variant.MissingRuntime
+    /// [runtime-trait]: ./trait.Runtime.html
+    pub fn runtime(mut self, runtime: impl Runtime) -> Self {
+        self.config.runtime = Some(Arc::new(runtime));
+        self
+    }
+
+    /// Sets a listener closure to be called when a background reload by
+    /// `get_with_refresh` fails or panics.
+    ///
+    /// The closure is called on the task of the reload with the key and the
+    /// [`RefreshError`][refresh-error]. The current value of the entry is
+    /// retained, and the entry will be reloaded again by the next call of
+    /// `get_with_refresh`. Without a listener, the errors are discarded.
+    ///
+    /// [refresh-error]: ./enum.RefreshError.html
+    pub fn refresh_error_listener(
+        mut self,
+        listener: impl Fn(Arc<K>, RefreshError) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.refresh_error_listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// Sets what happens to the calls waiting for the `init` closure of
+    /// `get_or_insert_with` (and its family) when the closure panics.
+    ///
+    /// The default is `InitPanicPolicy::Retry`, where one of the waiting calls
+    /// evaluates its own `init` closure. With `InitPanicPolicy::Fail`, the waiting
+    /// calls fail with an [`InitPanicked`][init-panicked] error without evaluating
+    /// their closures. See [`InitPanicPolicy`][init-panic-policy] for details.
+    ///
+    /// [init-panicked]: ../struct.InitPanicked.html
+    /// [init-panic-policy]: ../policy/enum.InitPanicPolicy.html
+    pub fn init_panic_policy(mut self, policy: InitPanicPolicy) -> Self {
+        self.config.init_panic_policy = policy;
+        self
+    }
+
+    /// Enables recording of the cache statistics.
+    ///
+    /// When enabled, the cache records the hit, miss, eviction and load counts,
+    /// which can be obtained by calling the `stats` method of the cache.
+    pub fn record_stats(mut self) -> Self {
+        self.config.record_stats = true;
+        self
+    }
+
+    /// Sets the spill store of the cache.
+    ///
+    /// Entries evicted from the cache by the `max_capacity` constraint will be
+    /// written to the store, and `get` will consult the store when the key is not
+    /// found in the cache. See the document of the
+    /// [`SpillStore`][spill-store-trait] for the consistency of the spilled entries.
+    ///
+    /// [spill-store-trait]: ../sync/trait.SpillStore.html
+    pub fn spill_to(mut self, store: impl SpillStore<K, V> + 'static) -> Self {
+        self.config.spill_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Sets the weigher closure of the cache.
+    ///
+    /// The closure should take `&K` and `&V` as the arguments and return a `u32`
+    /// representing the relative size of the entry. When a weigher is set, the
+    /// `max_capacity` of the cache bounds the sum of the weights of the entries
+    /// rather than the number of the entries. An entry whose weight alone
+    /// exceeds the `max_capacity` will never be admitted to the cache.
+    ///
+    /// The weight of an entry is calculated when it is inserted or updated, and
+    /// will not be changed while the entry is in the cache.
+    pub fn weigher(mut self, weigher: impl Fn(&K, &V) -> u32 + Send + Sync + 'static) -> Self {
+        self.config.weigher = Some(Arc::new(weigher));
+        self
+    }
+
+    /// Sets the max weight of an entry, which is independent of the
+    /// `max_capacity`. An entry heavier than this is never stored in the cache.
+    ///
+    /// This protects the cache from a single huge entry evicting many other
+    /// entries. Like an entry heavier than the `max_capacity`, such an entry is
+    /// removed from the cache (and passed to the eviction listener with
+    /// `RemovalCause::Size`) when the pending writes are applied, without going
+    /// through the admission policy. If it replaces a value of the key, the old
+    /// value is removed too. `insert_with_report` reports it as `Rejected`.
+    ///
+    /// Without a weigher, the weight of every entry is `1`.
+    pub fn max_entry_weight(mut self, weight: u32) -> Self {
+        self.config.max_entry_weight = Some(weight);
+        self
+    }
+
+    /// Makes the `entry_count` method of the cache return the exact number of the
+    /// entries.
+    ///
+    /// By default, `entry_count` returns the size of the internal hash table, which
+    /// may include the entries that have been rejected by the admission policy or
+    /// expired but not removed yet. With this option, the housekeeper maintains an
+    /// atomic counter of the entries when it admits them to or removes them from
+    /// the cache policy; replacing the value of an existing entry does not change
+    /// the count. After `run_pending_tasks`, `entry_count` equals the number of the
+    /// entries iterated by `to_hash_map`, as long as no entry has expired.
+    ///
+    /// This adds an atomic operation to each admission and removal, which slightly
+    /// reduces the write throughput.
+    pub fn exact_entry_count(mut self) -> Self {
+        self.config.exact_entry_count = true;
+        self
+    }
+
+    /// Makes the cache always admit a newly inserted entry, bypassing the
+    /// frequency-based admission filter of `EvictionPolicy::TinyLfu`.
+    ///
+    /// By default, when the cache is full, a new entry is admitted only if it is
+    /// accessed more often than the entries to be evicted for it. This protects the
+    /// cache from one-off keys, but a write-heavy workload may see its fresh
+    /// entries rejected. With this option, a new entry is always admitted. The
+    /// frequency sketch is still maintained, and used to choose the victims: the
+    /// least frequently used entries among the ones at the LRU end of the
+    /// probation region are evicted first.
+    ///
+    /// This option has no effect with `EvictionPolicy::Lru`, which always admits
+    /// new entries.
+    pub fn admit_on_insert(mut self) -> Self {
+        self.config.admit_on_insert = true;
+        self
+    }
+
+    /// Sets the max number of the entries that a housekeeping pass (e.g.
+    /// `run_pending_tasks`) may evict because of the max capacity, and so may
+    /// the pass removing the expired entries. The default is `500`.
+    ///
+    /// The housekeeping pass holds the lock of the cache policy while evicting the
+    /// entries, so a large batch may stall the other housekeeping work for a
+    /// while. On the other hand, with a small batch, a cache far over its capacity
+    /// (e.g. after shrinking the max capacity by `set_max_capacity`) takes many
+    /// passes to get back within the capacity.
+    ///
+    /// The batch size must be greater than `0`; otherwise `try_build` fails with
+    /// [`BuilderError::ZeroEvictionBatchSize`][zero-eviction-batch-size].
+    ///
+    /// [zero-eviction-batch-size]: ../enum.BuilderError.html#variant.ZeroEvictionBatchSize
+    pub fn eviction_batch_size(mut self, size: usize) -> Self {
+        self.config.eviction_batch_size = Some(size);
+        self
+    }
+
+    /// Sizes the frequency sketch of the TinyLFU policy for the expected number of
+    /// the distinct keys. The default is the max capacity of the cache.
+    ///
+    /// The sketch estimates how often the keys have been accessed, and the
+    /// estimates decide which entries are admitted to the cache. When many more
+    /// distinct keys than the max capacity are accessed, a larger sketch has fewer
+    /// hash collisions, so it gives more accurate estimates.
+    ///
+    /// The sketch uses 8 bytes per key of the capacity rounded up to the next power
+    /// of two (e.g. 8 MiB for a capacity of one million), with a minimum of 128
+    /// keys. The capacity is capped to 2^30 keys (2^24 on 32-bit platforms). The
+    /// sketch is not resized by `set_max_capacity` when this method is used. For a
+    /// segmented cache, the capacity is distributed across the segments. This has
+    /// no effect on the LRU eviction policy.
+    pub fn frequency_sketch_capacity(mut self, capacity: usize) -> Self {
+        self.config.frequency_sketch_capacity = Some(capacity);
+        self
+    }
+
+    /// Makes `get_or_try_insert_with` (and `try_get_with`) remember an `Err`
+    /// returned by the init future for the given duration.
+    ///
+    /// By default, an `Err` is returned only to the calls waiting for the same init
+    /// future, and the next call on the key evaluates its own future, which may
+    /// overload a failing backend. With this option, the calls on the key within
+    /// the duration get the same `Err` without evaluating their futures. After
+    /// the duration, the next call retries.
+    ///
+    /// The error is remembered per key and error type, and is not cleared by
+    /// inserting or invalidating the key. A successful value inserted by `insert`
+    /// is returned as usual though, as the cache is looked up first. The expired
+    /// errors are dropped by the housekeeper, so the errors of the keys that are
+    /// not requested again do not stay in memory.
+    pub fn cache_errors_for(mut self, duration: Duration) -> Self {
+        self.config.cache_errors_for = Some(duration);
+        self
+    }
+
+    /// Sets the eviction listener closure of the cache.
+    ///
+    /// The closure will be called with the key, the value and the
+    /// [`RemovalCause`][removal-cause] when an entry is removed from the cache by
+    /// expiration, by the `max_capacity` constraint, by `invalidate`-family
+    /// methods, or when its value is replaced by `insert`.
+    ///
+    /// The listener is called by the housekeeping thread while it is applying
+    /// the pending writes and evictions, rather than by the thread calling the
+    /// cache method. So the notification of a removal may be delayed.
+    ///
+    /// # Panics and Deadlocks
+    ///
+    /// The closure should not panic, and must not call back into the cache (e.g.
+    /// `insert` or `sync`). Doing so may cause a deadlock as the closure is
+    /// called while the housekeeper holds the internal locks of the cache.
+    ///
+    /// [removal-cause]: ../notification/enum.RemovalCause.html
+    pub fn eviction_listener(
+        mut self,
+        listener: impl Fn(Arc<K>, V, RemovalCause) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.eviction_listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// Sets the async eviction listener closure to the cache.
+    ///
+    /// The closure will be called with the key, the value and the
+    /// [`RemovalCause`][removal-cause] of a removed entry, like the closure set by
+    /// the `eviction_listener` method, and returns a future. The future is awaited
+    /// before the value is dropped, so it can e.g. write a large value back to a
+    /// slower storage before its memory is reclaimed.
+    ///
+    /// Unlike the `eviction_listener` closure, the futures are not run while the
+    /// internal locks of the cache are held. They are awaited by
+    /// `run_pending_tasks`, or driven by the housekeeping thread after each
+    /// housekeeping pass. At most `eviction_concurrency` futures run concurrently
+    /// (default: 1).
+    ///
+    /// [removal-cause]: ../notification/enum.RemovalCause.html
+    pub fn async_eviction_listener<F, Fut>(mut self, listener: F) -> Self
+    where
+        F: Fn(Arc<K>, V, RemovalCause) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener: AsyncEvictionListener<K, V> =
+            Arc::new(move |k, v, cause| -> ListenerFuture { Box::pin(listener(k, v, cause)) });
+        self.config.async_eviction_listener = Some(listener);
+        self
+    }
+
+    /// Sets the max number of the futures returned by the async eviction listener
+    /// to run concurrently. The default is 1, i.e. the futures are awaited one by
+    /// one. For a `SegmentedCache`, the limit applies to each segment.
+    ///
+    /// `try_build` returns an error if `n` is 0.
+    pub fn eviction_concurrency(mut self, n: usize) -> Self {
+        self.config.eviction_concurrency = n;
+        self
+    }
+
+    /// Sets the closure to be called after each housekeeping pass of the cache.
+    ///
+    /// The closure will be called with a [`MaintenanceReport`][report] telling
+    /// the numbers of the buffered reads and writes applied by the pass, the
+    /// number of the evicted entries, and the time taken by the pass. It can be
+    /// used to tune the configuration of the cache.
+    ///
+    /// Like the eviction listener, the closure is called by the housekeeping
+    /// thread while it holds the internal locks of the cache, so it should return
+    /// quickly and must not call back into the cache.
+    ///
+    /// [report]: ../notification/struct.MaintenanceReport.html
+    pub fn on_maintenance(
+        mut self,
+        callback: impl Fn(MaintenanceReport) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.maintenance_listener = Some(Arc::new(callback));
+        self
     }
 
     /// Enables support for [Cache::invalidate_entries_if][cache-invalidate-if]
@@ -132,17 +698,38 @@ impl<C> CacheBuilder<C> {
     /// `invalidate_entries_if` method.
     ///
     /// [cache-invalidate-if]: ./struct.Cache.html#method.invalidate_entries_if
-    pub fn support_invalidation_closures(self) -> Self {
-        Self {
-            invalidator_enabled: true,
-            ..self
-        }
+    pub fn support_invalidation_closures(mut self) -> Self {
+        self.config.invalidator_enabled = true;
+        self
+    }
+
+    /// Sets the max number of the closures registered by
+    /// [`Cache::invalidate_entries_if`][cache-invalidate-if] that have not been
+    /// applied to the entries yet.
+    ///
+    /// A registered closure is applied by every `get` call until the housekeeping
+    /// tasks have applied it to all the entries inserted before the registration,
+    /// so the cost of `get` grows with the number of the pending closures. When the
+    /// limit is reached, `invalidate_entries_if` fails with
+    /// [`PredicateError::TooManyPending`][too-many-pending] instead. By default,
+    /// there is no limit.
+    ///
+    /// The `max` must be greater than 0; otherwise `build` panics and `try_build`
+    /// returns an error. This has no effect unless `support_invalidation_closures`
+    /// is called.
+    ///
+    /// [cache-invalidate-if]: ./struct.Cache.html#method.invalidate_entries_if
+    /// [too-many-pending]: ../enum.PredicateError.html#variant.TooManyPending
+    pub fn max_pending_invalidation_closures(mut self, max: usize) -> Self {
+        self.config.max_pending_invalidation_closures = Some(max);
+        self
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::CacheBuilder;
+    use crate::{future::Cache, policy::EvictionPolicy, BuilderError};
 
     use std::time::Duration;
 
@@ -172,4 +759,106 @@ mod tests {
         cache.insert('a', "Alice").await;
         assert_eq!(cache.get(&'a'), Some("Alice"));
     }
+
+    #[tokio::test]
+    async fn build_fully_configured_cache() {
+        let cache = CacheBuilder::new(100)
+            .initial_capacity(50)
+            .time_to_live(Duration::from_secs(45 * 60))
+            .time_to_idle(Duration::from_secs(15 * 60))
+            .eviction_policy(EvictionPolicy::Lru)
+            .support_invalidation_closures()
+            .build();
+
+        assert_eq!(cache.max_capacity(), 100);
+        assert_eq!(cache.initial_capacity(), Some(50));
+        assert_eq!(cache.time_to_live(), Some(Duration::from_secs(45 * 60)));
+        assert_eq!(cache.time_to_idle(), Some(Duration::from_secs(15 * 60)));
+        assert_eq!(cache.eviction_policy(), EvictionPolicy::Lru);
+        assert!(cache.invalidation_closures_enabled());
+        assert_eq!(cache.num_segments(), 1);
+
+        cache.insert('a', "Alice").await;
+        assert_eq!(cache.get(&'a'), Some("Alice"));
+
+        // The defaults.
+        let cache: Cache<char, &str> = CacheBuilder::new(100).build();
+        assert_eq!(cache.initial_capacity(), None);
+        assert_eq!(cache.eviction_policy(), EvictionPolicy::TinyLfu);
+        assert!(!cache.invalidation_closures_enabled());
+    }
+
+    #[tokio::test]
+    async fn try_build() {
+        let cache = CacheBuilder::new(100).try_build().unwrap();
+        cache.insert('a', "Alice").await;
+        assert_eq!(cache.get(&'a'), Some("Alice"));
+
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .write_channel_capacity(0)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::ZeroWriteChannelCapacity));
+
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .read_buffer_flush_threshold(0)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::ZeroReadBufferFlushThreshold)
+        );
+
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .initial_capacity(101)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::InitialCapacityExceedsMaxCapacity {
+                initial_capacity: 101,
+                max_capacity: 100
+            })
+        );
+
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .eviction_concurrency(0)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::ZeroEvictionConcurrency));
+
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .refresh_concurrency(0)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::ZeroRefreshConcurrency));
+
+        let ttl = Duration::from_secs(10);
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .time_to_live(ttl)
+            .refresh_after_write(ttl)
+            .try_build();
+        assert_eq!(
+            result.err(),
+            Some(BuilderError::RefreshAfterWriteNotShorterThanTimeToLive {
+                refresh_after_write: ttl,
+                time_to_live: ttl,
+            })
+        );
+
+        let result = CacheBuilder::<char, String, Cache<_, _>>::new(100)
+            .refresh_after_write(ttl)
+            .try_build();
+        assert_eq!(result.err(), Some(BuilderError::MissingRuntime));
+
+        let result = Cache::<char, String>::builder().try_build();
+        assert_eq!(result.err(), Some(BuilderError::MissingMaxCapacity));
+    }
+
+    #[tokio::test]
+    async fn build_from_builder_fn() {
+        let cache = Cache::builder()
+            .max_capacity(100)
+            .time_to_live(Duration::from_secs(45 * 60))
+            .build();
+        assert_eq!(cache.max_capacity(), 100);
+        assert_eq!(cache.time_to_live(), Some(Duration::from_secs(45 * 60)));
+        cache.insert('a', "Alice").await;
+        assert_eq!(cache.get(&'a'), Some("Alice"));
+    }
 }
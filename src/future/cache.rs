@@ -1,27 +1,44 @@
 use super::{
-    value_initializer::{InitResult, ValueInitializer},
-    ConcurrentCacheExt,
+    refresher::Refresher,
+    value_initializer::{InitResult, OptionallyInit, ValueInitializer},
+    BlockingCache, CacheBuilder, ConcurrentCacheExt, InvalidationHandle, LoadError, RefreshError,
+    Runtime, WriteOpCompletion,
 };
 use crate::{
+    common::backoff::{Backoff, BackoffStep},
+    notification::{AsyncNotifier, RefreshErrorListener},
+    policy::{EntryMeta, EntryStatus, EvictionPolicy, InsertReport},
+    stats::CacheStats,
     sync::{
-        base_cache::{BaseCache, HouseKeeperArc, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS},
+        base_cache::{
+            BaseCache, HouseKeeperArc, RefreshKey, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS,
+        },
+        config::CacheConfig,
         housekeeper::InnerSync,
-        PredicateId, WriteOp,
+        EntryGuard, PredicateId, ValueEntry, WriteOp,
     },
-    PredicateError,
+    CacheError, InitPanicked, PredicateError,
 };
 
 use crossbeam_channel::{Sender, TrySendError};
 use std::{
     any::TypeId,
     borrow::Borrow,
-    collections::hash_map::RandomState,
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    fmt,
     future::Future,
     hash::{BuildHasher, Hash},
+    panic::resume_unwind,
+    pin::Pin,
     sync::Arc,
+    task::{Context, Poll},
     time::Duration,
 };
 
+/// The max number of the buffered reads (and writes) applied by
+/// `run_pending_tasks` before yielding to the executor.
+const RUN_PENDING_TASKS_BATCH_SIZE: usize = 512;
+
 /// A thread-safe, futures-aware concurrent in-memory cache.
 ///
 /// `Cache` supports full concurrency of retrievals and a high expected concurrency
@@ -191,6 +208,10 @@ use std::{
 pub struct Cache<K, V, S = RandomState> {
     base: BaseCache<K, V, S>,
     value_initializer: Arc<ValueInitializer<K, V, S>>,
+    load_timeout: Option<Duration>,
+    refresher: Option<Arc<Refresher>>,
+    refresh_error_listener: Option<RefreshErrorListener<K>>,
+    runtime: Option<Arc<dyn Runtime>>,
 }
 
 // TODO: https://github.com/moka-rs/moka/issues/54
@@ -211,6 +232,25 @@ where
 {
 }
 
+impl<K, V, S> fmt::Debug for Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Prints the configuration and the approximate size of this cache. The
+    /// entries are not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("max_capacity", &self.max_capacity())
+            .field("num_segments", &self.num_segments())
+            .field("time_to_live", &self.time_to_live())
+            .field("time_to_idle", &self.time_to_idle())
+            .field("entry_count", &self.entry_count())
+            .finish()
+    }
+}
+
 impl<K, V> Cache<K, V, RandomState>
 where
     K: Hash + Eq + Send + Sync + 'static,
@@ -218,13 +258,41 @@ where
 {
     /// Constructs a new `Cache<K, V>` that will store up to the `max_capacity` entries.
     ///
+    /// If `max_capacity` is `0`, the cache does not store any entry; values passed
+    /// to `insert` are evicted immediately, and `get` always returns `None`.
+    ///
     /// To adjust various configuration knobs such as `initial_capacity` or
     /// `time_to_live`, use the [`CacheBuilder`][builder-struct].
     ///
     /// [builder-struct]: ./struct.CacheBuilder.html
     pub fn new(max_capacity: usize) -> Self {
         let build_hasher = RandomState::default();
-        Self::with_everything(max_capacity, None, build_hasher, None, None, false)
+        Self::with_everything(max_capacity, build_hasher, CacheConfig::default(), None)
+    }
+
+    /// Returns a [`CacheBuilder`][builder-struct], which can build a `Cache` with
+    /// various configuration knobs.
+    ///
+    /// The max capacity must be set by the `max_capacity` method of the builder;
+    /// otherwise `build` panics.
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::future::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(1_000)
+    ///     .time_to_live(Duration::from_secs(30 * 60))
+    ///     .build();
+    ///
+    /// cache.blocking_insert("a", "alice");
+    /// ```
+    pub fn builder() -> CacheBuilder<K, V, Cache<K, V, RandomState>> {
+        CacheBuilder::default()
     }
 }
 
@@ -236,22 +304,40 @@ where
 {
     pub(crate) fn with_everything(
         max_capacity: usize,
-        initial_capacity: Option<usize>,
         build_hasher: S,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        mut config: CacheConfig<K, V>,
+        refresher: Option<Arc<Refresher>>,
     ) -> Self {
+        let async_notifier = config
+            .async_eviction_listener
+            .take()
+            .map(|listener| AsyncNotifier::new(listener, config.eviction_concurrency));
+        let cache_errors_for = config.cache_errors_for;
+        let value_initializer = Arc::new(ValueInitializer::with_hasher(
+            build_hasher.clone(),
+            config.init_panic_policy,
+            cache_errors_for,
+        ));
+        let load_timeout = config.load_timeout;
+        let refresh_error_listener = config.refresh_error_listener.take();
+        let runtime = config.runtime.clone();
+        let base = BaseCache::new(
+            max_capacity,
+            || build_hasher.clone(),
+            async_notifier,
+            config,
+        );
+        if cache_errors_for.is_some() {
+            let vi = Arc::clone(&value_initializer);
+            base.set_expired_error_remover(Box::new(move |now| vi.remove_expired_errors(now)));
+        }
         Self {
-            base: BaseCache::new(
-                max_capacity,
-                initial_capacity,
-                build_hasher.clone(),
-                time_to_live,
-                time_to_idle,
-                invalidator_enabled,
-            ),
-            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher)),
+            base,
+            value_initializer,
+            load_timeout,
+            refresher,
+            refresh_error_listener,
+            runtime,
         }
     }
 
@@ -273,6 +359,202 @@ where
         self.base.get_with_hash(key, self.base.hash(key))
     }
 
+    /// Returns a _clone_ of the value corresponding to the key, and reloads the
+    /// value in background if the entry is older than the `refresh_after_write`
+    /// period of the cache.
+    ///
+    /// The current (possibly stale) value is returned immediately. The `reload`
+    /// closure is called and its future is resolved on a task spawned on the
+    /// [`CacheBuilder::runtime`][builder-runtime], so the future can use the
+    /// facilities (e.g. I/O and timers) of that runtime. If the future resolves
+    /// to `Ok(V)`, the value is inserted to the cache. If it resolves to an `Err`
+    /// or panics, the current value is retained, the error is passed to the
+    /// [`CacheBuilder::refresh_error_listener`][refresh-error-listener] (if any),
+    /// and the entry will be reloaded again by the next call of this method.
+    ///
+    /// Only one reload per key is in flight at a time; the calls of this method on
+    /// the same key while the key is being reloaded will not call their `reload`
+    /// closures. At most
+    /// [`CacheBuilder::refresh_concurrency`][refresh-concurrency] reloads run
+    /// concurrently, and the others wait in a queue for their turns.
+    ///
+    /// This method behaves like `get` if `refresh_after_write` is not set.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.6", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros"] }
+    /// use moka::future::{CacheBuilder, Runtime};
+    /// use std::{future::Future, pin::Pin, time::Duration};
+    ///
+    /// struct TokioSpawner(tokio::runtime::Handle);
+    ///
+    /// impl Runtime for TokioSpawner {
+    ///     fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) {
+    ///         self.0.spawn(task);
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = CacheBuilder::new(100)
+    ///         .refresh_after_write(Duration::from_secs(60))
+    ///         .runtime(TokioSpawner(tokio::runtime::Handle::current()))
+    ///         .refresh_error_listener(|key, error| {
+    ///             eprintln!("Failed to reload {}: {}", key, error);
+    ///         })
+    ///         .build();
+    ///
+    ///     cache.insert("config", "v1".to_string()).await;
+    ///     let value = cache.get_with_refresh(&"config", |_key| async {
+    ///         // Load the config from somewhere.
+    ///         Ok::<_, std::io::Error>("v2".to_string())
+    ///     });
+    ///     assert_eq!(value, Some("v1".to_string()));
+    /// }
+    /// ```
+    ///
+    /// [builder-runtime]: ./struct.CacheBuilder.html#method.runtime
+    /// [refresh-error-listener]: ./struct.CacheBuilder.html#method.refresh_error_listener
+    /// [refresh-concurrency]: ./struct.CacheBuilder.html#method.refresh_concurrency
+    pub fn get_with_refresh<Q, F, Fut, E>(&self, key: &Q, reload: F) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&K) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>> + 'static,
+    {
+        let hash = self.base.hash(key);
+        let (v, refresh_key) = self.base.get_with_hash_and_refresh_key(key, hash)?;
+        if let Some(refresh_key) = refresh_key {
+            self.spawn_refresh(refresh_key, hash, reload);
+        }
+        Some(v)
+    }
+
+    /// Returns a guard giving a reference to the value corresponding to the key,
+    /// rather than a _clone_ of the value.
+    ///
+    /// This avoids the cost of cloning a large value on every read, without
+    /// wrapping the values by `Arc`. The guard keeps the value alive even if the
+    /// entry is evicted or invalidated while the guard is held; see
+    /// [`EntryGuard`][entry-guard] for the memory implications of holding many
+    /// guards.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// [entry-guard]: ../sync/struct.EntryGuard.html
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<EntryGuard<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.get_ref_with_hash(key, self.base.hash(key))
+    }
+
+    /// Returns `true` if the cache contains a value for the key.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation, so it does not update the historic popularity estimator or reset
+    /// the idle timer for the key. It also does not clone the value.
+    ///
+    /// Expired entries, and entries that have been invalidated by `invalidate_all`
+    /// or by a predicate of `invalidate_entries_if`, are treated as absent even if
+    /// they have not been evicted yet.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.contains_key(key)
+    }
+
+    /// Resets the idle timer of the entry for the key without reading its value,
+    /// and returns `true` if the cache contains a value for the key.
+    ///
+    /// This is cheaper than `get` when you only need to keep an entry warm, as it
+    /// does not clone the value. Like `get`, it updates the historic popularity
+    /// estimator and the access order of the entry. If `reset_ttl` is `true`, it
+    /// also resets the time-to-live timer of the entry, as if the value was
+    /// inserted again (but without notifying the eviction listener).
+    ///
+    /// Returns `false` if the key is not present, or the entry has been expired or
+    /// invalidated.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.touch(key, self.base.hash(key), reset_ttl)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, without affecting
+    /// the cache policy.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation, so it does not update the historic popularity estimator, the
+    /// recency of the entry, or reset the idle timer for the key. It is not
+    /// counted in the hit or miss statistics either. This is useful for monitoring
+    /// or debugging tools that should not perturb the eviction decisions.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.peek(key)
+    }
+
+    /// Returns a snapshot of the entries in the cache, e.g. to persist them on
+    /// shutdown.
+    ///
+    /// The snapshot is weakly consistent; it reflects the entries whose writes have
+    /// been applied by the housekeeper, so it may not include the entries being
+    /// inserted concurrently. The expired and invalidated entries are not included.
+    /// Like `peek`, this method does not affect the cache policy.
+    ///
+    /// The snapshot does not carry the access or frequency history of the entries,
+    /// so the entries loaded from it will start cold, and their time-to-live and
+    /// time-to-idle will start from the loading time.
+    pub fn to_hash_map(&self) -> HashMap<Arc<K>, V> {
+        self.base.to_hash_map()
+    }
+
+    /// Returns a histogram of the ages of the entries in the cache, to help
+    /// choosing the time-to-live and time-to-idle empirically.
+    ///
+    /// The age of an entry is the time elapsed since its current value was
+    /// inserted. Each element of the returned `Vec` is a pair of the (exclusive)
+    /// upper bound of the ages in the bucket and the number of the entries in it.
+    /// The first bucket holds the ages shorter than one second, and the upper
+    /// bounds double in the following buckets (2 seconds, 4 seconds, 8 seconds,
+    /// and so on). The buckets after the one holding the oldest entry are omitted,
+    /// so an empty cache returns an empty `Vec`.
+    ///
+    /// Like `to_hash_map`, the histogram is weakly consistent; it only counts the
+    /// entries whose writes have been applied by the housekeeper, and it does not
+    /// count the expired and invalidated entries.
+    ///
+    /// **NOTE**: This method is a diagnostic; it scans all the entries in the
+    /// cache, so it takes O(n) time. Do not call it on a hot path.
+    pub fn age_histogram(&self) -> Vec<(Duration, usize)> {
+        self.base.age_histogram()
+    }
+
     /// Ensures the value of the key exists by inserting the output of the init
     /// future if not exist, and returns a _clone_ of the value.
     ///
@@ -353,6 +635,12 @@ where
     /// task 3 in the above sample). If there are other calls in progress (e.g. task
     /// 0, 1 and 2 above), this method will restart and resolve one of the remaining
     /// `init` futures.
+    /// This is the default behavior, and it can be changed by
+    /// [`CacheBuilder::init_panic_policy`][init-panic-policy] so that the other
+    /// calls panic with an [`InitPanicked`][init-panicked] payload instead.
+    ///
+    /// [init-panic-policy]: ./struct.CacheBuilder.html#method.init_panic_policy
+    /// [init-panicked]: ../struct.InitPanicked.html
     ///
     pub async fn get_or_insert_with<F>(&self, key: K, init: F) -> V
     where
@@ -363,6 +651,283 @@ where
         self.get_or_insert_with_hash_and_fun(key, hash, init).await
     }
 
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// `default` value if not exist and returns a clone of it.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// with an init future resolving to `default`. When many tasks call this method
+    /// concurrently for the same absent key, only one of the `default` values is
+    /// inserted, and all the calls get that value.
+    pub async fn get_or_insert(&self, key: K, default: V) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_hash_and_fun(key, hash, async move { default })
+            .await
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but also returns
+    /// whether the value was inserted by this call (`EntryStatus::Created`), or
+    /// already existed (`EntryStatus::Existed`).
+    ///
+    /// When many tasks call this method concurrently for the same absent key,
+    /// exactly one of them resolves its `init` future and gets `Created`. The
+    /// others get `Existed` with the value inserted by that task.
+    pub async fn get_or_insert_with_status<F>(&self, key: K, init: F) -> (V, EntryStatus)
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_status_hash_and_fun(key, hash, init)
+            .await
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// output of the init future if not exist. The init future is created by the
+    /// `init` closure, which takes the key. This is convenient when the value is
+    /// loaded by the key.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// except the `init` argument. The closure is called before checking the
+    /// cache, but the future it returns is resolved only when the value is loaded.
+    pub async fn get_or_insert_with_key<F>(&self, key: K, init: impl FnOnce(&Arc<K>) -> F) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init = init(&key);
+        self.get_or_insert_with_hash_and_fun(key, hash, init).await
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// output of the init future if not exist. The init future may decline to
+    /// produce a value by resolving to `None`; then nothing is inserted and `None`
+    /// is returned.
+    ///
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), this method
+    /// prevents to resolve the init future multiple times on the same key even if
+    /// the method is concurrently called by many async tasks. When the resolved
+    /// future returns `None`, the other calls waiting for it will also return
+    /// `None` without resolving their futures.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.6", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///
+    ///     let v = cache.get_or_optionally_insert_with("a", async { None }).await;
+    ///     assert_eq!(v, None);
+    ///     assert_eq!(cache.get(&"a"), None);
+    ///
+    ///     let v = cache
+    ///         .get_or_optionally_insert_with("a", async { Some("alice") })
+    ///         .await;
+    ///     assert_eq!(v, Some("alice"));
+    ///     assert_eq!(cache.get(&"a"), Some("alice"));
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the `init` future has been panicked. See
+    /// `get_or_insert_with` for details.
+    pub async fn get_or_optionally_insert_with<F>(&self, key: K, init: F) -> Option<V>
+    where
+        F: Future<Output = Option<V>> + Send + 'static,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_optionally_insert_with_hash_and_fun(key, hash, init)
+            .await
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// output of the init future if not exist or if `should_recompute` returns
+    /// `true` on the existing value.
+    ///
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), this method
+    /// prevents to resolve the init future multiple times on the same key even if
+    /// the method is concurrently called by many async tasks. See
+    /// [`sync::Cache::get_with_if`][get-with-if] for details.
+    ///
+    /// [get-with-if]: ../sync/struct.Cache.html#method.get_with_if
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the `init` future has been panicked. See
+    /// `get_or_insert_with` for details.
+    pub async fn get_with_if<F>(&self, key: K, init: F, should_recompute: impl Fn(&V) -> bool) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+
+        match self.base.get_with_hash(&key, hash) {
+            Some(v) if !should_recompute(&v) => return v,
+            _ => (),
+        }
+
+        // Check the value again as another call may have recomputed it while we
+        // were waiting for the right to resolve the init future.
+        let mut is_fresh = false;
+        let init = async {
+            match self.base.peek(&key) {
+                Some(v) if !should_recompute(&v) => {
+                    is_fresh = true;
+                    v
+                }
+                _ => init.await,
+            }
+        };
+
+        match self
+            .value_initializer
+            .init_or_read(Arc::clone(&key), init)
+            .await
+        {
+            InitResult::Initialized(v) => {
+                if !is_fresh {
+                    self.base.record_load_success();
+                    self.insert_with_hash(Arc::clone(&key), hash, v.clone())
+                        .await;
+                }
+                self.value_initializer
+                    .remove_waiter(&key, TypeId::of::<()>());
+                v
+            }
+            InitResult::ReadExisting(v) => v,
+            InitResult::InitErr(e) => match *e {},
+            // The waiters of `init_or_read` never have `NoValue`.
+            InitResult::InitNone => unreachable!(),
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
+        }
+    }
+
+    /// Ensures the values of the keys exist by inserting the outputs of the
+    /// futures returned by the loader if not exist, and returns a map of the keys
+    /// to _clones_ of the values.
+    ///
+    /// The futures are resolved concurrently, but at most `max_concurrency` of them
+    /// are in flight at a time, to avoid overwhelming the backend of the loader.
+    /// (`0` is treated as `1`.)
+    ///
+    /// The futures are not spawned as tasks (e.g. by Tokio's `JoinSet`), but
+    /// polled by the calling task, like `futures::stream::buffer_unordered`. This
+    /// keeps the cache independent of a specific async runtime. As a consequence:
+    ///
+    /// - The futures run on the thread of the calling task, so a CPU-bound loader
+    ///   does not run in parallel on multiple threads.
+    /// - Dropping the future returned by this method cancels the loads in flight.
+    ///
+    /// The loader is called only for the keys to be loaded. To run the loads on
+    /// multiple threads, spawn them in the loader. See the second example below.
+    ///
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), the loads are
+    /// deduplicated; the duplicate keys are loaded only once, and a key being
+    /// loaded by another `get_or_*insert_with` call is not loaded again but waits
+    /// for that call.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.6", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///     cache.insert(1, "one".to_string()).await;
+    ///
+    ///     let map = cache
+    ///         .get_or_insert_all_with(vec![1, 2, 3, 2], 2, |&key| async move {
+    ///             format!("loaded {}", key)
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(map.len(), 3);
+    ///     assert_eq!(map[&1], "one");
+    ///     assert_eq!(map[&2], "loaded 2");
+    ///     assert_eq!(cache.get(&3), Some("loaded 3".to_string()));
+    /// }
+    /// ```
+    ///
+    /// Spawning the loads on the Tokio runtime:
+    ///
+    /// ```rust
+    /// use moka::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///
+    ///     let map = cache
+    ///         .get_or_insert_all_with(0..10, 4, |&key| {
+    ///             let handle = tokio::spawn(async move { key * 2 });
+    ///             async move { handle.await.expect("The load panicked") }
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(map.len(), 10);
+    ///     assert_eq!(map[&3], 6);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when one of the futures has been panicked.
+    pub async fn get_or_insert_all_with<I, F, Fut>(
+        &self,
+        keys: I,
+        max_concurrency: usize,
+        loader: F,
+    ) -> HashMap<Arc<K>, V>
+    where
+        I: IntoIterator<Item = K>,
+        F: Fn(&K) -> Fut,
+        Fut: Future<Output = V>,
+    {
+        use futures_util::stream::{self, StreamExt};
+
+        // Deduplicate the keys, keeping the first occurrence.
+        let mut seen = HashSet::new();
+        let keys = keys
+            .into_iter()
+            .map(Arc::new)
+            .filter(|key| seen.insert(Arc::clone(key)))
+            .collect::<Vec<_>>();
+
+        let loader = &loader;
+        stream::iter(keys)
+            .map(|key| async move {
+                let hash = self.base.hash(&key);
+                // Call the loader only when the key needs to be loaded.
+                let key1 = Arc::clone(&key);
+                let init = async move { loader(&key1).await };
+                let value = self
+                    .get_or_insert_with_hash_and_fun(Arc::clone(&key), hash, init)
+                    .await;
+                (key, value)
+            })
+            .buffer_unordered(max_concurrency.max(1))
+            .collect()
+            .await
+    }
+
     /// Try to ensure the value of the key exists by inserting an `Ok` output of the
     /// init future if not exist, and returns a _clone_ of the value or the `Err`
     /// produced by the future.
@@ -372,6 +937,12 @@ where
     /// of the calls resolves its future (as long as these futures return the same
     /// error type), and other calls wait for that future to complete.
     ///
+    /// An `Err` is not inserted into the cache. By default, the next call retries
+    /// right away; use [`CacheBuilder::cache_errors_for`][cache-errors-for] to
+    /// return the same `Err` to the calls for a while instead.
+    ///
+    /// [cache-errors-for]: ./struct.CacheBuilder.html#method.cache_errors_for
+    ///
     /// # Example
     ///
     /// ```rust
@@ -464,13 +1035,247 @@ where
             .await
     }
 
-    /// Inserts a key-value pair into the cache.
+    /// Try to ensure the value of the key exists by inserting an `Ok` output of the
+    /// init future if not exist, and returns a _clone_ of the value or the `Err`
+    /// produced by the future.
     ///
-    /// If the cache has this key present, the value is updated.
-    pub async fn insert(&self, key: K, value: V) {
-        let hash = self.base.hash(&key);
-        let key = Arc::new(key);
-        self.insert_with_hash(key, hash, value).await
+    /// This is the same as [`get_or_try_insert_with`][get-or-try-insert-with]. The
+    /// error is returned as `Arc<E>` with the concrete type of the error, so the
+    /// callers sharing the same failed load can match on it without downcasting.
+    ///
+    /// [get-or-try-insert-with]: #method.get_or_try_insert_with
+    pub async fn try_get_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        self.get_or_try_insert_with(key, init).await
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// output of the init future if not exist. Fails if the load does not complete
+    /// within the [`load_timeout`][load-timeout] of the cache.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with),
+    /// but the call, including the wait for the init future of another call on
+    /// the same key, is raced against a timer. When the timer fires first, the call
+    /// is cancelled and returns `Err(LoadError::TimedOut)`. If the call was
+    /// resolving its init future, the future is dropped and the calls waiting for
+    /// it will retry, so one of them (or a later call) will resolve its own future.
+    ///
+    /// If the cache has no `load_timeout`, this method never returns an error.
+    ///
+    /// [load-timeout]: ./struct.CacheBuilder.html#method.load_timeout
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the `init` future has been panicked. See
+    /// `get_or_insert_with` for details.
+    pub async fn load_with<F>(&self, key: K, init: F) -> Result<V, LoadError>
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.with_load_timeout(self.get_or_insert_with_hash_and_fun(key, hash, init))
+            .await
+            .ok_or(LoadError::TimedOut)
+    }
+
+    /// Try to ensure the value of the key exists by inserting an `Ok` output of the
+    /// init future if not exist, and returns a _clone_ of the value. Fails if the
+    /// init future resolves to an `Err`, or if the load does not complete within
+    /// the [`load_timeout`][load-timeout] of the cache.
+    ///
+    /// This is the same as [`try_get_with`](#method.try_get_with), but the call is
+    /// raced against a timer. See [`load_with`](#method.load_with) for details.
+    ///
+    /// [load-timeout]: ./struct.CacheBuilder.html#method.load_timeout
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the `init` future has been panicked. See
+    /// `get_or_insert_with` for details.
+    pub async fn try_load_with<F, E>(&self, key: K, init: F) -> Result<V, LoadError<E>>
+    where
+        F: Future<Output = Result<V, E>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        match self
+            .with_load_timeout(self.get_or_try_insert_with_hash_and_fun(key, hash, init))
+            .await
+        {
+            Some(Ok(v)) => Ok(v),
+            Some(Err(e)) => Err(LoadError::InitFailed(e)),
+            None => Err(LoadError::TimedOut),
+        }
+    }
+
+    /// Computes a new value for the key from the current one (if any) with the
+    /// async closure, and returns a _clone_ of the new value.
+    ///
+    /// The closure takes `Some(V)` (a _clone_ of the value) if the cache has a
+    /// value for the key, or `None` otherwise. If the returned future resolves to
+    /// `Some(V)`, the value is inserted to the cache. If it resolves to `None`, the
+    /// current value (if any) is discarded.
+    ///
+    /// The calls of this method on the same key are serialized; while a task is
+    /// awaiting its future, other tasks calling this method with the same key will
+    /// wait (without blocking the executor) for it to complete, and then evaluate
+    /// their closures with the new value. Note that `insert` and `invalidate` do
+    /// not wait, so the value written by them while a future is being awaited may
+    /// be overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // Cargo.toml
+    /// //
+    /// // [dependencies]
+    /// // moka = { version = "0.6", features = ["future"] }
+    /// // tokio = { version = "1", features = ["rt-multi-thread", "macros" ] }
+    /// use moka::future::Cache;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let cache = Cache::new(100);
+    ///
+    ///     // Increment the counter.
+    ///     let new_value = cache
+    ///         .compute("counter", |v| async move { Some(v.map_or(1, |v| v + 1)) })
+    ///         .await;
+    ///     assert_eq!(new_value, Some(1));
+    ///
+    ///     // Remove the counter.
+    ///     assert_eq!(cache.compute("counter", |_v| async { None }).await, None);
+    ///     assert_eq!(cache.get(&"counter"), None);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the future has been panicked. Other tasks waiting
+    /// for the future will go on and evaluate their own closures.
+    pub async fn compute<F, Fut>(&self, key: K, f: F) -> Option<V>
+    where
+        F: FnOnce(Option<V>) -> Fut,
+        Fut: Future<Output = Option<V>>,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.value_initializer
+            .serialize(&key, async {
+                let current = self.base.get_with_hash(&key, hash);
+                match f(current).await {
+                    Some(v) => {
+                        self.insert_with_hash(Arc::clone(&key), hash, v.clone())
+                            .await;
+                        Some(v)
+                    }
+                    None => {
+                        self.invalidate(&key).await;
+                        None
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub async fn insert(&self, key: K, value: V) {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash(key, hash, value).await
+    }
+
+    /// Inserts a key-value pair into the cache, returning an error instead of
+    /// panicking when the write cannot be recorded.
+    ///
+    /// `insert` panics if the write operation channel of the cache has been
+    /// disconnected. This method returns
+    /// [`CacheError::WriteChannelDisconnected`][disconnected] in that case, so the
+    /// caller can handle a broken cache (e.g. by rebuilding it).
+    ///
+    /// [disconnected]: ../enum.CacheError.html#variant.WriteChannelDisconnected
+    pub async fn try_insert(&self, key: K, value: V) -> Result<(), CacheError> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.try_insert_with_hash(key, hash, value).await
+    }
+
+    /// Inserts a key-value pair into the cache, and reports whether the key was
+    /// newly added or its value was replaced, and whether the new entry is expected
+    /// to be admitted. See [`InsertReport`][insert-report] for details.
+    ///
+    /// This is useful to apply back pressure when the cache is saturated and the
+    /// inserts cause evictions or get rejected. Unlike `insert`, this method may
+    /// wait for the housekeeper to finish applying the pending writes, as it
+    /// needs the eviction deques to predict the admission.
+    ///
+    /// [insert-report]: ../policy/enum.InsertReport.html
+    pub async fn insert_with_report(&self, key: K, value: V) -> InsertReport {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let (op, report) = self.base.do_insert_with_report(key, hash, value);
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+            .await
+            .expect("Failed to insert");
+        self.base.inner.notify_pending_async().await;
+        report
+    }
+
+    /// Inserts a key-value pair into the cache with the time-to-live for the entry.
+    ///
+    /// The entry will be expired after the specified duration past from this
+    /// insert, or after the cache-wide `time_to_live` (if set) if that is shorter.
+    /// If the cache has this key present, the value and its time-to-live are
+    /// updated. A later `insert` of the same key without a time-to-live resets the
+    /// entry to the cache-wide `time_to_live`.
+    ///
+    /// An expired entry will never be returned by `get`. However, it may remain in
+    /// the cache until the entries written before it have been evicted.
+    pub async fn insert_with_ttl(&self, key: K, value: V, time_to_live: Duration) {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_ttl(key, hash, value, Some(time_to_live))
+            .await
+    }
+
+    /// Returns a [`BlockingCache`][blocking-cache] wrapper of this cache, whose
+    /// methods block the current thread until they complete. Use it to access the
+    /// cache from synchronous code without an async runtime.
+    ///
+    /// The wrapper must not be used from an async context. See the documentation
+    /// of `BlockingCache` for details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::future::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// let blocking = cache.blocking();
+    ///
+    /// blocking.insert("a", "alice");
+    /// assert_eq!(blocking.get(&"a"), Some("alice"));
+    /// assert_eq!(blocking.get_with("b", || "bob"), "bob");
+    /// ```
+    ///
+    /// [blocking-cache]: ./struct.BlockingCache.html
+    pub fn blocking(&self) -> BlockingCache<'_, K, V, S> {
+        BlockingCache::new(self)
+    }
+
+    pub(crate) fn blocking_get_or_insert_with(&self, key: K, init: impl FnOnce() -> V) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init = async move { init() };
+        async_io::block_on(self.get_or_insert_with_hash_and_fun(key, hash, init))
     }
 
     /// Blocking [insert](#method.insert) to call outside of asynchronous contexts.
@@ -480,7 +1285,7 @@ where
     pub fn blocking_insert(&self, key: K, value: V) {
         let hash = self.base.hash(&key);
         let key = Arc::new(key);
-        let op = self.base.do_insert_with_hash(key, hash, value);
+        let op = self.base.do_insert_with_hash(key, hash, value, None);
         let hk = self.base.housekeeper.as_ref();
         Self::blocking_schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to insert");
     }
@@ -494,15 +1299,83 @@ where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        if let Some(entry) = self.base.remove(key) {
-            let op = WriteOp::Remove(entry);
+        if let Some(kv) = self.base.remove(key) {
+            let op = WriteOp::Remove(kv);
+            let hk = self.base.housekeeper.as_ref();
+            Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+                .await
+                .expect("Failed to remove");
+            self.base.inner.notify_pending_async().await;
+        }
+    }
+
+    /// Discards any cached value for the key, and waits until the removal has been
+    /// applied by the housekeeper.
+    ///
+    /// `invalidate` returns as soon as the removal is scheduled; the housekeeper
+    /// applies it to the cache policy later. The future returned by this method
+    /// completes only after the housekeeper has applied the removal, so the
+    /// subsequent `get` will not return the value, the eviction listener has been
+    /// notified, and `entry_count` and `weighted_size` no longer count the entry.
+    ///
+    /// This method asks the housekeeper to run without waiting for the periodical
+    /// one, but it is still much slower than `invalidate`. Use it only when you
+    /// need this consistency, e.g. in tests.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn invalidate_sync<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(kv) = self.base.remove(key) {
             let hk = self.base.housekeeper.as_ref();
+            Self::schedule_write_op(&self.base.write_op_ch, WriteOp::Remove(kv), hk)
+                .await
+                .expect("Failed to remove");
+            let completion = Arc::new(WriteOpCompletion::default());
+            let op = WriteOp::Notify(Arc::clone(&completion));
             Self::schedule_write_op(&self.base.write_op_ch, op, hk)
                 .await
                 .expect("Failed to remove");
+            if let Some(h) = hk {
+                h.try_schedule_sync();
+            }
+            completion.wait().await;
+            self.base.inner.notify_pending_async().await;
         }
     }
 
+    /// Discards any cached value for the key, and returns a _clone_ of the value.
+    ///
+    /// Returns `None` if the cache did not have a value for the key, or the value
+    /// has been expired or invalidated.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let kv = self.base.remove(key)?;
+        // Check the validity before scheduling the op because the housekeeper may
+        // update the timestamps of the entry.
+        let value = if self.base.is_valid_entry(&kv) {
+            Some(kv.entry.value.clone())
+        } else {
+            None
+        };
+        let op = WriteOp::Remove(kv);
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+            .await
+            .expect("Failed to remove");
+        self.base.inner.notify_pending_async().await;
+        value
+    }
+
     /// Blocking [invalidate](#method.invalidate) to call outside of asynchronous
     /// contexts.
     ///
@@ -513,8 +1386,8 @@ where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        if let Some(entry) = self.base.remove(key) {
-            let op = WriteOp::Remove(entry);
+        if let Some(kv) = self.base.remove(key) {
+            let op = WriteOp::Remove(kv);
             let hk = self.base.housekeeper.as_ref();
             Self::blocking_schedule_write_op(&self.base.write_op_ch, op, hk)
                 .expect("Failed to remove");
@@ -530,11 +1403,73 @@ where
     ///
     /// Like the `invalidate` method, this method does not clear the historic
     /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
+    /// trying to retrieve an item. Use the `reset` method to clear it as well.
     pub fn invalidate_all(&self) {
         self.base.invalidate_all();
     }
 
+    /// Discards all cached values, and clears the historic popularity estimator of
+    /// keys.
+    ///
+    /// Unlike `invalidate_all`, the keys inserted after calling this method are
+    /// not penalized by the access frequencies recorded before the call. This is
+    /// useful when the access pattern of the cache changes completely, e.g. when
+    /// the cache is reused for a different tenant.
+    pub fn reset(&self) {
+        self.base.reset();
+    }
+
+    /// Records an access to the key in the historic popularity estimator of keys,
+    /// without reading or inserting the value.
+    ///
+    /// This is an advanced tuning knob. With the TinyLFU eviction policy, a new
+    /// key is admitted only when it is accessed more often than the entry it would
+    /// evict, so a popular key may be rejected a few times before the estimator
+    /// learns about it. Calling this method for the popular keys (e.g. replaying
+    /// historic access logs) before serving traffic lets their first inserts be
+    /// admitted. It does nothing with the LRU eviction policy.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn record_access<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.record_access(key);
+    }
+
+    /// Discards all cached values, and waits for them to be removed from the cache.
+    ///
+    /// Unlike `invalidate_all`, this method applies the pending writes and removes
+    /// every entry from the internal hash table before returning, so that
+    /// `entry_count` and `weighted_size` are zero right after the call. This is
+    /// more expensive than `invalidate_all`, which only records a timestamp and
+    /// takes O(1) time: `clear` takes O(n) time on the calling task, where n is
+    /// the number of entries. It removes the entries in batches, and yields to the
+    /// async runtime between them, so it does not block the executor thread for
+    /// the whole time. Entries inserted by other tasks while this method is
+    /// running may remain in the cache.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys. Call the `reset` method afterward to clear it
+    /// as well.
+    pub async fn clear(&self) {
+        // Apply the pending writes first so that all the entries are in the deques.
+        self.run_pending_tasks().await;
+        let key_hashes = self.base.key_hashes();
+        for batch in key_hashes.chunks(RUN_PENDING_TASKS_BATCH_SIZE) {
+            for kh in batch {
+                self.invalidate(&kh.key).await;
+            }
+            // Apply the removals of this batch, so that the write channel will not
+            // be filled up. This also yields between the batches.
+            self.run_pending_tasks().await;
+        }
+        self.base.clear_spill();
+        self.run_pending_tasks().await;
+    }
+
     /// Discards cached values that satisfy a predicate.
     ///
     /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
@@ -543,6 +1478,10 @@ where
     /// called. If the closure returns `true` on a value, that value will be evicted
     /// from the cache.
     ///
+    /// This method returns an [`InvalidationHandle`][invalidation-handle]. You can
+    /// `.await` it to wait until the closure has been applied to all of these
+    /// values.
+    ///
     /// Also the `get` method will apply the closure to a value to determine if it
     /// should have been invalidated. Therefore, it is guaranteed that the `get`
     /// method must not return invalidated values.
@@ -554,17 +1493,155 @@ where
     /// fail with a
     /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
     ///
+    /// If the cache is built with
+    /// [`CacheBuilder::max_pending_invalidation_closures`][max-pending], this
+    /// method fails with
+    /// [`PredicateError::TooManyPending`][too-many-pending-error] when that many
+    /// closures have not been applied yet.
+    ///
     /// Like the `invalidate` method, this method does not clear the historic
     /// popularity estimator of keys so that it retains the client activities of
     /// trying to retrieve an item.
     ///
     /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
     /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
-    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    /// [max-pending]: ./struct.CacheBuilder.html#method.max_pending_invalidation_closures
+    /// [too-many-pending-error]: ../enum.PredicateError.html#variant.TooManyPending
+    /// [invalidation-handle]: ./struct.InvalidationHandle.html
+    pub fn invalidate_entries_if<F>(
+        &self,
+        predicate: F,
+    ) -> Result<InvalidationHandle, PredicateError>
     where
         F: Fn(&K, &V) -> bool + Send + Sync + 'static,
     {
-        self.base.invalidate_entries_if(Arc::new(predicate))
+        self.invalidate_entries_with_meta_if(move |k, v, _meta| predicate(k, v))
+    }
+
+    /// Discards cached values that satisfy a predicate on the entry metadata as
+    /// well as the key and value.
+    ///
+    /// This works like `invalidate_entries_if`, but the closure also receives an
+    /// [`EntryMeta`][entry-meta] telling the age, idle time and weight of the entry.
+    /// For example, `|_k, _v, meta| meta.age() >= Duration::from_secs(3600)`
+    /// discards the values inserted more than an hour before this method was
+    /// called, without keeping a timestamp in the values.
+    ///
+    /// [entry-meta]: ../policy/struct.EntryMeta.html
+    pub fn invalidate_entries_with_meta_if<F>(
+        &self,
+        predicate: F,
+    ) -> Result<InvalidationHandle, PredicateError>
+    where
+        F: Fn(&K, &V, EntryMeta) -> bool + Send + Sync + 'static,
+    {
+        let id = self.base.invalidate_entries_if(Arc::new(predicate))?;
+        let completion = self.base.invalidation_completion(&id);
+        Ok(InvalidationHandle::new(id, completion))
+    }
+
+    /// Returns the number of the entries that have been removed by the background
+    /// invalidation task, because they matched the predicate of the given
+    /// `PredicateId` (returned by the `predicate_id` method of the
+    /// `InvalidationHandle`).
+    ///
+    /// An entry matched by more than one predicate is counted for each of them.
+    /// The entries that matched the predicate but were removed by other means
+    /// (e.g. `invalidate` or expiration) before the task visited them are not
+    /// counted.
+    ///
+    /// The count is available while the predicate is registered. Returns `None`
+    /// once the predicate has been applied to all the entries and removed, or if
+    /// the `PredicateId` is unknown to this cache.
+    pub fn predicate_removed_count(&self, id: &PredicateId) -> Option<u64> {
+        self.base.predicate_removed_count(id)
+    }
+
+    /// Cancels the invalidation by the predicate of the given `PredicateId` (returned by
+    /// the `predicate_id` method of the `InvalidationHandle`),
+    /// so that the predicate will not be applied to the entries anymore. Returns
+    /// `true` if the predicate was still registered, or `false` if it has already
+    /// been applied to all the entries, has been cancelled, or is unknown to this
+    /// cache.
+    ///
+    /// The entries that have already been invalidated by the predicate remain
+    /// invalidated. If the background invalidation task is running, this method
+    /// waits for it to finish the current batch of the entries.
+    ///
+    /// The `InvalidationHandle` of the cancelled invalidation completes.
+    pub fn cancel_invalidation(&self, id: PredicateId) -> bool {
+        self.base.cancel_invalidation(&id)
+    }
+
+    /// Returns the approximate number of entries in this cache.
+    ///
+    /// The value is approximate because `insert`, `invalidate` and eviction are
+    /// applied to the internal hash table asynchronously via the write operation
+    /// channel. Entries that have been expired or scheduled for eviction may still
+    /// be counted until the housekeeper processes them.
+    ///
+    /// If the cache is built with
+    /// [`CacheBuilder::exact_entry_count`][exact-entry-count], this returns the
+    /// exact number of the entries admitted by the housekeeper instead.
+    ///
+    /// [exact-entry-count]: ./struct.CacheBuilder.html#method.exact_entry_count
+    pub fn entry_count(&self) -> usize {
+        self.base.entry_count()
+    }
+
+    /// Returns the approximate total weight of the entries in this cache.
+    ///
+    /// The weight of an entry is given by the weigher set by
+    /// `CacheBuilder::weigher`. Without a weigher, every entry weighs one, so this
+    /// returns the number of entries.
+    ///
+    /// Only the entries that have been applied to the cache policy are counted.
+    /// Call `run_pending_tasks` beforehand to get the up-to-date value.
+    pub fn weighted_size(&self) -> u64 {
+        self.base.weighted_size()
+    }
+
+    /// Performs the pending maintenance tasks of the cache.
+    ///
+    /// This applies the buffered reads and writes to the cache policy, and runs
+    /// the eviction of the expired and invalidated entries, and of the entries
+    /// exceeding the `max_capacity`. After the returned future completes,
+    /// `entry_count`, `weighted_size` and `stats` reflect all the writes made
+    /// before the call.
+    ///
+    /// Normally a background housekeeper runs these tasks periodically, or when
+    /// enough reads and writes have been buffered, so you do not need to call this
+    /// method. Call it when you need an up-to-date view of the cache, e.g. in tests.
+    ///
+    /// This method is cooperative: it applies the buffered reads and writes in
+    /// batches, and yields to the executor between the batches, so that it does
+    /// not starve the other tasks on the same thread while applying many writes
+    /// (which may evict many entries). It returns when all the reads and writes
+    /// buffered at the time of the call have been applied.
+    pub async fn run_pending_tasks(&self) {
+        let inner = &self.base.inner;
+        let (mut reads, mut writes) = inner.pending_op_counts();
+        while reads > 0 || writes > 0 {
+            let (r, w) = (
+                reads.min(RUN_PENDING_TASKS_BATCH_SIZE),
+                writes.min(RUN_PENDING_TASKS_BATCH_SIZE),
+            );
+            inner.apply_pending_ops(r, w);
+            reads -= r;
+            writes -= w;
+            yield_now().await;
+        }
+        // Run the other maintenance tasks, e.g. the evictions.
+        inner.sync(MAX_SYNC_REPEATS);
+        inner.notify_pending_async().await;
+    }
+
+    /// Returns a snapshot of the statistics of this cache.
+    ///
+    /// The statistics are recorded only when the cache is built with
+    /// `CacheBuilder::record_stats`; otherwise all of the counters are zero.
+    pub fn stats(&self) -> CacheStats {
+        self.base.stats()
     }
 
     /// Returns the `max_capacity` of this cache.
@@ -572,6 +1649,17 @@ where
         self.base.max_capacity()
     }
 
+    /// Changes the `max_capacity` of this cache, e.g. in response to memory
+    /// pressure.
+    ///
+    /// The change is applied asynchronously. When shrinking, the entries exceeding
+    /// the new capacity are evicted by the next housekeeping pass (or by
+    /// `run_pending_tasks`), so the cache may stay above the new capacity for a
+    /// while. When growing, the cache simply accepts more entries.
+    pub fn set_max_capacity(&self, max_capacity: usize) {
+        self.base.set_max_capacity(max_capacity)
+    }
+
     /// Returns the `time_to_live` of this cache.
     pub fn time_to_live(&self) -> Option<Duration> {
         self.base.time_to_live()
@@ -582,6 +1670,22 @@ where
         self.base.time_to_idle()
     }
 
+    /// Returns the `initial_capacity` of this cache, or `None` if it was not set.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        self.base.initial_capacity()
+    }
+
+    /// Returns the eviction policy of this cache.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.base.eviction_policy()
+    }
+
+    /// Returns `true` if this cache supports the invalidation closures, which is
+    /// enabled by the `support_invalidation_closures` method of the builder.
+    pub fn invalidation_closures_enabled(&self) -> bool {
+        self.base.invalidation_closures_enabled()
+    }
+
     /// Returns the number of internal segments of this cache.
     ///
     /// `Cache` always returns `1`.
@@ -593,7 +1697,7 @@ where
 impl<K, V, S> ConcurrentCacheExt<K, V> for Cache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     fn sync(&self) {
@@ -608,14 +1712,33 @@ where
     V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
-    async fn get_or_insert_with_hash_and_fun(
+    pub(crate) fn get_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.get_with_hash(key, hash)
+    }
+
+    pub(crate) async fn get_or_insert_with_hash_and_fun(
         &self,
         key: Arc<K>,
         hash: u64,
         init: impl Future<Output = V>,
     ) -> V {
+        self.get_or_insert_with_status_hash_and_fun(key, hash, init)
+            .await
+            .0
+    }
+
+    async fn get_or_insert_with_status_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl Future<Output = V>,
+    ) -> (V, EntryStatus) {
         if let Some(v) = self.base.get_with_hash(&key, hash) {
-            return v;
+            return (v, EntryStatus::Existed);
         }
 
         match self
@@ -624,18 +1747,52 @@ where
             .await
         {
             InitResult::Initialized(v) => {
+                self.base.record_load_success();
                 self.insert_with_hash(Arc::clone(&key), hash, v.clone())
                     .await;
                 self.value_initializer
                     .remove_waiter(&key, TypeId::of::<()>());
-                v
+                (v, EntryStatus::Created)
             }
-            InitResult::ReadExisting(v) => v,
-            InitResult::InitErr(_) => unreachable!(),
+            InitResult::ReadExisting(v) => (v, EntryStatus::Existed),
+            InitResult::InitErr(e) => match *e {},
+            // The waiters of `init_or_read` never have `NoValue`.
+            InitResult::InitNone => unreachable!(),
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
+        }
+    }
+
+    async fn get_or_optionally_insert_with_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl Future<Output = Option<V>>,
+    ) -> Option<V> {
+        if let Some(v) = self.base.get_with_hash(&key, hash) {
+            return Some(v);
+        }
+
+        match self
+            .value_initializer
+            .optionally_init_or_read(Arc::clone(&key), init)
+            .await
+        {
+            InitResult::Initialized(v) => {
+                self.base.record_load_success();
+                self.insert_with_hash(Arc::clone(&key), hash, v.clone())
+                    .await;
+                self.value_initializer
+                    .remove_waiter(&key, TypeId::of::<OptionallyInit>());
+                Some(v)
+            }
+            InitResult::ReadExisting(v) => Some(v),
+            InitResult::InitNone => None,
+            InitResult::InitErr(e) => match *e {},
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
         }
     }
 
-    async fn get_or_try_insert_with_hash_and_fun<F, E>(
+    pub(crate) async fn get_or_try_insert_with_hash_and_fun<F, E>(
         &self,
         key: Arc<K>,
         hash: u64,
@@ -649,12 +1806,24 @@ where
             return Ok(v);
         }
 
+        // Record the failure here rather than on `InitErr`, which is also returned
+        // to the other callers waiting for the same init future.
+        let base = &self.base;
+        let init = async move {
+            let result = init.await;
+            if result.is_err() {
+                base.record_load_failure();
+            }
+            result
+        };
+
         match self
             .value_initializer
-            .try_init_or_read(Arc::clone(&key), init)
+            .try_init_or_read(Arc::clone(&key), self.base.current_time(), init)
             .await
         {
             InitResult::Initialized(v) => {
+                self.base.record_load_success();
                 let hash = self.base.hash(&key);
                 self.insert_with_hash(Arc::clone(&key), hash, v.clone())
                     .await;
@@ -664,17 +1833,152 @@ where
             }
             InitResult::ReadExisting(v) => Ok(v),
             InitResult::InitErr(e) => Err(e),
+            // The waiters of `try_init_or_read` never have `NoValue`.
+            InitResult::InitNone => unreachable!(),
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
         }
     }
 
-    async fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
-        let op = self.base.do_insert_with_hash(key, hash, value);
-        let hk = self.base.housekeeper.as_ref();
-        Self::schedule_write_op(&self.base.write_op_ch, op, hk)
-            .await
+    /// Races the future against the `load_timeout` (if any). Returns `None` if the
+    /// timeout elapsed first, in which case the future is dropped (cancelled).
+    ///
+    /// The timeout is measured by the `sleep` of the runtime if it is set.
+    async fn with_load_timeout<T>(&self, fut: impl Future<Output = T>) -> Option<T> {
+        use futures_util::future::{self, Either, FutureExt};
+
+        let timeout = match self.load_timeout {
+            Some(timeout) => timeout,
+            None => return Some(fut.await),
+        };
+        let timer = match &self.runtime {
+            Some(runtime) => runtime.sleep(timeout),
+            None => async_io::Timer::after(timeout).map(|_| ()).boxed(),
+        };
+        futures_util::pin_mut!(fut);
+        match future::select(fut, timer).await {
+            Either::Left((v, _timer)) => Some(v),
+            Either::Right(_) => None,
+        }
+    }
+
+    fn spawn_refresh<F, Fut, E>(&self, (key, entry): RefreshKey<K, V>, hash: u64, reload: F)
+    where
+        F: FnOnce(&K) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>> + 'static,
+    {
+        use futures_util::FutureExt;
+        use std::panic::AssertUnwindSafe;
+
+        let refresher = match &self.refresher {
+            Some(refresher) => refresher,
+            None => return,
+        };
+        if !self.value_initializer.try_start_refresh(&key) {
+            // Another refresh of the key is in flight.
+            return;
+        }
+
+        let cache = Self {
+            base: self.base.clone(),
+            value_initializer: Arc::clone(&self.value_initializer),
+            load_timeout: self.load_timeout,
+            // Do not let the queued refresh own the refresher (and its queue).
+            refresher: None,
+            refresh_error_listener: self.refresh_error_listener.clone(),
+            runtime: self.runtime.clone(),
+        };
+        refresher.schedule(async move {
+            // Retain the current value if the reload fails or panics.
+            let reloaded = match AssertUnwindSafe(async { reload(&key).await })
+                .catch_unwind()
+                .await
+            {
+                Ok(Ok(v)) => Ok(v),
+                Ok(Err(e)) => Err(RefreshError::Failed(e.into())),
+                Err(_) => Err(RefreshError::Panicked),
+            };
+            let error = match reloaded {
+                Ok(v) => {
+                    cache
+                        .replace_entry_with_hash(Arc::clone(&key), hash, v, &entry)
+                        .await;
+                    None
+                }
+                Err(e) => Some(e),
+            };
+            cache.value_initializer.finish_refresh(&key);
+            if let (Some(error), Some(listener)) = (error, &cache.refresh_error_listener) {
+                listener(Arc::clone(&key), error);
+            }
+        });
+    }
+
+    /// Replaces the value of the entry due for refresh, only when the key still
+    /// has the entry.
+    async fn replace_entry_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        entry: &Arc<ValueEntry<K, V>>,
+    ) {
+        if let Some(op) = self
+            .base
+            .do_replace_entry_with_hash(key, hash, value, entry)
+        {
+            let hk = self.base.housekeeper.as_ref();
+            Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+                .await
+                .expect("Failed to replace a refreshed value");
+            self.base.inner.notify_pending_async().await;
+        }
+    }
+
+    pub(crate) async fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        self.insert_with_hash_and_ttl(key, hash, value, None).await
+    }
+
+    async fn insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_live: Option<Duration>,
+    ) {
+        self.try_insert_with_hash_and_ttl(key, hash, value, time_to_live)
+            .await
             .expect("Failed to insert");
     }
 
+    pub(crate) async fn try_insert_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<(), CacheError> {
+        self.try_insert_with_hash_and_ttl(key, hash, value, None)
+            .await
+    }
+
+    async fn try_insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_live: Option<Duration>,
+    ) -> Result<(), CacheError> {
+        let op = self
+            .base
+            .do_insert_with_hash(key, hash, value, time_to_live);
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+            .await
+            .map_err(|_| CacheError::WriteChannelDisconnected)?;
+        self.base.inner.notify_pending_async().await;
+        Ok(())
+    }
+
     #[inline]
     async fn schedule_write_op(
         ch: &Sender<WriteOp<K, V>>,
@@ -682,6 +1986,7 @@ where
         housekeeper: Option<&HouseKeeperArc<K, V, S>>,
     ) -> Result<(), TrySendError<WriteOp<K, V>>> {
         let mut op = op;
+        let mut backoff = Backoff::new(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
 
         // TODO: Try to replace the timer with an async event listener to see if it
         // can provide better performance.
@@ -691,8 +1996,13 @@ where
                 Ok(()) => break,
                 Err(TrySendError::Full(op1)) => {
                     op = op1;
-                    async_io::Timer::after(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS))
-                        .await;
+                    match backoff.next_step() {
+                        // Spinning would block the other tasks on this thread.
+                        BackoffStep::Spin(_) | BackoffStep::Yield => yield_now().await,
+                        BackoffStep::Sleep(duration) => {
+                            async_io::Timer::after(duration).await;
+                        }
+                    }
                 }
                 Err(e @ TrySendError::Disconnected(_)) => return Err(e),
             }
@@ -707,6 +2017,7 @@ where
         housekeeper: Option<&HouseKeeperArc<K, V, S>>,
     ) -> Result<(), TrySendError<WriteOp<K, V>>> {
         let mut op = op;
+        let mut backoff = Backoff::new(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
 
         loop {
             BaseCache::apply_reads_writes_if_needed(ch, housekeeper);
@@ -714,7 +2025,7 @@ where
                 Ok(()) => break,
                 Err(TrySendError::Full(op1)) => {
                     op = op1;
-                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
+                    backoff.snooze();
                 }
                 Err(e @ TrySendError::Disconnected(_)) => return Err(e),
             }
@@ -728,7 +2039,7 @@ where
 impl<K, V, S> Cache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     fn is_table_empty(&self) -> bool {
@@ -743,6 +2054,10 @@ where
         self.base.invalidation_predicate_count()
     }
 
+    pub(crate) fn write_op_ch_len(&self) -> usize {
+        self.base.write_op_ch_len()
+    }
+
     fn reconfigure_for_testing(&mut self) {
         self.base.reconfigure_for_testing();
     }
@@ -752,14 +2067,68 @@ where
     }
 }
 
+/// Yields to the executor once. Unlike `tokio::task::yield_now`, this does not
+/// depend on a specific async runtime.
+async fn yield_now() {
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    YieldNow(false).await
+}
+
 // To see the debug prints, run test as `cargo test -- --nocapture`
 #[cfg(test)]
 mod tests {
-    use super::{Cache, ConcurrentCacheExt};
-    use crate::{common::time::Clock, future::CacheBuilder};
+    use super::Cache;
+    use crate::{
+        common::time::Clock,
+        future::{CacheBuilder, Runtime},
+    };
+
+    use async_io::{block_on, Timer};
+    use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc, time::Duration};
 
-    use async_io::Timer;
-    use std::{convert::Infallible, sync::Arc, time::Duration};
+    // Spawns the background tasks of the caches on the Tokio runtime of a test.
+    struct TokioSpawner(tokio::runtime::Handle);
+
+    impl TokioSpawner {
+        fn current() -> Self {
+            Self(tokio::runtime::Handle::current())
+        }
+    }
+
+    impl Runtime for TokioSpawner {
+        fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) {
+            self.0.spawn(task);
+        }
+    }
+
+    #[tokio::test]
+    async fn debug_format() {
+        let cache = Cache::new(100);
+        cache.insert("a", "alice").await;
+        cache.run_pending_tasks().await;
+
+        let s = format!("{:?}", cache);
+        assert!(s.starts_with("Cache {"));
+        assert!(s.contains("max_capacity: 100"));
+        assert!(s.contains("num_segments: 1"));
+        assert!(s.contains("entry_count: 1"));
+        assert!(!s.contains("alice"));
+    }
 
     #[tokio::test]
     async fn basic_single_async_task() {
@@ -773,32 +2142,32 @@ mod tests {
         cache.insert("b", "bob").await;
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
+        cache.run_pending_tasks().await;
         // counts: a -> 1, b -> 1
 
         cache.insert("c", "cindy").await;
         assert_eq!(cache.get(&"c"), Some("cindy"));
         // counts: a -> 1, b -> 1, c -> 1
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
+        cache.run_pending_tasks().await;
         // counts: a -> 2, b -> 2, c -> 1
 
         // "d" should not be admitted because its frequency is too low.
         cache.insert("d", "david").await; //   count: d -> 0
-        cache.sync();
+        cache.run_pending_tasks().await;
         assert_eq!(cache.get(&"d"), None); //   d -> 1
 
         cache.insert("d", "david").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
         assert_eq!(cache.get(&"d"), None); //   d -> 2
 
         // "d" should be admitted and "c" should be evicted
         // because d's frequency is higher then c's.
         cache.insert("d", "dennis").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.get(&"c"), None);
@@ -820,32 +2189,32 @@ mod tests {
         cache.blocking_insert("b", "bob");
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
+        block_on(cache.run_pending_tasks());
         // counts: a -> 1, b -> 1
 
         cache.blocking_insert("c", "cindy");
         assert_eq!(cache.get(&"c"), Some("cindy"));
         // counts: a -> 1, b -> 1, c -> 1
-        cache.sync();
+        block_on(cache.run_pending_tasks());
 
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
+        block_on(cache.run_pending_tasks());
         // counts: a -> 2, b -> 2, c -> 1
 
         // "d" should not be admitted because its frequency is too low.
         cache.blocking_insert("d", "david"); //   count: d -> 0
-        cache.sync();
+        block_on(cache.run_pending_tasks());
         assert_eq!(cache.get(&"d"), None); //   d -> 1
 
         cache.blocking_insert("d", "david");
-        cache.sync();
+        block_on(cache.run_pending_tasks());
         assert_eq!(cache.get(&"d"), None); //   d -> 2
 
         // "d" should be admitted and "c" should be evicted
         // because d's frequency is higher then c's.
         cache.blocking_insert("d", "dennis");
-        cache.sync();
+        block_on(cache.run_pending_tasks());
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.get(&"c"), None);
@@ -887,6 +2256,223 @@ mod tests {
         assert!(cache.get(&20).is_some());
     }
 
+    #[tokio::test]
+    async fn try_insert_on_disconnected_channel() {
+        use crate::CacheError;
+
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+        assert_eq!(cache.try_insert("a", "alice").await, Ok(()));
+
+        // Simulate a dead cache by replacing the write op channel with the one
+        // whose receiver has been dropped.
+        let (snd, _) = crossbeam_channel::bounded(1);
+        cache.base.write_op_ch = snd;
+
+        assert_eq!(
+            cache.try_insert("b", "bob").await,
+            Err(CacheError::WriteChannelDisconnected)
+        );
+        assert_eq!(cache.get(&"a"), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn contains_key() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.run_pending_tasks().await;
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert!(cache.contains_key(&"a"));
+        assert!(!cache.contains_key(&"b"));
+        cache.run_pending_tasks().await;
+
+        // contains_key should not reset the idle timer.
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        assert!(!cache.contains_key(&"a"));
+    }
+
+    #[tokio::test]
+    async fn remove() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.run_pending_tasks().await;
+
+        assert_eq!(cache.remove(&"a").await, Some("alice"));
+        assert_eq!(cache.remove(&"a").await, None);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn insert_with_ttl() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache
+            .insert_with_ttl("a", "alice", Duration::from_secs(10))
+            .await;
+        cache
+            .insert_with_ttl("b", "bob", Duration::from_secs(20))
+            .await;
+        cache.insert("c", "cindy").await;
+        cache.run_pending_tasks().await;
+
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+
+        mock.increment(Duration::from_secs(10)); // 20 secs.
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.table_size(), 1);
+    }
+
+    #[tokio::test]
+    async fn entry_count() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.entry_count(), 0);
+
+        for i in 0..10 {
+            cache.insert(i, format!("value-{}", i)).await;
+        }
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 10);
+
+        cache.invalidate(&0).await;
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 9);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn run_pending_tasks_yields() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const NUM_KEYS: u32 = 5_000;
+
+        let mut cache = CacheBuilder::new(100)
+            .write_channel_capacity(NUM_KEYS as usize)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Buffer many writes, most of which will be rejected or evicted.
+        for key in 0..NUM_KEYS {
+            cache.insert(key, key).await;
+        }
+
+        // On the single-threaded runtime, this task runs only when the current task
+        // yields.
+        let progress = Arc::new(AtomicUsize::new(0));
+        let other = {
+            let progress = Arc::clone(&progress);
+            tokio::spawn(async move {
+                loop {
+                    progress.fetch_add(1, Ordering::Relaxed);
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        cache.run_pending_tasks().await;
+        assert!(progress.load(Ordering::Relaxed) > 0);
+        other.abort();
+
+        // All the writes buffered before the call have been applied.
+        assert!(cache.entry_count() <= 100);
+        assert_eq!(cache.base.inner.pending_op_counts().1, 0);
+    }
+
+    #[tokio::test]
+    async fn clear() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.run_pending_tasks().await;
+        // Not applied yet.
+        cache.insert("c", "cindy").await;
+
+        cache.clear().await;
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.weighted_size(), 0);
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+    }
+
+    // `#[tokio::test]` runs the tasks on a single thread, so the other task can run
+    // only when `clear` yields.
+    #[tokio::test]
+    async fn clear_yields_between_batches() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut cache = Cache::new(10_000);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..2_000 {
+            cache.insert(i, i).await;
+        }
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 2_000);
+
+        let ticks = Arc::new(AtomicUsize::default());
+        let ticks1 = Arc::clone(&ticks);
+        let ticker = tokio::spawn(async move {
+            loop {
+                ticks1.fetch_add(1, Ordering::Relaxed);
+                tokio::task::yield_now().await;
+            }
+        });
+
+        cache.clear().await;
+        let ticks_during_clear = ticks.load(Ordering::Relaxed);
+        ticker.abort();
+
+        assert!(ticks_during_clear > 1);
+        assert_eq!(cache.entry_count(), 0);
+    }
+
     #[tokio::test]
     async fn invalidate_all() {
         let mut cache = Cache::new(100);
@@ -901,13 +2487,13 @@ mod tests {
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.get(&"c"), Some("cindy"));
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         cache.invalidate_all();
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         cache.insert("d", "david").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert!(cache.get(&"a").is_none());
         assert!(cache.get(&"b").is_none());
@@ -933,10 +2519,10 @@ mod tests {
         cache.insert(0, "alice").await;
         cache.insert(1, "bob").await;
         cache.insert(2, "alex").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&0), Some("alice"));
         assert_eq!(cache.get(&1), Some("bob"));
@@ -951,9 +2537,9 @@ mod tests {
         cache.insert(3, "alice").await;
 
         // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
+        cache.run_pending_tasks().await; // To submit the invalidation task.
         std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
+        cache.run_pending_tasks().await; // To process the task result.
         std::thread::sleep(Duration::from_millis(200));
 
         assert!(cache.get(&0).is_none());
@@ -971,9 +2557,9 @@ mod tests {
         assert_eq!(cache.invalidation_predicate_count(), 2);
 
         // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
+        cache.run_pending_tasks().await; // To submit the invalidation task.
         std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
+        cache.run_pending_tasks().await; // To process the task result.
         std::thread::sleep(Duration::from_millis(200));
 
         assert!(cache.get(&1).is_none());
@@ -984,6 +2570,34 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn await_invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
+        // Do not call reconfigure_for_testing() so that the housekeeper will run
+        // the invalidation in background.
+        let cache = CacheBuilder::new(100)
+            .support_invalidation_closures()
+            .build();
+
+        cache.insert(0, "alice").await;
+        cache.insert(1, "bob").await;
+        cache.insert(2, "alex").await;
+        cache.run_pending_tasks().await;
+
+        let handle = cache.invalidate_entries_if(|_k, &v| v.starts_with('a'))?;
+        assert!(!handle.is_complete());
+
+        tokio::time::timeout(Duration::from_secs(10), handle).await?;
+
+        // The handle has completed, so the entries should be gone now.
+        assert_eq!(cache.table_size(), 1);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        assert!(cache.get(&0).is_none());
+        assert!(cache.get(&2).is_none());
+        assert_eq!(cache.get(&1), Some("bob"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn time_to_live() {
         let mut cache = CacheBuilder::new(100)
@@ -999,41 +2613,41 @@ mod tests {
         let cache = cache;
 
         cache.insert("a", "alice").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         cache.get(&"a");
 
         mock.increment(Duration::from_secs(5)); // 10 secs.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"a"), None);
         assert!(cache.is_table_empty());
 
         cache.insert("b", "bob").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.table_size(), 1);
 
         mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.table_size(), 1);
 
         cache.insert("b", "bill").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         mock.increment(Duration::from_secs(5)); // 20 secs
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"b"), Some("bill"));
         assert_eq!(cache.table_size(), 1);
 
         mock.increment(Duration::from_secs(5)); // 25 secs
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"a"), None);
         assert_eq!(cache.get(&"b"), None);
@@ -1055,30 +2669,30 @@ mod tests {
         let cache = cache;
 
         cache.insert("a", "alice").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"a"), Some("alice"));
 
         mock.increment(Duration::from_secs(5)); // 10 secs.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         cache.insert("b", "bob").await;
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.table_size(), 2);
 
         mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"a"), None);
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.table_size(), 1);
 
         mock.increment(Duration::from_secs(10)); // 25 secs
-        cache.sync();
+        cache.run_pending_tasks().await;
 
         assert_eq!(cache.get(&"a"), None);
         assert_eq!(cache.get(&"b"), None);
@@ -1169,6 +2783,100 @@ mod tests {
         futures_util::join!(task1, task2, task3, task4, task5);
     }
 
+    // Ensures `get_or_insert_with` does not need a Tokio runtime; the futures are
+    // driven by `async_io::block_on` only.
+    #[test]
+    fn get_or_insert_with_without_tokio() {
+        let cache = Cache::new(100);
+        let init_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let tasks = (0..8).map(|_| {
+            let cache = cache.clone();
+            let init_count = Arc::clone(&init_count);
+            async move {
+                cache
+                    .get_or_insert_with(0, async move {
+                        init_count.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+                        Timer::after(Duration::from_millis(100)).await;
+                        "value0"
+                    })
+                    .await
+            }
+        });
+
+        let values = block_on(futures_util::future::join_all(tasks));
+        assert!(values.iter().all(|v| *v == "value0"));
+        assert_eq!(init_count.load(std::sync::atomic::Ordering::Acquire), 1);
+        assert_eq!(cache.get(&0), Some("value0"));
+    }
+
+    #[tokio::test]
+    async fn get_or_insert_with_key() {
+        let cache = Cache::new(100);
+
+        let v = cache
+            .get_or_insert_with_key(1, |key| {
+                let key = Arc::clone(key);
+                async move { format!("value{}", key) }
+            })
+            .await;
+        assert_eq!(v, "value1");
+
+        // The init future is not resolved when the value exists.
+        let v = cache
+            .get_or_insert_with_key(1, |_key| async { unreachable!() })
+            .await;
+        assert_eq!(v, "value1");
+        assert_eq!(cache.get(&1), Some("value1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn get_or_insert_with_status() {
+        use crate::policy::EntryStatus;
+
+        const NUM_TASKS: usize = 8;
+
+        let cache = Cache::new(100);
+        let barrier = Arc::new(tokio::sync::Barrier::new(NUM_TASKS));
+
+        let handles: Vec<_> = (0..NUM_TASKS)
+            .map(|i| {
+                let (cache, barrier) = (cache.clone(), Arc::clone(&barrier));
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    cache
+                        .get_or_insert_with_status(1, async move {
+                            // Give the other tasks time to wait for this future.
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            i
+                        })
+                        .await
+                })
+            })
+            .collect();
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+
+        let created: Vec<_> = results
+            .iter()
+            .filter(|(_, status)| *status == EntryStatus::Created)
+            .collect();
+        assert_eq!(created.len(), 1);
+        // All tasks got the value of the task that created it.
+        let v = created[0].0;
+        assert!(results.iter().all(|(value, _)| *value == v));
+        assert_eq!(cache.get(&1), Some(v));
+
+        assert_eq!(
+            cache
+                .get_or_insert_with_status(1, async { unreachable!() })
+                .await,
+            (v, EntryStatus::Existed)
+        );
+    }
+
     #[tokio::test]
     async fn get_or_try_insert_with() {
         use std::sync::Arc;
@@ -1311,6 +3019,404 @@ mod tests {
         futures_util::join!(task1, task2, task3, task4, task5, task6, task7, task8);
     }
 
+    #[tokio::test]
+    async fn compute() {
+        let cache: Cache<&str, Vec<u8>> = Cache::new(100);
+        const NUM_TASKS: u8 = 16;
+
+        // Each task appends its ID to the vector. The futures yield while the key
+        // is being computed, so the tasks will contend for the key.
+        let tasks = (0..NUM_TASKS)
+            .map(|id| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    cache
+                        .compute("key", |v| async move {
+                            Timer::after(Duration::from_millis(1)).await;
+                            let mut v = v.unwrap_or_default();
+                            v.push(id);
+                            Some(v)
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut v = cache.get(&"key").unwrap();
+        v.sort_unstable();
+        assert_eq!(v, (0..NUM_TASKS).collect::<Vec<_>>());
+
+        // Remove the value.
+        assert_eq!(cache.compute("key", |_v| async { None }).await, None);
+        assert_eq!(cache.get(&"key"), None);
+    }
+
+    #[test]
+    fn blocking_wrapper() {
+        let cache = Cache::new(100);
+        const NUM_THREADS: u32 = 4;
+
+        let handles = (0..NUM_THREADS)
+            .map(|id| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    let blocking = cache.blocking();
+                    blocking.insert(id, id * 10);
+                    assert_eq!(blocking.get(&id), Some(id * 10));
+                    // Only one of the threads will evaluate the init closure.
+                    blocking.get_with(100, || {
+                        std::thread::sleep(Duration::from_millis(100));
+                        id
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let values = handles
+            .into_iter()
+            .map(|h| h.join().expect("Failed"))
+            .collect::<Vec<_>>();
+        assert!(values.iter().all(|v| *v == values[0]));
+
+        let blocking = cache.blocking();
+        for id in 0..NUM_THREADS {
+            assert_eq!(blocking.get(&id), Some(id * 10));
+        }
+        blocking.invalidate(&0);
+        assert_eq!(blocking.get(&0), None);
+    }
+
+    #[tokio::test]
+    async fn try_get_with_shares_error() {
+        #[derive(Debug, PartialEq)]
+        struct LoadError(u32);
+
+        let cache: Cache<u32, &str> = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // Task1 loads the value and fails.
+        let task1 = {
+            let cache1 = cache.clone();
+            tokio::spawn(async move {
+                cache1
+                    .try_get_with(KEY, async {
+                        Timer::after(Duration::from_millis(300)).await;
+                        Err(LoadError(42))
+                    })
+                    .await
+            })
+        };
+
+        // Task2 waits for task1's load, and gets the same error.
+        let task2 = {
+            let cache2 = cache.clone();
+            tokio::spawn(async move {
+                Timer::after(Duration::from_millis(100)).await;
+                cache2
+                    .try_get_with(KEY, async { unreachable!() as Result<_, LoadError> })
+                    .await
+            })
+        };
+
+        let e1 = task1.await.unwrap().unwrap_err();
+        let e2 = task2.await.unwrap().unwrap_err();
+        assert_eq!(*e1, LoadError(42));
+        assert!(Arc::ptr_eq(&e1, &e2));
+        assert!(!cache.contains_key(&KEY));
+    }
+
+    #[tokio::test]
+    async fn cache_errors_for_removes_expired_errors() {
+        #[derive(Debug, PartialEq)]
+        struct LoadError;
+
+        let mut cache = CacheBuilder::new(100)
+            .cache_errors_for(Duration::from_secs(5))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in &["a", "b"] {
+            let v = cache
+                .get_or_try_insert_with(*key, async { Err::<&str, _>(LoadError) })
+                .await;
+            assert_eq!(v, Err(Arc::new(LoadError)));
+        }
+        // The calls within the window get the remembered error.
+        let v = cache
+            .get_or_try_insert_with("a", async { Ok("alice") })
+            .await;
+        assert_eq!(v, Err(Arc::new(LoadError)));
+        assert_eq!(cache.value_initializer.waiter_count(), 2);
+
+        // The housekeeper removes the expired errors even though the keys are not
+        // requested again.
+        mock.increment(Duration::from_secs(5)); // 5 secs.
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.value_initializer.waiter_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn get_or_optionally_insert_with() {
+        let cache: Cache<u32, &str> = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // Task1 loads the value, but the loader declines to produce one.
+        let task1 = {
+            let cache1 = cache.clone();
+            tokio::spawn(async move {
+                cache1
+                    .get_or_optionally_insert_with(KEY, async {
+                        Timer::after(Duration::from_millis(300)).await;
+                        None
+                    })
+                    .await
+            })
+        };
+
+        // Task2 waits for task1's load, and gets `None` without resolving its own
+        // future.
+        let task2 = {
+            let cache2 = cache.clone();
+            tokio::spawn(async move {
+                Timer::after(Duration::from_millis(100)).await;
+                cache2
+                    .get_or_optionally_insert_with(KEY, async { unreachable!() })
+                    .await
+            })
+        };
+
+        assert_eq!(task1.await.unwrap(), None);
+        assert_eq!(task2.await.unwrap(), None);
+        assert!(!cache.contains_key(&KEY));
+
+        // The next call resolves its future again.
+        let v = cache
+            .get_or_optionally_insert_with(KEY, async { Some("task3") })
+            .await;
+        assert_eq!(v, Some("task3"));
+        assert_eq!(cache.get(&KEY), Some("task3"));
+    }
+
+    #[tokio::test]
+    async fn get_with_if() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // The value is (version, data).
+        let cache: Cache<u32, (u32, &str)> = Cache::new(100);
+        let is_stale = |v: &(u32, &str)| v.0 < 2;
+
+        // Absent: the init future is resolved.
+        let v = cache.get_with_if(0, async { (1, "v1") }, is_stale).await;
+        assert_eq!(v, (1, "v1"));
+        assert_eq!(cache.get(&0), Some((1, "v1")));
+
+        // Present and fresh: the init future is not resolved.
+        cache.insert(1, (2, "v2")).await;
+        let v = cache
+            .get_with_if(1, async { unreachable!() }, is_stale)
+            .await;
+        assert_eq!(v, (2, "v2"));
+
+        // Present and stale: the init future is resolved only once even if many
+        // tasks find the stale value at the same time.
+        let init_count = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                let init_count = Arc::clone(&init_count);
+                tokio::spawn(async move {
+                    Timer::after(Duration::from_millis(i * 50)).await;
+                    let init = async move {
+                        init_count.fetch_add(1, Ordering::AcqRel);
+                        Timer::after(Duration::from_millis(300)).await;
+                        (2, "v2")
+                    };
+                    cache.get_with_if(0, init, is_stale).await
+                })
+            })
+            .collect();
+        for t in tasks {
+            assert_eq!(t.await.expect("Task failed"), (2, "v2"));
+        }
+        assert_eq!(init_count.load(Ordering::Acquire), 1);
+        assert_eq!(cache.get(&0), Some((2, "v2")));
+    }
+
+    #[tokio::test]
+    async fn get_or_insert_all_with() {
+        use futures_util::future::Ready;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const NUM_KEYS: u32 = 100;
+        const MAX_CONCURRENCY: usize = 8;
+
+        let cache = Cache::new(1_000);
+        let calls = Arc::new(
+            (0..NUM_KEYS)
+                .map(|_| AtomicUsize::new(0))
+                .collect::<Vec<_>>(),
+        );
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        // Request every key twice.
+        let keys = (0..NUM_KEYS).chain(0..NUM_KEYS);
+        let map = cache
+            .get_or_insert_all_with(keys, MAX_CONCURRENCY, |&key| {
+                let calls = Arc::clone(&calls);
+                let in_flight = Arc::clone(&in_flight);
+                let max_in_flight = Arc::clone(&max_in_flight);
+                async move {
+                    calls[key as usize].fetch_add(1, Ordering::SeqCst);
+                    let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(n, Ordering::SeqCst);
+                    Timer::after(Duration::from_millis(5)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    key * 10
+                }
+            })
+            .await;
+
+        assert_eq!(map.len(), NUM_KEYS as usize);
+        for key in 0..NUM_KEYS {
+            assert_eq!(map.get(&key), Some(&(key * 10)));
+            assert_eq!(cache.get(&key), Some(key * 10));
+            assert_eq!(calls[key as usize].load(Ordering::SeqCst), 1);
+        }
+        let max_in_flight = max_in_flight.load(Ordering::SeqCst);
+        assert!(max_in_flight > 1 && max_in_flight <= MAX_CONCURRENCY);
+
+        // The loader is not even called for the cached keys.
+        let map = cache
+            .get_or_insert_all_with(0..NUM_KEYS, MAX_CONCURRENCY, |_| -> Ready<u32> {
+                unreachable!()
+            })
+            .await;
+        assert_eq!(map.len(), NUM_KEYS as usize);
+    }
+
+    #[tokio::test]
+    async fn cancel_get_or_insert_with() {
+        use tokio::time::timeout;
+
+        let cache = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // Task1 starts loading the value, but it will be cancelled by the timeout
+        // before the load finishes.
+        let task1 = {
+            let cache1 = cache.clone();
+            tokio::spawn(async move {
+                let result = timeout(
+                    Duration::from_millis(200),
+                    cache1.get_or_insert_with(KEY, async {
+                        Timer::after(Duration::from_secs(10)).await;
+                        "task1"
+                    }),
+                )
+                .await;
+                assert!(result.is_err());
+            })
+        };
+
+        // Task2 waits for task1's load. Once task1 is cancelled, task2 should take
+        // over the load rather than waiting forever.
+        let task2 = {
+            let cache2 = cache.clone();
+            tokio::spawn(async move {
+                Timer::after(Duration::from_millis(100)).await;
+                let v = timeout(
+                    Duration::from_secs(5),
+                    cache2.get_or_insert_with(KEY, async { "task2" }),
+                )
+                .await
+                .expect("task2 should not wait for the cancelled task1");
+                assert_eq!(v, "task2");
+            })
+        };
+
+        task1.await.unwrap();
+        task2.await.unwrap();
+        assert_eq!(cache.get(&KEY), Some("task2"));
+
+        // The init future of a cancelled try_get_or_insert_with is also cleaned up.
+        let result = timeout(
+            Duration::from_millis(100),
+            cache.get_or_try_insert_with(1, async {
+                Timer::after(Duration::from_secs(10)).await;
+                Ok::<_, Infallible>("cancelled")
+            }),
+        )
+        .await;
+        assert!(result.is_err());
+        let v = cache
+            .get_or_try_insert_with(1, async { Ok::<_, Infallible>("loaded") })
+            .await;
+        assert_eq!(v.unwrap(), "loaded");
+    }
+
+    #[tokio::test]
+    async fn load_timeout() {
+        use crate::future::LoadError;
+        use tokio::time::sleep;
+
+        let cache = CacheBuilder::new(100)
+            .load_timeout(Duration::from_millis(100))
+            .build();
+        const KEY: u32 = 0;
+
+        // The loader sleeps longer than the timeout.
+        let result = cache
+            .load_with(KEY, async {
+                sleep(Duration::from_secs(10)).await;
+                "slow"
+            })
+            .await;
+        assert!(matches!(result, Err(LoadError::TimedOut)));
+        assert_eq!(cache.get(&KEY), None);
+
+        // The waiter has been released, so a subsequent call can load the value.
+        let result = cache.load_with(KEY, async { "fast" }).await;
+        assert_eq!(result.unwrap(), "fast");
+        assert_eq!(cache.get(&KEY), Some("fast"));
+
+        // try_load_with times out in the same way, and reports the init error.
+        let result = cache
+            .try_load_with(1, async {
+                sleep(Duration::from_secs(10)).await;
+                Ok::<_, &str>("slow")
+            })
+            .await;
+        assert!(matches!(result, Err(LoadError::TimedOut)));
+        let result = cache
+            .try_load_with(1, async { Err::<&str, _>("error") })
+            .await;
+        assert!(matches!(result, Err(LoadError::InitFailed(e)) if *e == "error"));
+        let result = cache
+            .try_load_with(1, async { Ok::<_, &str>("loaded") })
+            .await;
+        assert_eq!(result.unwrap(), "loaded");
+
+        // get_or_insert_with is not affected by the timeout.
+        let v = cache
+            .get_or_insert_with(2, async {
+                sleep(Duration::from_millis(200)).await;
+                "not timed out"
+            })
+            .await;
+        assert_eq!(v, "not timed out");
+    }
+
     #[tokio::test]
     // https://github.com/moka-rs/moka/issues/43
     async fn handle_panic_in_get_or_insert_with() {
@@ -1335,6 +3441,410 @@ mod tests {
         assert_eq!(cache.get_or_insert_with(1, async { 5 }).await, 5);
     }
 
+    #[tokio::test]
+    async fn init_panic_policy_fail() {
+        use crate::{policy::InitPanicPolicy, InitPanicked};
+        use tokio::time::{sleep, Duration};
+
+        let cache = CacheBuilder::new(16)
+            .init_panic_policy(InitPanicPolicy::Fail)
+            .build();
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(0));
+
+        let task1 = {
+            let cache_ref = cache.clone();
+            let semaphore_ref = semaphore.clone();
+            tokio::task::spawn(async move {
+                cache_ref
+                    .get_or_insert_with(1, async move {
+                        semaphore_ref.add_permits(1);
+                        sleep(Duration::from_millis(100)).await;
+                        panic!("Panic during get_or_insert_with");
+                    })
+                    .await
+            })
+        };
+        let _ = semaphore.acquire().await.expect("semaphore acquire failed");
+
+        // The waiting task fails without evaluating its init future.
+        let task2 = {
+            let cache_ref = cache.clone();
+            tokio::task::spawn(async move {
+                cache_ref
+                    .get_or_insert_with(1, async { unreachable!() })
+                    .await
+            })
+        };
+
+        assert!(task1.await.is_err());
+        let err = task2.await.expect_err("Task 2 should fail");
+        assert!(err.into_panic().is::<InitPanicked>());
+        assert_eq!(cache.get(&1), None);
+
+        // The later calls evaluate their init futures as usual.
+        assert_eq!(cache.get_or_insert_with(1, async { 5 }).await, 5);
+    }
+
+    #[tokio::test]
+    async fn get_with_refresh() {
+        use std::{
+            collections::HashMap,
+            convert::Infallible,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        const CONCURRENCY: usize = 2;
+        const NUM_KEYS: u32 = 10;
+
+        let cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .refresh_concurrency(CONCURRENCY)
+            .runtime(TokioSpawner::current())
+            .build();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, 0).await;
+        }
+        cache.run_pending_tasks().await;
+
+        // Not due for refresh yet.
+        assert_eq!(
+            cache.get_with_refresh(&0, |_| async { Ok::<_, Infallible>(1) }),
+            Some(0)
+        );
+
+        mock.increment(Duration::from_secs(11));
+
+        let running = Arc::new(AtomicUsize::default());
+        let max_running = Arc::new(AtomicUsize::default());
+        let reloads = Arc::new(parking_lot::Mutex::new(HashMap::new()));
+
+        let tasks = (0..4).map(|_| {
+            let (cache, running, max_running, reloads) = (
+                cache.clone(),
+                Arc::clone(&running),
+                Arc::clone(&max_running),
+                Arc::clone(&reloads),
+            );
+            tokio::spawn(async move {
+                for key in 0..NUM_KEYS {
+                    let (running, max_running, reloads) = (
+                        Arc::clone(&running),
+                        Arc::clone(&max_running),
+                        Arc::clone(&reloads),
+                    );
+                    let value = cache.get_with_refresh(&key, move |&k| async move {
+                        let n = running.fetch_add(1, Ordering::AcqRel) + 1;
+                        max_running.fetch_max(n, Ordering::AcqRel);
+                        // The reload runs on the Tokio runtime.
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        *reloads.lock().entry(k).or_insert(0) += 1;
+                        running.fetch_sub(1, Ordering::AcqRel);
+                        Ok::<_, Infallible>(1)
+                    });
+                    // The stale value is served until the reload completes.
+                    assert!(value.is_some());
+                }
+            })
+        });
+        for task in tasks.collect::<Vec<_>>() {
+            task.await.expect("Failed to join");
+        }
+
+        // Wait for the reloads to complete.
+        for _ in 0..100 {
+            if (0..NUM_KEYS).all(|key| cache.get(&key) == Some(1)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!((0..NUM_KEYS).all(|key| cache.get(&key) == Some(1)));
+
+        assert_eq!(running.load(Ordering::Acquire), 0);
+        assert_eq!(max_running.load(Ordering::Acquire), CONCURRENCY);
+        let reloads = reloads.lock();
+        assert_eq!(reloads.len(), NUM_KEYS as usize);
+        assert!(reloads.values().all(|&count| count == 1));
+    }
+
+    #[tokio::test]
+    async fn get_with_refresh_reports_errors() {
+        use crate::future::RefreshError;
+
+        let errors = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let errors1 = Arc::clone(&errors);
+        let cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .refresh_concurrency(2)
+            .runtime(TokioSpawner::current())
+            .refresh_error_listener(move |key: Arc<u32>, error| {
+                let failed = matches!(error, RefreshError::Failed(_));
+                errors1.lock().push((*key, failed, error.to_string()));
+            })
+            .build();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        cache.insert(0, 0).await;
+        cache.insert(1, 0).await;
+        cache.run_pending_tasks().await;
+        mock.increment(Duration::from_secs(11));
+
+        // The reload of key 0 fails, and the one of key 1 panics.
+        let value = cache.get_with_refresh(&0, |_| async { Err::<u32, _>("oops".to_string()) });
+        assert_eq!(value, Some(0));
+        let value = cache.get_with_refresh(&1, |_| async {
+            if true {
+                panic!("Panic during get_with_refresh_reports_errors");
+            }
+            Ok::<_, Infallible>(1)
+        });
+        assert_eq!(value, Some(0));
+
+        for _ in 0..100 {
+            if errors.lock().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mut reported = errors.lock().clone();
+        reported.sort();
+        assert_eq!(
+            reported,
+            vec![
+                (0, true, "the reload failed: oops".to_string()),
+                (1, false, "the reload panicked".to_string())
+            ]
+        );
+
+        // The current values are retained, and the next calls reload them again.
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), Some(0));
+        for key in 0..2 {
+            cache.get_with_refresh(&key, |_| async { Ok::<_, Infallible>(2) });
+        }
+        for _ in 0..100 {
+            if cache.get(&0) == Some(2) && cache.get(&1) == Some(2) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(cache.get(&0), Some(2));
+        assert_eq!(cache.get(&1), Some(2));
+        assert_eq!(errors.lock().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn get_with_refresh_spawns_bounded_tasks() {
+        use async_lock::Barrier;
+        use std::{
+            convert::Infallible,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        const CONCURRENCY: usize = 2;
+        const NUM_KEYS: u32 = 20;
+
+        // Counts the tasks spawned on the runtime.
+        struct CountingSpawner(TokioSpawner, Arc<AtomicUsize>);
+
+        impl Runtime for CountingSpawner {
+            fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) {
+                self.1.fetch_add(1, Ordering::AcqRel);
+                self.0.spawn(task);
+            }
+        }
+
+        let spawned = Arc::new(AtomicUsize::default());
+        let cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .refresh_concurrency(CONCURRENCY)
+            .runtime(CountingSpawner(
+                TokioSpawner::current(),
+                Arc::clone(&spawned),
+            ))
+            .build();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, 0).await;
+        }
+        cache.run_pending_tasks().await;
+        mock.increment(Duration::from_secs(11));
+
+        // Start the reloads of all the keys while the first ones are blocked.
+        let barrier = Arc::new(Barrier::new(CONCURRENCY + 1));
+        for key in 0..NUM_KEYS {
+            let barrier = Arc::clone(&barrier);
+            let value = cache.get_with_refresh(&key, move |_| async move {
+                if key < CONCURRENCY as u32 {
+                    barrier.wait().await;
+                }
+                Ok::<_, Infallible>(1)
+            });
+            assert_eq!(value, Some(0));
+        }
+        assert_eq!(spawned.load(Ordering::Acquire), CONCURRENCY);
+
+        // Unblock the reloads. All of them are run by the spawned tasks.
+        barrier.wait().await;
+        for _ in 0..100 {
+            if (0..NUM_KEYS).all(|key| cache.get(&key) == Some(1)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!((0..NUM_KEYS).all(|key| cache.get(&key) == Some(1)));
+        assert!(spawned.load(Ordering::Acquire) <= CONCURRENCY * 2);
+    }
+
+    #[tokio::test]
+    async fn get_with_refresh_retains_newer_value() {
+        use async_lock::Barrier;
+        use std::convert::Infallible;
+
+        let cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .refresh_concurrency(2)
+            .runtime(TokioSpawner::current())
+            .build();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        cache.run_pending_tasks().await;
+        mock.increment(Duration::from_secs(10));
+
+        // Start the reloads, and update and invalidate the entries while the
+        // reloads are blocked.
+        let barrier = Arc::new(Barrier::new(3));
+        let reload = |barrier: &Arc<Barrier>, v| {
+            let barrier = Arc::clone(barrier);
+            move |_key: &&str| async move {
+                barrier.wait().await;
+                Ok::<_, Infallible>(v)
+            }
+        };
+        assert_eq!(
+            cache.get_with_refresh(&"a", reload(&barrier, "anna")),
+            Some("alice")
+        );
+        assert_eq!(
+            cache.get_with_refresh(&"b", reload(&barrier, "bill")),
+            Some("bob")
+        );
+
+        cache.insert("a", "amy").await;
+        cache.invalidate(&"b").await;
+        barrier.wait().await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        cache.run_pending_tasks().await;
+
+        // The reloaded values do not overwrite the newer value nor revive the
+        // invalidated entry.
+        assert_eq!(cache.get(&"a"), Some("amy"));
+        assert_eq!(cache.get(&"b"), None);
+    }
+
+    #[tokio::test]
+    async fn invalidate_sync() {
+        use crate::notification::RemovalCause;
+
+        let removed = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let removed1 = Arc::clone(&removed);
+        let cache = CacheBuilder::new(100)
+            .eviction_listener(move |k: Arc<u32>, _v: u32, cause| {
+                removed1.lock().push((*k, cause));
+            })
+            .build();
+
+        let tasks = (0..4u32).map(|t| {
+            let (cache, removed) = (cache.clone(), Arc::clone(&removed));
+            tokio::spawn(async move {
+                for i in 0..50 {
+                    let key = t * 100 + i;
+                    cache.insert(key, key).await;
+                    cache.invalidate_sync(&key).await;
+                    // The removal has been applied before `invalidate_sync` returns.
+                    assert_eq!(cache.get(&key), None);
+                    assert!(removed.lock().contains(&(key, RemovalCause::Explicit)));
+                }
+            })
+        });
+        for task in tasks.collect::<Vec<_>>() {
+            task.await.expect("Failed to join");
+        }
+
+        cache.insert(1000, 1000).await;
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.weighted_size(), 1);
+        cache.invalidate_sync(&1000).await;
+        assert_eq!(cache.get(&1000), None);
+        assert_eq!(cache.weighted_size(), 0);
+    }
+
+    #[tokio::test]
+    async fn async_eviction_listener() {
+        use crate::{notification::RemovalCause, policy::EvictionPolicy};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const CONCURRENCY: usize = 3;
+
+        let running = Arc::new(AtomicUsize::default());
+        let max_running = Arc::new(AtomicUsize::default());
+        let evicted = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        let (running1, max_running1, evicted1) = (
+            Arc::clone(&running),
+            Arc::clone(&max_running),
+            Arc::clone(&evicted),
+        );
+        let mut cache = CacheBuilder::new(10)
+            .eviction_policy(EvictionPolicy::Lru)
+            .async_eviction_listener(move |k: Arc<u32>, _v: u32, cause| {
+                let (running, max_running, evicted) = (
+                    Arc::clone(&running1),
+                    Arc::clone(&max_running1),
+                    Arc::clone(&evicted1),
+                );
+                async move {
+                    let n = running.fetch_add(1, Ordering::AcqRel) + 1;
+                    max_running.fetch_max(n, Ordering::AcqRel);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    evicted.lock().push((*k, cause));
+                    running.fetch_sub(1, Ordering::AcqRel);
+                }
+            })
+            .eviction_concurrency(CONCURRENCY)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..100 {
+            cache.insert(i, i).await;
+        }
+        // The listener futures are awaited before this returns.
+        cache.run_pending_tasks().await;
+
+        assert_eq!(cache.entry_count(), 10);
+        let mut evicted = evicted.lock().clone();
+        evicted.sort_unstable_by_key(|(k, _)| *k);
+        let expected: Vec<_> = (0..90).map(|i| (i, RemovalCause::Size)).collect();
+        assert_eq!(evicted, expected);
+        assert_eq!(running.load(Ordering::Acquire), 0);
+        assert_eq!(max_running.load(Ordering::Acquire), CONCURRENCY);
+    }
+
     #[tokio::test]
     // https://github.com/moka-rs/moka/issues/43
     async fn handle_panic_in_get_or_try_insert_with() {
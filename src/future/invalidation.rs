@@ -0,0 +1,100 @@
+use crate::sync::{invalidator::PredicateCompletion, PredicateId};
+
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+/// A handle to an invalidation by a predicate, returned by
+/// [`Cache::invalidate_entries_if`][invalidate-if].
+///
+/// Awaiting this handle completes when the predicate has been applied to all
+/// entries that were in the cache when `invalidate_entries_if` was called. You do
+/// not have to await it; dropping the handle does not cancel the invalidation.
+///
+/// Note that the invalidation is carried out by the housekeeping of the cache,
+/// so the handle will not complete until the housekeeping runs.
+///
+/// [invalidate-if]: ./struct.Cache.html#method.invalidate_entries_if
+pub struct InvalidationHandle {
+    predicate_id: PredicateId,
+    completion: Option<Arc<PredicateCompletion>>,
+}
+
+impl InvalidationHandle {
+    pub(crate) fn new(
+        predicate_id: PredicateId,
+        completion: Option<Arc<PredicateCompletion>>,
+    ) -> Self {
+        Self {
+            predicate_id,
+            completion,
+        }
+    }
+
+    /// Returns the ID of the predicate.
+    pub fn predicate_id(&self) -> &PredicateId {
+        &self.predicate_id
+    }
+
+    /// Returns `true` if the invalidation has been completed.
+    pub fn is_complete(&self) -> bool {
+        self.completion
+            .as_ref()
+            .map(|c| c.is_complete())
+            .unwrap_or(true)
+    }
+}
+
+impl Future for InvalidationHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &self.completion {
+            Some(completion) => completion.poll_complete(cx),
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+/// Signals that the housekeeper has applied a write op, i.e. the `WriteOp::Notify`
+/// scheduled after it.
+#[derive(Default)]
+pub(crate) struct WriteOpCompletion {
+    state: Mutex<WriteOpCompletionState>,
+}
+
+#[derive(Default)]
+struct WriteOpCompletionState {
+    is_complete: bool,
+    wakers: Vec<Waker>,
+}
+
+impl WriteOpCompletion {
+    pub(crate) fn complete(&self) {
+        let wakers = {
+            let mut state = self.state.lock();
+            state.is_complete = true;
+            std::mem::take(&mut state.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    pub(crate) async fn wait(&self) {
+        futures_util::future::poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if state.is_complete {
+                Poll::Ready(())
+            } else {
+                if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
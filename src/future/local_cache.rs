@@ -0,0 +1,291 @@
+use crate::unsync;
+
+use async_lock::RwLock;
+use std::{
+    borrow::Borrow,
+    cell::RefCell,
+    collections::{hash_map::RandomState, HashMap},
+    future::Future,
+    hash::{BuildHasher, Hash},
+    rc::Rc,
+};
+
+type Waiter<V> = Rc<RwLock<Option<V>>>;
+type Waiters<K, V> = RefCell<HashMap<Rc<K>, Waiter<V>>>;
+
+/// A cache for the async tasks running on a single thread, e.g. the tasks on a
+/// single-threaded runtime or in a Tokio `LocalSet`.
+///
+/// Unlike [`future::Cache`][future-cache], `LocalCache` does not require the keys
+/// and values to be `Send` and `Sync`, so it can cache values like `Rc<T>`. It is
+/// backed by an [`unsync::Cache`][unsync-cache] and has no background thread; the
+/// expired and evicted entries are removed as a part of the cache operations.
+///
+/// `LocalCache` is `!Send` and `!Sync`. It can be cloned cheaply to share it
+/// between the tasks on the same thread, but it cannot be moved to another
+/// thread:
+///
+/// ```compile_fail
+/// use moka::future::LocalCache;
+///
+/// fn assert_send<T: Send>(_: T) {}
+/// assert_send(LocalCache::<u32, u32>::new(100));
+/// ```
+///
+/// To configure the cache (e.g. `time_to_live`), build an `unsync::Cache` with
+/// [`unsync::CacheBuilder`][unsync-builder] and convert it by `LocalCache::from`.
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka = { version = "0.6", features = ["future"] }
+/// // tokio = { version = "1", features = ["rt", "macros"] }
+/// use moka::future::LocalCache;
+/// use std::rc::Rc;
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() {
+///     let local = tokio::task::LocalSet::new();
+///     local
+///         .run_until(async {
+///             let cache = LocalCache::new(100);
+///
+///             let cache1 = cache.clone();
+///             let task = tokio::task::spawn_local(async move {
+///                 cache1
+///                     .get_or_insert_with(1, async { Rc::new("alice".to_string()) })
+///                     .await
+///             });
+///             assert_eq!(*task.await.unwrap(), "alice");
+///             assert_eq!(cache.get(&1), Some(Rc::new("alice".to_string())));
+///         })
+///         .await;
+/// }
+/// ```
+///
+/// [future-cache]: ./struct.Cache.html
+/// [unsync-cache]: ../unsync/struct.Cache.html
+/// [unsync-builder]: ../unsync/struct.CacheBuilder.html
+pub struct LocalCache<K, V, S = RandomState> {
+    inner: Rc<Inner<K, V, S>>,
+}
+
+struct Inner<K, V, S> {
+    cache: RefCell<unsync::Cache<K, V, S>>,
+    // The waiters for the init futures of `get_or_insert_with` being resolved.
+    waiters: Waiters<K, V>,
+}
+
+impl<K, V, S> Clone for LocalCache<K, V, S> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates a reference counted pointer to
+    /// the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<K, V, S> From<unsync::Cache<K, V, S>> for LocalCache<K, V, S> {
+    fn from(cache: unsync::Cache<K, V, S>) -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                cache: RefCell::new(cache),
+                waiters: RefCell::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+impl<K, V> LocalCache<K, V, RandomState>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// Constructs a new `LocalCache<K, V>` that will store up to the
+    /// `max_capacity` entries.
+    pub fn new(max_capacity: usize) -> Self {
+        Self::from(unsync::Cache::new(max_capacity))
+    }
+}
+
+impl<K, V, S> LocalCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    /// Returns a _clone_ of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Rc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache.borrow_mut().get(key).cloned()
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value resolved by the `init` future if not exist.
+    ///
+    /// When the tasks call this method concurrently for the same absent key, only
+    /// the `init` future of the first call is resolved, and the other calls wait
+    /// for it and get its value. If the first call is cancelled or its `init`
+    /// future panics, one of the waiting calls resolves its own `init` future.
+    ///
+    /// The `init` future must not call this method for the same key, as it would
+    /// wait for itself forever.
+    pub async fn get_or_insert_with(&self, key: K, init: impl Future<Output = V>) -> V {
+        let key = Rc::new(key);
+
+        loop {
+            if let Some(value) = self.get(&*key) {
+                return value;
+            }
+
+            let waiter = self.inner.waiters.borrow().get(&*key).map(Rc::clone);
+            if let Some(waiter) = waiter {
+                if let Some(value) = &*waiter.read().await {
+                    return value.clone();
+                }
+                // The other call was cancelled or its init future panicked.
+                continue;
+            }
+
+            let waiter: Waiter<V> = Rc::new(RwLock::new(None));
+            let mut lock = waiter.try_write().expect("Failed to lock a new waiter");
+            self.inner
+                .waiters
+                .borrow_mut()
+                .insert(Rc::clone(&key), Rc::clone(&waiter));
+            let guard = WaiterGuard {
+                waiters: &self.inner.waiters,
+                key: &key,
+            };
+
+            let value = init.await;
+
+            // Remove the waiter, and then insert the value while the waiting calls
+            // are still blocked by the write lock.
+            std::mem::drop(guard);
+            let key = match Rc::try_unwrap(key) {
+                Ok(key) => key,
+                Err(_) => unreachable!("The key of a removed waiter is still shared"),
+            };
+            self.insert(key, value.clone());
+            *lock = Some(value.clone());
+            return value;
+        }
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub fn insert(&self, key: K, value: V) {
+        self.inner.cache.borrow_mut().insert(key, value);
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        Rc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.cache.borrow_mut().invalidate(key);
+    }
+
+    /// Discards all cached values.
+    pub fn invalidate_all(&self) {
+        self.inner.cache.borrow_mut().invalidate_all();
+    }
+
+    /// Returns the `max_capacity` of this cache.
+    pub fn max_capacity(&self) -> usize {
+        self.inner.cache.borrow().max_capacity()
+    }
+}
+
+// Removes the waiter of the key when dropped, including when the init future is
+// cancelled or panics, so that the waiting calls can retry.
+struct WaiterGuard<'a, K: Hash + Eq, V> {
+    waiters: &'a Waiters<K, V>,
+    key: &'a K,
+}
+
+impl<K: Hash + Eq, V> Drop for WaiterGuard<'_, K, V> {
+    fn drop(&mut self) {
+        self.waiters.borrow_mut().remove(self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalCache;
+
+    use async_io::Timer;
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    #[tokio::test]
+    async fn get_or_insert_with_in_local_set() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let cache = LocalCache::new(100);
+                let init_count = Rc::new(Cell::new(0));
+
+                let tasks = (0..4)
+                    .map(|_| {
+                        let cache = cache.clone();
+                        let init_count = Rc::clone(&init_count);
+                        tokio::task::spawn_local(async move {
+                            cache
+                                .get_or_insert_with("a", async move {
+                                    init_count.set(init_count.get() + 1);
+                                    Timer::after(Duration::from_millis(100)).await;
+                                    Rc::new("alice".to_string())
+                                })
+                                .await
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                for task in tasks {
+                    assert_eq!(*task.await.expect("Failed to join"), "alice");
+                }
+                assert_eq!(init_count.get(), 1);
+                assert_eq!(cache.get(&"a"), Some(Rc::new("alice".to_string())));
+
+                cache.invalidate(&"a");
+                assert_eq!(cache.get(&"a"), None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn cancel_get_or_insert_with() {
+        let cache = LocalCache::new(100);
+
+        {
+            let first = cache.get_or_insert_with(1, async {
+                Timer::after(Duration::from_secs(60)).await;
+                Rc::new(1)
+            });
+            futures_util::pin_mut!(first);
+            assert!(futures_util::poll!(first.as_mut()).is_pending());
+        }
+
+        // The first call was dropped, so this call resolves its own init future.
+        assert_eq!(*cache.get_or_insert_with(1, async { Rc::new(2) }).await, 2);
+        assert_eq!(cache.get(&1), Some(Rc::new(2)));
+    }
+}
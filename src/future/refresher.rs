@@ -0,0 +1,71 @@
+use super::Runtime;
+
+use async_lock::Semaphore;
+use parking_lot::Mutex;
+use std::{collections::VecDeque, future::Future, pin::Pin, sync::Arc};
+
+type RefreshFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Runs the background refreshes of a cache on the async runtime set by
+/// `CacheBuilder::runtime`.
+///
+/// The refreshes are queued, and a worker task is spawned on the runtime only
+/// when a permit of the semaphore is available. The worker runs the queued
+/// refreshes one by one until the queue becomes empty. So at most `concurrency`
+/// tasks are spawned at a time, however many entries are due for refresh. Only one
+/// refresh per key is queued at a time, so the queue does not grow beyond the
+/// number of the keys due for refresh.
+pub(crate) struct Refresher {
+    runtime: Arc<dyn Runtime>,
+    queue: Arc<Mutex<VecDeque<RefreshFuture>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl Refresher {
+    pub(crate) fn new(runtime: Arc<dyn Runtime>, concurrency: usize) -> Self {
+        Self {
+            runtime,
+            queue: Arc::default(),
+            permits: Arc::new(Semaphore::new(concurrency)),
+        }
+    }
+
+    pub(crate) fn schedule(&self, refresh: impl Future<Output = ()> + Send + 'static) {
+        self.queue.lock().push_back(Box::pin(refresh));
+        self.spawn_worker_if_permitted();
+    }
+
+    fn spawn_worker_if_permitted(&self) {
+        let mut permit = match self.permits.try_acquire_arc() {
+            Some(permit) => permit,
+            // All the workers are busy. One of them will run the refresh.
+            None => return,
+        };
+
+        let queue = Arc::clone(&self.queue);
+        let permits = Arc::clone(&self.permits);
+        self.runtime.spawn(Box::pin(async move {
+            loop {
+                loop {
+                    let refresh = queue.lock().pop_front();
+                    match refresh {
+                        Some(refresh) => refresh.await,
+                        None => break,
+                    }
+                }
+                drop(permit);
+
+                // A refresh may have been queued after the queue became empty but
+                // before the permit was released. Run it unless another worker has
+                // the permit; that worker will run it.
+                if queue.lock().is_empty() {
+                    break;
+                }
+                permit = match permits.try_acquire_arc() {
+                    Some(permit) => permit,
+                    None => break,
+                };
+            }
+        }));
+    }
+}
@@ -0,0 +1,303 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+/// An async runtime that a cache spawns its background tasks on, and uses for its
+/// timers.
+///
+/// The cache does not depend on a particular async runtime, so it needs a
+/// `Runtime` to run the background reloads of
+/// [`Cache::get_with_refresh`][get-with-refresh]. Set it by
+/// [`CacheBuilder::runtime`][builder-runtime]. When a runtime is set, the
+/// [`load_timeout`][load-timeout] is also measured by its `sleep` method.
+///
+/// With the "rt-tokio" and "rt-async-std" crate features,
+/// [`TokioRuntime`][tokio-runtime] and [`AsyncStdRuntime`][async-std-runtime]
+/// implement this trait. For the other runtimes (e.g. actix-rt), implement it by
+/// the `spawn` function of the runtime.
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka = { version = "0.6", features = ["future"] }
+/// // tokio = { version = "1", features = ["rt-multi-thread", "macros"] }
+/// use moka::future::{CacheBuilder, Runtime};
+/// use std::{future::Future, pin::Pin, time::Duration};
+///
+/// // Spawns the tasks on a Tokio runtime. The handle can spawn the tasks from
+/// // any thread, even one outside of the runtime.
+/// struct TokioSpawner(tokio::runtime::Handle);
+///
+/// impl Runtime for TokioSpawner {
+///     fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) {
+///         self.0.spawn(task);
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cache = CacheBuilder::new(100)
+///         .refresh_after_write(Duration::from_secs(60))
+///         .runtime(TokioSpawner(tokio::runtime::Handle::current()))
+///         .build();
+///     cache.insert("config", "v1".to_string()).await;
+/// }
+/// ```
+///
+/// [get-with-refresh]: ./struct.Cache.html#method.get_with_refresh
+/// [builder-runtime]: ./struct.CacheBuilder.html#method.runtime
+/// [load-timeout]: ./struct.CacheBuilder.html#method.load_timeout
+/// [tokio-runtime]: ./struct.TokioRuntime.html
+/// [async-std-runtime]: ./struct.AsyncStdRuntime.html
+pub trait Runtime: Send + Sync + 'static {
+    /// Spawns the task on the runtime, and lets it run to completion in
+    /// background.
+    ///
+    /// The cache does not wait for the task, so the returned handle (if any) of
+    /// the runtime can be dropped. The task catches the panics of the user code,
+    /// so it does not panic.
+    fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>);
+
+    /// Returns a future that completes after the duration.
+    ///
+    /// The default implementation uses the timer of [`async-io`][async-io-crate],
+    /// which runs on its own thread and works with any runtime.
+    ///
+    /// [async-io-crate]: https://crates.io/crates/async-io
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async move {
+            async_io::Timer::after(duration).await;
+        })
+    }
+}
+
+/// A [`Runtime`][runtime-trait] spawning the tasks on a Tokio runtime, and using
+/// the timers of the Tokio runtime.
+///
+/// To use it, enable a crate feature called "rt-tokio". It holds a
+/// [`Handle`][tokio-handle] of the Tokio runtime, so the tasks are spawned on that
+/// runtime even when they are spawned from a thread outside of it.
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka = { version = "0.6", features = ["rt-tokio"] }
+/// // tokio = { version = "1", features = ["rt-multi-thread", "macros"] }
+/// use moka::future::{CacheBuilder, TokioRuntime};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let cache = CacheBuilder::new(100)
+///         .refresh_after_write(Duration::from_secs(60))
+///         .runtime(TokioRuntime::current())
+///         .build();
+///     cache.insert("config", "v1".to_string()).await;
+/// }
+/// ```
+///
+/// [runtime-trait]: ./trait.Runtime.html
+/// [tokio-handle]: https://docs.rs/tokio/1/tokio/runtime/struct.Handle.html
+#[cfg(feature = "rt-tokio")]
+#[derive(Clone, Debug)]
+pub struct TokioRuntime(tokio::runtime::Handle);
+
+#[cfg(feature = "rt-tokio")]
+impl TokioRuntime {
+    /// Returns a `TokioRuntime` of the Tokio runtime running the current task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn current() -> Self {
+        Self(tokio::runtime::Handle::current())
+    }
+}
+
+#[cfg(feature = "rt-tokio")]
+impl From<tokio::runtime::Handle> for TokioRuntime {
+    fn from(handle: tokio::runtime::Handle) -> Self {
+        Self(handle)
+    }
+}
+
+#[cfg(feature = "rt-tokio")]
+impl Runtime for TokioRuntime {
+    fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) {
+        self.0.spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        // Register the timer to the time driver of the runtime, so it can be
+        // polled outside of the runtime.
+        let _guard = self.0.enter();
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A [`Runtime`][runtime-trait] spawning the tasks on the global executor of
+/// async-std, and using the timers of async-std.
+///
+/// To use it, enable a crate feature called "rt-async-std". Unlike Tokio,
+/// async-std has one global executor, so the tasks can be spawned from any
+/// thread.
+///
+/// # Example
+///
+/// ```rust
+/// // Cargo.toml
+/// //
+/// // [dependencies]
+/// // moka = { version = "0.6", features = ["rt-async-std"] }
+/// // async-std = { version = "1", features = ["attributes"] }
+/// use moka::future::{AsyncStdRuntime, CacheBuilder};
+/// use std::time::Duration;
+///
+/// #[async_std::main]
+/// async fn main() {
+///     let cache = CacheBuilder::new(100)
+///         .refresh_after_write(Duration::from_secs(60))
+///         .runtime(AsyncStdRuntime)
+///         .build();
+///     cache.insert("config", "v1".to_string()).await;
+/// }
+/// ```
+///
+/// [runtime-trait]: ./trait.Runtime.html
+#[cfg(feature = "rt-async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "rt-async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send + 'static>>) {
+        // Dropping the handle detaches the task.
+        async_std::task::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+#[cfg(all(test, feature = "rt-tokio"))]
+mod tests {
+    use super::TokioRuntime;
+    use crate::future::{CacheBuilder, LoadError};
+
+    use std::{convert::Infallible, time::Duration};
+
+    #[test]
+    fn tokio_runtime_from_outside_thread() {
+        let rt = tokio::runtime::Runtime::new().expect("Failed to create a runtime");
+        let cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_millis(10))
+            .load_timeout(Duration::from_millis(50))
+            .runtime(TokioRuntime::from(rt.handle().clone()))
+            .build();
+
+        // The timer of the load timeout is driven by the Tokio runtime, even though
+        // the future is polled outside of it.
+        let result = async_io::block_on(cache.load_with(0, async {
+            async_io::Timer::after(Duration::from_secs(10)).await;
+            0
+        }));
+        assert!(matches!(result, Err(LoadError::TimedOut)));
+
+        async_io::block_on(async {
+            cache.insert(0, 0).await;
+            cache.run_pending_tasks().await;
+        });
+        std::thread::sleep(Duration::from_millis(20));
+
+        // The reload is spawned on the Tokio runtime from this thread, and it can
+        // use the timers of Tokio.
+        let value = cache.get_with_refresh(&0, |_| async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, Infallible>(1)
+        });
+        assert_eq!(value, Some(0));
+        for _ in 0..100 {
+            if cache.get(&0) == Some(1) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(cache.get(&0), Some(1));
+    }
+}
+
+#[cfg(all(test, feature = "rt-async-std"))]
+mod async_std_tests {
+    use super::AsyncStdRuntime;
+    use crate::future::{CacheBuilder, LoadError};
+
+    use std::{convert::Infallible, time::Duration};
+
+    #[async_std::test]
+    async fn get_or_insert_with() {
+        let cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_millis(10))
+            .runtime(AsyncStdRuntime)
+            .build();
+
+        // The concurrent calls on the same key evaluate only one init future.
+        let tasks = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                async_std::task::spawn(async move {
+                    cache
+                        .get_or_insert_with(0, async move {
+                            async_std::task::sleep(Duration::from_millis(50)).await;
+                            i
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut values = Vec::new();
+        for task in tasks {
+            values.push(task.await);
+        }
+        assert!(values.iter().all(|&v| v == values[0]));
+        assert_eq!(cache.get(&0), Some(values[0]));
+
+        // The reload is spawned on async-std, and it can use the timers of
+        // async-std.
+        cache.run_pending_tasks().await;
+        async_std::task::sleep(Duration::from_millis(20)).await;
+        let value = cache.get_with_refresh(&0, |_| async {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+            Ok::<_, Infallible>(10)
+        });
+        assert_eq!(value, Some(values[0]));
+        for _ in 0..100 {
+            if cache.get(&0) == Some(10) {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(cache.get(&0), Some(10));
+    }
+
+    #[async_std::test]
+    async fn load_timeout() {
+        let cache = CacheBuilder::new(100)
+            .load_timeout(Duration::from_millis(50))
+            .runtime(AsyncStdRuntime)
+            .build();
+
+        // The load timeout is measured by the timer of async-std.
+        let result = cache
+            .load_with(0, async {
+                async_std::task::sleep(Duration::from_secs(10)).await;
+                0
+            })
+            .await;
+        assert!(matches!(result, Err(LoadError::TimedOut)));
+    }
+}
@@ -0,0 +1,764 @@
+use super::{refresher::Refresher, Cache, CacheBuilder, ConcurrentCacheExt};
+use crate::{
+    policy::{EntryMeta, EvictionPolicy},
+    stats::{CacheStats, SegmentStat},
+    sync::{
+        config::CacheConfig,
+        segment::{mix_hash, segment_capacity},
+    },
+    CacheError, PredicateError,
+};
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// A thread-safe, futures-aware concurrent in-memory cache, with multiple internal
+/// segments.
+///
+/// `SegmentedCache` has multiple internal [`Cache`][cache-struct] instances, each
+/// having its own write operation channel and housekeeper. This increases the
+/// throughput when many async tasks write to the cache concurrently, at a small
+/// cost of selecting the segment on every call.
+///
+/// Build it by calling the `segments` method of the
+/// [`CacheBuilder`][builder-struct]. For usage examples, see the document of the
+/// [`Cache`][cache-struct].
+///
+/// [cache-struct]: ./struct.Cache.html
+/// [builder-struct]: ./struct.CacheBuilder.html
+///
+pub struct SegmentedCache<K, V, S = RandomState> {
+    inner: Arc<Inner<K, V, S>>,
+}
+
+// TODO: https://github.com/moka-rs/moka/issues/54
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<K, V, S> Send for SegmentedCache<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Send,
+{
+}
+
+unsafe impl<K, V, S> Sync for SegmentedCache<K, V, S>
+where
+    K: Send + Sync,
+    V: Send + Sync,
+    S: Sync,
+{
+}
+
+impl<K, V, S> Clone for SegmentedCache<K, V, S> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Prints the configuration and the approximate size of this cache. The
+    /// entries are not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SegmentedCache")
+            .field("max_capacity", &self.max_capacity())
+            .field("num_segments", &self.num_segments())
+            .field("time_to_live", &self.time_to_live())
+            .field("time_to_idle", &self.time_to_idle())
+            .field("entry_count", &self.entry_count())
+            .finish()
+    }
+}
+
+impl<K, V> SegmentedCache<K, V, RandomState>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Constructs a new `SegmentedCache<K, V>` that has multiple internal
+    /// segments and will store up to the `max_capacity` entries.
+    ///
+    /// `num_segments` is rounded up to the next power of two, and `max_capacity`
+    /// is distributed across the segments.
+    ///
+    /// To adjust various configuration knobs such as `initial_capacity` or
+    /// `time_to_live`, use the [`CacheBuilder`][builder-struct].
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub fn new(max_capacity: usize, num_segments: usize) -> Self {
+        let build_hasher = RandomState::default();
+        Self::with_everything(
+            max_capacity,
+            num_segments,
+            build_hasher,
+            CacheConfig::default(),
+            None,
+        )
+    }
+
+    /// Returns a [`CacheBuilder`][builder-struct], which can build a
+    /// `SegmentedCache` with `num_segments` segments and various configuration
+    /// knobs.
+    ///
+    /// The max capacity must be set by the `max_capacity` method of the builder;
+    /// otherwise `build` panics.
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::future::SegmentedCache;
+    ///
+    /// let cache: SegmentedCache<&str, &str> =
+    ///     SegmentedCache::builder(4).max_capacity(1_000).build();
+    /// assert_eq!(cache.num_segments(), 4);
+    /// ```
+    pub fn builder(num_segments: usize) -> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>> {
+        CacheBuilder::default().segments(num_segments)
+    }
+}
+
+impl<K, V, S> SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// # Panics
+    ///
+    /// Panics if `num_segments` is 0.
+    pub(crate) fn with_everything(
+        max_capacity: usize,
+        num_segments: usize,
+        build_hasher: S,
+        config: CacheConfig<K, V>,
+        refresher: Option<Arc<Refresher>>,
+    ) -> Self {
+        assert!(num_segments > 0);
+
+        let actual_num_segments = num_segments.next_power_of_two();
+        let segment_shift = 64 - actual_num_segments.trailing_zeros();
+        // NOTE: We cannot initialize the segments as `vec![cache; actual_num_segments]`
+        // because Cache::clone() does not clone its inner but shares the same inner.
+        let segments = (0..actual_num_segments)
+            .map(|i| {
+                Cache::with_everything(
+                    segment_capacity(max_capacity, actual_num_segments, i),
+                    build_hasher.clone(),
+                    config.for_segment(actual_num_segments, i),
+                    refresher.as_ref().map(Arc::clone),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            inner: Arc::new(Inner {
+                desired_capacity: AtomicUsize::new(max_capacity),
+                desired_initial_capacity: config.initial_capacity,
+                segments: segments.into_boxed_slice(),
+                build_hasher,
+                segment_shift,
+            }),
+        }
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).get_with_hash(key, hash)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, and reloads the
+    /// value in background if the entry is older than the `refresh_after_write`
+    /// period of the cache.
+    ///
+    /// The segments share the limit of
+    /// [`CacheBuilder::refresh_concurrency`][refresh-concurrency]. See
+    /// [`Cache::get_with_refresh`][get-with-refresh] for details.
+    ///
+    /// [refresh-concurrency]: ./struct.CacheBuilder.html#method.refresh_concurrency
+    /// [get-with-refresh]: ./struct.Cache.html#method.get_with_refresh
+    pub fn get_with_refresh<Q, F, Fut, E>(&self, key: &Q, reload: F) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&K) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>> + 'static,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).get_with_refresh(key, reload)
+    }
+
+    /// Returns `true` if the cache contains a value for the key.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read operation,
+    /// so it does not update the historic popularity estimator or reset the idle
+    /// timer for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).contains_key(key)
+    }
+
+    /// Resets the idle timer of the entry for the key without reading its value,
+    /// and returns `true` if the cache contains a value for the key.
+    ///
+    /// See [`Cache::touch`][touch] for details.
+    ///
+    /// [touch]: ./struct.Cache.html#method.touch
+    pub fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).touch(key, reset_ttl)
+    }
+
+    /// Ensures the value of the key exists by inserting the output of the init
+    /// future if not exist, and returns a _clone_ of the value.
+    ///
+    /// See [`Cache::get_or_insert_with`][get-or-insert-with] for details.
+    ///
+    /// [get-or-insert-with]: ./struct.Cache.html#method.get_or_insert_with
+    pub async fn get_or_insert_with<F>(&self, key: K, init: F) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .get_or_insert_with_hash_and_fun(key, hash, init)
+            .await
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// `default` value if not exist and returns a clone of it.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// with an init future resolving to `default`. When many tasks call this method
+    /// concurrently for the same absent key, only one of the `default` values is
+    /// inserted, and all the calls get that value.
+    pub async fn get_or_insert(&self, key: K, default: V) -> V {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .get_or_insert_with_hash_and_fun(key, hash, async move { default })
+            .await
+    }
+
+    /// Try to ensure the value of the key exists by inserting an `Ok` output of the
+    /// init future if not exist, and returns a _clone_ of the value or the `Err`
+    /// produced by the future.
+    ///
+    /// See [`Cache::get_or_try_insert_with`][get-or-try-insert-with] for details.
+    ///
+    /// [get-or-try-insert-with]: ./struct.Cache.html#method.get_or_try_insert_with
+    pub async fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>> + Send + 'static,
+        E: Send + Sync + 'static,
+    {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .get_or_try_insert_with_hash_and_fun(key, hash, init)
+            .await
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub async fn insert(&self, key: K, value: V) {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .insert_with_hash(key, hash, value)
+            .await
+    }
+
+    /// Inserts a key-value pair into the cache, returning an error instead of
+    /// panicking when the write cannot be recorded.
+    ///
+    /// See [`Cache::try_insert`][try-insert] for details.
+    ///
+    /// [try-insert]: ./struct.Cache.html#method.try_insert
+    pub async fn try_insert(&self, key: K, value: V) -> Result<(), CacheError> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(hash)
+            .try_insert_with_hash(key, hash, value)
+            .await
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub async fn invalidate<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).invalidate(key).await
+    }
+
+    /// Discards any cached value for the key, and waits until the removal has been
+    /// applied by the housekeeper.
+    ///
+    /// See the document of [`Cache::invalidate_sync`][cache-invalidate-sync] for
+    /// details.
+    ///
+    /// [cache-invalidate-sync]: ./struct.Cache.html#method.invalidate_sync
+    pub async fn invalidate_sync<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).invalidate_sync(key).await
+    }
+
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately and a background thread will evict all the
+    /// cached values inserted before the time when this method was called. It is
+    /// guaranteed that the `get` method must not return these invalidated values
+    /// even if they have not been evicted.
+    pub fn invalidate_all(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_all();
+        }
+    }
+
+    /// Records an access to the key in the historic popularity estimator of keys,
+    /// without reading or inserting the value.
+    ///
+    /// See [`Cache::record_access`][record-access] for details.
+    ///
+    /// [record-access]: ./struct.Cache.html#method.record_access
+    pub fn record_access<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select(hash).record_access(key);
+    }
+
+    /// Discards cached values that satisfy a predicate.
+    ///
+    /// The closure is registered to all the segments. See
+    /// [`Cache::invalidate_entries_if`][invalidate-if] for details. Unlike that
+    /// method, this method does not return a handle to await the invalidation.
+    ///
+    /// [invalidate-if]: ./struct.Cache.html#method.invalidate_entries_if
+    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<(), PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        let pred = Arc::new(predicate);
+        for segment in self.inner.segments.iter() {
+            let pred = Arc::clone(&pred);
+            segment.invalidate_entries_if(move |k, v| pred(k, v))?;
+        }
+        Ok(())
+    }
+
+    /// Discards cached values that satisfy a predicate on the entry metadata as
+    /// well as the key and value.
+    ///
+    /// The closure is registered to all the segments. See
+    /// [`Cache::invalidate_entries_with_meta_if`][invalidate-with-meta-if] for
+    /// details.
+    ///
+    /// [invalidate-with-meta-if]: ./struct.Cache.html#method.invalidate_entries_with_meta_if
+    pub fn invalidate_entries_with_meta_if<F>(&self, predicate: F) -> Result<(), PredicateError>
+    where
+        F: Fn(&K, &V, EntryMeta) -> bool + Send + Sync + 'static,
+    {
+        let pred = Arc::new(predicate);
+        for segment in self.inner.segments.iter() {
+            let pred = Arc::clone(&pred);
+            segment.invalidate_entries_with_meta_if(move |k, v, meta| pred(k, v, meta))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the approximate number of entries in this cache.
+    ///
+    /// The returned value is the sum of the entry counts of all segments.
+    pub fn entry_count(&self) -> usize {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.entry_count())
+            .sum()
+    }
+
+    /// Returns the approximate total weight of the entries in this cache.
+    ///
+    /// See [`Cache::weighted_size`][weighted-size] for details.
+    ///
+    /// [weighted-size]: ./struct.Cache.html#method.weighted_size
+    pub fn weighted_size(&self) -> u64 {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.weighted_size())
+            .sum()
+    }
+
+    /// Performs the pending maintenance tasks of all the segments, one segment at
+    /// a time.
+    ///
+    /// See [`Cache::run_pending_tasks`][run-pending-tasks] for details.
+    ///
+    /// [run-pending-tasks]: ./struct.Cache.html#method.run_pending_tasks
+    pub async fn run_pending_tasks(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.run_pending_tasks().await;
+        }
+    }
+
+    /// Returns a snapshot of the statistics of this cache, aggregated across
+    /// the segments.
+    ///
+    /// The statistics are recorded only when the cache is built with
+    /// `CacheBuilder::record_stats`; otherwise all of the counters are zero.
+    pub fn stats(&self) -> CacheStats {
+        self.inner
+            .segments
+            .iter()
+            .fold(CacheStats::default(), |mut stats, seg| {
+                stats.merge(&seg.stats());
+                stats
+            })
+    }
+
+    /// Returns a snapshot of the entry count, the weighted size and the statistics
+    /// of each segment, in the order of the segments.
+    ///
+    /// This is useful to diagnose the imbalance of the segments. The statistics
+    /// are recorded only when the cache is built with `CacheBuilder::record_stats`;
+    /// otherwise all of the counters are zero.
+    pub fn segment_stats(&self) -> Vec<SegmentStat> {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| SegmentStat::new(seg.entry_count(), seg.weighted_size(), seg.stats()))
+            .collect()
+    }
+
+    /// Returns the `max_capacity` of this cache.
+    pub fn max_capacity(&self) -> usize {
+        self.inner.desired_capacity.load(Ordering::Acquire)
+    }
+
+    /// Changes the `max_capacity` of this cache, e.g. in response to memory
+    /// pressure.
+    ///
+    /// The new capacity is distributed across the segments in the same way as at
+    /// the creation time. See [`Cache::set_max_capacity`][set-max-capacity] for
+    /// details.
+    ///
+    /// [set-max-capacity]: ./struct.Cache.html#method.set_max_capacity
+    pub fn set_max_capacity(&self, max_capacity: usize) {
+        self.inner
+            .desired_capacity
+            .store(max_capacity, Ordering::Release);
+        let num_segments = self.inner.segments.len();
+        for (i, segment) in self.inner.segments.iter().enumerate() {
+            segment.set_max_capacity(segment_capacity(max_capacity, num_segments, i));
+        }
+    }
+
+    /// Returns the `time_to_live` of this cache.
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.inner.segments[0].time_to_live()
+    }
+
+    /// Returns the `time_to_idle` of this cache.
+    pub fn time_to_idle(&self) -> Option<Duration> {
+        self.inner.segments[0].time_to_idle()
+    }
+
+    /// Returns the `initial_capacity` of this cache, or `None` if it was not set.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        self.inner.desired_initial_capacity
+    }
+
+    /// Returns the eviction policy of this cache.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.inner.segments[0].eviction_policy()
+    }
+
+    /// Returns `true` if this cache supports the invalidation closures, which is
+    /// enabled by the `support_invalidation_closures` method of the builder.
+    pub fn invalidation_closures_enabled(&self) -> bool {
+        self.inner.segments[0].invalidation_closures_enabled()
+    }
+
+    /// Returns the number of internal segments of this cache.
+    pub fn num_segments(&self) -> usize {
+        self.inner.segments.len()
+    }
+}
+
+impl<K, V, S> ConcurrentCacheExt<K, V> for SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn sync(&self) {
+        for segment in self.inner.segments.iter() {
+            #[allow(deprecated)]
+            segment.sync();
+        }
+    }
+}
+
+struct Inner<K, V, S> {
+    // This can be updated by `set_max_capacity`.
+    desired_capacity: AtomicUsize,
+    desired_initial_capacity: Option<usize>,
+    segments: Box<[Cache<K, V, S>]>,
+    build_hasher: S,
+    segment_shift: u32,
+}
+
+impl<K, V, S> Inner<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    #[inline]
+    fn hash<Q>(&self, key: &Q) -> u64
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut hasher = self.build_hasher.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Selects the segment for the hash of a key.
+    #[inline]
+    fn select(&self, hash: u64) -> &Cache<K, V, S> {
+        let index = if self.segment_shift == 64 {
+            0
+        } else {
+            (mix_hash(hash) >> self.segment_shift) as usize
+        };
+        &self.segments[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentedCache;
+    use crate::future::CacheBuilder;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn basic_single_async_task() {
+        let cache = SegmentedCache::new(100, 4);
+        assert_eq!(cache.num_segments(), 4);
+
+        cache.insert("a", "alice").await;
+        cache.insert("b", "bob").await;
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert!(cache.contains_key(&"b"));
+
+        assert_eq!(
+            cache.get_or_insert_with("c", async { "cindy" }).await,
+            "cindy"
+        );
+        assert_eq!(
+            cache
+                .get_or_insert_with("c", async { unreachable!() })
+                .await,
+            "cindy"
+        );
+
+        cache.invalidate(&"a").await;
+        assert_eq!(cache.get(&"a"), None);
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), 2);
+
+        let s = format!("{:?}", cache);
+        assert!(s.starts_with("SegmentedCache {"));
+        assert!(s.contains("num_segments: 4"));
+    }
+
+    #[tokio::test]
+    async fn builder() {
+        let cache = CacheBuilder::new(100)
+            .initial_capacity(40)
+            .segments(3)
+            .build();
+        assert_eq!(cache.num_segments(), 4);
+        assert_eq!(cache.max_capacity(), 100);
+        assert_eq!(cache.initial_capacity(), Some(40));
+
+        cache.insert(1, "one").await;
+        assert_eq!(cache.get(&1), Some("one"));
+
+        let result = CacheBuilder::<u32, u32, _>::new(100)
+            .segments(0)
+            .try_build();
+        assert_eq!(result.err(), Some(crate::BuilderError::ZeroSegments));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writers() {
+        const NUM_TASKS: u32 = 32;
+        const NUM_KEYS: u32 = 1_000;
+
+        // Leave room for the keys unevenly distributed across the segments.
+        let cache = CacheBuilder::new((NUM_TASKS * NUM_KEYS * 2) as usize)
+            .segments(8)
+            .build();
+
+        let tasks: Vec<_> = (0..NUM_TASKS)
+            .map(|t| {
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    for k in 0..NUM_KEYS {
+                        let key = t * NUM_KEYS + k;
+                        cache.insert(key, key * 10).await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        cache.run_pending_tasks().await;
+        assert_eq!(cache.entry_count(), (NUM_TASKS * NUM_KEYS) as usize);
+        for key in 0..(NUM_TASKS * NUM_KEYS) {
+            assert_eq!(cache.get(&key), Some(key * 10));
+        }
+    }
+
+    // Writes from many tasks by different numbers of segments, and checks that
+    // all writes land, that every segment gets a share of them, and that the
+    // write channel of each segment never grows past its capacity.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writers_by_num_segments() {
+        const NUM_TASKS: u32 = 16;
+        const NUM_KEYS: u32 = 500;
+        const CHANNEL_CAPACITY: usize = 1024;
+
+        for &num_segments in &[1, 4, 16] {
+            // Leave room for the keys unevenly distributed across the segments.
+            let cache = Arc::new(
+                CacheBuilder::new((NUM_TASKS * NUM_KEYS * 2) as usize)
+                    .segments(num_segments)
+                    .write_channel_capacity(CHANNEL_CAPACITY)
+                    .build(),
+            );
+
+            let done = Arc::new(AtomicBool::new(false));
+            let max_ch_len = Arc::new(AtomicUsize::new(0));
+            let monitor = {
+                let cache = Arc::clone(&cache);
+                let done = Arc::clone(&done);
+                let max_ch_len = Arc::clone(&max_ch_len);
+                tokio::spawn(async move {
+                    while !done.load(Ordering::Acquire) {
+                        for segment in cache.inner.segments.iter() {
+                            max_ch_len.fetch_max(segment.write_op_ch_len(), Ordering::AcqRel);
+                        }
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                    }
+                })
+            };
+
+            let tasks: Vec<_> = (0..NUM_TASKS)
+                .map(|t| {
+                    let cache = Arc::clone(&cache);
+                    tokio::spawn(async move {
+                        for k in 0..NUM_KEYS {
+                            let key = t * NUM_KEYS + k;
+                            cache.insert(key, key).await;
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.expect("Failed to join");
+            }
+            done.store(true, Ordering::Release);
+            monitor.await.expect("Failed to join");
+
+            assert!(max_ch_len.load(Ordering::Acquire) <= CHANNEL_CAPACITY);
+
+            cache.run_pending_tasks().await;
+            for segment in cache.inner.segments.iter() {
+                assert_eq!(segment.write_op_ch_len(), 0);
+                assert!(segment.entry_count() > 0);
+            }
+            assert_eq!(cache.entry_count(), (NUM_TASKS * NUM_KEYS) as usize);
+            for key in 0..(NUM_TASKS * NUM_KEYS) {
+                assert_eq!(cache.get(&key), Some(key));
+            }
+        }
+    }
+}
@@ -1,19 +1,130 @@
-use async_lock::RwLock;
+use crate::{common::time::Instant, policy::InitPanicPolicy};
+
+use async_lock::{RwLock, RwLockWriteGuard};
+use parking_lot::Mutex;
 use std::{
     any::{Any, TypeId},
+    collections::VecDeque,
+    convert::Infallible,
     future::Future,
     hash::{BuildHasher, Hash},
     sync::Arc,
+    time::Duration,
 };
 
 type ErrorObject = Arc<dyn Any + Send + Sync + 'static>;
-type WaiterValue<V> = Option<Result<V, ErrorObject>>;
+type WaiterValue<V> = Option<InitValue<V>>;
 type Waiter<V> = Arc<RwLock<WaiterValue<V>>>;
 
+/// The type ID of this type is used as a part of the waiter key for `serialize`.
+struct Serialize;
+
+/// The type ID of this type is used as a part of the waiter key for
+/// `optionally_init_or_read`.
+pub(crate) struct OptionallyInit;
+
+/// The type ID of this type is used as a part of the waiter key for the
+/// refreshes.
+struct Refresh;
+
+/// The outcome of the init future, which is shared with the other callers waiting
+/// for the same waiter.
+enum InitValue<V> {
+    Value(V),
+    // The init future of `optionally_init_or_read` returned `None`.
+    NoValue,
+    Err(ErrorObject),
+    // The init future of `try_init_or_read` returned an error, which is remembered
+    // until the instant (`cache_errors_for`).
+    CachedErr(ErrorObject, Instant),
+    // The init future panicked, and the waiting callers should not retry
+    // (`InitPanicPolicy::Fail`).
+    Panicked,
+}
+
+/// A waiter holding `CachedErr`, which is removed by `remove_expired_errors` after
+/// the error expires.
+struct CachedError<K, V> {
+    key: Arc<K>,
+    type_id: TypeId,
+    waiter: Waiter<V>,
+    expires_at: Instant,
+}
+
 pub(crate) enum InitResult<V, E> {
     Initialized(V),
     ReadExisting(V),
     InitErr(Arc<E>),
+    // The init future returned `None` (on this call or on a concurrent call).
+    InitNone,
+    // The init future of a concurrent call panicked (`InitPanicPolicy::Fail`).
+    InitPanicked,
+}
+
+/// Removes the waiter from the waiter map when the `init` future is dropped before
+/// it is resolved, which happens when the task calling `get_or_insert_with` is
+/// cancelled, or when the future panics. The other tasks waiting for the waiter
+/// will see `None` and retry, so one of them will become the new initializer,
+/// unless the future panicked and the `InitPanicPolicy` is `Fail`.
+struct WaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher,
+{
+    is_resolved: bool,
+    key: &'a Arc<K>,
+    type_id: TypeId,
+    value_initializer: &'a ValueInitializer<K, V, S>,
+    write_lock: RwLockWriteGuard<'a, WaiterValue<V>>,
+}
+
+impl<'a, K, V, S> WaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher,
+{
+    fn new(
+        key: &'a Arc<K>,
+        type_id: TypeId,
+        value_initializer: &'a ValueInitializer<K, V, S>,
+        write_lock: RwLockWriteGuard<'a, WaiterValue<V>>,
+    ) -> Self {
+        Self {
+            is_resolved: false,
+            key,
+            type_id,
+            value_initializer,
+            write_lock,
+        }
+    }
+
+    fn set_resolved(&mut self) {
+        self.is_resolved = true;
+    }
+}
+
+impl<'a, K, V, S> Drop for WaiterGuard<'a, K, V, S>
+where
+    Arc<K>: Eq + Hash,
+    V: Clone,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        if !self.is_resolved {
+            // A cancelled init future can always be retried by the waiting tasks.
+            let panicked = std::thread::panicking();
+            *self.write_lock = match self.value_initializer.init_panic_policy {
+                InitPanicPolicy::Fail if panicked => Some(InitValue::Panicked),
+                _ => None,
+            };
+            // Remove the waiter before unlocking it, so that the retrying tasks
+            // will not get the same waiter again.
+            self.value_initializer.remove_waiter(self.key, self.type_id);
+        }
+        // The write lock will be unlocked here.
+    }
 }
 
 pub(crate) struct ValueInitializer<K, V, S> {
@@ -22,6 +133,12 @@ pub(crate) struct ValueInitializer<K, V, S> {
     // we can always downcast the trait object ErrorObject (in Waiter<V>) into
     // its concrete type.
     waiters: moka_cht::SegmentedHashMap<(Arc<K>, TypeId), Waiter<V>, S>,
+    init_panic_policy: InitPanicPolicy,
+    // How long an error returned by the init future of `try_init_or_read` is
+    // remembered.
+    error_ttl: Option<Duration>,
+    // The waiters holding `CachedErr`, in the order that the errors were returned.
+    cached_errors: Mutex<VecDeque<CachedError<K, V>>>,
 }
 
 impl<K, V, S> ValueInitializer<K, V, S>
@@ -30,32 +147,76 @@ where
     V: Clone,
     S: BuildHasher,
 {
-    pub(crate) fn with_hasher(hasher: S) -> Self {
+    pub(crate) fn with_hasher(
+        hasher: S,
+        init_panic_policy: InitPanicPolicy,
+        error_ttl: Option<Duration>,
+    ) -> Self {
         Self {
             waiters: moka_cht::SegmentedHashMap::with_num_segments_and_hasher(16, hasher),
+            init_panic_policy,
+            error_ttl,
+            cached_errors: Mutex::default(),
         }
     }
 
     /// # Panics
     /// Panics if the `init` future has been panicked.
-    pub(crate) async fn init_or_read<F>(&self, key: Arc<K>, init: F) -> InitResult<V, ()>
+    pub(crate) async fn init_or_read<F>(&self, key: Arc<K>, init: F) -> InitResult<V, Infallible>
     where
         F: Future<Output = V>,
     {
         // This closure will be called after the init closure has returned a value.
         // It will convert the returned value (from init) into an InitResult.
         let post_init = |_key, value: V, lock: &mut WaiterValue<V>| {
-            *lock = Some(Ok(value.clone()));
+            *lock = Some(InitValue::Value(value.clone()));
             InitResult::Initialized(value)
         };
 
         let type_id = TypeId::of::<()>();
-        self.do_try_init(&key, type_id, init, post_init).await
+        self.do_try_init(&key, type_id, None, init, post_init).await
+    }
+
+    /// Like `init_or_read`, but the `init` future may resolve to `None`, in which
+    /// case `InitNone` is returned to this and the waiting callers.
+    ///
+    /// # Panics
+    /// Panics if the `init` future has been panicked.
+    pub(crate) async fn optionally_init_or_read<F>(
+        &self,
+        key: Arc<K>,
+        init: F,
+    ) -> InitResult<V, Infallible>
+    where
+        F: Future<Output = Option<V>>,
+    {
+        let type_id = TypeId::of::<OptionallyInit>();
+
+        // This closure will be called after the init future has returned a value.
+        // It will convert the returned value (from init) into an InitResult.
+        let post_init = |key, value: Option<V>, lock: &mut WaiterValue<V>| match value {
+            Some(value) => {
+                *lock = Some(InitValue::Value(value.clone()));
+                InitResult::Initialized(value)
+            }
+            None => {
+                *lock = Some(InitValue::NoValue);
+                self.remove_waiter(key, type_id);
+                InitResult::InitNone
+            }
+        };
+
+        self.do_try_init(&key, type_id, None, init, post_init).await
     }
 
     /// # Panics
     /// Panics if the `init` future has been panicked.
-    pub(crate) async fn try_init_or_read<F, E>(&self, key: Arc<K>, init: F) -> InitResult<V, E>
+    pub(crate) async fn try_init_or_read<F, E>(
+        &self,
+        key: Arc<K>,
+        now: Instant,
+        init: F,
+    ) -> InitResult<V, E>
     where
         F: Future<Output = Result<V, E>>,
         E: Send + Sync + 'static,
@@ -66,18 +227,26 @@ where
         // It will convert the returned value (from init) into an InitResult.
         let post_init = |key, value: Result<V, E>, lock: &mut WaiterValue<V>| match value {
             Ok(value) => {
-                *lock = Some(Ok(value.clone()));
+                *lock = Some(InitValue::Value(value.clone()));
                 InitResult::Initialized(value)
             }
             Err(e) => {
                 let err: ErrorObject = Arc::new(e);
-                *lock = Some(Err(Arc::clone(&err)));
-                self.remove_waiter(key, type_id);
+                match self.error_ttl {
+                    // Keep the waiter so that the later calls will get the error
+                    // until it expires.
+                    Some(ttl) => *lock = Some(InitValue::CachedErr(Arc::clone(&err), now + ttl)),
+                    None => {
+                        *lock = Some(InitValue::Err(Arc::clone(&err)));
+                        self.remove_waiter(key, type_id);
+                    }
+                }
                 InitResult::InitErr(err.downcast().unwrap())
             }
         };
 
-        self.do_try_init(&key, type_id, init, post_init).await
+        self.do_try_init(&key, type_id, Some(now), init, post_init)
+            .await
     }
 
     /// # Panics
@@ -86,6 +255,9 @@ where
         &self,
         key: &'a Arc<K>,
         type_id: TypeId,
+        // The current time to check the expiration of `CachedErr`. `None` for the
+        // waiters that never have `CachedErr`.
+        now: Option<Instant>,
         init: F,
         mut post_init: C,
     ) -> InitResult<V, E>
@@ -94,8 +266,6 @@ where
         C: FnMut(&'a Arc<K>, O, &mut WaiterValue<V>) -> InitResult<V, E>,
         E: Send + Sync + 'static,
     {
-        use futures_util::FutureExt;
-        use std::panic::{resume_unwind, AssertUnwindSafe};
         use InitResult::*;
 
         const MAX_RETRIES: usize = 200;
@@ -103,32 +273,51 @@ where
 
         loop {
             let waiter = Arc::new(RwLock::new(None));
-            let mut lock = waiter.write().await;
+            let lock = waiter.write().await;
 
             match self.try_insert_waiter(key, type_id, &waiter) {
                 None => {
                     // Our waiter was inserted. Let's resolve the init future.
-                    // Catching panic is safe here as we do not try to resolve the future again.
-                    match AssertUnwindSafe(init).catch_unwind().await {
-                        // Resolved.
-                        Ok(value) => return post_init(key, value, &mut lock),
-                        // Panicked.
-                        Err(payload) => {
-                            *lock = None;
-                            // Remove the waiter so that others can retry.
-                            self.remove_waiter(key, type_id);
-                            resume_unwind(payload);
-                        } // The lock will be unlocked here.
+                    //
+                    // If the init future panics, or this future is dropped before
+                    // the init future is resolved (e.g. the task is cancelled), the
+                    // guard will remove the waiter so that others can retry.
+                    let mut guard = WaiterGuard::new(key, type_id, self, lock);
+                    let value = init.await;
+                    let result = post_init(key, value, &mut guard.write_lock);
+                    if let Some(InitValue::CachedErr(_, expires_at)) = &*guard.write_lock {
+                        self.cached_errors.lock().push_back(CachedError {
+                            key: Arc::clone(key),
+                            type_id,
+                            waiter: Arc::clone(&waiter),
+                            expires_at: *expires_at,
+                        });
                     }
+                    guard.set_resolved();
+                    return result;
                 }
                 Some(res) => {
                     // Somebody else's waiter already exists. Drop our write lock and wait
                     // for a read lock to become available.
                     std::mem::drop(lock);
                     match &*res.read().await {
-                        Some(Ok(value)) => return ReadExisting(value.clone()),
-                        Some(Err(e)) => return InitErr(Arc::clone(e).downcast().unwrap()),
-                        // None means somebody else's init future has been panicked.
+                        Some(InitValue::Value(value)) => return ReadExisting(value.clone()),
+                        Some(InitValue::NoValue) => return InitNone,
+                        Some(InitValue::Panicked) => return InitPanicked,
+                        Some(InitValue::Err(e)) => {
+                            return InitErr(Arc::clone(e).downcast().unwrap())
+                        }
+                        Some(InitValue::CachedErr(e, expires_at)) => match now {
+                            Some(now) if now >= *expires_at => {
+                                // The error has expired. Remove the waiter (unless
+                                // somebody else has already replaced it) and retry.
+                                self.remove_waiter_if_same(key, type_id, &res);
+                                continue;
+                            }
+                            _ => return InitErr(Arc::clone(e).downcast().unwrap()),
+                        },
+                        // None means somebody else's init future has been panicked,
+                        // or dropped before it was resolved.
                         None => {
                             retries += 1;
                             if retries < MAX_RETRIES {
@@ -148,12 +337,85 @@ where
         }
     }
 
+    /// Removes the waiters of the errors remembered by `cache_errors_for` that have
+    /// expired at `now`, so that they do not stay in the waiter map until their
+    /// keys are requested again.
+    pub(crate) fn remove_expired_errors(&self, now: Instant) {
+        let mut errors = self.cached_errors.lock();
+        // The errors expire roughly in the order that they were returned. An error
+        // whose init future ran shorter than the ones before it waits for them.
+        while let Some(error) = errors.front() {
+            if error.expires_at > now {
+                break;
+            }
+            if let Some(error) = errors.pop_front() {
+                self.remove_waiter_if_same(&error.key, error.type_id, &error.waiter);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn waiter_count(&self) -> usize {
+        self.waiters.len()
+    }
+
+    /// Resolves the future while holding the waiter of the key, so that the calls
+    /// of this method on the same key are serialized.
+    ///
+    /// # Panics
+    /// Panics if the future has been panicked.
+    pub(crate) async fn serialize<F, O>(&self, key: &Arc<K>, f: F) -> O
+    where
+        F: Future<Output = O>,
+    {
+        let type_id = TypeId::of::<Serialize>();
+
+        loop {
+            let waiter = Arc::new(RwLock::new(None));
+            let lock = waiter.write().await;
+
+            match self.try_insert_waiter(key, type_id, &waiter) {
+                None => {
+                    // Our waiter was inserted. Let's resolve the future. The guard
+                    // will remove the waiter when it is dropped, even if the future
+                    // panics or this future is dropped.
+                    let _guard = WaiterGuard::new(key, type_id, self, lock);
+                    return f.await;
+                }
+                Some(res) => {
+                    // Somebody else's waiter already exists. Drop our write lock and
+                    // wait for the other call to complete, then retry.
+                    std::mem::drop(lock);
+                    std::mem::drop(res.read().await);
+                }
+            }
+        }
+    }
+
     #[inline]
+    /// Tries to mark the key as being refreshed. Returns `false` if another refresh
+    /// of the key is already in flight.
+    pub(crate) fn try_start_refresh(&self, key: &Arc<K>) -> bool {
+        let waiter = Arc::new(RwLock::new(None));
+        self.try_insert_waiter(key, TypeId::of::<Refresh>(), &waiter)
+            .is_none()
+    }
+
+    pub(crate) fn finish_refresh(&self, key: &Arc<K>) {
+        self.remove_waiter(key, TypeId::of::<Refresh>());
+    }
+
     pub(crate) fn remove_waiter(&self, key: &Arc<K>, type_id: TypeId) {
         let key = Arc::clone(key);
         self.waiters.remove(&(key, type_id));
     }
 
+    fn remove_waiter_if_same(&self, key: &Arc<K>, type_id: TypeId, waiter: &Waiter<V>) {
+        let key = Arc::clone(key);
+        self.waiters
+            .remove_if(&(key, type_id), |_, w| Arc::ptr_eq(w, waiter));
+    }
+
     #[inline]
     fn try_insert_waiter(
         &self,
@@ -1,3 +1,4 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(clippy::all)]
 #![warn(rust_2018_idioms)]
 
@@ -59,15 +60,47 @@
 //!
 //! | Feature    | Enabled by default? | MSRV        |
 //! |:-----------|:-------------------:|:-----------:|
-//! | no feature |                     | Rust 1.45.2 |
+//! | `std`      |       yes           | Rust 1.45.2 |
 //! | `atomic64` |       yes           | Rust 1.45.2 |
 //! | `future`   |                     | Rust 1.46.0 |
+//! | `alloc`    |                     | Rust 1.45.2 |
 //!
 //! If only the default features are enabled, MSRV will be updated conservatively.
 //! When using other features, like `future`, MSRV might be updated more frequently,
 //! up to the latest stable. In both cases, increasing MSRV is _not_ considered a
 //! semver-breaking change.
 //!
+//! # `no_std` Support
+//!
+//! The thread-safe caches depend on `std::thread`, `crossbeam-channel`,
+//! `parking_lot` and `moka-cht`, which all require `std`. So they are only provided
+//! with the "std" crate feature, which is enabled by default.
+//!
+//! The not thread-safe [`unsync::Cache`][unsync-cache-struct] can be used on
+//! `no_std` targets with a global allocator. Disable the default features and
+//! enable the "alloc" feature instead:
+//!
+//! ```toml
+//! [dependencies]
+//! moka = { version = "0.6", default-features = false, features = ["alloc"] }
+//! ```
+//!
+//! Then `unsync::Cache` uses the `HashMap` of the [hashbrown][hashbrown-crate]
+//! crate. It has the following limitations compared to the one with `std`:
+//!
+//! - The expiration policies (time to live and time to idle) are not available,
+//!   as there is no clock to read the time from.
+//! - The default hasher is SipHash 1-3 with _fixed_ keys, as there is no source of
+//!   random keys, so it does not protect against HashDoS attacks. Use
+//!   `CacheBuilder::build_with_hasher` with a randomly seeded hasher if the keys
+//!   may come from an untrusted source.
+//!
+//! When disabling the default features with the caches requiring `std` (e.g. to
+//! disable the "atomic64" feature), enable the "std" feature, or the "future"
+//! feature which implies it.
+//!
+//! [hashbrown-crate]: https://crates.io/crates/hashbrown
+//!
 //! # Implementation Details
 //!
 //! ## Concurrency
@@ -109,15 +142,20 @@
 //! retained in a historic popularity estimator. This estimator has a tiny memory
 //! footprint as it uses hashing to probabilistically estimate an item's frequency.
 //!
-//! All caches employ [TinyLFU] (Least Frequently Used) as the admission policy. When
-//! a new entry is inserted to the cache, it is temporary admitted to the cache, and
-//! a recording of this insertion is added to the write queue. When the write queue
+//! By default, all caches employ [TinyLFU] (Least Frequently Used) as the admission
+//! policy. (It can be switched to LRU, which always admits new entries, by the
+//! `eviction_policy` method of the `CacheBuilder`.) When a new entry is inserted to
+//! the cache, it is temporary admitted to the cache, and a recording of this
+//! insertion is added to the write queue. When the write queue
 //! is drained and the main space of the cache is already full, then the historic
 //! popularity estimator determines to evict one of the following entries:
 //!
 //! - The temporary admitted entry.
-//! - Or, an entry that is selected from the main cache space by LRU (Least Recently
-//!   Used) eviction policy.
+//! - Or, the entries that are selected from the main cache space by LRU (Least
+//!   Recently Used) eviction policy. When the caches are bounded by the total weight
+//!   of the entries, multiple entries may need to be evicted to make room for a
+//!   heavy entry. In that case, the temporary admitted entry must be more popular
+//!   than all of them put together.
 //!
 //! In a future release of this crate, TinyLFU admission policy will be replaced by
 //! Window TinyLFU (W-TinyLFU) policy. W-TinyLFU has an admission window in front of
@@ -151,15 +189,30 @@
 //!
 //! [timer-wheel]: http://www.cs.columbia.edu/~nahum/w6998/papers/ton97-timing-wheels.pdf
 
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("Either the \"std\" or \"alloc\" crate feature must be enabled.");
+
+extern crate alloc;
+
 #[cfg(feature = "future")]
 pub mod future;
 
+#[cfg(feature = "std")]
+pub mod notification;
+#[cfg(feature = "std")]
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
 pub mod sync;
+#[cfg(feature = "std")]
+pub mod time_source;
 pub mod unsync;
 
 pub(crate) mod common;
 
-pub use common::error::PredicateError;
+#[cfg(feature = "std")]
+pub use common::error::{BuilderError, CacheError, InitPanicked, PredicateError, RefreshError};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,126 @@
+//! Common data types for notifications.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+pub(crate) type EvictionListener<K, V> =
+    Arc<dyn Fn(Arc<K>, V, RemovalCause) + Send + Sync + 'static>;
+
+pub(crate) type ListenerFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+pub(crate) type AsyncEvictionListener<K, V> =
+    Arc<dyn Fn(Arc<K>, V, RemovalCause) -> ListenerFuture + Send + Sync + 'static>;
+
+/// An async eviction listener and the permits to limit the number of its futures
+/// running concurrently. Only used by the future cache.
+pub(crate) struct AsyncNotifier<K, V> {
+    #[cfg_attr(not(feature = "future"), allow(dead_code))]
+    pub(crate) listener: AsyncEvictionListener<K, V>,
+    #[cfg(feature = "future")]
+    pub(crate) permits: async_lock::Semaphore,
+}
+
+#[cfg(feature = "future")]
+impl<K, V> AsyncNotifier<K, V> {
+    pub(crate) fn new(listener: AsyncEvictionListener<K, V>, concurrency: usize) -> Self {
+        Self {
+            listener,
+            permits: async_lock::Semaphore::new(concurrency),
+        }
+    }
+}
+
+/// An eviction event received from the channel created by the `eviction_channel`
+/// method of the cache builder: the key, the value and the cause of a removal.
+pub type EvictionEvent<K, V> = (Arc<K>, V, RemovalCause);
+
+pub(crate) type MaintenanceListener = Arc<dyn Fn(MaintenanceReport) + Send + Sync + 'static>;
+
+pub(crate) type RefreshErrorListener<K> =
+    Arc<dyn Fn(Arc<K>, crate::RefreshError) + Send + Sync + 'static>;
+
+/// Indicates the reason why a cached entry was removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalCause {
+    /// The entry's expiration timestamp has passed.
+    Expired,
+    /// The entry was manually removed by the user, e.g. by `invalidate`,
+    /// `invalidate_all` or `invalidate_entries_if`.
+    Explicit,
+    /// The entry itself was not actually removed, but its value was replaced by
+    /// the user, e.g. by `insert`.
+    Replaced,
+    /// The entry was evicted due to the `max_capacity` constraint.
+    Size,
+}
+
+/// Specifies what to do with an eviction event when the channel created by the
+/// `eviction_channel_with_policy` method of the cache builder is full, i.e. the
+/// receiver is lagging behind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelFullPolicy {
+    /// Drops the event, and increments the counter returned by the
+    /// `dropped_eviction_events` method of the cache. This is the default.
+    Drop,
+    /// Blocks the housekeeper until the receiver takes an event. The other
+    /// housekeeping tasks of the cache (and the writers waiting for them) are
+    /// blocked meanwhile.
+    Block,
+}
+
+// `#[derive(Default)]` on enums requires Rust 1.62, which is newer than our MSRV.
+#[allow(clippy::derivable_impls)]
+impl Default for ChannelFullPolicy {
+    fn default() -> Self {
+        Self::Drop
+    }
+}
+
+/// A report of a housekeeping pass of a cache, passed to the callback set by the
+/// `on_maintenance` method of the cache builder.
+///
+/// A housekeeping pass applies the buffered reads and writes to the cache policy,
+/// and evicts the entries. A `SegmentedCache` runs a pass per segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    read_count: usize,
+    write_count: usize,
+    evicted_count: usize,
+    elapsed: Duration,
+}
+
+impl MaintenanceReport {
+    pub(crate) fn new(
+        read_count: usize,
+        write_count: usize,
+        evicted_count: usize,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            read_count,
+            write_count,
+            evicted_count,
+            elapsed,
+        }
+    }
+
+    /// Returns the number of the buffered reads applied by the pass.
+    pub fn read_count(&self) -> usize {
+        self.read_count
+    }
+
+    /// Returns the number of the buffered writes applied by the pass.
+    pub fn write_count(&self) -> usize {
+        self.write_count
+    }
+
+    /// Returns the number of the entries evicted by the pass, because of the
+    /// `max_capacity` constraint or the expiration.
+    pub fn evicted_count(&self) -> usize {
+        self.evicted_count
+    }
+
+    /// Returns the time taken by the pass.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
@@ -0,0 +1,192 @@
+//! Cache policies.
+
+use std::time::Duration;
+
+/// The eviction (and admission) policy of a cache.
+///
+/// When the cache is full, the eviction policy determines which entry to evict
+/// to make room for a new entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// The TinyLFU policy. This is the default.
+    ///
+    /// A new entry is admitted to the cache only when it has been accessed more
+    /// frequently than the entry that would be evicted for it, so a new entry with
+    /// a low frequency may be rejected. This keeps the popular entries in the cache
+    /// and gives a better hit rate for most workloads.
+    TinyLfu,
+    /// The LRU (Least Recently Used) policy.
+    ///
+    /// A new entry is always admitted to the cache, and the least recently used
+    /// entry is evicted to make room for it.
+    Lru,
+}
+
+// `#[derive(Default)]` on enums requires Rust 1.62, which is newer than our MSRV.
+#[allow(clippy::derivable_impls)]
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy::TinyLfu
+    }
+}
+
+/// The outcome of `insert_with_report` method of a cache.
+///
+/// The admission of a new entry is decided by the housekeeper when it applies the
+/// pending writes, so `Admitted` and `Rejected` are predictions made at the
+/// insertion time. They can be wrong when other writes are pending, e.g. a burst
+/// of inserts may fill the cache before the housekeeper runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertReport {
+    /// The key was not in the cache, and the new entry is expected to be admitted,
+    /// maybe by evicting other entries.
+    Admitted,
+    /// The key was in the cache, and its value was replaced. The cache size does
+    /// not grow by the entries of this kind unless their values get heavier.
+    Replaced,
+    /// The key was not in the cache, and the new entry is expected to be rejected
+    /// by the admission policy (e.g. TinyLFU) because the cache is full and the
+    /// key has been accessed less frequently than the entries to evict for it. Or
+    /// the entry is heavier than the `max_capacity` of the cache.
+    ///
+    /// A rejected entry is removed from the cache when the pending writes are
+    /// applied.
+    Rejected,
+}
+
+/// Tells whether `get_or_insert_with_status` method of a cache inserted the value
+/// or found an existing one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryStatus {
+    /// The `init` closure (or future) of this call was evaluated, and its value
+    /// was inserted.
+    Created,
+    /// The value already existed, or was inserted by the `init` closure of
+    /// another call running concurrently.
+    Existed,
+}
+
+/// The metadata of a cached entry, passed to the predicate of
+/// `invalidate_entries_with_meta_if` method of a cache.
+///
+/// The durations are measured by the clock of the cache, at the time when
+/// `invalidate_entries_with_meta_if` was called (not when the predicate is applied
+/// by the background task). Therefore, a predicate like
+/// `|_k, _v, meta| meta.age() >= threshold` invalidates the entries that were
+/// older than the threshold at the time of the call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryMeta {
+    age: Duration,
+    idle: Duration,
+    weight: u32,
+}
+
+impl EntryMeta {
+    pub(crate) fn new(age: Duration, idle: Duration, weight: u32) -> Self {
+        Self { age, idle, weight }
+    }
+
+    /// Returns the time elapsed since the current value of the entry was created
+    /// (inserted or updated).
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+
+    /// Returns the time elapsed since the entry was last accessed (read or
+    /// written).
+    pub fn idle(&self) -> Duration {
+        self.idle
+    }
+
+    /// Returns the weight of the entry. It is the value returned by the weigher
+    /// of the cache, or `1` if the cache has no weigher.
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+}
+
+/// What happens to the calls waiting for the `init` closure of
+/// `get_or_insert_with` (and its family) on the same key, when the closure panics.
+///
+/// The panic itself is always propagated to the caller whose `init` closure
+/// panicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitPanicPolicy {
+    /// One of the waiting calls is promoted to evaluate its own `init` closure,
+    /// and the other calls wait for it. This is the default.
+    Retry,
+    /// All the waiting calls fail without evaluating their `init` closures. They
+    /// unwind with an [`InitPanicked`][init-panicked] payload, which can be
+    /// recovered by `std::panic::catch_unwind` and then downcast.
+    ///
+    /// [init-panicked]: ../struct.InitPanicked.html
+    Fail,
+}
+
+// `#[derive(Default)]` on enums requires Rust 1.62, which is newer than our MSRV.
+#[allow(clippy::derivable_impls)]
+impl Default for InitPanicPolicy {
+    fn default() -> Self {
+        InitPanicPolicy::Retry
+    }
+}
+
+/// Calculates when the entries of a cache expire, from their keys and values.
+///
+/// Unlike the `time_to_live` and `time_to_idle` of the cache, which apply the same
+/// duration to all the entries, an `Expiry` can give a different duration to each
+/// entry, e.g. from the `max-age` of a cached HTTP response. Set it to a cache with
+/// the `expire_after` method of the `CacheBuilder`.
+///
+/// Each method returns the duration until the entry expires, measured from the
+/// time of the event. The cache-wide `time_to_live` and `time_to_idle` (if set)
+/// still apply, so an entry expires by whichever comes first. The per-entry
+/// time-to-live given to `insert_with_ttl` takes precedence over this policy.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::{policy::Expiry, sync::CacheBuilder};
+/// use std::time::Duration;
+///
+/// // The value is the max-age in seconds.
+/// struct MaxAge;
+///
+/// impl Expiry<&str, u64> for MaxAge {
+///     fn expire_after_create(&self, _key: &&str, max_age: &u64) -> Option<Duration> {
+///         Some(Duration::from_secs(*max_age))
+///     }
+/// }
+///
+/// let cache = CacheBuilder::new(100).expire_after(MaxAge).build();
+/// cache.insert("a", 60);
+/// ```
+pub trait Expiry<K, V>: Send + Sync {
+    /// Returns the duration until the entry expires after it has been created
+    /// (inserted). `None` means the entry will not expire by this policy.
+    ///
+    /// The default implementation returns `None`.
+    #[allow(unused_variables)]
+    fn expire_after_create(&self, key: &K, value: &V) -> Option<Duration> {
+        None
+    }
+
+    /// Returns the duration until the entry expires after it has been read.
+    /// `None` keeps the current expiration of the entry.
+    ///
+    /// The default implementation returns `None`.
+    #[allow(unused_variables)]
+    fn expire_after_read(&self, key: &K, value: &V) -> Option<Duration> {
+        None
+    }
+
+    /// Returns the duration until the entry expires after its value has been
+    /// updated. `None` keeps the current time-to-live of the entry, which is then
+    /// measured from the update.
+    ///
+    /// The default implementation returns `None`.
+    #[allow(unused_variables)]
+    fn expire_after_update(&self, key: &K, value: &V) -> Option<Duration> {
+        None
+    }
+}
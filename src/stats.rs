@@ -0,0 +1,190 @@
+//! Cache statistics.
+
+#[cfg(feature = "atomic64")]
+use std::sync::atomic::AtomicU64 as AtomicCount;
+#[cfg(not(feature = "atomic64"))]
+use std::sync::atomic::AtomicUsize as AtomicCount;
+
+use std::sync::atomic::Ordering;
+
+/// Statistics about the performance of a cache.
+///
+/// A `CacheStats` is a snapshot of the counters recorded by a cache. It can be
+/// obtained by calling the `stats` method of the cache. The counters are recorded
+/// only when the cache is built with `CacheBuilder::record_stats`; otherwise all of
+/// them are zero.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    hit_count: u64,
+    miss_count: u64,
+    eviction_count: u64,
+    load_success_count: u64,
+    load_failure_count: u64,
+}
+
+impl CacheStats {
+    /// Returns the number of times the lookup methods (e.g. `get`) returned a
+    /// cached value.
+    pub fn hit_count(&self) -> u64 {
+        self.hit_count
+    }
+
+    /// Returns the number of times the lookup methods (e.g. `get`) did not find a
+    /// cached value.
+    pub fn miss_count(&self) -> u64 {
+        self.miss_count
+    }
+
+    /// Returns the number of lookups, which is the sum of `hit_count` and
+    /// `miss_count`.
+    pub fn request_count(&self) -> u64 {
+        self.hit_count.saturating_add(self.miss_count)
+    }
+
+    /// Returns the ratio of the lookups that were hits. Returns `1.0` if there
+    /// has been no lookup.
+    pub fn hit_rate(&self) -> f64 {
+        match self.request_count() {
+            0 => 1.0,
+            n => self.hit_count as f64 / n as f64,
+        }
+    }
+
+    /// Returns the number of entries evicted from the cache because of the
+    /// `max_capacity` constraint or the expiration.
+    ///
+    /// This does not include the entries removed explicitly (e.g. `invalidate`)
+    /// or replaced by `insert`.
+    pub fn eviction_count(&self) -> u64 {
+        self.eviction_count
+    }
+
+    /// Returns the number of times the `get_or_insert_with` family of methods
+    /// successfully loaded a new value.
+    pub fn load_success_count(&self) -> u64 {
+        self.load_success_count
+    }
+
+    /// Returns the number of times the `get_or_insert_with` family of methods
+    /// failed to load a new value, because the init closure returned an `Err`.
+    pub fn load_failure_count(&self) -> u64 {
+        self.load_failure_count
+    }
+
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.hit_count = self.hit_count.saturating_add(other.hit_count);
+        self.miss_count = self.miss_count.saturating_add(other.miss_count);
+        self.eviction_count = self.eviction_count.saturating_add(other.eviction_count);
+        self.load_success_count = self
+            .load_success_count
+            .saturating_add(other.load_success_count);
+        self.load_failure_count = self
+            .load_failure_count
+            .saturating_add(other.load_failure_count);
+    }
+}
+
+/// A snapshot of the size and the statistics of a segment of a segmented cache.
+///
+/// `SegmentStat`s can be obtained by calling the `segment_stats` method of a
+/// `SegmentedCache`, one for each segment. They help to diagnose the imbalance of
+/// the segments, e.g. a hot segment caused by a skewed key distribution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentStat {
+    entry_count: usize,
+    weighted_size: u64,
+    stats: CacheStats,
+}
+
+impl SegmentStat {
+    pub(crate) fn new(entry_count: usize, weighted_size: u64, stats: CacheStats) -> Self {
+        Self {
+            entry_count,
+            weighted_size,
+            stats,
+        }
+    }
+
+    /// Returns the approximate number of entries in the segment. See the
+    /// `entry_count` method of the cache for details.
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    /// Returns the approximate total weight of the entries in the segment.
+    pub fn weighted_size(&self) -> u64 {
+        self.weighted_size
+    }
+
+    /// Returns the statistics of the segment. All of the counters are zero unless
+    /// the cache is built with `CacheBuilder::record_stats`.
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+}
+
+/// Atomic counters backing the `CacheStats`.
+#[derive(Default)]
+pub(crate) struct StatsCounter {
+    hit_count: AtomicCount,
+    miss_count: AtomicCount,
+    eviction_count: AtomicCount,
+    load_success_count: AtomicCount,
+    load_failure_count: AtomicCount,
+}
+
+impl StatsCounter {
+    #[inline]
+    pub(crate) fn record_hit(&self) {
+        self.hit_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_miss(&self) {
+        self.miss_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_eviction(&self) {
+        self.eviction_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_load_success(&self) {
+        self.load_success_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_load_failure(&self) {
+        self.load_failure_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> CacheStats {
+        // The cast is needed when the `atomic64` feature is disabled.
+        #[allow(clippy::unnecessary_cast)]
+        let load = |c: &AtomicCount| c.load(Ordering::Relaxed) as u64;
+        CacheStats {
+            hit_count: load(&self.hit_count),
+            miss_count: load(&self.miss_count),
+            eviction_count: load(&self.eviction_count),
+            load_success_count: load(&self.load_success_count),
+            load_failure_count: load(&self.load_failure_count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CacheStats;
+
+    #[test]
+    fn hit_rate() {
+        let mut stats = CacheStats::default();
+        assert_eq!(stats.hit_rate(), 1.0);
+
+        stats.hit_count = 3;
+        stats.miss_count = 1;
+        assert_eq!(stats.request_count(), 4);
+        assert_eq!(stats.hit_rate(), 0.75);
+    }
+}
@@ -10,23 +10,36 @@ use parking_lot::Mutex;
 use std::{
     ptr::NonNull,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 pub(crate) mod base_cache;
 mod builder;
 mod cache;
+mod cache_view;
+pub(crate) mod config;
 mod deques;
+mod entry;
+mod entry_guard;
 pub(crate) mod housekeeper;
-mod invalidator;
-mod segment;
+pub(crate) mod invalidator;
+mod refresher;
+pub(crate) mod segment;
+pub(crate) mod spill;
 mod value_initializer;
+mod weak_value_cache;
 
 pub use builder::CacheBuilder;
-pub use cache::Cache;
+pub use cache::{Cache, SharedCache};
+pub use cache_view::CacheView;
+pub use entry::Entry;
+pub use entry_guard::EntryGuard;
 pub use segment::SegmentedCache;
+pub use spill::SpillStore;
+pub use weak_value_cache::WeakValueCache;
 
 /// The type of the unique ID to identify a predicate used by
 /// [`Cache#invalidate_entries_if`][invalidate-if] method.
@@ -38,9 +51,16 @@ pub type PredicateId = String;
 
 pub(crate) type PredicateIdStr<'a> = &'a str;
 
+pub(crate) type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> u32 + Send + Sync + 'static>;
+
+pub(crate) type SegmentKeyFn<K> = Arc<dyn Fn(&K) -> u64 + Send + Sync + 'static>;
+
 /// Provides extra methods that will be useful for testing.
 pub trait ConcurrentCacheExt<K, V> {
     /// Performs any pending maintenance operations needed by the cache.
+    ///
+    /// This is a deprecated alias of the `run_pending_tasks` method of the cache.
+    #[deprecated(since = "0.6.2", note = "Use `run_pending_tasks` method instead")]
     fn sync(&self);
 }
 
@@ -111,19 +131,36 @@ unsafe impl<K> Send for DeqNodes<K> {}
 
 pub(crate) struct ValueEntry<K, V> {
     pub(crate) value: V,
+    weight: u32,
+    // The per-entry time-to-live overriding the cache-wide one. It can be updated
+    // by an expiry policy when the entry is read.
+    time_to_live: Mutex<Option<Duration>>,
+    // The weight of the key currently accounted by the cache policy. This is shared
+    // with the older and newer ValueEntries of the same key, and will be updated
+    // when applying writes.
+    policy_weight: Arc<AtomicU32>,
     is_admitted: Arc<AtomicBool>,
     last_accessed: Arc<AtomicInstant>,
     last_modified: Arc<AtomicInstant>,
+    // The time when the idle timer was last reset by a read or by the insertion of
+    // the key. It is shared with the older and newer ValueEntries of the same key,
+    // and is used instead of `last_accessed` when the cache is configured not to
+    // reset the idle timer on updates.
+    last_read: Arc<AtomicInstant>,
     nodes: Mutex<DeqNodes<K>>,
 }
 
 impl<K, V> ValueEntry<K, V> {
-    pub(crate) fn new(value: V) -> Self {
+    pub(crate) fn new(value: V, weight: u32, time_to_live: Option<Duration>) -> Self {
         Self {
             value,
+            weight,
+            time_to_live: Mutex::new(time_to_live),
+            policy_weight: Arc::new(AtomicU32::new(0)),
             is_admitted: Arc::new(AtomicBool::new(false)),
             last_accessed: Default::default(),
             last_modified: Default::default(),
+            last_read: Default::default(),
             nodes: Mutex::new(DeqNodes {
                 access_order_q_node: None,
                 write_order_q_node: None,
@@ -131,7 +168,12 @@ impl<K, V> ValueEntry<K, V> {
         }
     }
 
-    pub(crate) fn new_with(value: V, other: &Self) -> Self {
+    pub(crate) fn new_with(
+        value: V,
+        weight: u32,
+        time_to_live: Option<Duration>,
+        other: &Self,
+    ) -> Self {
         let nodes = {
             let other_nodes = other.nodes.lock();
             DeqNodes {
@@ -148,13 +190,39 @@ impl<K, V> ValueEntry<K, V> {
         last_modified.reset();
         Self {
             value,
+            weight,
+            time_to_live: Mutex::new(time_to_live),
+            policy_weight: Arc::clone(&other.policy_weight),
             is_admitted: Arc::clone(&other.is_admitted),
             last_accessed,
             last_modified,
+            last_read: Arc::clone(&other.last_read),
             nodes: Mutex::new(nodes),
         }
     }
 
+    pub(crate) fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    pub(crate) fn time_to_live(&self) -> Option<Duration> {
+        *self.time_to_live.lock()
+    }
+
+    pub(crate) fn set_time_to_live(&self, time_to_live: Option<Duration>) {
+        *self.time_to_live.lock() = time_to_live;
+    }
+
+    /// Returns the weight of the entry recorded by the cache policy.
+    pub(crate) fn policy_weight(&self) -> u32 {
+        self.policy_weight.load(Ordering::Acquire)
+    }
+
+    /// Sets the policy weight of the key, and returns the previous one.
+    pub(crate) fn swap_policy_weight(&self, weight: u32) -> u32 {
+        self.policy_weight.swap(weight, Ordering::AcqRel)
+    }
+
     pub(crate) fn is_admitted(&self) -> bool {
         self.is_admitted.load(Ordering::Acquire)
     }
@@ -163,6 +231,24 @@ impl<K, V> ValueEntry<K, V> {
         self.is_admitted.store(value, Ordering::Release);
     }
 
+    pub(crate) fn last_read(&self) -> Option<Instant> {
+        self.last_read.instant()
+    }
+
+    pub(crate) fn set_last_read(&self, timestamp: Instant) {
+        self.last_read.set_instant(timestamp);
+    }
+
+    /// Restarts the idle timer of the key, e.g. when this entry replaces an expired
+    /// one. The timer will be started when the write is applied.
+    pub(crate) fn restart_idle_timer(&self) {
+        self.last_read.reset();
+    }
+
+    pub(crate) fn raw_last_read(&self) -> Arc<AtomicInstant> {
+        Arc::clone(&self.last_read)
+    }
+
     pub(crate) fn raw_last_accessed(&self) -> Arc<AtomicInstant> {
         Arc::clone(&self.last_accessed)
     }
@@ -271,9 +357,32 @@ impl<K> AccessTime for DeqNode<KeyHashDate<K>> {
 pub(crate) enum ReadOp<K, V> {
     Hit(u64, Arc<ValueEntry<K, V>>, Instant),
     Miss(u64),
+    // A hit by `touch`, which has reset the time-to-live of the entry.
+    TouchTtl(u64, Arc<K>, Arc<ValueEntry<K, V>>, Instant),
+}
+
+pub(crate) struct KvEntry<K, V> {
+    pub(crate) key: Arc<K>,
+    pub(crate) entry: Arc<ValueEntry<K, V>>,
+}
+
+impl<K, V> KvEntry<K, V> {
+    pub(crate) fn new(key: Arc<K>, entry: Arc<ValueEntry<K, V>>) -> Self {
+        Self { key, entry }
+    }
 }
 
 pub(crate) enum WriteOp<K, V> {
-    Upsert(KeyHash<K>, Arc<ValueEntry<K, V>>),
-    Remove(Arc<ValueEntry<K, V>>),
+    // The third field is the replaced entry. It is only set when the cache has an
+    // eviction listener.
+    Upsert(
+        KeyHash<K>,
+        Arc<ValueEntry<K, V>>,
+        Option<Arc<ValueEntry<K, V>>>,
+    ),
+    Remove(KvEntry<K, V>),
+    // Completes the write op completion when the housekeeper applies this op,
+    // i.e. after it has applied all the write ops scheduled before this one.
+    #[cfg(feature = "future")]
+    Notify(Arc<crate::future::WriteOpCompletion>),
 }
@@ -1,16 +1,29 @@
 use super::{
+    config::CacheConfig,
     deques::Deques,
     housekeeper::{Housekeeper, InnerSync, SyncPace},
-    invalidator::{GetOrRemoveEntry, InvalidationResult, Invalidator, KeyDateLite, PredicateFun},
-    KeyDate, KeyHash, KeyHashDate, PredicateId, ReadOp, ValueEntry, WriteOp,
+    invalidator::{
+        GetOrRemoveEntry, InvalidationResult, Invalidator, KeyDateLite, PredicateCompletion,
+        PredicateFun,
+    },
+    spill::Spill,
+    EntryGuard, KeyDate, KeyHash, KeyHashDate, KvEntry, PredicateId, ReadOp, ValueEntry, Weigher,
+    WriteOp,
 };
 use crate::{
     common::{
-        deque::{CacheRegion, DeqNode, Deque},
+        deque::{CacheRegion, Deque},
         frequency_sketch::FrequencySketch,
         time::{AtomicInstant, Clock, Instant},
+        timer_wheel::TimerWheel,
         AccessTime,
     },
+    notification::{
+        AsyncNotifier, EvictionListener, MaintenanceListener, MaintenanceReport, RemovalCause,
+    },
+    policy::{EvictionPolicy, Expiry, InsertReport},
+    stats::{CacheStats, StatsCounter},
+    time_source::TimeSource,
     PredicateError,
 };
 
@@ -18,13 +31,13 @@ use crossbeam_channel::{Receiver, Sender, TrySendError};
 use parking_lot::{Mutex, RwLock};
 use std::{
     borrow::Borrow,
-    collections::hash_map::RandomState,
+    collections::{hash_map::RandomState, HashMap},
     convert::TryInto,
     hash::{BuildHasher, Hash, Hasher},
     ptr::NonNull,
     rc::Rc,
     sync::{
-        atomic::{AtomicBool, AtomicU8, Ordering},
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
@@ -33,13 +46,19 @@ use std::{
 pub(crate) const MAX_SYNC_REPEATS: usize = 4;
 
 const READ_LOG_FLUSH_POINT: usize = 512;
-const READ_LOG_SIZE: usize = READ_LOG_FLUSH_POINT * (MAX_SYNC_REPEATS + 2);
 
 const WRITE_LOG_FLUSH_POINT: usize = 512;
 const WRITE_LOG_LOW_WATER_MARK: usize = WRITE_LOG_FLUSH_POINT / 2;
 // const WRITE_LOG_HIGH_WATER_MARK: usize = WRITE_LOG_FLUSH_POINT * (MAX_SYNC_REPEATS - 1);
 const WRITE_LOG_SIZE: usize = WRITE_LOG_FLUSH_POINT * (MAX_SYNC_REPEATS + 2);
 
+// The minimum number of the entries at the LRU end of the probation deque to
+// compare their frequencies when choosing the victims for `admit_on_insert`.
+const VICTIM_SAMPLE_SIZE: usize = 8;
+
+// The default max number of the entries to evict in a housekeeping pass.
+pub(crate) const EVICTION_BATCH_SIZE: usize = 500;
+
 pub(crate) const WRITE_RETRY_INTERVAL_MICROS: u64 = 50;
 
 pub(crate) const PERIODICAL_SYNC_INITIAL_DELAY_MILLIS: u64 = 500;
@@ -48,6 +67,14 @@ pub(crate) const PERIODICAL_SYNC_FAST_PACE_NANOS: u64 = 500;
 
 pub(crate) type HouseKeeperArc<K, V, S> = Arc<Housekeeper<Inner<K, V, S>>>;
 
+/// The key and the entry due for refresh by `refresh_after_write`. The reloaded
+/// value replaces the entry only when the key still has it.
+pub(crate) type RefreshKey<K, V> = (Arc<K>, Arc<ValueEntry<K, V>>);
+
+/// Removes the waiters of the errors remembered by `cache_errors_for` that have
+/// expired at the given instant.
+pub(crate) type ExpiredErrorRemover = Box<dyn Fn(Instant) + Send + Sync + 'static>;
+
 pub(crate) struct BaseCache<K, V, S = RandomState> {
     pub(crate) inner: Arc<Inner<K, V, S>>,
     read_op_ch: Sender<ReadOp<K, V>>,
@@ -81,32 +108,36 @@ impl<K, V, S> BaseCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
 {
     pub(crate) fn new(
         max_capacity: usize,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        hasher_factory: impl Fn() -> S,
+        async_notifier: Option<AsyncNotifier<K, V>>,
+        config: CacheConfig<K, V>,
     ) -> Self {
-        let (r_snd, r_rcv) = crossbeam_channel::bounded(READ_LOG_SIZE);
-        let (w_snd, w_rcv) = crossbeam_channel::bounded(WRITE_LOG_SIZE);
+        let r_flush_point = config
+            .read_buffer_flush_threshold
+            .unwrap_or(READ_LOG_FLUSH_POINT);
+        let r_size = r_flush_point * (MAX_SYNC_REPEATS + 2);
+        let (r_snd, r_rcv) = crossbeam_channel::bounded(r_size);
+        let w_size = config.write_channel_capacity.unwrap_or(WRITE_LOG_SIZE);
+        let (w_snd, w_rcv) = crossbeam_channel::bounded(w_size);
+        let invalidator_enabled = config.invalidator_enabled;
+        let manual_housekeeping = config.manual_housekeeping;
         let inner = Arc::new(Inner::new(
             max_capacity,
-            initial_capacity,
-            build_hasher,
+            hasher_factory,
+            async_notifier,
             r_rcv,
             w_rcv,
-            time_to_live,
-            time_to_idle,
-            invalidator_enabled,
+            r_flush_point,
+            config,
         ));
         if invalidator_enabled {
             inner.set_invalidator(&inner);
         }
-        let housekeeper = Housekeeper::new(Arc::downgrade(&inner));
+        let housekeeper = Housekeeper::new(Arc::downgrade(&inner), manual_housekeeping);
         Self {
             inner,
             read_op_ch: r_snd,
@@ -125,6 +156,52 @@ where
     }
 
     pub(crate) fn get_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_with_hash_and_refresh_key(key, hash)
+            .map(|(v, _)| v)
+    }
+
+    /// Returns a _clone_ of the value, and the key and the entry if the entry is due
+    /// for refresh by `refresh_after_write`.
+    pub(crate) fn get_with_hash_and_refresh_key<Q>(
+        &self,
+        key: &Q,
+        hash: u64,
+    ) -> Option<(V, Option<RefreshKey<K, V>>)>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.do_get_with_hash(key, hash, |entry| entry.value.clone(), |v| v)
+    }
+
+    /// Returns a guard holding the entry, rather than a _clone_ of the value.
+    pub(crate) fn get_ref_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<EntryGuard<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.do_get_with_hash(
+            key,
+            hash,
+            |entry| EntryGuard::new(Arc::clone(entry)),
+            EntryGuard::spilled,
+        )
+        .map(|(guard, _)| guard)
+    }
+
+    /// Reads the entry of the key with `read`, or the value from the spill store
+    /// with `read_spilled`, and records the read op.
+    fn do_get_with_hash<Q, T>(
+        &self,
+        key: &Q,
+        hash: u64,
+        read: impl FnOnce(&Arc<ValueEntry<K, V>>) -> T,
+        read_spilled: impl FnOnce(V) -> T,
+    ) -> Option<(T, Option<RefreshKey<K, V>>)>
     where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
@@ -136,38 +213,248 @@ where
         match self.inner.get_key_value(key) {
             None => {
                 record(ReadOp::Miss(hash));
-                None
+                // Consult the spill store, if any, before reporting the absence.
+                let maybe_v = self
+                    .inner
+                    .spill
+                    .as_ref()
+                    .and_then(|spill| spill.get(key, hash));
+                if maybe_v.is_some() {
+                    self.inner.record_stats(StatsCounter::record_hit);
+                } else {
+                    self.inner.record_stats(StatsCounter::record_miss);
+                }
+                maybe_v.map(|v| (read_spilled(v), None))
             }
             Some((arc_key, entry)) => {
-                let i = &self.inner;
-                let (ttl, tti, va) = (&i.time_to_live(), &i.time_to_idle(), &i.valid_after());
-                let now = i.current_time_from_expiration_clock();
+                let now = self.inner.current_time_from_expiration_clock();
 
-                if is_expired_entry_wo(ttl, va, &entry, now)
-                    || is_expired_entry_ao(tti, va, &entry, now)
-                    || self.inner.is_invalidated_entry(&arc_key, &entry)
-                {
+                if !self.inner.is_valid_entry(&arc_key, &entry, now) {
                     // Expired or invalidated entry. Record this access as a cache miss
                     // rather than a hit.
                     record(ReadOp::Miss(hash));
+                    self.inner.record_stats(StatsCounter::record_miss);
                     None
                 } else {
                     // Valid entry.
-                    let v = entry.value.clone();
+                    self.inner.expire_after_read(&arc_key, &entry, now);
+                    let v = read(&entry);
+                    let refresh_key = if self.inner.is_due_for_refresh(&entry, now) {
+                        Some((arc_key, Arc::clone(&entry)))
+                    } else {
+                        None
+                    };
                     record(ReadOp::Hit(hash, entry, now));
-                    Some(v)
+                    self.inner.record_stats(StatsCounter::record_hit);
+                    Some((v, refresh_key))
+                }
+            }
+        }
+    }
+
+    /// Resets the idle timer (and the time-to-live timer if `reset_ttl` is `true`)
+    /// of the entry without reading the value. Returns `false` if the key is not
+    /// present, or the entry has been expired or invalidated.
+    pub(crate) fn touch<Q>(&self, key: &Q, hash: u64, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (arc_key, mut entry) = match self.inner.get_key_value(key) {
+            Some(kv) => kv,
+            None => return false,
+        };
+        let now = self.inner.current_time_from_expiration_clock();
+        if !self.inner.is_valid_entry(&arc_key, &entry, now) {
+            return false;
+        }
+
+        // Update the timestamps right away rather than when the read op is applied,
+        // so that the entry will not expire in the meantime.
+        entry.set_last_accessed(now);
+        entry.set_last_read(now);
+        if reset_ttl {
+            entry.set_last_modified(now);
+        }
+        self.inner.expire_after_read(&arc_key, &entry, now);
+
+        let op = if reset_ttl {
+            ReadOp::TouchTtl(hash, arc_key, entry, now)
+        } else {
+            ReadOp::Hit(hash, entry, now)
+        };
+        self.record_read_op(op)
+            .expect("Failed to record a touch op");
+        true
+    }
+
+    /// Applies the write ops buffered before the call to the cache policy.
+    pub(crate) fn apply_pending_writes(&self) {
+        let (_reads, writes) = self.inner.pending_op_counts();
+        self.inner.apply_pending_ops(0, writes);
+    }
+
+    /// Returns a snapshot of the keys (and their hashes) of the admitted entries.
+    pub(crate) fn key_hashes(&self) -> Vec<KeyHash<K>> {
+        self.inner.key_hashes()
+    }
+
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Unlike get_with_hash, do not record a read op as this is not an access.
+        match self.inner.get_key_value(key) {
+            None => self
+                .inner
+                .spill
+                .as_ref()
+                .map(|spill| spill.contains_key(key, self.inner.hash(key)))
+                .unwrap_or_default(),
+            Some((arc_key, entry)) => {
+                let now = self.inner.current_time_from_expiration_clock();
+                self.inner.is_valid_entry(&arc_key, &entry, now)
+            }
+        }
+    }
+
+    /// Returns `true` if the hash table has an entry for the key, regardless of
+    /// whether it has been expired or invalidated.
+    pub(crate) fn has_entry<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.get_key_value(key).is_some()
+    }
+
+    /// Returns a _clone_ of the value without recording a read op.
+    pub(crate) fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.inner.get_key_value(key) {
+            None => self
+                .inner
+                .spill
+                .as_ref()
+                .and_then(|spill| spill.get(key, self.inner.hash(key))),
+            Some((arc_key, entry)) => {
+                let now = self.inner.current_time_from_expiration_clock();
+                if self.inner.is_valid_entry(&arc_key, &entry, now) {
+                    Some(entry.value.clone())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Returns a snapshot of the admitted entries that have been neither expired
+    /// nor invalidated, without recording read ops.
+    pub(crate) fn to_hash_map(&self) -> HashMap<Arc<K>, V> {
+        let now = self.inner.current_time_from_expiration_clock();
+        self.inner
+            .key_hashes()
+            .into_iter()
+            .filter_map(|kh| {
+                let (key, entry) = self.inner.get_key_value(&kh.key)?;
+                if self.inner.is_valid_entry(&key, &entry, now) {
+                    Some((key, entry.value.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the numbers of the valid admitted entries bucketed by their ages.
+    /// See `age_bucket_index` for the buckets.
+    pub(crate) fn age_histogram(&self) -> Vec<(Duration, usize)> {
+        let now = self.inner.current_time_from_expiration_clock();
+        let mut counts = Vec::new();
+        for kh in self.inner.key_hashes() {
+            let (key, entry) = match self.inner.get_key_value(&kh.key) {
+                Some(kv) => kv,
+                None => continue,
+            };
+            if !self.inner.is_valid_entry(&key, &entry, now) {
+                continue;
+            }
+            if let Some(ts) = entry.last_modified() {
+                let index = age_bucket_index(now.saturating_duration_since(ts));
+                if counts.len() <= index {
+                    counts.resize(index + 1, 0);
                 }
+                counts[index] += 1;
             }
         }
+
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (age_bucket_bound(i), count))
+            .collect()
     }
 
     #[inline]
-    pub(crate) fn remove<Q>(&self, key: &Q) -> Option<Arc<ValueEntry<K, V>>>
+    pub(crate) fn remove<Q>(&self, key: &Q) -> Option<KvEntry<K, V>>
     where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.inner.remove(key)
+        let remove = || {
+            self.inner
+                .remove_entry(key)
+                .map(|(key, entry)| KvEntry::new(key, entry))
+        };
+        match &self.inner.spill {
+            None => remove(),
+            Some(spill) => {
+                // Remove the key from the cache and the spill store under the lock,
+                // so that the entry being spilled by the housekeeper is removed too.
+                let hash = self.inner.hash(key);
+                let _guard = spill.lock(hash);
+                let entry = remove();
+                spill.remove(key, hash);
+                entry
+            }
+        }
+    }
+
+    /// Removes the entry of the key if its value satisfies the condition.
+    pub(crate) fn remove_if<Q>(
+        &self,
+        key: &Q,
+        mut condition: impl FnMut(&V) -> bool,
+    ) -> Option<KvEntry<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut removed_key = None;
+        let spill = self
+            .inner
+            .spill
+            .as_ref()
+            .map(|spill| (spill, self.inner.hash(key)));
+        let _guard = spill.map(|(spill, hash)| spill.lock(hash));
+        let entry = self.inner.cache.remove_if(key, |k, entry| {
+            removed_key = Some(Arc::clone(k));
+            condition(&entry.value)
+        })?;
+        if let Some((spill, hash)) = spill {
+            spill.remove(key, hash);
+        }
+        removed_key.map(|key| KvEntry::new(key, entry))
+    }
+
+    /// Returns `true` if the entry has been neither expired nor invalidated.
+    pub(crate) fn is_valid_entry(&self, kv: &KvEntry<K, V>) -> bool {
+        let now = self.inner.current_time_from_expiration_clock();
+        self.inner.is_valid_entry(&kv.key, &kv.entry, now)
     }
 
     #[inline]
@@ -187,6 +474,27 @@ where
     pub(crate) fn invalidate_all(&self) {
         let now = self.inner.current_time_from_expiration_clock();
         self.inner.set_valid_after(now);
+        self.clear_spill();
+    }
+
+    pub(crate) fn clear_spill(&self) {
+        if let Some(spill) = &self.inner.spill {
+            spill.clear();
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.invalidate_all();
+        self.inner.reset_frequency();
+    }
+
+    pub(crate) fn record_access<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash(key);
+        self.inner.record_access(hash);
     }
 
     pub(crate) fn invalidate_entries_if(
@@ -197,10 +505,87 @@ where
         self.inner.register_invalidation_predicate(predicate, now)
     }
 
+    /// Sets a predicate that the housekeeper applies to all the entries once per
+    /// `interval`. The invalidation closures must be enabled.
+    pub(crate) fn set_sweep_predicate(&self, predicate: PredicateFun<K, V>, interval: Duration) {
+        *self.inner.sweep_predicate.lock() = Some(SweepPredicate {
+            predicate,
+            interval,
+            id: None,
+            last_registered: None,
+        });
+    }
+
+    /// Sets the closure that the housekeeper calls to remove the expired errors
+    /// remembered by `cache_errors_for`.
+    pub(crate) fn set_expired_error_remover(&self, remover: ExpiredErrorRemover) {
+        *self.inner.expired_error_remover.write() = Some(remover);
+    }
+
+    /// Returns the completion of the invalidation by the predicate, or `None` if
+    /// the invalidation has already been completed.
+    pub(crate) fn invalidation_completion(
+        &self,
+        id: &PredicateId,
+    ) -> Option<Arc<PredicateCompletion>> {
+        self.inner
+            .invalidator
+            .read()
+            .as_ref()
+            .and_then(|inv| inv.completion(id))
+    }
+
+    pub(crate) fn cancel_invalidation(&self, id: &PredicateId) -> bool {
+        self.inner
+            .invalidator
+            .read()
+            .as_ref()
+            .map(|inv| inv.cancel_predicate(id))
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of the entries removed by the invalidation task for the
+    /// predicate, or `None` if the invalidation has already been completed.
+    pub(crate) fn predicate_removed_count(&self, id: &PredicateId) -> Option<u64> {
+        self.invalidation_completion(id)
+            .map(|completion| completion.removed_count())
+    }
+
     pub(crate) fn max_capacity(&self) -> usize {
         self.inner.max_capacity()
     }
 
+    pub(crate) fn set_max_capacity(&self, max_capacity: usize) {
+        self.inner.set_max_capacity(max_capacity)
+    }
+
+    pub(crate) fn entry_count(&self) -> usize {
+        match &self.inner.entry_count {
+            Some(count) => count.load(Ordering::Acquire),
+            None => self.inner.len(),
+        }
+    }
+
+    pub(crate) fn weighted_size(&self) -> u64 {
+        self.inner.weighted_size()
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.inner.stats()
+    }
+
+    pub(crate) fn record_load_success(&self) {
+        self.inner.record_stats(StatsCounter::record_load_success);
+    }
+
+    pub(crate) fn record_load_failure(&self) {
+        self.inner.record_stats(StatsCounter::record_load_failure);
+    }
+
+    pub(crate) fn current_time(&self) -> Instant {
+        self.inner.current_time_from_expiration_clock()
+    }
+
     pub(crate) fn time_to_live(&self) -> Option<Duration> {
         self.inner.time_to_live()
     }
@@ -208,6 +593,18 @@ where
     pub(crate) fn time_to_idle(&self) -> Option<Duration> {
         self.inner.time_to_idle()
     }
+
+    pub(crate) fn initial_capacity(&self) -> Option<usize> {
+        self.inner.initial_capacity
+    }
+
+    pub(crate) fn eviction_policy(&self) -> EvictionPolicy {
+        self.inner.eviction_policy
+    }
+
+    pub(crate) fn invalidation_closures_enabled(&self) -> bool {
+        self.inner.invalidator_enabled
+    }
 }
 
 //
@@ -217,7 +614,7 @@ impl<K, V, S> BaseCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
 {
     #[inline]
     fn record_read_op(&self, op: ReadOp<K, V>) -> Result<(), TrySendError<ReadOp<K, V>>> {
@@ -231,7 +628,54 @@ where
     }
 
     #[inline]
-    pub(crate) fn do_insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) -> WriteOp<K, V> {
+    pub(crate) fn do_insert_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_live: Option<Duration>,
+    ) -> WriteOp<K, V> {
+        self.do_insert_with_hash_and_replaced(key, hash, value, time_to_live)
+            .0
+    }
+
+    /// Inserts the value, and reports whether it replaced an existing entry or it
+    /// is predicted to be admitted by the housekeeper.
+    ///
+    /// The prediction locks the deques, so it may wait for the housekeeper.
+    pub(crate) fn do_insert_with_report(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> (WriteOp<K, V>, InsertReport) {
+        let (op, replaced) = self.do_insert_with_hash_and_replaced(key, hash, value, None);
+        let report = match &op {
+            WriteOp::Upsert(_, entry, _) if self.inner.exceeds_max_entry_weight(entry.weight()) => {
+                InsertReport::Rejected
+            }
+            _ if replaced => InsertReport::Replaced,
+            WriteOp::Upsert(_, entry, _) if self.inner.predict_admission(hash, entry.weight()) => {
+                InsertReport::Admitted
+            }
+            _ => InsertReport::Rejected,
+        };
+        (op, report)
+    }
+
+    /// Returns the write op and whether the value replaced an existing entry in the
+    /// hash table.
+    fn do_insert_with_hash_and_replaced(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_live: Option<Duration>,
+    ) -> (WriteOp<K, V>, bool) {
+        let weight = self.inner.weigh(&key, &value);
+        if time_to_live.is_some() {
+            self.inner.enable_per_entry_ttl();
+        }
         let op_cnt1 = Rc::new(AtomicU8::new(0));
         let op_cnt2 = Rc::clone(&op_cnt1);
         let mut op1 = None;
@@ -249,50 +693,279 @@ where
             Arc::clone(&key),
             // on_insert
             || {
-                let entry = Arc::new(ValueEntry::new(value.clone()));
+                let ttl = time_to_live.or_else(|| self.inner.expire_after_create(&key, &value));
+                let entry = Arc::new(ValueEntry::new(value.clone(), weight, ttl));
                 let cnt = op_cnt1.fetch_add(1, Ordering::Relaxed);
                 op1 = Some((
                     cnt,
-                    WriteOp::Upsert(KeyHash::new(Arc::clone(&key), hash), Arc::clone(&entry)),
+                    WriteOp::Upsert(
+                        KeyHash::new(Arc::clone(&key), hash),
+                        Arc::clone(&entry),
+                        None,
+                    ),
                 ));
                 entry
             },
             // on_modify
-            |_k, old_entry| {
-                let entry = Arc::new(ValueEntry::new_with(value.clone(), old_entry));
+            |k, old_entry| {
+                let ttl = time_to_live
+                    .or_else(|| self.inner.expire_after_update(&key, &value, old_entry));
+                let entry = Arc::new(ValueEntry::new_with(value.clone(), weight, ttl, old_entry));
+                if self.inner.time_to_idle_reads_only {
+                    let now = self.inner.current_time_from_expiration_clock();
+                    if !self.inner.is_valid_entry(k, old_entry, now) {
+                        // The value replaces an expired one, so it is a new value to
+                        // the readers.
+                        entry.restart_idle_timer();
+                    }
+                }
                 let cnt = op_cnt2.fetch_add(1, Ordering::Relaxed);
+                // Keep the replaced entry only when it needs to be notified.
+                let replaced = if self.inner.has_eviction_listener() {
+                    Some(Arc::clone(old_entry))
+                } else {
+                    None
+                };
                 op2 = Some((
                     cnt,
                     Arc::clone(old_entry),
-                    WriteOp::Upsert(KeyHash::new(Arc::clone(&key), hash), Arc::clone(&entry)),
+                    WriteOp::Upsert(
+                        KeyHash::new(Arc::clone(&key), hash),
+                        Arc::clone(&entry),
+                        replaced,
+                    ),
                 ));
                 entry
             },
         );
 
         match (op1, op2) {
-            (Some((_cnt, ins_op)), None) => ins_op,
+            (Some((_cnt, ins_op)), None) => (ins_op, false),
             (None, Some((_cnt, old_entry, upd_op))) => {
                 old_entry.unset_q_nodes();
-                upd_op
+                (upd_op, true)
             }
             (Some((cnt1, ins_op)), Some((cnt2, old_entry, upd_op))) => {
                 if cnt1 > cnt2 {
-                    ins_op
+                    (ins_op, false)
                 } else {
                     old_entry.unset_q_nodes();
-                    upd_op
+                    (upd_op, true)
                 }
             }
             (None, None) => unreachable!(),
         }
     }
 
+    /// Inserts the value only when the key does not have a valid entry. Returns
+    /// the value back if it has one.
+    ///
+    /// The check and the insertion are done atomically by the hash table, so only
+    /// one of the concurrent calls on the same key will succeed.
+    pub(crate) fn do_insert_if_absent_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<WriteOp<K, V>, V> {
+        let weight = self.inner.weigh(&key, &value);
+        let now = self.inner.current_time_from_expiration_clock();
+        let op_cnt1 = Rc::new(AtomicU8::new(0));
+        let op_cnt2 = Rc::clone(&op_cnt1);
+        let mut op1 = None;
+        let mut op2 = None;
+
+        // See the comment in do_insert_with_hash for the serial numbers.
+        self.inner.cache.insert_with_or_modify(
+            Arc::clone(&key),
+            // on_insert
+            || {
+                let ttl = self.inner.expire_after_create(&key, &value);
+                let entry = Arc::new(ValueEntry::new(value.clone(), weight, ttl));
+                let cnt = op_cnt1.fetch_add(1, Ordering::Relaxed);
+                op1 = Some((
+                    cnt,
+                    WriteOp::Upsert(
+                        KeyHash::new(Arc::clone(&key), hash),
+                        Arc::clone(&entry),
+                        None,
+                    ),
+                ));
+                entry
+            },
+            // on_modify
+            |k, old_entry| {
+                let cnt = op_cnt2.fetch_add(1, Ordering::Relaxed);
+                if self.inner.is_valid_entry(k, old_entry, now) {
+                    // A live entry exists. Keep it as is.
+                    op2 = Some((cnt, None));
+                    return Arc::clone(old_entry);
+                }
+                // The old entry has been expired or invalidated. Replace it.
+                let ttl = self.inner.expire_after_create(&key, &value);
+                let entry = Arc::new(ValueEntry::new_with(value.clone(), weight, ttl, old_entry));
+                entry.restart_idle_timer();
+                let replaced = if self.inner.has_eviction_listener() {
+                    Some(Arc::clone(old_entry))
+                } else {
+                    None
+                };
+                op2 = Some((
+                    cnt,
+                    Some((
+                        Arc::clone(old_entry),
+                        WriteOp::Upsert(
+                            KeyHash::new(Arc::clone(&key), hash),
+                            Arc::clone(&entry),
+                            replaced,
+                        ),
+                    )),
+                ));
+                entry
+            },
+        );
+
+        let op2 = match (op1, op2) {
+            (Some((_cnt, ins_op)), None) => return Ok(ins_op),
+            (None, Some((_cnt, upd))) => upd,
+            (Some((cnt1, ins_op)), Some((cnt2, upd))) => {
+                if cnt1 > cnt2 {
+                    return Ok(ins_op);
+                }
+                upd
+            }
+            (None, None) => unreachable!(),
+        };
+        match op2 {
+            Some((old_entry, upd_op)) => {
+                old_entry.unset_q_nodes();
+                Ok(upd_op)
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Replaces the value only when the key has a valid entry. Returns the
+    /// previous value and the write op, or `None` if the key does not have a valid
+    /// entry. In that case, the entry is left as is.
+    ///
+    /// The check and the replacement are done atomically by the hash table, so a
+    /// concurrent removal of the entry will never be overwritten.
+    pub(crate) fn do_replace_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Option<(V, WriteOp<K, V>)> {
+        self.do_replace_with_hash_if(key, hash, value, |_| true, |old| old.value.clone())
+    }
+
+    /// Replaces the value only when the key still has the valid `entry`, e.g. the
+    /// one a background refresh has started from. Returns the write op, or `None`
+    /// if the entry has been updated, removed, expired or invalidated since then.
+    pub(crate) fn do_replace_entry_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        entry: &Arc<ValueEntry<K, V>>,
+    ) -> Option<WriteOp<K, V>> {
+        let is_replaceable = |old_entry: &Arc<ValueEntry<K, V>>| Arc::ptr_eq(old_entry, entry);
+        self.do_replace_with_hash_if(key, hash, value, is_replaceable, |_| ())
+            .map(|((), op)| op)
+    }
+
+    fn do_replace_with_hash_if<T>(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        is_replaceable: impl Fn(&Arc<ValueEntry<K, V>>) -> bool,
+        read_old: impl FnOnce(&ValueEntry<K, V>) -> T,
+    ) -> Option<(T, WriteOp<K, V>)> {
+        let i = &self.inner;
+        let weight = i.weigh(&key, &value);
+        let now = i.current_time_from_expiration_clock();
+        let mut op = None;
+
+        // The on_modify closure may be called more than once when it conflicts with
+        // other concurrent hash table operations. The last call is the one that
+        // actually has replaced the entry, so just overwrite the op on every call.
+        let old_entry = i.cache.modify(Arc::clone(&key), |k, old_entry| {
+            if !is_replaceable(old_entry) || !i.is_valid_entry(k, old_entry, now) {
+                op = None;
+                return Arc::clone(old_entry);
+            }
+            let ttl = i.expire_after_update(k, &value, old_entry);
+            let entry = Arc::new(ValueEntry::new_with(value.clone(), weight, ttl, old_entry));
+            // Keep the replaced entry only when it needs to be notified.
+            let replaced = if i.has_eviction_listener() {
+                Some(Arc::clone(old_entry))
+            } else {
+                None
+            };
+            op = Some(WriteOp::Upsert(
+                KeyHash::new(Arc::clone(&key), hash),
+                Arc::clone(&entry),
+                replaced,
+            ));
+            entry
+        })?;
+
+        op.map(|op| {
+            old_entry.unset_q_nodes();
+            (read_old(&old_entry), op)
+        })
+    }
+
+    /// Replaces the value of the key with the one modified by the closure.
+    ///
+    /// Returns `None` if the key does not exist or its entry has been expired or
+    /// invalidated. In that case, the entry is left as is.
+    pub(crate) fn do_modify_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        f: impl Fn(&K, &mut V),
+    ) -> Option<WriteOp<K, V>> {
+        let i = &self.inner;
+        let now = i.current_time_from_expiration_clock();
+        let mut op = None;
+
+        // The on_modify closure may be called more than once when it conflicts with
+        // other concurrent hash table operations. The last call is the one that
+        // actually has replaced the entry, so just overwrite the op on every call.
+        let old_entry = i.cache.modify(Arc::clone(&key), |k, old_entry| {
+            if !i.is_valid_entry(k, old_entry, now) {
+                op = None;
+                return Arc::clone(old_entry);
+            }
+            let mut value = old_entry.value.clone();
+            f(k, &mut value);
+            let weight = i.weigh(k, &value);
+            let ttl = i
+                .expire_after_update(k, &value, old_entry)
+                .or_else(|| old_entry.time_to_live());
+            let entry = Arc::new(ValueEntry::new_with(value, weight, ttl, old_entry));
+            op = Some(WriteOp::Upsert(
+                KeyHash::new(Arc::clone(&key), hash),
+                Arc::clone(&entry),
+                None,
+            ));
+            entry
+        })?;
+
+        if op.is_some() {
+            old_entry.unset_q_nodes();
+        }
+        op
+    }
+
     #[inline]
     fn apply_reads_if_needed(&self) {
         let len = self.read_op_ch.len();
 
-        if Self::should_apply_reads(len) {
+        if self.should_apply_reads(len) {
             if let Some(h) = &self.housekeeper {
                 h.try_schedule_sync();
             }
@@ -300,8 +973,8 @@ where
     }
 
     #[inline]
-    fn should_apply_reads(ch_len: usize) -> bool {
-        ch_len >= READ_LOG_FLUSH_POINT
+    fn should_apply_reads(&self, ch_len: usize) -> bool {
+        ch_len >= self.inner.read_log_flush_point
     }
 
     #[inline]
@@ -317,8 +990,8 @@ where
 impl<K, V, S> BaseCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
 {
     pub(crate) fn table_size(&self) -> usize {
         self.inner.len()
@@ -328,6 +1001,25 @@ where
         self.inner.invalidation_predicate_count()
     }
 
+    #[cfg(test)]
+    pub(crate) fn read_op_ch_len(&self) -> usize {
+        self.read_op_ch.len()
+    }
+
+    #[cfg(all(test, feature = "future"))]
+    pub(crate) fn write_op_ch_len(&self) -> usize {
+        self.write_op_ch.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_invalidate_all_pending(&self) -> bool {
+        self.inner.has_valid_after()
+    }
+
+    pub(crate) fn timer_count(&self) -> usize {
+        self.inner.deques.lock().timer_wheel.len()
+    }
+
     pub(crate) fn reconfigure_for_testing(&mut self) {
         // Stop the housekeeping job that may cause sync() method to return earlier.
         if let Some(housekeeper) = &self.housekeeper {
@@ -348,19 +1040,86 @@ type CacheStore<K, V, S> = moka_cht::SegmentedHashMap<Arc<K>, Arc<ValueEntry<K,
 
 type CacheEntry<K, V> = (Arc<K>, Arc<ValueEntry<K, V>>);
 
+/// A predicate registered by the housekeeper periodically. It is registered again
+/// when the interval has passed since the last registration, and the last one has
+/// been applied to all the entries.
+struct SweepPredicate<K, V> {
+    predicate: PredicateFun<K, V>,
+    interval: Duration,
+    // The ID of the current registration.
+    id: Option<PredicateId>,
+    last_registered: Option<Instant>,
+}
+
+/// The entries selected to be evicted to make room for a candidate.
+struct Victims<K> {
+    keys: Vec<Arc<K>>,
+    // The total policy weight of the victims.
+    weight: u64,
+    // The total access frequency of the victims.
+    freq: u32,
+}
+
 pub(crate) struct Inner<K, V, S> {
-    max_capacity: usize,
+    // This can be updated by `set_max_capacity`.
+    max_capacity: AtomicUsize,
+    // The max weight of an entry. A heavier entry is never admitted.
+    max_entry_weight: Option<u32>,
+    // The initial capacity set to the builder.
+    initial_capacity: Option<usize>,
     cache: CacheStore<K, V, S>,
     build_hasher: S,
+    spill: Option<Spill<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    eviction_listener: Option<EvictionListener<K, V>>,
+    async_notifier: Option<AsyncNotifier<K, V>>,
+    // The notifications waiting to be passed to the async eviction listener. The
+    // evicted values are kept alive until the listener futures complete.
+    pending_notifications: Mutex<Vec<(Arc<K>, V, RemovalCause)>>,
+    // The sum of the weights of the admitted entries. This is only updated while
+    // holding the lock of the deques.
+    weighted_size: Mutex<u64>,
+    // The number of the admitted entries, maintained only when the exact entry
+    // count is enabled. This is only updated while holding the lock of the deques.
+    entry_count: Option<AtomicUsize>,
+    // If `true`, new entries are always admitted regardless of their frequency.
+    admit_on_insert: bool,
+    // The max number of the entries to evict in a housekeeping pass.
+    eviction_batch_size: usize,
+    // If `true`, the frequency sketch was sized by the builder, so it is not
+    // resized when the max capacity grows.
+    fixed_sketch_capacity: bool,
     deques: Mutex<Deques<K>>,
     frequency_sketch: RwLock<FrequencySketch>,
     read_op_ch: Receiver<ReadOp<K, V>>,
     write_op_ch: Receiver<WriteOp<K, V>>,
+    read_log_flush_point: usize,
     time_to_live: Option<Duration>,
     time_to_idle: Option<Duration>,
+    time_to_idle_reads_only: bool,
+    refresh_after_write: Option<Duration>,
+    eviction_policy: EvictionPolicy,
+    maintenance_listener: Option<MaintenanceListener>,
+    // The number of the entries evicted by the current housekeeping pass. Only
+    // counted when the maintenance listener is set.
+    evicted_in_pass: AtomicUsize,
+    expiry: Option<Arc<dyn Expiry<K, V>>>,
+    time_source: Option<Arc<dyn TimeSource>>,
+    // The instant that the zero of the time source corresponds to.
+    time_origin: Instant,
+    stats: Option<StatsCounter>,
+    has_per_entry_ttl: AtomicBool,
     valid_after: AtomicInstant,
     invalidator_enabled: bool,
+    max_pending_invalidation_closures: Option<usize>,
     invalidator: RwLock<Option<Invalidator<K, V, S>>>,
+    // Keeps removing the entries that become invalid by themselves, e.g. the dead
+    // weak references of a `WeakValueCache`.
+    sweep_predicate: Mutex<Option<SweepPredicate<K, V>>>,
+    // Set by the cache when `cache_errors_for` is enabled, so that the expired
+    // errors are removed from its value initializer even when the keys are not
+    // requested again.
+    expired_error_remover: RwLock<Option<ExpiredErrorRemover>>,
     has_expiration_clock: AtomicBool,
     expiration_clock: RwLock<Option<Clock>>,
 }
@@ -369,59 +1128,117 @@ pub(crate) struct Inner<K, V, S> {
 impl<K, V, S> Inner<K, V, S>
 where
     K: Hash + Eq,
-    S: BuildHasher + Clone,
+    S: BuildHasher,
 {
-    // Disable a Clippy warning for having more than seven arguments.
-    // https://rust-lang.github.io/rust-clippy/master/index.html#too_many_arguments
-    #[allow(clippy::too_many_arguments)]
     fn new(
         max_capacity: usize,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
+        hasher_factory: impl Fn() -> S,
+        async_notifier: Option<AsyncNotifier<K, V>>,
         read_op_ch: Receiver<ReadOp<K, V>>,
         write_op_ch: Receiver<WriteOp<K, V>>,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        read_log_flush_point: usize,
+        config: CacheConfig<K, V>,
     ) -> Self {
-        let initial_capacity = initial_capacity
+        let CacheConfig {
+            initial_capacity,
+            spill_store,
+            weigher,
+            eviction_listener,
+            time_to_live,
+            time_to_idle,
+            time_to_idle_reads_only,
+            refresh_after_write,
+            record_stats,
+            eviction_policy,
+            maintenance_listener,
+            expiry,
+            time_source,
+            invalidator_enabled,
+            max_pending_invalidation_closures,
+            max_entry_weight,
+            exact_entry_count,
+            admit_on_insert,
+            eviction_batch_size,
+            frequency_sketch_capacity,
+            ..
+        } = config;
+        let table_capacity = initial_capacity
             .map(|cap| cap + WRITE_LOG_SIZE * 4)
             .unwrap_or_default();
         let num_segments = 64;
         let cache = moka_cht::SegmentedHashMap::with_num_segments_capacity_and_hasher(
             num_segments,
-            initial_capacity,
-            build_hasher.clone(),
+            table_capacity,
+            hasher_factory(),
         );
 
-        // Ensure skt_capacity fits in a range of `128u32..=u32::MAX`.
-        let skt_capacity = max_capacity
-            .try_into() // Convert to u32.
-            .unwrap_or(u32::MAX)
-            .max(128);
+        // The frequency sketch is not used by the LRU policy. Keep it minimal.
+        let skt_capacity = if eviction_policy == EvictionPolicy::TinyLfu {
+            sketch_capacity(frequency_sketch_capacity.unwrap_or(max_capacity))
+        } else {
+            0
+        };
         let frequency_sketch = FrequencySketch::with_capacity(skt_capacity);
+        let spill = spill_store.map(Spill::new);
 
         Self {
-            max_capacity,
+            max_capacity: AtomicUsize::new(max_capacity),
+            initial_capacity,
             cache,
-            build_hasher,
+            build_hasher: hasher_factory(),
+            spill,
+            weigher,
+            eviction_listener,
+            async_notifier,
+            pending_notifications: Mutex::new(Vec::new()),
+            weighted_size: Mutex::new(0),
+            entry_count: if exact_entry_count {
+                Some(AtomicUsize::new(0))
+            } else {
+                None
+            },
+            admit_on_insert,
+            eviction_batch_size: eviction_batch_size.unwrap_or(EVICTION_BATCH_SIZE),
+            fixed_sketch_capacity: frequency_sketch_capacity.is_some(),
             deques: Mutex::new(Deques::default()),
             frequency_sketch: RwLock::new(frequency_sketch),
             read_op_ch,
             write_op_ch,
+            read_log_flush_point,
             time_to_live,
             time_to_idle,
+            time_to_idle_reads_only,
+            refresh_after_write,
+            eviction_policy,
+            maintenance_listener,
+            evicted_in_pass: AtomicUsize::default(),
+            expiry,
+            time_source,
+            time_origin: Instant::now(),
+            stats: if record_stats {
+                Some(StatsCounter::default())
+            } else {
+                None
+            },
+            has_per_entry_ttl: AtomicBool::new(false),
             valid_after: AtomicInstant::default(),
             invalidator_enabled,
+            max_pending_invalidation_closures,
+            max_entry_weight,
             // When enabled, this field will be set later via the set_invalidator method.
             invalidator: RwLock::new(None),
+            sweep_predicate: Mutex::new(None),
+            expired_error_remover: RwLock::new(None),
             has_expiration_clock: AtomicBool::new(false),
             expiration_clock: RwLock::new(None),
         }
     }
 
     fn set_invalidator(&self, self_ref: &Arc<Self>) {
-        *self.invalidator.write() = Some(Invalidator::new(Arc::downgrade(&Arc::clone(self_ref))));
+        *self.invalidator.write() = Some(Invalidator::new(
+            Arc::downgrade(&Arc::clone(self_ref)),
+            self.max_pending_invalidation_closures,
+        ));
     }
 
     #[inline]
@@ -444,37 +1261,191 @@ where
         self.cache.get_key_value(key)
     }
 
+    fn key_hashes(&self) -> Vec<KeyHash<K>> {
+        let deqs = self.deques.lock();
+        let (window, probation, protected) = (&deqs.window, &deqs.probation, &deqs.protected);
+        window
+            .iter()
+            .chain(probation.iter())
+            .chain(protected.iter())
+            .map(|khd| KeyHash::new(Arc::clone(&khd.key), khd.hash))
+            .collect()
+    }
+
     #[inline]
-    fn remove<Q>(&self, key: &Q) -> Option<Arc<ValueEntry<K, V>>>
+    fn remove_entry<Q>(&self, key: &Q) -> Option<CacheEntry<K, V>>
     where
         Arc<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.cache.remove(key)
+        self.cache.remove_entry(key)
+    }
+
+    #[inline]
+    fn max_capacity(&self) -> usize {
+        self.max_capacity.load(Ordering::Acquire)
+    }
+
+    /// Updates the max capacity. When shrinking, the entries exceeding the new
+    /// capacity will be evicted by the next `sync`.
+    fn set_max_capacity(&self, max_capacity: usize) {
+        let old = self.max_capacity.swap(max_capacity, Ordering::AcqRel);
+        if self.eviction_policy == EvictionPolicy::TinyLfu
+            && !self.fixed_sketch_capacity
+            && max_capacity > old
+        {
+            // Grow the frequency sketch if the larger capacity needs a larger
+            // table. The frequencies recorded so far are lost in that case.
+            let sketch = FrequencySketch::with_capacity(sketch_capacity(max_capacity));
+            let mut freq = self.frequency_sketch.write();
+            if sketch.table_len() > freq.table_len() {
+                *freq = sketch;
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns the weight of the entry. Without a weigher, every entry weighs one.
+    #[inline]
+    fn weigh(&self, key: &K, value: &V) -> u32 {
+        self.weigher.as_ref().map(|w| w(key, value)).unwrap_or(1)
+    }
+
+    #[inline]
+    fn has_eviction_listener(&self) -> bool {
+        self.eviction_listener.is_some() || self.async_notifier.is_some()
+    }
+
+    #[inline]
+    pub(crate) fn weighted_size(&self) -> u64 {
+        *self.weighted_size.lock()
+    }
+
+    /// Clears the frequency sketch. The read ops that have been recorded but not
+    /// applied yet are discarded, so that they will not be counted in the cleared
+    /// sketch.
+    fn reset_frequency(&self) {
+        // Hold the lock to prevent the housekeeper from applying the read ops.
+        let _deqs = self.deques.lock();
+        let ch = &self.read_op_ch;
+        for _ in 0..ch.len() {
+            if ch.try_recv().is_err() {
+                break;
+            }
+        }
+        self.frequency_sketch.write().clear();
+    }
+
+    /// Increments the frequency of the key hash in the sketch. Unlike a read op,
+    /// this is applied right away and never discarded.
+    fn record_access(&self, hash: u64) {
+        if self.eviction_policy == EvictionPolicy::TinyLfu {
+            self.frequency_sketch.write().increment(hash);
+        }
+    }
+
+    #[inline]
+    fn is_due_for_refresh(&self, entry: &impl AccessTime, now: Instant) -> bool {
+        match (self.refresh_after_write, entry.last_modified()) {
+            (Some(period), Some(ts)) => ts + period <= now,
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn record_stats(&self, f: impl FnOnce(&StatsCounter)) {
+        if let Some(stats) = &self.stats {
+            f(stats);
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+            .as_ref()
+            .map(StatsCounter::snapshot)
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn time_to_live(&self) -> Option<Duration> {
+        self.time_to_live
+    }
+
+    #[inline]
+    fn time_to_idle(&self) -> Option<Duration> {
+        self.time_to_idle
+    }
+
+    #[inline]
+    fn has_per_entry_ttl(&self) -> bool {
+        self.has_per_entry_ttl.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn enable_per_entry_ttl(&self) {
+        if !self.has_per_entry_ttl() {
+            self.has_per_entry_ttl.store(true, Ordering::Release);
+        }
     }
 
-    fn max_capacity(&self) -> usize {
-        self.max_capacity
+    #[inline]
+    fn has_expiry(&self) -> bool {
+        self.time_to_live.is_some() || self.time_to_idle.is_some() || self.has_per_entry_ttl()
     }
 
+    /// Returns the time-to-live of a new entry calculated by the expiry policy.
     #[inline]
-    fn time_to_live(&self) -> Option<Duration> {
-        self.time_to_live
+    fn expire_after_create(&self, key: &K, value: &V) -> Option<Duration> {
+        let ttl = self.expiry.as_ref()?.expire_after_create(key, value);
+        if ttl.is_some() {
+            self.enable_per_entry_ttl();
+        }
+        ttl
     }
 
+    /// Returns the time-to-live of an updated entry calculated by the expiry
+    /// policy, or the one of the old entry if the policy does not change it.
     #[inline]
-    fn time_to_idle(&self) -> Option<Duration> {
-        self.time_to_idle
+    fn expire_after_update(
+        &self,
+        key: &K,
+        value: &V,
+        old_entry: &ValueEntry<K, V>,
+    ) -> Option<Duration> {
+        let ttl = self.expiry.as_ref()?.expire_after_update(key, value);
+        if ttl.is_some() {
+            self.enable_per_entry_ttl();
+        }
+        ttl.or_else(|| old_entry.time_to_live())
     }
 
+    /// Updates the time-to-live of a read entry by the expiry policy, so that it
+    /// will expire after the duration from now.
     #[inline]
-    fn has_expiry(&self) -> bool {
-        self.time_to_live.is_some() || self.time_to_idle.is_some()
+    fn expire_after_read(&self, key: &K, entry: &Arc<ValueEntry<K, V>>, now: Instant) {
+        let ttl = match &self.expiry {
+            Some(expiry) => expiry.expire_after_read(key, &entry.value),
+            None => return,
+        };
+        if let (Some(ttl), Some(last_modified)) = (ttl, entry.last_modified()) {
+            let elapsed = now.saturating_duration_since(last_modified);
+            entry.set_time_to_live(elapsed.checked_add(ttl));
+            self.enable_per_entry_ttl();
+        }
     }
 
     #[inline]
     fn is_write_order_queue_enabled(&self) -> bool {
-        self.time_to_live.is_some() || self.invalidator_enabled
+        self.time_to_live.is_some()
+            || self.invalidator_enabled
+            || self.has_per_entry_ttl()
+            // The access order queues are ordered by the last read times, so the
+            // write order queue is needed to find the entries written before
+            // `invalidate_all`.
+            || self.time_to_idle_reads_only
     }
 
     #[inline]
@@ -515,8 +1486,49 @@ where
         false
     }
 
+    #[inline]
+    fn is_valid_entry(&self, key: &Arc<K>, entry: &Arc<ValueEntry<K, V>>, now: Instant) -> bool {
+        // A zero-capacity cache never holds any entry. The entry is still in the
+        // hash map until the pending write op is applied.
+        if self.max_capacity() == 0 {
+            return false;
+        }
+        let (ttl, va) = (&self.time_to_live, &self.valid_after());
+        !(is_expired_entry_wo(&entry_ttl(ttl, entry), va, entry, now)
+            || self.is_idle_entry(va, entry, now)
+            || self.is_invalidated_entry(key, entry))
+    }
+
+    /// Returns `true` if the entry has been idle for the time-to-idle, or has not
+    /// been accessed since `valid_after`.
+    ///
+    /// When the cache is configured with `time_to_idle_reads_only`, the idle timer
+    /// is checked against the last read time rather than the last accessed time,
+    /// which is still updated by writes.
+    #[inline]
+    fn is_idle_entry(
+        &self,
+        valid_after: &Option<Instant>,
+        entry: &Arc<ValueEntry<K, V>>,
+        now: Instant,
+    ) -> bool {
+        if !self.time_to_idle_reads_only {
+            return is_expired_entry_ao(&self.time_to_idle, valid_after, entry, now);
+        }
+        if is_expired_entry_ao(&None, valid_after, entry, now) {
+            return true;
+        }
+        match (self.time_to_idle, entry.last_read()) {
+            (Some(tti), Some(ts)) => ts + tti <= now,
+            _ => false,
+        }
+    }
+
     #[inline]
     fn current_time_from_expiration_clock(&self) -> Instant {
+        if let Some(ts) = &self.time_source {
+            return self.time_origin + ts.now();
+        }
         if self.has_expiration_clock.load(Ordering::Relaxed) {
             self.expiration_clock
                 .read()
@@ -549,44 +1561,71 @@ where
 impl<K, V, S> InnerSync for Inner<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
 {
     fn sync(&self, max_repeats: usize) -> Option<SyncPace> {
-        const EVICTION_BATCH_SIZE: usize = 500;
         const INVALIDATION_BATCH_SIZE: usize = 500;
 
+        let started_at = self
+            .maintenance_listener
+            .as_ref()
+            .map(|_| std::time::Instant::now());
         let mut deqs = self.deques.lock();
         let mut calls = 0;
         let mut should_sync = true;
+        let (mut read_count, mut write_count) = (0, 0);
 
         while should_sync && calls <= max_repeats {
             let r_len = self.read_op_ch.len();
             if r_len > 0 {
                 self.apply_reads(&mut deqs, r_len);
+                read_count += r_len;
             }
 
             let w_len = self.write_op_ch.len();
             if w_len > 0 {
                 self.apply_writes(&mut deqs, w_len);
+                write_count += w_len;
             }
             calls += 1;
-            should_sync = self.read_op_ch.len() >= READ_LOG_FLUSH_POINT
+            should_sync = self.read_op_ch.len() >= self.read_log_flush_point
                 || self.write_op_ch.len() >= WRITE_LOG_FLUSH_POINT;
         }
 
         if self.has_expiry() || self.has_valid_after() {
-            self.evict(&mut deqs, EVICTION_BATCH_SIZE);
+            self.evict(&mut deqs, self.eviction_batch_size);
+        }
+
+        // Updates may have made the existing entries heavier.
+        if self.weighted_size() > self.max_capacity() as u64 {
+            self.evict_lru_entries(&mut deqs, self.eviction_batch_size);
         }
 
         if self.invalidator_enabled {
             if let Some(invalidator) = &*self.invalidator.read() {
+                self.register_sweep_predicate(invalidator);
                 if !invalidator.is_empty() && !invalidator.is_task_running() {
                     self.invalidate_entries(invalidator, &mut deqs, INVALIDATION_BATCH_SIZE);
                 }
             }
         }
 
+        if let Some(remover) = &*self.expired_error_remover.read() {
+            remover(self.current_time_from_expiration_clock());
+        }
+
+        if let (Some(listener), Some(started_at)) = (&self.maintenance_listener, started_at) {
+            let evicted_count = self.evicted_in_pass.swap(0, Ordering::Relaxed);
+            let report = MaintenanceReport::new(
+                read_count,
+                write_count,
+                evicted_count,
+                started_at.elapsed(),
+            );
+            listener(report);
+        }
+
         if should_sync {
             Some(SyncPace::Fast)
         } else if self.write_op_ch.len() <= WRITE_LOG_LOW_WATER_MARK {
@@ -604,21 +1643,88 @@ where
 impl<K, V, S> Inner<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
 {
+    /// Passes the pending notifications to the async eviction listener, and waits
+    /// for the listener futures to complete. The evicted values are dropped after
+    /// their futures complete.
+    ///
+    /// The futures hold the permits of the notifier while running, so at most
+    /// `eviction_concurrency` futures run concurrently, even when this method is
+    /// called by multiple tasks.
+    #[cfg(feature = "future")]
+    pub(crate) async fn notify_pending_async(&self) {
+        use futures_util::stream::{self, StreamExt};
+
+        let notifier = match &self.async_notifier {
+            Some(notifier) => notifier,
+            None => return,
+        };
+        let pending = std::mem::take(&mut *self.pending_notifications.lock());
+        stream::iter(pending)
+            .for_each_concurrent(None, |(key, value, cause)| async move {
+                let _permit = notifier.permits.acquire().await;
+                (notifier.listener)(key, value, cause).await;
+            })
+            .await;
+    }
+    /// Returns the numbers of the buffered read and write ops.
+    pub(crate) fn pending_op_counts(&self) -> (usize, usize) {
+        (self.read_op_ch.len(), self.write_op_ch.len())
+    }
+
+    /// Applies at most `max_reads` buffered reads and `max_writes` buffered writes
+    /// to the cache policy. Unlike `sync`, this does not run the other maintenance
+    /// tasks such as the eviction of the expired entries.
+    pub(crate) fn apply_pending_ops(&self, max_reads: usize, max_writes: usize) {
+        let mut deqs = self.deques.lock();
+        if max_reads > 0 {
+            self.apply_reads(&mut deqs, max_reads);
+        }
+        if max_writes > 0 {
+            self.apply_writes(&mut deqs, max_writes);
+        }
+    }
+
     fn apply_reads(&self, deqs: &mut Deques<K>, count: usize) {
         use ReadOp::*;
         let mut freq = self.frequency_sketch.write();
+        let is_tiny_lfu = self.eviction_policy == EvictionPolicy::TinyLfu;
         let ch = &self.read_op_ch;
         for _ in 0..count {
             match ch.try_recv() {
                 Ok(Hit(hash, mut entry, timestamp)) => {
-                    freq.increment(hash);
+                    if is_tiny_lfu {
+                        freq.increment(hash);
+                    }
                     entry.set_last_accessed(timestamp);
+                    entry.set_last_read(timestamp);
                     deqs.move_to_back_ao(&entry)
                 }
-                Ok(Miss(hash)) => freq.increment(hash),
+                Ok(TouchTtl(hash, key, mut entry, timestamp)) => {
+                    if is_tiny_lfu {
+                        freq.increment(hash);
+                    }
+                    entry.set_last_accessed(timestamp);
+                    entry.set_last_read(timestamp);
+                    deqs.move_to_back_ao(&entry);
+                    // The time-to-live has been reset, so the entry is now the
+                    // youngest one in the write order.
+                    deqs.move_to_back_wo(&entry);
+                    if entry.time_to_live().is_some() {
+                        if let Some(ttl) = entry_ttl(&self.time_to_live, &entry) {
+                            let deadline =
+                                timestamp.checked_add(ttl).map_or(u64::MAX, |d| d.as_u64());
+                            deqs.timer_wheel.schedule(key, deadline);
+                        }
+                    }
+                }
+                Ok(Miss(hash)) => {
+                    if is_tiny_lfu {
+                        freq.increment(hash);
+                    }
+                }
                 Err(_) => break,
             }
         }
@@ -632,8 +1738,18 @@ where
 
         for _ in 0..count {
             match ch.try_recv() {
-                Ok(Upsert(kh, entry)) => self.handle_upsert(kh, entry, ts, deqs, &freq),
-                Ok(Remove(entry)) => Self::handle_remove(deqs, entry),
+                Ok(Upsert(kh, entry, replaced)) => {
+                    if let Some(old_entry) = replaced {
+                        self.notify(&kh.key, &old_entry, RemovalCause::Replaced);
+                    }
+                    self.handle_upsert(kh, entry, ts, deqs, &freq)
+                }
+                Ok(Remove(KvEntry { key, entry })) => {
+                    self.notify(&key, &entry, RemovalCause::Explicit);
+                    self.handle_remove(deqs, &key, entry)
+                }
+                #[cfg(feature = "future")]
+                Ok(Notify(completion)) => completion.complete(),
                 Err(_) => break,
             };
         }
@@ -653,52 +1769,133 @@ where
 
         entry.set_last_accessed(timestamp);
         entry.set_last_modified(timestamp);
-        let last_accessed = entry.raw_last_accessed();
+        // Start the idle timer of a new value. An updated value keeps the timer of
+        // the older one.
+        let is_new_value = entry.last_read().is_none();
+        if is_new_value {
+            entry.set_last_read(timestamp);
+        }
+        // The access order queues are ordered by the times that reset the idle
+        // timer.
+        let last_accessed = if self.time_to_idle_reads_only {
+            entry.raw_last_read()
+        } else {
+            entry.raw_last_accessed()
+        };
+
+        // The cache has a newer value, so remove the spilled one if any.
+        if let Some(spill) = &self.spill {
+            let _guard = spill.lock(kh.hash);
+            spill.remove(&kh.key, kh.hash);
+        }
         let last_modified = entry.raw_last_modified();
 
+        if entry.time_to_live().is_some() {
+            // The entry has its own time-to-live, so its position in the write order
+            // queue does not tell when it will expire. Schedule a timer for it.
+            if let Some(ttl) = entry_ttl(&self.time_to_live, &entry) {
+                let deadline = timestamp.checked_add(ttl).map_or(u64::MAX, |d| d.as_u64());
+                deqs.timer_wheel.schedule(Arc::clone(&kh.key), deadline);
+            }
+        } else if self.has_per_entry_ttl() {
+            // The entry no longer has its own time-to-live.
+            deqs.timer_wheel.cancel(&kh.key);
+        }
+
         while tries < MAX_RETRY {
             tries += 1;
 
-            if entry.is_admitted() {
+            if self.exceeds_max_entry_weight(entry.weight()) {
+                // The candidate is heavier than the max entry weight. Never store it,
+                // even if it updates an admitted entry. Remove it from the cache
+                // (hash map) and from the deques.
+                if let Some(entry) = self.remove_for_size_eviction(&kh.key) {
+                    self.handle_size_eviction(&kh.key, &entry);
+                    self.handle_remove(deqs, &kh.key, entry);
+                }
+            } else if entry.is_admitted() {
                 // The entry has been already admitted, so treat this as an update.
-                deqs.move_to_back_ao(&entry);
-                deqs.move_to_back_wo(&entry);
-            } else if self.cache.len() <= self.max_capacity {
+                let old_weight = entry.swap_policy_weight(entry.weight());
+                self.update_weighted_size(old_weight, entry.weight());
+                if is_new_value || !self.time_to_idle_reads_only {
+                    deqs.move_to_back_ao(&entry);
+                }
+                if entry.write_order_q_node().is_some() {
+                    deqs.move_to_back_wo(&entry);
+                } else if self.is_write_order_queue_enabled() {
+                    // The entry was admitted before the write order queue was
+                    // enabled by a per-entry time-to-live.
+                    let kd = KeyDate::new(Arc::clone(&kh.key), Arc::clone(&last_modified));
+                    deqs.push_back_wo(kd, &entry);
+                }
+            } else if !self.is_current_entry(&kh.key, &entry) {
+                // The candidate has been invalidated or replaced by a newer value
+                // before this write op was applied. Do not admit it; the write op
+                // for the removal or the newer value will follow.
+            } else if self.max_capacity() == 0 || entry.weight() as u64 > self.max_capacity() as u64
+            {
+                // The cache has zero capacity, or the candidate alone is heavier
+                // than the whole cache. Never admit it. Remove it from the cache
+                // (hash map).
+                if let Some(entry) = self.remove_for_size_eviction(&kh.key) {
+                    self.handle_size_eviction(&kh.key, &entry);
+                }
+            } else if self.has_enough_capacity(entry.weight()) {
                 // There are some room in the cache. Add the candidate to the deques.
                 self.handle_admit(kh.clone(), &entry, last_accessed, last_modified, deqs);
             } else {
-                let victim = match Self::find_cache_victim(deqs, freq) {
-                    // Found a victim.
-                    Some(node) => node,
+                // Select the victims from the LRU end of the probation deque, until
+                // evicting them will make enough room for the candidate.
+                let victims = match self.find_cache_victims(deqs, entry.weight(), freq) {
+                    Some(victims) => victims,
                     // Not found a victim. This condition should be unreachable
                     // because there was no room in the cache. But rather than
                     // panicking here, admit the candidate as there might be some
                     // room in te cache now.
-                    None => {
+                    None if deqs.probation.len() == 0 => {
                         self.handle_admit(kh.clone(), &entry, last_accessed, last_modified, deqs);
                         done = true;
                         break;
                     }
+                    // Evicting all the entries cannot make enough room for the
+                    // candidate. (Some of them may have been removed but their
+                    // write ops have not been applied yet)
+                    None => {
+                        if let Some(entry) = self.remove_for_size_eviction(&kh.key) {
+                            self.handle_size_eviction(&kh.key, &entry);
+                        }
+                        done = true;
+                        break;
+                    }
                 };
 
-                if Self::admit(kh.hash, victim, freq) {
-                    // The candidate is admitted. Try to remove the victim from the
+                // The LRU policy always admits the candidate and evicts the least
+                // recently used entries. So does `admit_on_insert`.
+                if self.eviction_policy == EvictionPolicy::Lru
+                    || self.admit_on_insert
+                    || Self::admit(kh.hash, &victims, freq)
+                {
+                    // The candidate is admitted. Try to remove the victims from the
                     // cache (hash map).
-                    if let Some(vic_entry) = self.cache.remove(&victim.element.key) {
-                        // And then remove the victim from the deques.
-                        Self::handle_remove(deqs, vic_entry);
-                    } else {
-                        // Could not remove the victim from the cache. Skip this
-                        // victim node as its ValueEntry might have been
-                        // invalidated. Since the invalidated ValueEntry (which
-                        // should be still in the write op queue) has a pointer to
-                        // this node, we move the node to the back of the deque
-                        // instead of unlinking (dropping) it.
-                        let victim = NonNull::from(victim);
-                        unsafe { deqs.probation.move_to_back(victim) };
-
-                        continue; // Retry
+                    for vic_key in victims.keys {
+                        if let Some(vic_entry) = self.remove_for_size_eviction(&vic_key) {
+                            self.handle_size_eviction(&vic_key, &vic_entry);
+                            // And then remove the victim from the deques.
+                            self.handle_remove(deqs, &vic_key, vic_entry);
+                        }
+                        // Otherwise, the victim has been removed by others (e.g.
+                        // invalidated) after it was selected. Its write op (which
+                        // should be still in the write op queue) will remove it
+                        // from the deques.
+                    }
+
+                    if !self.has_enough_capacity(entry.weight()) {
+                        // Some of the victims could not be removed, so evicting them
+                        // did not make enough room for the candidate. Retry with the
+                        // next victims.
+                        continue;
                     }
+
                     // Add the candidate to the deques.
                     self.handle_admit(
                         kh.clone(),
@@ -709,7 +1906,9 @@ where
                     );
                 } else {
                     // The candidate is not admitted. Remove it from the cache (hash map).
-                    self.cache.remove(&Arc::clone(&kh.key));
+                    if let Some(entry) = self.remove_for_size_eviction(&kh.key) {
+                        self.handle_size_eviction(&kh.key, &entry);
+                    }
                 }
             }
             done = true;
@@ -718,29 +1917,245 @@ where
 
         if !done {
             // Too mary retries. Remove the candidate from the cache.
-            self.cache.remove(&Arc::clone(&kh.key));
+            if let Some(entry) = self.remove_for_size_eviction(&kh.key) {
+                self.handle_size_eviction(&kh.key, &entry);
+            }
+        }
+    }
+
+    #[inline]
+    fn is_current_entry(&self, key: &Arc<K>, entry: &Arc<ValueEntry<K, V>>) -> bool {
+        match self.cache.get(key) {
+            Some(current) => Arc::ptr_eq(&current, entry),
+            None => false,
+        }
+    }
+
+    #[inline]
+    fn exceeds_max_entry_weight(&self, weight: u32) -> bool {
+        matches!(self.max_entry_weight, Some(max) if weight > max)
+    }
+
+    #[inline]
+    fn has_enough_capacity(&self, candidate_weight: u32) -> bool {
+        self.weighted_size() + candidate_weight as u64 <= self.max_capacity() as u64
+    }
+
+    #[inline]
+    fn update_weighted_size(&self, old_weight: u32, new_weight: u32) {
+        let mut ws = self.weighted_size.lock();
+        *ws = (*ws + new_weight as u64).saturating_sub(old_weight as u64);
+    }
+
+    /// Updates the exact entry count (if enabled) when an entry has been admitted
+    /// to or removed from the cache policy.
+    #[inline]
+    fn update_entry_count(&self, is_admitted: bool) {
+        if let Some(count) = &self.entry_count {
+            if is_admitted {
+                count.fetch_add(1, Ordering::AcqRel);
+            } else {
+                count.fetch_sub(1, Ordering::AcqRel);
+            }
+        }
+    }
+
+    /// Removes the entry from the cache (hash map) to evict it by the
+    /// `max_capacity` constraint, and spills it to the spill store if any.
+    fn remove_for_size_eviction(&self, key: &Arc<K>) -> Option<Arc<ValueEntry<K, V>>> {
+        let spill = match &self.spill {
+            Some(spill) => spill,
+            None => return self.cache.remove(key),
+        };
+
+        // Hold the lock of the key until the entry is spilled, so that a concurrent
+        // removal of the key also removes the spilled entry.
+        let hash = self.hash(key);
+        let _guard = spill.lock(hash);
+        let entry = self.cache.remove(key)?;
+        let now = self.current_time_from_expiration_clock();
+        if self.is_valid_entry(key, &entry, now) {
+            spill.put(key, hash, &entry.value);
         }
+        Some(entry)
     }
 
+    /// Notifies the eviction listener of the entry removed by the `max_capacity`
+    /// constraint.
     #[inline]
-    fn find_cache_victim<'a>(
-        deqs: &'a Deques<K>,
-        _freq: &FrequencySketch,
-    ) -> Option<&'a DeqNode<KeyHashDate<K>>> {
-        // TODO: Check its frequency. If it is not very low, maybe we should
-        // check frequencies of next few others and pick from them.
-        deqs.probation.peek_front()
+    fn handle_size_eviction(&self, key: &Arc<K>, entry: &ValueEntry<K, V>) {
+        self.record_stats(StatsCounter::record_eviction);
+        self.record_eviction_in_pass();
+        self.notify(key, entry, RemovalCause::Size);
     }
 
+    /// Notifies the eviction listener of the removal of the expired entry.
     #[inline]
-    fn admit(
-        candidate_hash: u64,
-        victim: &DeqNode<KeyHashDate<K>>,
+    fn handle_expiration(&self, key: &Arc<K>, entry: &Arc<ValueEntry<K, V>>) {
+        let cause = self.expiration_cause(entry);
+        if cause == RemovalCause::Expired {
+            self.record_stats(StatsCounter::record_eviction);
+            self.record_eviction_in_pass();
+        }
+        self.notify(key, entry, cause);
+    }
+
+    #[inline]
+    fn record_eviction_in_pass(&self) {
+        if self.maintenance_listener.is_some() {
+            self.evicted_in_pass.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    fn notify(&self, key: &Arc<K>, entry: &ValueEntry<K, V>, cause: RemovalCause) {
+        if let Some(listener) = &self.eviction_listener {
+            listener(Arc::clone(key), entry.value.clone(), cause);
+        }
+        if self.async_notifier.is_some() {
+            self.pending_notifications
+                .lock()
+                .push((Arc::clone(key), entry.value.clone(), cause));
+        }
+    }
+
+    /// Returns the cause of the removal of the expired entry. It is `Explicit` if
+    /// the entry was invalidated by `invalidate_all`.
+    #[inline]
+    fn expiration_cause(&self, entry: &Arc<ValueEntry<K, V>>) -> RemovalCause {
+        match (entry.last_modified(), self.valid_after()) {
+            (Some(ts), Some(va)) if ts < va => RemovalCause::Explicit,
+            _ => RemovalCause::Expired,
+        }
+    }
+
+    /// Selects the entries to evict from the LRU end of the probation deque, so
+    /// that evicting them will make enough room for the candidate. Returns `None`
+    /// if evicting all the entries will not make enough room.
+    fn find_cache_victims(
+        &self,
+        deqs: &Deques<K>,
+        candidate_weight: u32,
         freq: &FrequencySketch,
-    ) -> bool {
+    ) -> Option<Victims<K>> {
+        let needed = (self.weighted_size() + candidate_weight as u64)
+            .saturating_sub(self.max_capacity() as u64);
+        if self.admit_on_insert {
+            return self.find_least_frequent_victims(deqs, needed, freq);
+        }
+
+        let mut victims = Victims {
+            keys: Vec::new(),
+            weight: 0,
+            freq: 0,
+        };
+
+        for element in deqs.probation.iter() {
+            if victims.weight >= needed {
+                break;
+            }
+            // Skip the entry that has been removed from the cache (e.g.
+            // invalidated), but whose write op has not been applied yet.
+            if let Some(entry) = self.cache.get(&element.key) {
+                victims.weight += entry.policy_weight() as u64;
+                victims.freq += freq.frequency(element.hash) as u32;
+                victims.keys.push(Arc::clone(&element.key));
+            }
+        }
+
+        if victims.weight >= needed {
+            Some(victims)
+        } else {
+            None
+        }
+    }
+
+    /// Used by `admit_on_insert`. Takes at least `VICTIM_SAMPLE_SIZE` entries from
+    /// the LRU end of the probation deque (more if they do not weigh `needed`),
+    /// and selects the least frequently used ones among them as the victims. The
+    /// ties are broken by the LRU order.
+    fn find_least_frequent_victims(
+        &self,
+        deqs: &Deques<K>,
+        needed: u64,
+        freq: &FrequencySketch,
+    ) -> Option<Victims<K>> {
+        let mut candidates = Vec::new();
+        let mut total_weight = 0u64;
+
+        for element in deqs.probation.iter() {
+            if total_weight >= needed && candidates.len() >= VICTIM_SAMPLE_SIZE {
+                break;
+            }
+            // Skip the entry that has been removed from the cache (e.g.
+            // invalidated), but whose write op has not been applied yet.
+            if let Some(entry) = self.cache.get(&element.key) {
+                let weight = entry.policy_weight() as u64;
+                total_weight += weight;
+                candidates.push((
+                    freq.frequency(element.hash),
+                    weight,
+                    Arc::clone(&element.key),
+                ));
+            }
+        }
+
+        if total_weight < needed {
+            return None;
+        }
+
+        // A stable sort keeps the LRU order among the same frequency.
+        candidates.sort_by_key(|(f, _, _)| *f);
+
+        let mut victims = Victims {
+            keys: Vec::new(),
+            weight: 0,
+            freq: 0,
+        };
+        for (f, weight, key) in candidates {
+            if victims.weight >= needed {
+                break;
+            }
+            victims.weight += weight;
+            victims.freq += f as u32;
+            victims.keys.push(key);
+        }
+        Some(victims)
+    }
+
+    /// Predicts whether a new entry will be admitted when its write op is applied,
+    /// in the same way as `handle_upsert` does. The prediction does not take the
+    /// pending write ops into account, so it may not match the final decision.
+    fn predict_admission(&self, candidate_hash: u64, candidate_weight: u32) -> bool {
+        if self.max_capacity() == 0
+            || candidate_weight as u64 > self.max_capacity() as u64
+            || self.exceeds_max_entry_weight(candidate_weight)
+        {
+            return false;
+        }
+        if self.has_enough_capacity(candidate_weight)
+            || self.eviction_policy == EvictionPolicy::Lru
+            || self.admit_on_insert
+        {
+            return true;
+        }
+
+        let deqs = self.deques.lock();
+        let freq = self.frequency_sketch.read();
+        match self.find_cache_victims(&deqs, candidate_weight, &freq) {
+            Some(victims) => Self::admit(candidate_hash, &victims, &freq),
+            // See the comments in `handle_upsert`.
+            None => deqs.probation.len() == 0,
+        }
+    }
+
+    /// Returns `true` if the candidate should be admitted. It should be accessed
+    /// more frequently than all the victims put together.
+    #[inline]
+    fn admit(candidate_hash: u64, victims: &Victims<K>, freq: &FrequencySketch) -> bool {
         // TODO: Implement some randomness to mitigate hash DoS attack.
         // See Caffeine's implementation.
-        freq.frequency(candidate_hash) > freq.frequency(victim.element.hash)
+        freq.frequency(candidate_hash) as u32 > victims.freq
     }
 
     fn handle_admit(
@@ -761,11 +2176,19 @@ where
             deqs.push_back_wo(KeyDate::new(key, raw_last_modified), entry);
         }
         entry.set_is_admitted(true);
+        self.update_entry_count(true);
+        let old_weight = entry.swap_policy_weight(entry.weight());
+        self.update_weighted_size(old_weight, entry.weight());
     }
 
-    fn handle_remove(deqs: &mut Deques<K>, entry: Arc<ValueEntry<K, V>>) {
+    fn handle_remove(&self, deqs: &mut Deques<K>, key: &K, entry: Arc<ValueEntry<K, V>>) {
+        if self.has_per_entry_ttl() {
+            deqs.timer_wheel.cancel(key);
+        }
         if entry.is_admitted() {
             entry.set_is_admitted(false);
+            self.update_entry_count(false);
+            self.update_weighted_size(entry.swap_policy_weight(0), 0);
             deqs.unlink_ao(&entry);
             Deques::unlink_wo(&mut deqs.write_order, &entry);
         }
@@ -773,13 +2196,21 @@ where
     }
 
     fn handle_remove_with_deques(
+        &self,
         ao_deq_name: &str,
         ao_deq: &mut Deque<KeyHashDate<K>>,
         wo_deq: &mut Deque<KeyDate<K>>,
+        timer_wheel: &mut TimerWheel<K>,
+        key: &K,
         entry: Arc<ValueEntry<K, V>>,
     ) {
+        if self.has_per_entry_ttl() {
+            timer_wheel.cancel(key);
+        }
         if entry.is_admitted() {
             entry.set_is_admitted(false);
+            self.update_entry_count(false);
+            self.update_weighted_size(entry.swap_policy_weight(0), 0);
             Deques::unlink_ao_from_deque(ao_deq_name, ao_deq, &entry);
             Deques::unlink_wo(wo_deq, &entry);
         }
@@ -793,21 +2224,94 @@ where
             self.remove_expired_wo(deqs, batch_size, now);
         }
 
+        if self.has_per_entry_ttl() {
+            self.remove_expired_timers(deqs, batch_size, now);
+        }
+
         if self.time_to_idle.is_some() || self.has_valid_after() {
-            let (window, probation, protected, wo) = (
+            let (window, probation, protected, wo, timers) = (
                 &mut deqs.window,
                 &mut deqs.probation,
                 &mut deqs.protected,
                 &mut deqs.write_order,
+                &mut deqs.timer_wheel,
             );
 
             let mut rm_expired_ao =
-                |name, deq| self.remove_expired_ao(name, deq, wo, batch_size, now);
+                |name, deq| self.remove_expired_ao(name, deq, wo, timers, batch_size, now);
 
             rm_expired_ao("window", window);
             rm_expired_ao("probation", probation);
             rm_expired_ao("protected", protected);
         }
+
+        if let Some(va) = self.valid_after() {
+            self.clear_valid_after_if_done(deqs, va);
+        }
+    }
+
+    /// Clears the timestamp set by `invalidate_all` once all the entries written
+    /// before it have been removed, so that `get` no longer has to check the
+    /// entries against it.
+    ///
+    /// The expiration passes of `evict` remove the invalidated entries from the
+    /// fronts of the deques, but they stop at the first live entry, and an
+    /// invalidated entry can be left behind it, e.g. when its node was moved to the
+    /// back by a read recorded before `invalidate_all`. So once the fronts have no
+    /// invalidated entries, this makes a full pass over the deques to remove the
+    /// ones left behind, and then clears the timestamp.
+    ///
+    /// The entries written after `invalidate_all` will have newer timestamps, so
+    /// the number of the entries to remove does not grow under continuous writes,
+    /// and the timestamp is cleared by the first full pass.
+    fn clear_valid_after_if_done(&self, deqs: &mut Deques<K>, valid_after: Instant) {
+        let va = Some(valid_after);
+        let is_front_done = |deq: &Deque<KeyHashDate<K>>| match deq.peek_front() {
+            Some(node) => !is_expired_entry_ao(&None, &va, node, valid_after),
+            None => true,
+        };
+        let is_before_va = |ts: Option<Instant>| matches!(ts, Some(ts) if ts < valid_after);
+
+        let keys: Vec<Arc<K>> = if self.time_to_idle_reads_only {
+            // The access order deques are ordered by the last read times instead, so
+            // check the write order deque.
+            let deq = &deqs.write_order;
+            if let Some(node) = deq.peek_front() {
+                if is_expired_entry_wo(&None, &va, node, valid_after) {
+                    return;
+                }
+            }
+            deq.iter()
+                .filter(|kd| is_before_va(kd.timestamp()))
+                .map(|kd| Arc::clone(&kd.key))
+                .collect()
+        } else {
+            let (window, probation, protected) = (&deqs.window, &deqs.probation, &deqs.protected);
+            if !(is_front_done(window) && is_front_done(probation) && is_front_done(protected)) {
+                return;
+            }
+            window
+                .iter()
+                .chain(probation.iter())
+                .chain(protected.iter())
+                .filter(|khd| is_before_va(khd.timestamp.instant()))
+                .map(|khd| Arc::clone(&khd.key))
+                .collect()
+        };
+
+        for key in keys {
+            // The entry may have been updated by a write not applied yet.
+            let maybe_entry = self
+                .cache
+                .remove_if(&key, |_, v| is_expired_entry_wo(&None, &va, v, valid_after));
+            if let Some(entry) = maybe_entry {
+                self.handle_expiration(&key, &entry);
+                self.handle_remove(deqs, &key, entry);
+            }
+        }
+
+        // Do not clear a newer timestamp set by a concurrent `invalidate_all`.
+        self.valid_after.compare_and_reset(valid_after);
     }
 
     #[inline]
@@ -816,17 +2320,25 @@ where
         deq_name: &str,
         deq: &mut Deque<KeyHashDate<K>>,
         write_order_deq: &mut Deque<KeyDate<K>>,
+        timer_wheel: &mut TimerWheel<K>,
         batch_size: usize,
         now: Instant,
     ) {
         let tti = &self.time_to_idle;
         let va = &self.valid_after();
+        // When the nodes have the last read times, the entries written before
+        // `invalidate_all` are found by the write order queue instead.
+        let node_va = if self.time_to_idle_reads_only {
+            &None
+        } else {
+            va
+        };
         for _ in 0..batch_size {
             // Peek the front node of the deque and check if it is expired.
             let (key, _ts) = deq
                 .peek_front()
                 .and_then(|node| {
-                    if is_expired_entry_ao(tti, va, &*node, now) {
+                    if is_expired_entry_ao(tti, node_va, &*node, now) {
                         Some((
                             Some(Arc::clone(&node.element.key)),
                             Some(&node.element.timestamp),
@@ -849,13 +2361,21 @@ where
             // above have not been updated yet.
             let maybe_entry = self
                 .cache
-                .remove_if(key, |_, v| is_expired_entry_ao(tti, va, v, now));
+                .remove_if(key, |_, v| self.is_idle_entry(va, v, now));
 
             if let Some(entry) = maybe_entry {
-                Self::handle_remove_with_deques(deq_name, deq, write_order_deq, entry);
+                self.handle_expiration(key, &entry);
+                self.handle_remove_with_deques(
+                    deq_name,
+                    deq,
+                    write_order_deq,
+                    timer_wheel,
+                    key,
+                    entry,
+                );
             } else if let Some(entry) = self.cache.get(key) {
                 let ts = entry.last_accessed();
-                if ts.is_none() {
+                if ts.is_none() && !self.time_to_idle_reads_only {
                     // The key exists and the entry has been updated.
                     Deques::move_to_back_ao_in_deque(deq_name, deq, &entry);
                     Deques::move_to_back_wo_in_deque(write_order_deq, &entry);
@@ -885,7 +2405,9 @@ where
                 .write_order
                 .peek_front()
                 .and_then(|node| {
-                    if is_expired_entry_wo(ttl, va, &*node, now) {
+                    if is_expired_entry_wo(ttl, va, &*node, now)
+                        || self.is_expired_by_entry_ttl(&node.element.key, now)
+                    {
                         Some((
                             Some(Arc::clone(&node.element.key)),
                             Some(&node.element.timestamp),
@@ -902,12 +2424,13 @@ where
 
             let key = key.as_ref().unwrap();
 
-            let maybe_entry = self
-                .cache
-                .remove_if(key, |_, v| is_expired_entry_wo(ttl, va, v, now));
+            let maybe_entry = self.cache.remove_if(key, |_, v| {
+                is_expired_entry_wo(&entry_ttl(ttl, v), va, v, now)
+            });
 
             if let Some(entry) = maybe_entry {
-                Self::handle_remove(deqs, entry);
+                self.handle_expiration(key, &entry);
+                self.handle_remove(deqs, key, entry);
             } else if let Some(entry) = self.cache.get(key) {
                 let ts = entry.last_modified();
                 if ts.is_none() {
@@ -930,6 +2453,104 @@ where
         }
     }
 
+    /// Removes up to `batch_size` entries whose own time-to-live have been
+    /// expired, by advancing the timer wheel. This only examines the timers that
+    /// have become due, rather than scanning the write order queue.
+    fn remove_expired_timers(&self, deqs: &mut Deques<K>, batch_size: usize, now: Instant) {
+        let ttl = &self.time_to_live;
+        let va = &self.valid_after();
+        for timer in deqs.timer_wheel.advance(now.as_u64(), batch_size) {
+            let key = &timer.key;
+            // The timer is replaced when the entry is updated, but the entry may
+            // have been updated by a write not applied yet, so check it again.
+            let maybe_entry = self.cache.remove_if(key, |_, v| {
+                is_expired_entry_wo(&entry_ttl(ttl, v), va, v, now)
+            });
+
+            if let Some(entry) = maybe_entry {
+                self.handle_expiration(key, &entry);
+                self.handle_remove(deqs, key, entry);
+            } else if let Some(entry) = self.cache.get(key) {
+                // Not expired yet. Schedule the timer again for its current deadline.
+                if entry.time_to_live().is_some() {
+                    if let (Some(ts), Some(ttl)) = (entry.last_modified(), entry_ttl(ttl, &entry)) {
+                        let deadline = ts.checked_add(ttl).map_or(u64::MAX, |d| d.as_u64());
+                        deqs.timer_wheel.schedule(Arc::clone(key), deadline);
+                    }
+                }
+            }
+        }
+    }
+
+    fn evict_lru_entries(&self, deqs: &mut Deques<K>, batch_size: usize) {
+        for _ in 0..batch_size {
+            if self.weighted_size() <= self.max_capacity() as u64 {
+                break;
+            }
+
+            let key = match deqs.probation.peek_front() {
+                Some(node) => Arc::clone(&node.element.key),
+                None => break,
+            };
+
+            if let Some(entry) = self.remove_for_size_eviction(&key) {
+                self.handle_size_eviction(&key, &entry);
+                self.handle_remove(deqs, &key, entry);
+            } else if let Some(node) = deqs.probation.peek_front() {
+                // Skip this entry as the key might have been invalidated. Move the
+                // node to the back of the deque instead of popping (dropping) it.
+                let node = NonNull::from(node);
+                unsafe { deqs.probation.move_to_back(node) };
+            }
+        }
+    }
+
+    /// Returns `true` if the entry of the key has been expired by its own
+    /// time-to-live.
+    #[inline]
+    fn is_expired_by_entry_ttl(&self, key: &Arc<K>, now: Instant) -> bool {
+        if !self.has_per_entry_ttl() {
+            return false;
+        }
+        self.cache
+            .get(key)
+            .map(|entry| {
+                let ttl = entry.time_to_live();
+                ttl.is_some() && is_expired_entry_wo(&ttl, &None, &entry, now)
+            })
+            .unwrap_or_default()
+    }
+
+    fn register_sweep_predicate(&self, invalidator: &Invalidator<K, V, S>) {
+        if let Some(sweep) = &mut *self.sweep_predicate.lock() {
+            let is_registered = match &sweep.id {
+                Some(id) => invalidator.completion(id).is_some(),
+                None => false,
+            };
+            if is_registered {
+                return;
+            }
+            let now = self.current_time_from_expiration_clock();
+            let is_due = match sweep.last_registered {
+                Some(ts) => ts
+                    .checked_add(sweep.interval)
+                    .map(|due| due <= now)
+                    .unwrap_or_default(),
+                None => true,
+            };
+            if is_due {
+                // This fails when there are too many pending predicates. Then try
+                // again on the next run.
+                sweep.id = invalidator
+                    .register_predicate(Arc::clone(&sweep.predicate), now)
+                    .ok();
+                if sweep.id.is_some() {
+                    sweep.last_registered = Some(now);
+                }
+            }
+        }
+    }
+
     fn invalidate_entries(
         &self,
         invalidator: &Invalidator<K, V, S>,
@@ -950,8 +2571,9 @@ where
             is_done,
         }) = invalidator.task_result()
         {
-            for entry in invalidated {
-                Self::handle_remove(deqs, entry);
+            for KvEntry { key, entry } in invalidated {
+                self.notify(&key, &entry, RemovalCause::Explicit);
+                self.handle_remove(deqs, &key, entry);
             }
             if is_done {
                 deqs.write_order.reset_cursor();
@@ -992,6 +2614,11 @@ where
         if len > 0 {
             let is_truncated = len == batch_size && iter.peek().is_some();
             invalidator.submit_task(candidates, is_truncated);
+        } else {
+            // None of the entries has a timestamp, so no entry can be invalidated
+            // by the predicates registered by now. Remove them so that they will not
+            // be applied by `get` anymore.
+            invalidator.remove_predicates_registered_before(now);
         }
     }
 }
@@ -1003,12 +2630,8 @@ where
 impl<K, V, S> Inner<K, V, S>
 where
     K: Hash + Eq,
-    S: BuildHasher + Clone,
+    S: BuildHasher,
 {
-    fn len(&self) -> usize {
-        self.cache.len()
-    }
-
     fn invalidation_predicate_count(&self) -> usize {
         self.invalidator
             .read()
@@ -1052,6 +2675,41 @@ fn is_expired_entry_ao(
     false
 }
 
+/// Returns the time-to-live of the entry, which is the shorter one of the
+/// cache-wide time-to-live and the entry's own.
+#[inline]
+fn entry_ttl<K, V>(time_to_live: &Option<Duration>, entry: &ValueEntry<K, V>) -> Option<Duration> {
+    match (*time_to_live, entry.time_to_live()) {
+        (Some(ttl), Some(entry_ttl)) => Some(ttl.min(entry_ttl)),
+        (ttl, entry_ttl) => ttl.or(entry_ttl),
+    }
+}
+
+/// Returns the capacity of the frequency sketch for the max capacity of the cache.
+#[inline]
+fn sketch_capacity(max_capacity: usize) -> u32 {
+    // Ensure the capacity fits in a range of `128u32..=u32::MAX`.
+    max_capacity
+        .try_into() // Convert to u32.
+        .unwrap_or(u32::MAX)
+        .max(128)
+}
+
+/// Returns the index of the age histogram bucket for the age. The bucket 0 holds
+/// the ages shorter than one second, and the bucket `i` (`i > 0`) holds the ages
+/// in `[2^(i-1), 2^i)` seconds.
+#[inline]
+fn age_bucket_index(age: Duration) -> usize {
+    let secs = age.as_secs();
+    (64 - secs.leading_zeros()) as usize
+}
+
+/// Returns the (exclusive) upper bound of the ages in the age histogram bucket.
+#[inline]
+fn age_bucket_bound(index: usize) -> Duration {
+    Duration::from_secs(1u64.checked_shl(index as u32).unwrap_or(u64::MAX))
+}
+
 #[inline]
 fn is_expired_entry_wo(
     time_to_live: &Option<Duration>,
@@ -1074,7 +2732,7 @@ fn is_expired_entry_wo(
 
 #[cfg(test)]
 mod tests {
-    use super::BaseCache;
+    use super::{BaseCache, CacheConfig};
 
     #[cfg_attr(target_pointer_width = "16", ignore)]
     #[test]
@@ -1087,11 +2745,9 @@ mod tests {
         let ensure_sketch_len = |max_capacity, len, name| {
             let cache = BaseCache::<u8, u8>::new(
                 max_capacity,
+                RandomState::default,
                 None,
-                RandomState::default(),
-                None,
-                None,
-                false,
+                CacheConfig::default(),
             );
             assert_eq!(
                 cache.inner.frequency_sketch.read().table_len(),
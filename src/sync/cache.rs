@@ -1,21 +1,43 @@
 use super::{
-    base_cache::{BaseCache, HouseKeeperArc, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS},
+    base_cache::{
+        BaseCache, HouseKeeperArc, RefreshKey, MAX_SYNC_REPEATS, WRITE_RETRY_INTERVAL_MICROS,
+    },
+    config::CacheConfig,
     housekeeper::InnerSync,
+    invalidator::PredicateFun,
+    refresher::Refresher,
     value_initializer::ValueInitializer,
-    ConcurrentCacheExt, PredicateId, WriteOp,
+    CacheBuilder, CacheView, ConcurrentCacheExt, Entry, EntryGuard, PredicateId, ValueEntry,
+    WriteOp,
+};
+use crate::{
+    common::backoff::Backoff,
+    notification::RefreshErrorListener,
+    policy::{EntryMeta, EntryStatus, EvictionPolicy, InsertReport},
+    stats::CacheStats,
+    sync::value_initializer::{InitResult, OptionallyInit},
+    CacheError, InitPanicked, PredicateError, RefreshError,
 };
-use crate::{sync::value_initializer::InitResult, PredicateError};
 
 use crossbeam_channel::{Sender, TrySendError};
 use std::{
     any::TypeId,
     borrow::Borrow,
-    collections::hash_map::RandomState,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
     hash::{BuildHasher, Hash},
-    sync::Arc,
+    panic::resume_unwind,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
+// The default max number of times `get_or_insert_with_retrying` calls the `init`
+// closure when it keeps panicking.
+const DEFAULT_MAX_INIT_ATTEMPTS: usize = 3;
+
 /// A thread-safe concurrent in-memory cache.
 ///
 /// `Cache` supports full concurrency of retrievals and a high expected concurrency
@@ -109,6 +131,11 @@ use std::{
 /// Cloning is a cheap operation for `Cache` as it only creates thread-safe
 /// reference-counted pointers to the internal data structures.
 ///
+/// The housekeeping tasks of the caches run on a background thread pool shared by
+/// all caches. When the last clone of a cache is dropped, its housekeeping job is
+/// stopped, and when no cache is left, the threads in the pool exit. So creating
+/// and dropping many short-lived caches will not leak threads.
+///
 /// [once-cell-crate]: https://crates.io/crates/once_cell
 ///
 /// # Avoiding to clone the value at `get`
@@ -154,15 +181,39 @@ use std::{
 /// The hashing algorithm can be replaced on a per-`Cache` basis using the
 /// [`build_with_hasher`][build-with-hasher-method] method of the
 /// `CacheBuilder`. Many alternative algorithms are available on crates.io, such
-/// as the [aHash][ahash-crate] crate.
+/// as the [aHash][ahash-crate] crate. If the hasher state is not `Clone`, use the
+/// [`build_with_hasher_factory`][build-with-hasher-factory-method] method instead.
 ///
 /// [build-with-hasher-method]: ./struct.CacheBuilder.html#method.build_with_hasher
+/// [build-with-hasher-factory-method]: ./struct.CacheBuilder.html#method.build_with_hasher_factory
 /// [ahash-crate]: https://crates.io/crates/ahash
 ///
-#[derive(Clone)]
 pub struct Cache<K, V, S = RandomState> {
     base: BaseCache<K, V, S>,
     value_initializer: Arc<ValueInitializer<K, V, S>>,
+    max_init_attempts: usize,
+    // The number of the eviction events dropped because the channel created by
+    // `CacheBuilder::eviction_channel` was full.
+    dropped_eviction_events: Arc<AtomicU64>,
+    refresher: Option<Arc<Refresher>>,
+    refresh_error_listener: Option<RefreshErrorListener<K>>,
+}
+
+impl<K, V, S> Clone for Cache<K, V, S> {
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+            value_initializer: Arc::clone(&self.value_initializer),
+            max_init_attempts: self.max_init_attempts,
+            dropped_eviction_events: Arc::clone(&self.dropped_eviction_events),
+            refresher: self.refresher.clone(),
+            refresh_error_listener: self.refresh_error_listener.clone(),
+        }
+    }
 }
 
 // TODO: https://github.com/moka-rs/moka/issues/54
@@ -183,6 +234,25 @@ where
 {
 }
 
+impl<K, V, S> fmt::Debug for Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    /// Prints the configuration and the approximate size of this cache. The
+    /// entries are not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("max_capacity", &self.max_capacity())
+            .field("num_segments", &self.num_segments())
+            .field("time_to_live", &self.time_to_live())
+            .field("time_to_idle", &self.time_to_idle())
+            .field("entry_count", &self.entry_count())
+            .finish()
+    }
+}
+
 impl<K, V> Cache<K, V, RandomState>
 where
     K: Hash + Eq + Send + Sync + 'static,
@@ -190,13 +260,45 @@ where
 {
     /// Constructs a new `Cache<K, V>` that will store up to the `max_capacity` entries.
     ///
+    /// If `max_capacity` is `0`, the cache does not store any entry; values passed
+    /// to `insert` are evicted immediately, and `get` always returns `None`.
+    ///
     /// To adjust various configuration knobs such as `initial_capacity` or
     /// `time_to_live`, use the [`CacheBuilder`][builder-struct].
     ///
     /// [builder-struct]: ./struct.CacheBuilder.html
     pub fn new(max_capacity: usize) -> Self {
         let build_hasher = RandomState::default();
-        Self::with_everything(max_capacity, None, build_hasher, None, None, false)
+        Self::with_everything(
+            max_capacity,
+            || build_hasher.clone(),
+            CacheConfig::default(),
+        )
+    }
+
+    /// Returns a [`CacheBuilder`][builder-struct], which can build a `Cache` with
+    /// various configuration knobs.
+    ///
+    /// The max capacity must be set by the `max_capacity` method of the builder;
+    /// otherwise `build` panics.
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::builder()
+    ///     .max_capacity(1_000)
+    ///     .time_to_live(Duration::from_secs(30 * 60))
+    ///     .build();
+    ///
+    /// cache.insert("a", "alice");
+    /// ```
+    pub fn builder() -> CacheBuilder<K, V, Cache<K, V, RandomState>> {
+        CacheBuilder::default()
     }
 }
 
@@ -204,29 +306,46 @@ impl<K, V, S> Cache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
     V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
 {
     pub(crate) fn with_everything(
         max_capacity: usize,
-        initial_capacity: Option<usize>,
-        build_hasher: S,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        hasher_factory: impl Fn() -> S,
+        config: CacheConfig<K, V>,
     ) -> Self {
+        let init_panic_policy = config.init_panic_policy;
+        let cache_errors_for = config.cache_errors_for;
+        let max_init_attempts = config
+            .max_init_attempts
+            .unwrap_or(DEFAULT_MAX_INIT_ATTEMPTS);
+        let refresher = config
+            .refresh_after_write
+            .map(|_| Arc::new(Refresher::new()));
+        let refresh_error_listener = config.refresh_error_listener.clone();
+        let base = BaseCache::new(max_capacity, &hasher_factory, None, config);
+        let value_initializer = Arc::new(ValueInitializer::with_hasher(
+            hasher_factory(),
+            init_panic_policy,
+            cache_errors_for,
+        ));
+        if cache_errors_for.is_some() {
+            let vi = Arc::clone(&value_initializer);
+            base.set_expired_error_remover(Box::new(move |now| vi.remove_expired_errors(now)));
+        }
         Self {
-            base: BaseCache::new(
-                max_capacity,
-                initial_capacity,
-                build_hasher.clone(),
-                time_to_live,
-                time_to_idle,
-                invalidator_enabled,
-            ),
-            value_initializer: Arc::new(ValueInitializer::with_hasher(build_hasher)),
+            base,
+            value_initializer,
+            max_init_attempts,
+            dropped_eviction_events: Arc::default(),
+            refresher,
+            refresh_error_listener,
         }
     }
 
+    pub(crate) fn set_dropped_eviction_events_counter(&mut self, counter: Arc<AtomicU64>) {
+        self.dropped_eviction_events = counter;
+    }
+
     /// Returns a _clone_ of the value corresponding to the key.
     ///
     /// If you want to store values that will be expensive to clone, wrap them by
@@ -245,6 +364,225 @@ where
         self.base.get_with_hash(key, self.base.hash(key))
     }
 
+    /// Returns a guard giving a reference to the value corresponding to the key,
+    /// rather than a _clone_ of the value.
+    ///
+    /// This avoids the cost of cloning a large value on every read, without
+    /// wrapping the values by `Arc`. The guard keeps the value alive even if the
+    /// entry is evicted or invalidated while the guard is held; see
+    /// [`EntryGuard`][entry-guard] for the memory implications of holding many
+    /// guards.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// [entry-guard]: ./struct.EntryGuard.html
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<EntryGuard<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.get_ref_with_hash(key, self.base.hash(key))
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, after applying the
+    /// pending writes to the cache.
+    ///
+    /// `insert` and `invalidate` update the hash table immediately, but their
+    /// effects on the cache policy, such as the admission of a new entry, are
+    /// applied later by the housekeeper. So `get` may return a value that is going
+    /// to be rejected by the admission policy. This method applies all the writes
+    /// scheduled before the call, so the result reflects them. This is useful for
+    /// read-your-writes scenarios and for tests.
+    ///
+    /// **NOTE**: This method is much slower than `get`, as it takes the lock
+    /// shared with the housekeeper and applies the pending writes on the calling
+    /// thread. Do not call it on a hot path.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get_consistent<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.apply_pending_writes();
+        self.get(key)
+    }
+
+    /// Returns _clones_ of the values corresponding to the keys, in the same order
+    /// as the keys. The element is `None` if the key does not exist in the cache.
+    ///
+    /// The values are looked up one by one without a global lock, so the result
+    /// is a weakly-consistent snapshot; it may not reflect the concurrent updates
+    /// made while this method is running.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    /// cache.insert("a", 1);
+    /// cache.insert("c", 3);
+    ///
+    /// assert_eq!(cache.get_all(&["a", "b", "c"]), vec![Some(1), None, Some(3)]);
+    /// ```
+    pub fn get_all<'a, Q, I>(&self, keys: I) -> Vec<Option<V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter()
+            .map(|key| self.base.get_with_hash(key, self.base.hash(key)))
+            .collect()
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, and reloads the
+    /// value in background if the entry is older than the `refresh_after_write`
+    /// period of the cache.
+    ///
+    /// The current (possibly stale) value is returned immediately, while the
+    /// `reload` closure is called on a background thread. If the closure returns
+    /// `Ok(V)`, the value replaces the current one, unless the entry has been
+    /// updated, invalidated or expired during the reload. If it returns an `Err` or
+    /// panics, the current value is retained, the error is passed to the
+    /// [`refresh_error_listener`][refresh-error-listener] of the cache (if any),
+    /// and the entry will be reloaded again by the next call of this method.
+    ///
+    /// The reloads run on a thread pool shared by all the caches, which has as
+    /// many threads as the CPU cores. Only one reload per key is in flight at a
+    /// time; the calls of this method on the same key while the key is being
+    /// reloaded will not call their `reload` closures.
+    ///
+    /// This method behaves like `get` if `refresh_after_write` is not set.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::CacheBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let cache = CacheBuilder::new(100)
+    ///     .refresh_after_write(Duration::from_secs(60))
+    ///     .build();
+    ///
+    /// cache.insert("config", "v1".to_string());
+    /// let value = cache.get_with_refresh(&"config", |_key| {
+    ///     // Load the config from somewhere.
+    ///     Ok::<_, std::io::Error>("v2".to_string())
+    /// });
+    /// assert_eq!(value, Some("v1".to_string()));
+    /// ```
+    ///
+    /// [refresh-error-listener]: ./struct.CacheBuilder.html#method.refresh_error_listener
+    pub fn get_with_refresh<Q, F, E>(&self, key: &Q, reload: F) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&K) -> Result<V, E> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>> + 'static,
+    {
+        let hash = self.base.hash(key);
+        let (v, refresh_key) = self.base.get_with_hash_and_refresh_key(key, hash)?;
+        if let Some(refresh_key) = refresh_key {
+            self.spawn_refresh(refresh_key, hash, reload);
+        }
+        Some(v)
+    }
+
+    /// Returns `true` if the cache contains a value for the key.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation, so it does not update the historic popularity estimator or reset
+    /// the idle timer for the key. It also does not clone the value.
+    ///
+    /// Expired entries, and entries that have been invalidated by `invalidate_all`
+    /// or by a predicate of `invalidate_entries_if`, are treated as absent even if
+    /// they have not been evicted yet.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.contains_key(key)
+    }
+
+    /// Resets the idle timer of the entry for the key without reading its value,
+    /// and returns `true` if the cache contains a value for the key.
+    ///
+    /// This is cheaper than `get` when you only need to keep an entry warm, as it
+    /// does not clone the value. Like `get`, it updates the historic popularity
+    /// estimator and the access order of the entry. If `reset_ttl` is `true`, it
+    /// also resets the time-to-live timer of the entry, as if the value was
+    /// inserted again (but without notifying the eviction listener).
+    ///
+    /// Returns `false` if the key is not present, or the entry has been expired or
+    /// invalidated.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.touch(key, self.base.hash(key), reset_ttl)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, without affecting
+    /// the cache policy.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation, so it does not update the historic popularity estimator, the
+    /// recency of the entry, or reset the idle timer for the key. It is not
+    /// counted in the hit or miss statistics either. This is useful for monitoring
+    /// or debugging tools that should not perturb the eviction decisions.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.peek(key)
+    }
+
+    /// Returns a read-only handle to this cache, which can only `get`, `peek` and
+    /// check the keys and the entry count.
+    ///
+    /// Like `clone`, this operation is cheap as it only creates thread-safe
+    /// reference counted pointers to the shared internal data structures. See
+    /// [`CacheView`][cache-view] for details.
+    ///
+    /// [cache-view]: ./struct.CacheView.html
+    pub fn view(&self) -> CacheView<K, V, S> {
+        CacheView::new(self.clone())
+    }
+
+    /// Returns a snapshot of the entries in the cache, e.g. to persist them on
+    /// shutdown.
+    ///
+    /// The snapshot is weakly consistent; it reflects the entries whose writes have
+    /// been applied by the housekeeper, so it may not include the entries being
+    /// inserted concurrently. The expired and invalidated entries are not included.
+    /// Like `peek`, this method does not affect the cache policy.
+    ///
+    /// The snapshot does not carry the access or frequency history of the entries,
+    /// so the entries loaded from it (e.g. by [`CacheBuilder::build_with_entries`]
+    /// [build-with-entries]) will start cold, and their time-to-live and
+    /// time-to-idle will start from the loading time.
+    ///
+    /// [build-with-entries]: ./struct.CacheBuilder.html#method.build_with_entries
+    pub fn to_hash_map(&self) -> HashMap<Arc<K>, V> {
+        self.base.to_hash_map()
+    }
+
     pub(crate) fn get_with_hash<Q>(&self, key: &Q, hash: u64) -> Option<V>
     where
         Arc<K>: Borrow<Q>,
@@ -253,6 +591,27 @@ where
         self.base.get_with_hash(key, hash)
     }
 
+    /// Returns a histogram of the ages of the entries in the cache, to help
+    /// choosing the time-to-live and time-to-idle empirically.
+    ///
+    /// The age of an entry is the time elapsed since its current value was
+    /// inserted. Each element of the returned `Vec` is a pair of the (exclusive)
+    /// upper bound of the ages in the bucket and the number of the entries in it.
+    /// The first bucket holds the ages shorter than one second, and the upper
+    /// bounds double in the following buckets (2 seconds, 4 seconds, 8 seconds,
+    /// and so on). The buckets after the one holding the oldest entry are omitted,
+    /// so an empty cache returns an empty `Vec`.
+    ///
+    /// Like `to_hash_map`, the histogram is weakly consistent; it only counts the
+    /// entries whose writes have been applied by the housekeeper, and it does not
+    /// count the expired and invalidated entries.
+    ///
+    /// **NOTE**: This method is a diagnostic; it scans all the entries in the
+    /// cache, so it takes O(n) time. Do not call it on a hot path.
+    pub fn age_histogram(&self) -> Vec<(Duration, usize)> {
+        self.base.age_histogram()
+    }
+
     /// Ensures the value of the key exists by inserting the result of the init
     /// function if not exist, and returns a _clone_ of the value.
     ///
@@ -325,6 +684,17 @@ where
     /// (e.g. only thread 1 in the above sample). If there are other calls in
     /// progress (e.g. thread 0, 2 and 3 above), this method will restart and resolve
     /// one of the remaining `init` closure.
+    /// This is the default behavior, and it can be changed by
+    /// [`CacheBuilder::init_panic_policy`][init-panic-policy] so that the other
+    /// calls panic with an [`InitPanicked`][init-panicked] payload instead.
+    ///
+    /// [init-panic-policy]: ./struct.CacheBuilder.html#method.init_panic_policy
+    /// [init-panicked]: ../struct.InitPanicked.html
+    ///
+    /// The `init` closure must not call this method (or the other
+    /// `get_or_*insert_with` methods) for the same key, as it would wait for
+    /// itself. Such a re-entrant call is detected and this method panics instead
+    /// of deadlocking.
     ///
     pub fn get_or_insert_with(&self, key: K, init: impl FnOnce() -> V) -> V {
         let hash = self.base.hash(&key);
@@ -332,99 +702,409 @@ where
         self.get_or_insert_with_hash_and_fun(key, hash, init)
     }
 
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// `default` value if not exist and returns a clone of it.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// with an init closure returning `default`. When many threads call this method
+    /// concurrently for the same absent key, only one of the `default` values is
+    /// inserted, and all the calls get that value.
+    pub fn get_or_insert(&self, key: K, default: V) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_hash_and_fun(key, hash, move || default)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value returned by the init closure if not exist. The init closure takes the
+    /// key, which is convenient when the value is loaded by the key.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// except the argument of the init closure.
+    pub fn get_or_insert_with_key(&self, key: K, init: impl FnOnce(&Arc<K>) -> V) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        let init_key = Arc::clone(&key);
+        self.get_or_insert_with_hash_and_fun(key, hash, move || init(&init_key))
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but also returns
+    /// whether the value was inserted by this call (`EntryStatus::Created`), or
+    /// already existed (`EntryStatus::Existed`).
+    ///
+    /// When many threads call this method concurrently for the same absent key,
+    /// exactly one of them evaluates its `init` closure and gets `Created`. The
+    /// others get `Existed` with the value inserted by that thread.
+    pub fn get_or_insert_with_status(&self, key: K, init: impl FnOnce() -> V) -> (V, EntryStatus) {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_status_hash_and_fun(key, hash, init)
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but the init
+    /// closure returns the time-to-live of the entry along with the value, e.g.
+    /// derived from the expiry of a fetched resource.
+    ///
+    /// The time-to-live is applied to the inserted entry in the same way as
+    /// [`insert_with_ttl`](#method.insert_with_ttl). The calls waiting for the same
+    /// init closure get the value, and the calls after that get the value until
+    /// the entry expires.
+    pub fn get_or_insert_with_ttl(&self, key: K, init: impl FnOnce() -> (V, Duration)) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_ttl_hash_and_fun(key, hash, init)
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but the init
+    /// closure is called again when it panics, up to three times in total by
+    /// default. Set the number by
+    /// [`CacheBuilder::max_init_attempts`][max-init-attempts].
+    ///
+    /// # Which call retries the closure
+    ///
+    /// The closure is retried by the call that started evaluating it (the
+    /// _leader_), on its own thread. It is not handed off to one of the calls
+    /// waiting for the same key; they keep waiting while the leader retries, and
+    /// they get the value once an attempt succeeds. If the closure panics on the
+    /// last attempt, the panic is propagated to the leader, and the waiting calls
+    /// are handled by the [`InitPanicPolicy`][init-panic-policy] as in
+    /// `get_or_insert_with`.
+    ///
+    /// The panics of the earlier attempts are caught by `catch_unwind` and
+    /// discarded, but the [panic hook][panic-hook] still runs for each of them,
+    /// e.g. the default hook prints the panic message to the standard error.
+    ///
+    /// As the closure may be called more than once, it must be `Fn`. Use interior
+    /// mutability (e.g. a `Mutex` or an atomic) for the state that it updates.
+    ///
+    /// [max-init-attempts]: ./struct.CacheBuilder.html#method.max_init_attempts
+    /// [init-panic-policy]: ../policy/enum.InitPanicPolicy.html
+    /// [panic-hook]: https://doc.rust-lang.org/std/panic/fn.set_hook.html
+    pub fn get_or_insert_with_retrying(&self, key: K, init: impl Fn() -> V) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_insert_with_retrying_hash_and_fun(key, hash, init)
+    }
+
+    pub(crate) fn get_or_insert_with_retrying_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl Fn() -> V,
+    ) -> V {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let max_attempts = self.max_init_attempts;
+        let init_retrying = || {
+            let mut attempts = 1;
+            loop {
+                match catch_unwind(AssertUnwindSafe(&init)) {
+                    Ok(value) => return value,
+                    Err(payload) if attempts >= max_attempts => resume_unwind(payload),
+                    Err(_) => attempts += 1,
+                }
+            }
+        };
+        self.get_or_insert_with_hash_and_fun(key, hash, init_retrying)
+    }
+
     pub(crate) fn get_or_insert_with_hash_and_fun(
         &self,
         key: Arc<K>,
         hash: u64,
         init: impl FnOnce() -> V,
     ) -> V {
+        self.get_or_insert_with_status_hash_and_fun(key, hash, init)
+            .0
+    }
+
+    pub(crate) fn get_or_insert_with_status_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> V,
+    ) -> (V, EntryStatus) {
+        self.do_get_or_insert_with(key, hash, || (init(), None))
+    }
+
+    pub(crate) fn get_or_insert_with_ttl_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> (V, Duration),
+    ) -> V {
+        self.do_get_or_insert_with(key, hash, || {
+            let (v, ttl) = init();
+            (v, Some(ttl))
+        })
+        .0
+    }
+
+    fn do_get_or_insert_with(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> (V, Option<Duration>),
+    ) -> (V, EntryStatus) {
         if let Some(v) = self.get_with_hash(&key, hash) {
-            return v;
+            return (v, EntryStatus::Existed);
         }
 
+        // The waiter only holds the value, so keep the time-to-live here.
+        let mut time_to_live = None;
+        let init = || {
+            let (v, ttl) = init();
+            time_to_live = ttl;
+            v
+        };
+
         match self.value_initializer.init_or_read(Arc::clone(&key), init) {
             InitResult::Initialized(v) => {
-                self.insert_with_hash(Arc::clone(&key), hash, v.clone());
+                self.base.record_load_success();
+                self.insert_with_hash_and_ttl(Arc::clone(&key), hash, v.clone(), time_to_live);
                 self.value_initializer
                     .remove_waiter(&key, TypeId::of::<()>());
-                v
+                (v, EntryStatus::Created)
             }
-            InitResult::ReadExisting(v) => v,
-            InitResult::InitErr(_) => unreachable!(),
+            InitResult::ReadExisting(v) => (v, EntryStatus::Existed),
+            InitResult::InitErr(e) => match *e {},
+            // The waiters of `init_or_read` never have `NoValue`.
+            InitResult::InitNone => unreachable!(),
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
         }
     }
 
-    /// Try to ensure the value of the key exists by inserting an `Ok` result of the
-    /// init closure if not exist, and returns a _clone_ of the value or the `Err`
-    /// returned by the closure.
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value returned by the init closure if not exist or if `should_recompute`
+    /// returns `true` on the existing value.
     ///
-    /// This method prevents to evaluate the init closure multiple times on the same
-    /// key even if the method is concurrently called by many threads; only one of
-    /// the calls evaluates its closure (as long as these closures return the same
-    /// error type), and other calls wait for that closure to complete.
+    /// This is useful when the validity of a value depends on its content (e.g. an
+    /// embedded version tag) rather than on its age. Like
+    /// [`get_or_insert_with`](#method.get_or_insert_with), this method prevents to
+    /// evaluate the init closure multiple times on the same key even if the method
+    /// is concurrently called by many threads; the other calls wait for the
+    /// evaluated closure and get its value. `should_recompute` is also applied to
+    /// the value found after acquiring the right to evaluate the closure, so a
+    /// value that has just been recomputed by another call is not recomputed again.
     ///
     /// # Example
     ///
     /// ```rust
     /// use moka::sync::Cache;
-    /// use std::{path::Path, time::Duration, thread};
     ///
-    /// /// This function tries to get the file size in bytes.
-    /// fn get_file_size(thread_id: u8, path: impl AsRef<Path>) -> Result<u64, std::io::Error> {
-    ///     println!("get_file_size() called by thread {}.", thread_id);
-    ///     Ok(std::fs::metadata(path)?.len())
-    /// }
+    /// const VERSION: u32 = 2;
     ///
+    /// // The value is (version, data).
     /// let cache = Cache::new(100);
+    /// cache.insert("a", (1, "old"));
     ///
-    /// // Spawn four threads.
-    /// let threads: Vec<_> = (0..4_u8)
-    ///     .map(|thread_id| {
-    ///         let my_cache = cache.clone();
-    ///         thread::spawn(move || {
-    ///             println!("Thread {} started.", thread_id);
-    ///
-    ///             // Try to insert and get the value for key1. Although all four
-    ///             // threads will call `get_or_try_insert_with` at the same time,
-    ///             // get_file_size() must be called only once.
-    ///             let value = my_cache.get_or_try_insert_with(
-    ///                 "key1",
-    ///                 || get_file_size(thread_id, "./Cargo.toml"),
-    ///             );
-    ///
-    ///             // Ensure the value exists now.
-    ///             assert!(value.is_ok());
-    ///             thread::sleep(Duration::from_millis(10));
-    ///             assert!(my_cache.get(&"key1").is_some());
-    ///
-    ///             println!(
-    ///                 "Thread {} got the value. (len: {})",
-    ///                 thread_id,
-    ///                 value.unwrap()
-    ///             );
-    ///         })
-    ///     })
-    ///     .collect();
-    ///
-    /// // Wait all threads to complete.
-    /// threads
-    ///     .into_iter()
-    ///     .for_each(|t| t.join().expect("Thread failed"));
+    /// let v = cache.get_with_if("a", || (VERSION, "new"), |(ver, _)| *ver < VERSION);
+    /// assert_eq!(v, (2, "new"));
+    /// assert_eq!(cache.get(&"a"), Some((2, "new")));
     /// ```
     ///
-    /// **Result**
-    ///
-    /// - `get_file_size()` was called exactly once by thread 1.
-    /// - Other threads were blocked until thread 1 inserted the value.
+    /// # Panics
     ///
-    /// ```console
-    /// Thread 1 started.
-    /// Thread 2 started.
-    /// get_file_size() called by thread 1.
-    /// Thread 3 started.
-    /// Thread 0 started.
-    /// Thread 2 got the value. (len: 1466)
-    /// Thread 0 got the value. (len: 1466)
+    /// This method panics when the `init` closure has been panicked. See
+    /// `get_or_insert_with` for details.
+    pub fn get_with_if(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+        should_recompute: impl Fn(&V) -> bool,
+    ) -> V {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_with_if_hash_and_fun(key, hash, init, should_recompute)
+    }
+
+    pub(crate) fn get_with_if_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> V,
+        should_recompute: impl Fn(&V) -> bool,
+    ) -> V {
+        match self.get_with_hash(&key, hash) {
+            Some(v) if !should_recompute(&v) => return v,
+            _ => (),
+        }
+
+        // Check the value again as another call may have recomputed it while we
+        // were waiting for the right to evaluate the init closure.
+        let mut is_fresh = false;
+        let init = || match self.base.peek(&key) {
+            Some(v) if !should_recompute(&v) => {
+                is_fresh = true;
+                v
+            }
+            _ => init(),
+        };
+
+        match self.value_initializer.init_or_read(Arc::clone(&key), init) {
+            InitResult::Initialized(v) => {
+                if !is_fresh {
+                    self.base.record_load_success();
+                    self.insert_with_hash(Arc::clone(&key), hash, v.clone());
+                }
+                self.value_initializer
+                    .remove_waiter(&key, TypeId::of::<()>());
+                v
+            }
+            InitResult::ReadExisting(v) => v,
+            InitResult::InitErr(e) => match *e {},
+            // The waiters of `init_or_read` never have `NoValue`.
+            InitResult::InitNone => unreachable!(),
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
+        }
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value returned by the init closure if not exist. The init closure may
+    /// decline to produce a value by returning `None`; then nothing is inserted and
+    /// `None` is returned.
+    ///
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), this method
+    /// prevents to evaluate the init closure multiple times on the same key even if
+    /// the method is concurrently called by many threads. When the evaluated
+    /// closure returns `None`, the other calls waiting for it will also return
+    /// `None` without evaluating their closures.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// let v = cache.get_or_optionally_insert_with("a", || None);
+    /// assert_eq!(v, None);
+    /// assert_eq!(cache.get(&"a"), None);
+    ///
+    /// let v = cache.get_or_optionally_insert_with("a", || Some("alice"));
+    /// assert_eq!(v, Some("alice"));
+    /// assert_eq!(cache.get(&"a"), Some("alice"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the `init` closure has been panicked. See
+    /// `get_or_insert_with` for details.
+    pub fn get_or_optionally_insert_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> Option<V>,
+    ) -> Option<V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.get_or_optionally_insert_with_hash_and_fun(key, hash, init)
+    }
+
+    pub(crate) fn get_or_optionally_insert_with_hash_and_fun(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        init: impl FnOnce() -> Option<V>,
+    ) -> Option<V> {
+        if let Some(v) = self.get_with_hash(&key, hash) {
+            return Some(v);
+        }
+
+        match self
+            .value_initializer
+            .optionally_init_or_read(Arc::clone(&key), init)
+        {
+            InitResult::Initialized(v) => {
+                self.base.record_load_success();
+                self.insert_with_hash(Arc::clone(&key), hash, v.clone());
+                self.value_initializer
+                    .remove_waiter(&key, TypeId::of::<OptionallyInit>());
+                Some(v)
+            }
+            InitResult::ReadExisting(v) => Some(v),
+            InitResult::InitNone => None,
+            InitResult::InitErr(e) => match *e {},
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
+        }
+    }
+
+    /// Try to ensure the value of the key exists by inserting an `Ok` result of the
+    /// init closure if not exist, and returns a _clone_ of the value or the `Err`
+    /// returned by the closure.
+    ///
+    /// This method prevents to evaluate the init closure multiple times on the same
+    /// key even if the method is concurrently called by many threads; only one of
+    /// the calls evaluates its closure (as long as these closures return the same
+    /// error type), and other calls wait for that closure to complete.
+    ///
+    /// An `Err` is not inserted into the cache. By default, the next call retries
+    /// right away; use [`CacheBuilder::cache_errors_for`][cache-errors-for] to
+    /// return the same `Err` to the calls for a while instead.
+    ///
+    /// [cache-errors-for]: ./struct.CacheBuilder.html#method.cache_errors_for
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    /// use std::{path::Path, time::Duration, thread};
+    ///
+    /// /// This function tries to get the file size in bytes.
+    /// fn get_file_size(thread_id: u8, path: impl AsRef<Path>) -> Result<u64, std::io::Error> {
+    ///     println!("get_file_size() called by thread {}.", thread_id);
+    ///     Ok(std::fs::metadata(path)?.len())
+    /// }
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// // Spawn four threads.
+    /// let threads: Vec<_> = (0..4_u8)
+    ///     .map(|thread_id| {
+    ///         let my_cache = cache.clone();
+    ///         thread::spawn(move || {
+    ///             println!("Thread {} started.", thread_id);
+    ///
+    ///             // Try to insert and get the value for key1. Although all four
+    ///             // threads will call `get_or_try_insert_with` at the same time,
+    ///             // get_file_size() must be called only once.
+    ///             let value = my_cache.get_or_try_insert_with(
+    ///                 "key1",
+    ///                 || get_file_size(thread_id, "./Cargo.toml"),
+    ///             );
+    ///
+    ///             // Ensure the value exists now.
+    ///             assert!(value.is_ok());
+    ///             thread::sleep(Duration::from_millis(10));
+    ///             assert!(my_cache.get(&"key1").is_some());
+    ///
+    ///             println!(
+    ///                 "Thread {} got the value. (len: {})",
+    ///                 thread_id,
+    ///                 value.unwrap()
+    ///             );
+    ///         })
+    ///     })
+    ///     .collect();
+    ///
+    /// // Wait all threads to complete.
+    /// threads
+    ///     .into_iter()
+    ///     .for_each(|t| t.join().expect("Thread failed"));
+    /// ```
+    ///
+    /// **Result**
+    ///
+    /// - `get_file_size()` was called exactly once by thread 1.
+    /// - Other threads were blocked until thread 1 inserted the value.
+    ///
+    /// ```console
+    /// Thread 1 started.
+    /// Thread 2 started.
+    /// get_file_size() called by thread 1.
+    /// Thread 3 started.
+    /// Thread 0 started.
+    /// Thread 2 got the value. (len: 1466)
+    /// Thread 0 got the value. (len: 1466)
     /// Thread 1 got the value. (len: 1466)
     /// Thread 3 got the value. (len: 1466)
     /// ```
@@ -437,6 +1117,11 @@ where
     /// progress (e.g. thread 0, 2 and 3 above), this method will restart and resolve
     /// one of the remaining `init` closure.
     ///
+    /// The `init` closure must not call this method (or the other
+    /// `get_or_*insert_with` methods) for the same key, as it would wait for
+    /// itself. Such a re-entrant call is detected and this method panics instead
+    /// of deadlocking.
+    ///
     pub fn get_or_try_insert_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
     where
         F: FnOnce() -> Result<V, E>,
@@ -447,6 +1132,42 @@ where
         self.get_or_try_insert_with_hash_and_fun(key, hash, init)
     }
 
+    /// Try to ensure the value of the key exists by inserting an `Ok` result of the
+    /// init closure if not exist, and returns a _clone_ of the value or the `Err`
+    /// returned by the closure.
+    ///
+    /// This is the same as [`get_or_try_insert_with`][get-or-try-insert-with]. The
+    /// error is returned as `Arc<E>` with the concrete type of the error, so the
+    /// callers sharing the same failed load can match on it without downcasting.
+    ///
+    /// [get-or-try-insert-with]: #method.get_or_try_insert_with
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum LoadError {
+    ///     NotFound,
+    ///     Timeout,
+    /// }
+    ///
+    /// let cache: Cache<u32, String> = Cache::new(100);
+    ///
+    /// match cache.try_get_with(1, || Err(LoadError::NotFound)) {
+    ///     Err(e) => assert_eq!(*e, LoadError::NotFound),
+    ///     Ok(v) => panic!("unexpected value: {}", v),
+    /// }
+    /// ```
+    pub fn try_get_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+        E: Send + Sync + 'static,
+    {
+        self.get_or_try_insert_with(key, init)
+    }
+
     pub(crate) fn get_or_try_insert_with_hash_and_fun<F, E>(
         &self,
         key: Arc<K>,
@@ -461,11 +1182,23 @@ where
             return Ok(v);
         }
 
-        match self
-            .value_initializer
-            .try_init_or_read(Arc::clone(&key), init)
-        {
+        // Record the failure here rather than on `InitErr`, which is also returned
+        // to the other callers waiting for the same init closure.
+        let init = || {
+            let result = init();
+            if result.is_err() {
+                self.base.record_load_failure();
+            }
+            result
+        };
+
+        match self.value_initializer.try_init_or_read(
+            Arc::clone(&key),
+            self.base.current_time(),
+            init,
+        ) {
             InitResult::Initialized(v) => {
+                self.base.record_load_success();
                 self.insert_with_hash(Arc::clone(&key), hash, v.clone());
                 self.value_initializer
                     .remove_waiter(&key, TypeId::of::<E>());
@@ -473,6 +1206,9 @@ where
             }
             InitResult::ReadExisting(v) => Ok(v),
             InitResult::InitErr(e) => Err(e),
+            // The waiters of `try_init_or_read` never have `NoValue`.
+            InitResult::InitNone => unreachable!(),
+            InitResult::InitPanicked => resume_unwind(Box::new(InitPanicked)),
         }
     }
 
@@ -485,192 +1221,3150 @@ where
         self.insert_with_hash(key, hash, value)
     }
 
-    pub(crate) fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
-        let op = self.base.do_insert_with_hash(key, hash, value);
-        let hk = self.base.housekeeper.as_ref();
-        Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to insert");
-    }
-
-    /// Discards any cached value for the key.
+    /// Inserts a key-value pair into the cache, returning an error instead of
+    /// panicking when the write cannot be recorded.
     ///
-    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
-    /// on the borrowed form _must_ match those for the key type.
-    pub fn invalidate<Q>(&self, key: &Q)
-    where
-        Arc<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(entry) = self.base.remove(key) {
-            let op = WriteOp::Remove(entry);
-            let hk = self.base.housekeeper.as_ref();
-            Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to remove");
-        }
+    /// `insert` panics if the write operation channel of the cache has been
+    /// disconnected. This method returns
+    /// [`CacheError::WriteChannelDisconnected`][disconnected] in that case, so the
+    /// caller can handle a broken cache (e.g. by rebuilding it).
+    ///
+    /// [disconnected]: ../enum.CacheError.html#variant.WriteChannelDisconnected
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), CacheError> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.try_insert_with_hash(key, hash, value)
     }
 
-    /// Discards all cached values.
+    /// Inserts a key-value pair into the cache, and reports whether the key was
+    /// newly added or its value was replaced, and whether the new entry is expected
+    /// to be admitted. See [`InsertReport`][insert-report] for details.
     ///
-    /// This method returns immediately and a background thread will evict all the
-    /// cached values inserted before the time when this method was called. It is
-    /// guaranteed that the `get` method must not return these invalidated values
-    /// even if they have not been evicted.
+    /// This is useful to apply back pressure when the cache is saturated and the
+    /// inserts cause evictions or get rejected. Unlike `insert`, this method may
+    /// wait for the housekeeper to finish applying the pending writes, as it
+    /// needs the eviction deques to predict the admission.
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
-    pub fn invalidate_all(&self) {
-        self.base.invalidate_all();
+    /// [insert-report]: ../policy/enum.InsertReport.html
+    pub fn insert_with_report(&self, key: K, value: V) -> InsertReport {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_report(key, hash, value)
     }
 
-    /// Discards cached values that satisfy a predicate.
-    ///
-    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
-    /// method returns immediately and a background thread will apply the closure to
-    /// each cached value inserted before the time when `invalidate_entries_if` was
-    /// called. If the closure returns `true` on a value, that value will be evicted
-    /// from the cache.
-    ///
-    /// Also the `get` method will apply the closure to a value to determine if it
-    /// should have been invalidated. Therefore, it is guaranteed that the `get`
-    /// method must not return invalidated values.
-    ///
-    /// Note that you must call
-    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
-    /// at the cache creation time as the cache needs to maintain additional internal
-    /// data structures to support this method. Otherwise, calling this method will
-    /// fail with a
-    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    pub(crate) fn insert_with_hash_and_report(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> InsertReport {
+        let (op, report) = self.base.do_insert_with_report(key, hash, value);
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to insert");
+        report
+    }
+
+    /// Inserts a key-value pair into the cache only when the cache does not have a
+    /// value for the key.
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
+    /// Returns `Ok(())` if the pair was inserted, or `Err(value)` to hand the
+    /// value back if the cache already had a value for the key. An expired or
+    /// invalidated entry is treated as absent and replaced.
     ///
-    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
-    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
-    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
-    where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
-    {
-        self.base.invalidate_entries_if(Arc::new(predicate))
+    /// The check and the insertion are done atomically, so if multiple threads
+    /// call this method for the same key at the same time, only one of them will
+    /// succeed.
+    pub fn insert_if_absent(&self, key: K, value: V) -> Result<(), V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_if_absent_with_hash(key, hash, value)
     }
 
-    pub(crate) fn invalidate_entries_with_arc_fun<F>(
+    pub(crate) fn insert_if_absent_with_hash(
         &self,
-        predicate: Arc<F>,
-    ) -> Result<PredicateId, PredicateError>
-    where
-        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
-    {
-        self.base.invalidate_entries_if(predicate)
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<(), V> {
+        let op = self.base.do_insert_if_absent_with_hash(key, hash, value)?;
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to insert");
+        Ok(())
     }
 
-    /// Returns the `max_capacity` of this cache.
-    pub fn max_capacity(&self) -> usize {
-        self.base.max_capacity()
+    /// Replaces the value of the key only when the cache has a value for the key,
+    /// and returns the previous value.
+    ///
+    /// Returns `None` and does nothing if the cache does not have a value for the
+    /// key. An expired or invalidated entry is treated as absent. This is useful to
+    /// refresh the entries that are already cached, without populating the cache
+    /// with the other ones.
+    ///
+    /// The check and the replacement are done atomically, so if the entry is
+    /// invalidated by another thread at the same time, this method will never
+    /// bring it back.
+    pub fn replace(&self, key: K, value: V) -> Option<V> {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.replace_with_hash(key, hash, value)
     }
 
-    /// Returns the `time_to_live` of this cache.
-    pub fn time_to_live(&self) -> Option<Duration> {
-        self.base.time_to_live()
+    pub(crate) fn replace_with_hash(&self, key: Arc<K>, hash: u64, value: V) -> Option<V> {
+        let (old_value, op) = self.base.do_replace_with_hash(key, hash, value)?;
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to replace");
+        Some(old_value)
     }
 
-    /// Returns the `time_to_idle` of this cache.
+    /// Inserts a key-value pair into the cache with the time-to-live for the entry.
+    ///
+    /// The entry will be expired after the specified duration past from this
+    /// insert, or after the cache-wide `time_to_live` (if set) if that is shorter.
+    /// If the cache has this key present, the value and its time-to-live are
+    /// updated. A later `insert` of the same key without a time-to-live resets the
+    /// entry to the cache-wide `time_to_live`.
+    ///
+    /// An expired entry will never be returned by `get`. However, it may remain in
+    /// the cache until the entries written before it have been evicted.
+    pub fn insert_with_ttl(&self, key: K, value: V, time_to_live: Duration) {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.insert_with_hash_and_ttl(key, hash, value, Some(time_to_live))
+    }
+
+    pub(crate) fn insert_with_hash(&self, key: Arc<K>, hash: u64, value: V) {
+        self.insert_with_hash_and_ttl(key, hash, value, None)
+    }
+
+    pub(crate) fn insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_live: Option<Duration>,
+    ) {
+        self.try_insert_with_hash_and_ttl(key, hash, value, time_to_live)
+            .expect("Failed to insert");
+    }
+
+    pub(crate) fn try_insert_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+    ) -> Result<(), CacheError> {
+        self.try_insert_with_hash_and_ttl(key, hash, value, None)
+    }
+
+    fn try_insert_with_hash_and_ttl(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        time_to_live: Option<Duration>,
+    ) -> Result<(), CacheError> {
+        let op = self
+            .base
+            .do_insert_with_hash(key, hash, value, time_to_live);
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+            .map_err(|_| CacheError::WriteChannelDisconnected)
+    }
+
+    /// Inserts the key-value pairs into the cache.
+    ///
+    /// This is equivalent to calling `insert` for each pair, but has less overhead
+    /// when inserting many entries at once. Instead of checking whether to apply
+    /// the pending writes on every insert, it schedules the housekeeper only when
+    /// the write operation channel gets full, and once after the last pair.
+    ///
+    /// If the cache already has a value for a key, the value is updated.
+    pub fn insert_many(&self, entries: impl IntoIterator<Item = (K, V)>) {
+        self.insert_many_with_arc_keys(entries.into_iter().map(|(k, v)| (Arc::new(k), v)))
+    }
+
+    pub(crate) fn insert_many_with_arc_keys(&self, entries: impl IntoIterator<Item = (Arc<K>, V)>) {
+        let ch = &self.base.write_op_ch;
+        let hk = self.base.housekeeper.as_ref();
+        let mut inserted = false;
+
+        for (key, value) in entries {
+            let hash = self.base.hash(&key);
+            let op = self.base.do_insert_with_hash(key, hash, value, None);
+            Self::schedule_write_op_in_batch(ch, op, hk).expect("Failed to insert");
+            inserted = true;
+        }
+
+        if inserted {
+            if let Some(h) = hk {
+                h.try_schedule_sync();
+            }
+        }
+    }
+
+    /// Computes a new value for the key from the current one (if any) with the
+    /// closure, and returns a _clone_ of the new value.
+    ///
+    /// The closure takes `Some(&V)` if the cache has a value for the key, or `None`
+    /// otherwise. If it returns `Some(V)`, the value is inserted to the cache. If it
+    /// returns `None`, the current value (if any) is discarded.
+    ///
+    /// The calls of this method on the same key are serialized; while a thread is
+    /// evaluating its closure, other threads calling this method with the same key
+    /// will wait for it to complete, and then evaluate their closures with the new
+    /// value. Note that `insert` and `invalidate` do not wait, so the value written
+    /// by them while a closure is being evaluated may be overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache = Cache::new(100);
+    ///
+    /// // Increment the counter.
+    /// let new_value = cache.compute("counter", |v| Some(v.map_or(1, |v| v + 1)));
+    /// assert_eq!(new_value, Some(1));
+    /// let new_value = cache.compute("counter", |v| Some(v.map_or(1, |v| v + 1)));
+    /// assert_eq!(new_value, Some(2));
+    ///
+    /// // Remove the counter.
+    /// assert_eq!(cache.compute("counter", |_v| None), None);
+    /// assert_eq!(cache.get(&"counter"), None);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the closure has been panicked. Other threads waiting
+    /// for the closure will go on and evaluate their own closures.
+    pub fn compute<F>(&self, key: K, f: F) -> Option<V>
+    where
+        F: FnOnce(Option<&V>) -> Option<V>,
+    {
+        let hash = self.base.hash(&key);
+        let key = Arc::new(key);
+        self.compute_with_hash(key, hash, f)
+    }
+
+    pub(crate) fn compute_with_hash<F>(&self, key: Arc<K>, hash: u64, f: F) -> Option<V>
+    where
+        F: FnOnce(Option<&V>) -> Option<V>,
+    {
+        self.value_initializer.serialize(&key, || {
+            let current = self.get_with_hash(&key, hash);
+            match f(current.as_ref()) {
+                Some(v) => {
+                    self.insert_with_hash(Arc::clone(&key), hash, v.clone());
+                    Some(v)
+                }
+                None => {
+                    self.invalidate(&key);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Returns the [`Entry`][entry-struct] of the key for in-place manipulation.
+    ///
+    /// Unlike `std::collections::HashMap::entry`, the returned `Entry` does not
+    /// hold any lock on the cache. See the documentation of `Entry` for details.
+    ///
+    /// [entry-struct]: ./struct.Entry.html
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::Cache;
+    ///
+    /// let cache: Cache<&str, u32> = Cache::new(100);
+    ///
+    /// // Does nothing as the entry is empty.
+    /// cache.entry("counter").and_modify(|v| *v += 1);
+    /// assert_eq!(cache.get(&"counter"), None);
+    ///
+    /// assert_eq!(cache.entry("counter").or_insert(1), 1);
+    /// assert_eq!(cache.entry("counter").and_modify(|v| *v += 1).or_default(), 2);
+    /// ```
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, S> {
+        let hash = self.base.hash(&key);
+        Entry::new(self, Arc::new(key), hash)
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(kv) = self.base.remove(key) {
+            let op = WriteOp::Remove(kv);
+            let hk = self.base.housekeeper.as_ref();
+            Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to remove");
+        }
+    }
+
+    /// Discards any cached values for the keys, and returns the number of the
+    /// entries removed.
+    ///
+    /// This is equivalent to calling `invalidate` for each key, but has less
+    /// overhead when invalidating many keys at once. Like `insert_many`, it
+    /// schedules the housekeeper only when the write operation channel gets full,
+    /// and once after the last key.
+    ///
+    /// The count includes the entries that have been expired but not evicted yet.
+    pub fn invalidate_keys_in(&self, keys: impl IntoIterator<Item = K>) -> usize {
+        let ch = &self.base.write_op_ch;
+        let hk = self.base.housekeeper.as_ref();
+        let mut removed = 0;
+
+        for key in keys {
+            if let Some(kv) = self.base.remove(&key) {
+                let op = WriteOp::Remove(kv);
+                Self::schedule_write_op_in_batch(ch, op, hk).expect("Failed to remove");
+                removed += 1;
+            }
+        }
+
+        if removed > 0 {
+            if let Some(h) = hk {
+                h.try_schedule_sync();
+            }
+        }
+        removed
+    }
+
+    /// Discards the cached value for the key if it satisfies the condition.
+    pub(crate) fn invalidate_if<Q>(&self, key: &Q, condition: impl FnMut(&V) -> bool)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(kv) = self.base.remove_if(key, condition) {
+            let op = WriteOp::Remove(kv);
+            let hk = self.base.housekeeper.as_ref();
+            Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to remove");
+        }
+    }
+
+    /// Discards any cached value for the key, and returns a _clone_ of the value.
+    ///
+    /// Returns `None` if the cache did not have a value for the key, or the value
+    /// has been expired or invalidated.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let kv = self.base.remove(key)?;
+        // Check the validity before scheduling the op because the housekeeper may
+        // update the timestamps of the entry.
+        let value = if self.base.is_valid_entry(&kv) {
+            Some(kv.entry.value.clone())
+        } else {
+            None
+        };
+        let op = WriteOp::Remove(kv);
+        let hk = self.base.housekeeper.as_ref();
+        Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to remove");
+        value
+    }
+
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately and a background thread will evict all the
+    /// cached values inserted before the time when this method was called. It is
+    /// guaranteed that the `get` method must not return these invalidated values
+    /// even if they have not been evicted.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item. Use the `reset` method to clear it as well.
+    pub fn invalidate_all(&self) {
+        self.base.invalidate_all();
+    }
+
+    /// Discards all cached values, and clears the historic popularity estimator of
+    /// keys.
+    ///
+    /// Unlike `invalidate_all`, the keys inserted after calling this method are
+    /// not penalized by the access frequencies recorded before the call. This is
+    /// useful when the access pattern of the cache changes completely, e.g. when
+    /// the cache is reused for a different tenant.
+    pub fn reset(&self) {
+        self.base.reset();
+    }
+
+    /// Records an access to the key in the historic popularity estimator of keys,
+    /// without reading or inserting the value.
+    ///
+    /// This is an advanced tuning knob. With the TinyLFU eviction policy, a new
+    /// key is admitted only when it is accessed more often than the entry it would
+    /// evict, so a popular key may be rejected a few times before the estimator
+    /// learns about it. Calling this method for the popular keys (e.g. replaying
+    /// historic access logs) before serving traffic lets their first inserts be
+    /// admitted. It does nothing with the LRU eviction policy.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn record_access<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.record_access(key);
+    }
+
+    /// Discards all cached values, and waits for them to be removed from the cache.
+    ///
+    /// Unlike `invalidate_all`, this method applies the pending writes and removes
+    /// every entry from the internal hash table before returning, so that
+    /// `entry_count` and `weighted_size` are zero right after the call. This is
+    /// more expensive than `invalidate_all`, which only records a timestamp and
+    /// takes O(1) time: `clear` takes O(n) time on the calling thread, where n is
+    /// the number of entries, and holds the lock of the housekeeping tasks
+    /// meanwhile. Entries inserted by other threads while this method is running
+    /// may remain in the cache.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys. Call the `reset` method afterward to clear it
+    /// as well.
+    pub fn clear(&self) {
+        // Apply the pending writes first so that all the entries are in the deques.
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+        for kh in self.base.key_hashes() {
+            self.invalidate(&kh.key);
+        }
+        self.base.clear_spill();
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+    }
+
+    /// Retains only the entries specified by the predicate, and waits for the
+    /// other entries to be removed from the cache.
+    ///
+    /// In other words, removes all entries for which `f(&k, &v)` returns `false`.
+    /// Unlike `invalidate_entries_if`, which returns immediately and lets a
+    /// background thread apply the closure, this method scans the cache on the
+    /// calling thread, and applies the pending writes before returning, so that
+    /// `entry_count` and `weighted_size` reflect the result right after the call.
+    /// It does not need
+    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures].
+    ///
+    /// This method takes O(n) time, where n is the number of entries, but it does
+    /// not lock out the other threads; they can keep reading and writing the cache
+    /// meanwhile. So it is weakly consistent with the concurrent writes: an entry
+    /// inserted or updated by another thread while this method is running may
+    /// remain in the cache without being passed to `f`. The removed entries are
+    /// passed to the eviction listener with `RemovalCause::Explicit`.
+    ///
+    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
+        // Apply the pending writes first so that all the entries are in the deques.
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+        for kh in self.base.key_hashes() {
+            self.invalidate_if(&kh.key, |v| !f(&kh.key, v));
+        }
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+    }
+
+    /// Discards cached values that satisfy a predicate.
+    ///
+    /// `invalidate_entries_if` takes a closure that returns `true` or `false`. This
+    /// method returns immediately and a background thread will apply the closure to
+    /// each cached value inserted before the time when `invalidate_entries_if` was
+    /// called. If the closure returns `true` on a value, that value will be evicted
+    /// from the cache.
+    ///
+    /// Also the `get` method will apply the closure to a value to determine if it
+    /// should have been invalidated. Therefore, it is guaranteed that the `get`
+    /// method must not return invalidated values.
+    ///
+    /// Note that you must call
+    /// [`CacheBuilder::support_invalidation_closures`][support-invalidation-closures]
+    /// at the cache creation time as the cache needs to maintain additional internal
+    /// data structures to support this method. Otherwise, calling this method will
+    /// fail with a
+    /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
+    ///
+    /// If the cache is built with
+    /// [`CacheBuilder::max_pending_invalidation_closures`][max-pending], this
+    /// method fails with
+    /// [`PredicateError::TooManyPending`][too-many-pending-error] when that many
+    /// closures have not been applied yet.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item.
+    ///
+    /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
+    /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
+    /// [max-pending]: ./struct.CacheBuilder.html#method.max_pending_invalidation_closures
+    /// [too-many-pending-error]: ../enum.PredicateError.html#variant.TooManyPending
+    pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.base
+            .invalidate_entries_if(Arc::new(move |k, v, _meta| predicate(k, v)))
+    }
+
+    /// Discards cached values that satisfy a predicate on the entry metadata as
+    /// well as the key and value.
+    ///
+    /// This works like `invalidate_entries_if`, but the closure also receives an
+    /// [`EntryMeta`][entry-meta] telling the age, idle time and weight of the entry.
+    /// For example, `|_k, _v, meta| meta.age() >= Duration::from_secs(3600)`
+    /// discards the values inserted more than an hour before this method was
+    /// called, without keeping a timestamp in the values.
+    ///
+    /// [entry-meta]: ../policy/struct.EntryMeta.html
+    pub fn invalidate_entries_with_meta_if<F>(
+        &self,
+        predicate: F,
+    ) -> Result<PredicateId, PredicateError>
+    where
+        F: Fn(&K, &V, EntryMeta) -> bool + Send + Sync + 'static,
+    {
+        self.base.invalidate_entries_if(Arc::new(predicate))
+    }
+
+    /// Returns the number of the entries that have been removed by the background
+    /// invalidation task, because they matched the predicate of the given
+    /// `PredicateId` (returned by `invalidate_entries_if`).
+    ///
+    /// An entry matched by more than one predicate is counted for each of them.
+    /// The entries that matched the predicate but were removed by other means
+    /// (e.g. `invalidate` or expiration) before the task visited them are not
+    /// counted.
+    ///
+    /// The count is available while the predicate is registered. Returns `None`
+    /// once the predicate has been applied to all the entries and removed, or if
+    /// the `PredicateId` is unknown to this cache.
+    pub fn predicate_removed_count(&self, id: &PredicateId) -> Option<u64> {
+        self.base.predicate_removed_count(id)
+    }
+
+    /// Cancels the invalidation by the predicate of the given `PredicateId` (returned by
+    /// `invalidate_entries_if`),
+    /// so that the predicate will not be applied to the entries anymore. Returns
+    /// `true` if the predicate was still registered, or `false` if it has already
+    /// been applied to all the entries, has been cancelled, or is unknown to this
+    /// cache.
+    ///
+    /// The entries that have already been invalidated by the predicate remain
+    /// invalidated. If the background invalidation task is running, this method
+    /// waits for it to finish the current batch of the entries.
+    pub fn cancel_invalidation(&self, id: PredicateId) -> bool {
+        self.base.cancel_invalidation(&id)
+    }
+
+    pub(crate) fn has_entry<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.base.has_entry(key)
+    }
+
+    pub(crate) fn set_sweep_predicate(&self, predicate: PredicateFun<K, V>, interval: Duration) {
+        self.base.set_sweep_predicate(predicate, interval);
+    }
+
+    pub(crate) fn invalidate_entries_with_arc_fun(
+        &self,
+        predicate: PredicateFun<K, V>,
+    ) -> Result<PredicateId, PredicateError> {
+        self.base.invalidate_entries_if(predicate)
+    }
+
+    /// Modifies the values of all cached entries in place by applying the closure.
+    ///
+    /// `modify_all` takes a closure that receives the key and a mutable reference to
+    /// a _clone_ of the value. Each entry is replaced with the modified value
+    /// atomically, so concurrent `get` calls will see either the old value or the
+    /// new value of an entry, never a partially modified one. Expired or
+    /// invalidated entries are left as is.
+    ///
+    /// Like the `insert` method, modifying an entry resets its time-to-live and
+    /// time-to-idle.
+    ///
+    /// This method runs in O(n) time on the calling thread. It does _not_ take a
+    /// snapshot of the whole cache; entries inserted or updated by other threads
+    /// while this method is running may or may not be modified.
+    pub fn modify_all(&self, f: impl Fn(&K, &mut V)) {
+        // Apply the pending writes so that recently inserted entries are also
+        // modified.
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+
+        let hk = self.base.housekeeper.as_ref();
+        for kh in self.base.key_hashes() {
+            if let Some(op) = self.base.do_modify_with_hash(kh.key, kh.hash, &f) {
+                Self::schedule_write_op(&self.base.write_op_ch, op, hk).expect("Failed to modify");
+            }
+        }
+    }
+
+    /// Returns the approximate number of entries in this cache.
+    ///
+    /// The value is approximate because `insert`, `invalidate` and eviction are
+    /// applied to the internal hash table asynchronously via the write operation
+    /// channel. Entries that have been expired or scheduled for eviction may still
+    /// be counted until the housekeeper processes them.
+    ///
+    /// If the cache is built with
+    /// [`CacheBuilder::exact_entry_count`][exact-entry-count], this returns the
+    /// exact number of the entries admitted by the housekeeper instead.
+    ///
+    /// [exact-entry-count]: ./struct.CacheBuilder.html#method.exact_entry_count
+    pub fn entry_count(&self) -> usize {
+        self.base.entry_count()
+    }
+
+    /// Returns the approximate total weight of the entries in this cache.
+    ///
+    /// The weight of an entry is given by the weigher set by
+    /// `CacheBuilder::weigher`. Without a weigher, every entry weighs one, so this
+    /// returns the number of entries.
+    ///
+    /// Only the entries that have been applied to the cache policy are counted.
+    /// Call `run_pending_tasks` beforehand to get the up-to-date value.
+    pub fn weighted_size(&self) -> u64 {
+        self.base.weighted_size()
+    }
+
+    /// Performs the pending maintenance tasks of the cache on the calling thread.
+    ///
+    /// This applies the buffered reads and writes to the cache policy, and runs
+    /// the eviction of the expired and invalidated entries, and of the entries
+    /// exceeding the `max_capacity`. After this method returns, `entry_count`,
+    /// `weighted_size` and `stats` reflect all the writes made before the call.
+    ///
+    /// Normally a background housekeeper runs these tasks periodically, or when
+    /// enough reads and writes have been buffered, so you do not need to call this
+    /// method. Call it when you need an up-to-date view of the cache, e.g. in tests.
+    /// If the cache is built with
+    /// [`CacheBuilder::manual_housekeeping`][manual-housekeeping], the tasks are
+    /// not run automatically, and you need to call this method periodically.
+    ///
+    /// [manual-housekeeping]: ./struct.CacheBuilder.html#method.manual_housekeeping
+    pub fn run_pending_tasks(&self) {
+        self.base.inner.sync(MAX_SYNC_REPEATS);
+    }
+
+    /// Returns a snapshot of the statistics of this cache.
+    ///
+    /// The statistics are recorded only when the cache is built with
+    /// `CacheBuilder::record_stats`; otherwise all of the counters are zero.
+    pub fn stats(&self) -> CacheStats {
+        self.base.stats()
+    }
+
+    /// Returns the number of the eviction events dropped because the channel
+    /// created by the `eviction_channel` method of the builder was full.
+    ///
+    /// Always returns `0` if the cache was not built by that method, or was built
+    /// with the `ChannelFullPolicy::Block` policy.
+    pub fn dropped_eviction_events(&self) -> u64 {
+        self.dropped_eviction_events.load(Ordering::Acquire)
+    }
+
+    /// Returns the `max_capacity` of this cache.
+    pub fn max_capacity(&self) -> usize {
+        self.base.max_capacity()
+    }
+
+    /// Changes the `max_capacity` of this cache, e.g. in response to memory
+    /// pressure.
+    ///
+    /// The change is applied asynchronously. When shrinking, the entries exceeding
+    /// the new capacity are evicted by the next housekeeping pass (or by
+    /// `run_pending_tasks`), so the cache may stay above the new capacity for a
+    /// while. When growing, the cache simply accepts more entries.
+    pub fn set_max_capacity(&self, max_capacity: usize) {
+        self.base.set_max_capacity(max_capacity)
+    }
+
+    /// Returns the `time_to_live` of this cache.
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.base.time_to_live()
+    }
+
+    /// Returns the `time_to_idle` of this cache.
     pub fn time_to_idle(&self) -> Option<Duration> {
         self.base.time_to_idle()
     }
 
-    /// Returns the number of internal segments of this cache.
-    ///
-    /// `Cache` always returns `1`.
-    pub fn num_segments(&self) -> usize {
-        1
+    /// Returns the `initial_capacity` of this cache, or `None` if it was not set.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        self.base.initial_capacity()
+    }
+
+    /// Returns the eviction policy of this cache.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.base.eviction_policy()
+    }
+
+    /// Returns `true` if this cache supports the invalidation closures, which is
+    /// enabled by the `support_invalidation_closures` method of the builder.
+    pub fn invalidation_closures_enabled(&self) -> bool {
+        self.base.invalidation_closures_enabled()
+    }
+
+    /// Returns the number of internal segments of this cache.
+    ///
+    /// `Cache` always returns `1`.
+    pub fn num_segments(&self) -> usize {
+        1
+    }
+}
+
+/// A [`Cache`][cache-struct] storing the values behind `Arc`s.
+///
+/// The cache returns a _clone_ of the value on `get`, so the values of a `Cache`
+/// must implement `Clone`. A `SharedCache<K, V>` is a `Cache<K, Arc<V>>`, whose
+/// values are cheap to clone, and `V` itself does not have to implement `Clone`.
+/// The `insert_shared` method wraps the value in an `Arc`.
+///
+/// The weigher of a `SharedCache` is given a `&Arc<V>`, which dereferences to the
+/// inner `V`, so it can measure the inner value as is.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::sync::{CacheBuilder, SharedCache};
+/// use std::sync::Arc;
+///
+/// // This struct does not implement `Clone`.
+/// struct Blob(Vec<u8>);
+///
+/// let cache: SharedCache<u32, Blob> = CacheBuilder::new(1024)
+///     .weigher(|_k, v: &Arc<Blob>| v.0.len() as u32)
+///     .build();
+///
+/// cache.insert_shared(0, Blob(vec![0u8; 512]));
+/// let blob: Arc<Blob> = cache.get_shared(&0).unwrap();
+/// assert_eq!(blob.0.len(), 512);
+/// ```
+///
+/// [cache-struct]: ./struct.Cache.html
+pub type SharedCache<K, V, S = RandomState> = Cache<K, Arc<V>, S>;
+
+impl<K, V, S> Cache<K, Arc<V>, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    /// Wraps the value in an `Arc` and inserts it into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub fn insert_shared(&self, key: K, value: V) {
+        self.insert(key, Arc::new(value))
+    }
+
+    /// Returns the `Arc` of the value corresponding to the key.
+    ///
+    /// This is the same as `get`, as cloning the `Arc` does not clone the value.
+    pub fn get_shared<Q>(&self, key: &Q) -> Option<Arc<V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key)
+    }
+}
+
+impl<K, V, S> ConcurrentCacheExt<K, V> for Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    fn sync(&self) {
+        self.run_pending_tasks();
+    }
+}
+
+impl<K, V> std::iter::FromIterator<(K, V)> for Cache<K, V, RandomState>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Constructs a `Cache<K, V>` holding the key-value pairs of the iterator.
+    ///
+    /// The `max_capacity` of the cache is set to the number of the pairs, so all
+    /// of them are stored. (If the iterator yields a key more than once, the last
+    /// value wins, and the cache has room for more entries.) The other
+    /// configurations are the defaults of `Cache::new`.
+    ///
+    /// The pending tasks are run before returning, so `entry_count` and
+    /// `weighted_size` already reflect the pairs.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let pairs: Vec<_> = iter.into_iter().collect();
+        let cache = Self::new(pairs.len());
+        for (key, value) in pairs {
+            cache.insert(key, value);
+        }
+        cache.run_pending_tasks();
+        cache
+    }
+}
+
+// private methods
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    #[inline]
+    fn schedule_write_op(
+        ch: &Sender<WriteOp<K, V>>,
+        op: WriteOp<K, V>,
+        housekeeper: Option<&HouseKeeperArc<K, V, S>>,
+    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let mut op = op;
+        let mut backoff = Backoff::new(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
+
+        // NOTES:
+        // - This will block when the channel is full.
+        // - We are doing a busy-loop here. We were originally calling `ch.send(op)?`,
+        //   but we got a notable performance degradation. The backoff spins and
+        //   yields first, and then sleeps up to `WRITE_RETRY_INTERVAL_MICROS`.
+        loop {
+            BaseCache::apply_reads_writes_if_needed(ch, housekeeper);
+            match ch.try_send(op) {
+                Ok(()) => break,
+                Err(TrySendError::Full(op1)) => {
+                    op = op1;
+                    if let Some(h) = housekeeper {
+                        h.on_write_channel_full();
+                    }
+                    backoff.snooze();
+                }
+                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn spawn_refresh<F, E>(&self, (key, entry): RefreshKey<K, V>, hash: u64, reload: F)
+    where
+        F: FnOnce(&K) -> Result<V, E> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>> + 'static,
+    {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let refresher = match &self.refresher {
+            Some(refresher) => refresher,
+            None => return,
+        };
+        if !self.value_initializer.try_start_refresh(&key) {
+            // Another refresh of the key is in flight.
+            return;
+        }
+
+        // Do not let the job own the refresher, so that the thread pool will not be
+        // released by its own thread.
+        let cache = Self {
+            refresher: None,
+            ..self.clone()
+        };
+        refresher.schedule(move || {
+            // Retain the current value if the reload fails or panics.
+            let error = match catch_unwind(AssertUnwindSafe(|| reload(&key))) {
+                Ok(Ok(v)) => {
+                    cache.replace_entry_with_hash(Arc::clone(&key), hash, v, &entry);
+                    None
+                }
+                Ok(Err(e)) => Some(RefreshError::Failed(e.into())),
+                Err(_) => Some(RefreshError::Panicked),
+            };
+            cache.value_initializer.finish_refresh(&key);
+            if let (Some(error), Some(listener)) = (error, &cache.refresh_error_listener) {
+                listener(key, error);
+            }
+        });
+    }
+
+    /// Replaces the value of the entry due for refresh, only when the key still
+    /// has the entry.
+    fn replace_entry_with_hash(
+        &self,
+        key: Arc<K>,
+        hash: u64,
+        value: V,
+        entry: &Arc<ValueEntry<K, V>>,
+    ) {
+        if let Some(op) = self
+            .base
+            .do_replace_entry_with_hash(key, hash, value, entry)
+        {
+            let hk = self.base.housekeeper.as_ref();
+            Self::schedule_write_op(&self.base.write_op_ch, op, hk)
+                .expect("Failed to replace a refreshed value");
+        }
+    }
+
+    /// Like `schedule_write_op`, but schedules the housekeeper only when the
+    /// channel is full. Used by `insert_many`.
+    #[inline]
+    fn schedule_write_op_in_batch(
+        ch: &Sender<WriteOp<K, V>>,
+        op: WriteOp<K, V>,
+        housekeeper: Option<&HouseKeeperArc<K, V, S>>,
+    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
+        let mut op = op;
+        let mut backoff = Backoff::new(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
+
+        loop {
+            match ch.try_send(op) {
+                Ok(()) => break,
+                Err(TrySendError::Full(op1)) => {
+                    op = op1;
+                    if let Some(h) = housekeeper {
+                        h.try_schedule_sync();
+                        h.on_write_channel_full();
+                    }
+                    backoff.snooze();
+                }
+                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// For unit tests.
+#[cfg(test)]
+impl<K, V, S> Cache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    pub(crate) fn is_table_empty(&self) -> bool {
+        self.table_size() == 0
+    }
+
+    pub(crate) fn table_size(&self) -> usize {
+        self.base.table_size()
+    }
+
+    pub(crate) fn invalidation_predicate_count(&self) -> usize {
+        self.base.invalidation_predicate_count()
+    }
+
+    pub(crate) fn reconfigure_for_testing(&mut self) {
+        self.base.reconfigure_for_testing();
+    }
+
+    pub(crate) fn on_demand_sync_count(&self) -> usize {
+        self.base
+            .housekeeper
+            .as_ref()
+            .map(|h| h.on_demand_sync_count())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
+        self.base.set_expiration_clock(clock);
+    }
+}
+
+// To see the debug prints, run test as `cargo test -- --nocapture`
+#[cfg(test)]
+mod tests {
+    use super::{Cache, SharedCache};
+    use crate::{
+        common::time::Clock,
+        notification::RemovalCause,
+        policy::EvictionPolicy,
+        stats::CacheStats,
+        sync::{CacheBuilder, SpillStore},
+    };
+
+    use crossbeam_channel::TrySendError;
+    use parking_lot::Mutex;
+    use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+
+    #[test]
+    fn on_maintenance() {
+        use crate::notification::MaintenanceReport;
+        use parking_lot::Mutex;
+
+        let reports = Arc::new(Mutex::new(Vec::<MaintenanceReport>::new()));
+        let reports1 = Arc::clone(&reports);
+
+        let mut cache = CacheBuilder::new(3)
+            .eviction_policy(EvictionPolicy::Lru)
+            .on_maintenance(move |report| reports1.lock().push(report))
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in 0..5 {
+            cache.insert(key, key);
+        }
+        assert_eq!(cache.get(&4), Some(4));
+        assert_eq!(cache.get(&5), None);
+        cache.run_pending_tasks();
+
+        let reports = reports.lock();
+        assert!(!reports.is_empty());
+        let sum = |f: fn(&MaintenanceReport) -> usize| reports.iter().map(f).sum::<usize>();
+        assert_eq!(sum(MaintenanceReport::read_count), 2);
+        assert_eq!(sum(MaintenanceReport::write_count), 5);
+        assert_eq!(sum(MaintenanceReport::evicted_count), 2);
+    }
+
+    #[test]
+    fn shared_cache() {
+        // This struct does not implement `Clone`.
+        #[derive(Debug, PartialEq)]
+        struct Blob(Vec<u8>);
+
+        let mut cache: SharedCache<&str, Blob> = CacheBuilder::new(1_000)
+            .weigher(|_k, v: &Arc<Blob>| v.0.len() as u32)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert_shared("a", Blob(vec![0; 300]));
+        cache.insert_shared("b", Blob(vec![1; 400]));
+        cache.run_pending_tasks();
+
+        let a1 = cache.get_shared(&"a").unwrap();
+        let a2 = cache.get_shared(&"a").unwrap();
+        assert_eq!(*a1, Blob(vec![0; 300]));
+        // The value is shared, not cloned.
+        assert!(Arc::ptr_eq(&a1, &a2));
+        // The weigher measured the inner values.
+        assert_eq!(cache.weighted_size(), 700);
+    }
+
+    #[test]
+    fn from_iter() {
+        let cache: Cache<_, _> = (0..100).map(|i| (i, i * 10)).collect();
+        assert_eq!(cache.max_capacity(), 100);
+        assert_eq!(cache.entry_count(), 100);
+        for i in 0..100 {
+            assert_eq!(cache.get(&i), Some(i * 10));
+        }
+
+        let cache: Cache<u32, u32> = std::iter::empty().collect();
+        assert_eq!(cache.max_capacity(), 0);
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    #[test]
+    fn debug_format() {
+        let cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(60))
+            .build();
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        let s = format!("{:?}", cache);
+        assert!(s.starts_with("Cache {"));
+        assert!(s.contains("max_capacity: 100"));
+        assert!(s.contains("num_segments: 1"));
+        assert!(s.contains("time_to_live: Some(60s)"));
+        assert!(s.contains("time_to_idle: None"));
+        assert!(s.contains("entry_count: 1"));
+        // The entries are not printed.
+        assert!(!s.contains("alice"));
+    }
+
+    #[test]
+    fn basic_single_thread() {
+        let mut cache = Cache::new(3);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 1
+
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        // counts: a -> 1, b -> 1, c -> 1
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // counts: a -> 2, b -> 2, c -> 1
+
+        // "d" should not be admitted because its frequency is too low.
+        cache.insert("d", "david"); //   count: d -> 0
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None); //   d -> 1
+
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None); //   d -> 2
+
+        // "d" should be admitted and "c" should be evicted
+        // because d's frequency is higher then c's.
+        cache.insert("d", "dennis");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"d"), Some("dennis"));
+
+        cache.invalidate(&"b");
+        assert_eq!(cache.get(&"b"), None);
+    }
+
+    #[test]
+    fn record_access() {
+        let mut cache = Cache::new(3);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 1, c -> 1
+
+        // Pre-warm "d" without inserting it.
+        for _ in 0..5 {
+            cache.record_access(&"d");
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), None);
+        // counts: a -> 1, b -> 1, c -> 1, d -> 6
+
+        // Unlike the cold "d" in `basic_single_thread`, "d" is admitted on the
+        // first insert.
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), Some("david"));
+        assert_eq!(cache.entry_count(), 3);
+    }
+
+    #[test]
+    fn admit_on_insert() {
+        let mut cache = CacheBuilder::new(3).admit_on_insert().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+
+        for _ in 0..3 {
+            assert_eq!(cache.get(&"c"), Some("cindy"));
+        }
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // LRU order: c -> a -> b
+        // counts: a -> 1, b -> 1, c -> 3
+
+        // The cold "d" is admitted even though the cache is full. "a", the least
+        // frequently used (and then least recently used) entry, is evicted
+        // instead of "c" at the LRU end.
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), Some("david"));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        assert_eq!(cache.entry_count(), 3);
+    }
+
+    #[test]
+    fn peek() {
+        let mut cache = CacheBuilder::new(3).record_stats().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in &["a", "b", "c"] {
+            cache.insert(*key, *key);
+            cache.get(key);
+        }
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 1, c -> 1
+
+        // "d" should not be admitted because its frequency is too low.
+        cache.insert("d", "d");
+        cache.run_pending_tasks();
+        assert_eq!(cache.peek(&"a"), Some("a"));
+
+        // Peeking does not increase the frequency of "d".
+        for _ in 0..10 {
+            assert_eq!(cache.peek(&"d"), None);
+        }
+        cache.insert("d", "d");
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.entry_count(), 3);
+
+        // Peeking is not counted in the statistics.
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 3);
+        assert_eq!(stats.miss_count(), 0);
+    }
+
+    #[test]
+    fn get_ref() {
+        let mut cache = CacheBuilder::new(100).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let value = Arc::new("alice".to_string());
+        let weak = Arc::downgrade(&value);
+        cache.insert("a", value);
+        cache.run_pending_tasks();
+
+        let guard = cache.get_ref(&"a").unwrap();
+        assert_eq!(guard.as_str(), "alice");
+        assert!(cache.get_ref(&"b").is_none());
+
+        // Invalidate the entry on another thread while the guard is held.
+        {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                cache.invalidate(&"a");
+                cache.run_pending_tasks();
+            })
+            .join()
+            .unwrap();
+        }
+        assert!(cache.get(&"a").is_none());
+
+        // The value is not dropped while the guard is held.
+        assert!(weak.upgrade().is_some());
+        assert_eq!(guard.as_str(), "alice");
+
+        drop(guard);
+        cache.run_pending_tasks();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn clear() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+        // Not applied yet.
+        cache.insert("c", "cindy");
+
+        cache.clear();
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.weighted_size(), 0);
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), Some("david"));
+    }
+
+    #[test]
+    fn retain() {
+        let mut cache = CacheBuilder::new(100).exact_entry_count().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i * 10);
+        }
+        cache.run_pending_tasks();
+        // Not applied yet.
+        for i in 10..20 {
+            cache.insert(i, i * 10);
+        }
+
+        // No `run_pending_tasks` is needed to see the result.
+        cache.retain(|k, _v| k % 2 == 0);
+        assert_eq!(cache.entry_count(), 10);
+        assert_eq!(cache.weighted_size(), 10);
+        for i in 0..20 {
+            if i % 2 == 0 {
+                assert_eq!(cache.get(&i), Some(i * 10));
+            } else {
+                assert_eq!(cache.get(&i), None);
+            }
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut cache = Cache::new(2);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let make_popular = |key| {
+            cache.insert(key, key);
+            for _ in 0..5 {
+                cache.get(&key);
+            }
+        };
+
+        make_popular("a");
+        make_popular("b");
+        cache.run_pending_tasks();
+
+        // "c" is colder than "a" and "b", so it is not admitted.
+        assert_eq!(cache.get(&"c"), None);
+        cache.insert("c", "c");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"c"), None);
+
+        // invalidate_all retains the popularity of "a" and "b".
+        cache.invalidate_all();
+        cache.run_pending_tasks();
+        cache.insert("a", "a");
+        cache.insert("b", "b");
+        cache.insert("c", "c");
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"c"));
+
+        // reset clears the popularity. "c" with one access is admitted.
+        make_popular("a");
+        make_popular("b");
+        cache.reset();
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"c"), None);
+        cache.insert("a", "a");
+        cache.insert("b", "b");
+        cache.insert("c", "c");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"c"), Some("c"));
+        assert_eq!(cache.entry_count(), 2);
+    }
+
+    #[test]
+    fn lru_eviction_policy() {
+        let mut cache = CacheBuilder::new(3)
+            .eviction_policy(EvictionPolicy::Lru)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.run_pending_tasks();
+        // LRU order: c, b, a
+
+        // "d" should be admitted even though it has never been accessed, and "c"
+        // (the least recently used) should be evicted.
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), Some("david"));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // LRU order: d, a, b
+
+        cache.insert("e", "emily");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"e"), Some("emily"));
+        assert_eq!(cache.get(&"d"), None);
+        assert_eq!(cache.entry_count(), 3);
+    }
+
+    #[test]
+    fn read_buffer_flush_threshold() {
+        let mut cache = CacheBuilder::new(3)
+            .eviction_policy(EvictionPolicy::Lru)
+            .read_buffer_flush_threshold(1)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+        // LRU order: a, b, c
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        // The next read finds the buffered read of "a" and schedules the
+        // housekeeper, which applies it without calling run_pending_tasks.
+        assert_eq!(cache.get(&"x"), None);
+        for _ in 0..100 {
+            if cache.base.read_op_ch_len() < 2 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(cache.base.read_op_ch_len() < 2);
+        // LRU order: b, c, a
+
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), None);
+
+        // With the default threshold, the reads stay in the buffer.
+        let mut cache = CacheBuilder::new(3).build();
+        cache.reconfigure_for_testing();
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"x"), None);
+        assert_eq!(cache.base.read_op_ch_len(), 2);
+    }
+
+    #[test]
+    fn contains_key() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+
+        assert!(cache.contains_key(&"a"));
+        assert!(cache.contains_key(&"b"));
+        assert!(!cache.contains_key(&"c"));
+
+        // An entry to be invalidated by a predicate should not be contained.
+        cache.invalidate_entries_if(|_k, &v| v == "bob")?;
+        assert!(!cache.contains_key(&"b"));
+
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+
+        // An expired entry should not be contained.
+        assert!(!cache.contains_key(&"a"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn touch() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(20))
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(9)); // 9 secs from the start.
+        assert!(cache.touch(&"a", false));
+        assert!(!cache.touch(&"c", false));
+        cache.run_pending_tasks();
+
+        // "a" has been touched just before its idle expiry, so it survives. "b" has
+        // not, so it has expired.
+        mock.increment(Duration::from_secs(2)); // 11 secs.
+        cache.run_pending_tasks();
+        assert!(cache.touch(&"a", false));
+        assert!(!cache.touch(&"b", false));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.entry_count(), 1);
+
+        // Touching only resets the idle timer, not the time-to-live timer. Touch "a"
+        // again with resetting the time-to-live timer.
+        mock.increment(Duration::from_secs(8)); // 19 secs.
+        assert!(cache.touch(&"a", true));
+        cache.run_pending_tasks();
+
+        // "a" survives beyond its original time-to-live (20 secs).
+        mock.increment(Duration::from_secs(8)); // 27 secs.
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        // "a" expires when the idle timer elapses.
+        mock.increment(Duration::from_secs(10)); // 37 secs.
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"a"));
+        assert_eq!(cache.entry_count(), 0);
+    }
+
+    type SpilledEntries<K, V> = HashMap<u64, Vec<(Arc<K>, V)>>;
+
+    // A spill store holding the entries in memory.
+    #[derive(Clone)]
+    struct MapStore<K, V>(Arc<Mutex<SpilledEntries<K, V>>>);
+
+    impl<K, V> Default for MapStore<K, V> {
+        fn default() -> Self {
+            Self(Arc::new(Mutex::new(HashMap::new())))
+        }
+    }
+
+    impl<K: PartialEq, V: Clone> MapStore<K, V> {
+        fn spilled(&self, key: &K) -> Option<V> {
+            let map = self.0.lock();
+            let mut entries = map.values().flatten();
+            entries.find(|(k, _)| **k == *key).map(|(_, v)| v.clone())
+        }
+
+        fn is_empty(&self) -> bool {
+            self.0.lock().is_empty()
+        }
+    }
+
+    impl<K, V> SpillStore<K, V> for MapStore<K, V>
+    where
+        K: PartialEq + Send + Sync,
+        V: Clone + Send + Sync,
+    {
+        fn get(&self, hash: u64, is_key: &dyn Fn(&Arc<K>) -> bool) -> Option<V> {
+            let map = self.0.lock();
+            let entries = map.get(&hash)?;
+            entries
+                .iter()
+                .find(|(k, _)| is_key(k))
+                .map(|(_, v)| v.clone())
+        }
+
+        fn put(&self, hash: u64, key: &Arc<K>, value: &V) {
+            let mut map = self.0.lock();
+            let entries = map.entry(hash).or_default();
+            entries.retain(|(k, _)| k != key);
+            entries.push((Arc::clone(key), value.clone()));
+        }
+
+        fn remove(&self, hash: u64, is_key: &dyn Fn(&Arc<K>) -> bool) {
+            let mut map = self.0.lock();
+            if let Some(entries) = map.get_mut(&hash) {
+                entries.retain(|(k, _)| !is_key(k));
+                if entries.is_empty() {
+                    map.remove(&hash);
+                }
+            }
+        }
+
+        fn clear(&self) {
+            self.0.lock().clear();
+        }
+    }
+
+    #[test]
+    fn spill_to_store() {
+        let store = MapStore::default();
+        let mut cache = CacheBuilder::new(3).spill_to(store.clone()).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 1, c -> 0
+
+        // "d" should not be admitted, so it should be spilled to the store.
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(store.spilled(&"d"), Some("david"));
+        assert_eq!(cache.get(&"d"), Some("david")); // d -> 1
+
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"d"), Some("david")); // d -> 2
+
+        // "d" should be admitted and "c" should be evicted to the store.
+        cache.insert("d", "dennis");
+        cache.run_pending_tasks();
+        assert_eq!(store.spilled(&"c"), Some("cindy"));
+        assert_eq!(store.spilled(&"d"), None);
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        assert_eq!(cache.get(&"d"), Some("dennis"));
+
+        // Invalidating a key should remove it from the store too.
+        cache.invalidate(&"c");
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(store.spilled(&"c"), None);
+
+        cache.insert("e", "emily");
+        cache.run_pending_tasks();
+        assert!(!store.is_empty());
+
+        cache.invalidate_all();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn spill_with_concurrent_invalidation() {
+        use std::{sync::mpsc, thread::JoinHandle};
+
+        type Invalidations = Arc<Mutex<Vec<JoinHandle<()>>>>;
+
+        // A spill store invalidating the key in another thread while it is
+        // spilling the key, and giving the invalidation a chance to complete
+        // before spilling.
+        #[derive(Clone, Default)]
+        struct InvalidatingStore {
+            store: MapStore<u32, u32>,
+            cache: Arc<Mutex<Option<Cache<u32, u32>>>>,
+            invalidations: Invalidations,
+        }
+
+        impl SpillStore<u32, u32> for InvalidatingStore {
+            fn get(&self, hash: u64, is_key: &dyn Fn(&Arc<u32>) -> bool) -> Option<u32> {
+                self.store.get(hash, is_key)
+            }
+
+            fn put(&self, hash: u64, key: &Arc<u32>, value: &u32) {
+                let cache = self.cache.lock().clone().expect("No cache");
+                let (tx, rx) = mpsc::channel();
+                let key1 = **key;
+                self.invalidations.lock().push(std::thread::spawn(move || {
+                    cache.invalidate(&key1);
+                    // The receiver may have been dropped by the timeout.
+                    let _ = tx.send(());
+                }));
+                let _ = rx.recv_timeout(Duration::from_millis(100));
+                self.store.put(hash, key, value);
+            }
+
+            fn remove(&self, hash: u64, is_key: &dyn Fn(&Arc<u32>) -> bool) {
+                self.store.remove(hash, is_key);
+            }
+
+            fn clear(&self) {
+                self.store.clear();
+            }
+        }
+
+        let store = InvalidatingStore::default();
+        let mut cache = CacheBuilder::new(2).spill_to(store.clone()).build();
+        cache.reconfigure_for_testing();
+        *store.cache.lock() = Some(cache.clone());
+
+        cache.insert(0, 0);
+        cache.insert(1, 1);
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), Some(1));
+
+        // 2 is not admitted, so it is spilled while being invalidated.
+        cache.insert(2, 2);
+        cache.run_pending_tasks();
+        let invalidations = std::mem::take(&mut *store.invalidations.lock());
+        assert_eq!(invalidations.len(), 1);
+        for handle in invalidations {
+            handle.join().expect("Failed to join");
+        }
+
+        // The invalidated value must not be read back from the store.
+        assert_eq!(store.store.spilled(&2), None);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&0), Some(0));
+
+        // Break the reference cycle between the cache and the store.
+        store.cache.lock().take();
+    }
+
+    #[test]
+    fn modify_all() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, "alice".to_string());
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+
+        cache.insert(1, "bob".to_string());
+        // Do not call sync() here; modify_all should apply the pending insert.
+
+        mock.increment(Duration::from_secs(5)); // 10 secs. "alice" has expired.
+
+        cache.modify_all(|k, v| v.push_str(&format!("-{}", k)));
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.get(&1), Some("bob-1".to_string()));
+
+        // The modification resets the time-to-live.
+        mock.increment(Duration::from_secs(9)); // 19 secs.
+        assert_eq!(cache.get(&1), Some("bob-1".to_string()));
+    }
+
+    #[test]
+    fn get_consistent() {
+        use std::sync::mpsc;
+
+        const NUM_KEYS: u32 = 1_000;
+        let cache = Cache::new(NUM_KEYS as usize);
+
+        // The writer thread inserts a key, and then hands it to the reader thread.
+        let (tx, rx) = mpsc::channel();
+        let writer = {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                for key in 0..NUM_KEYS {
+                    cache.insert(key, key * 10);
+                    tx.send(key).unwrap();
+                }
+            })
+        };
+
+        // The reader thread never misses a key whose insert has completed.
+        let reader = {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                for key in rx {
+                    assert_eq!(cache.get_consistent(&key), Some(key * 10));
+                }
+            })
+        };
+
+        writer.join().unwrap();
+        reader.join().unwrap();
+
+        // get_consistent applies the admission decision of a pending insert.
+        let mut cache = Cache::new(1);
+        cache.reconfigure_for_testing();
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+        cache.get(&"a");
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 0
+
+        cache.insert("b", "bob");
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        // b is not admitted, as it is accessed less frequently than a.
+        assert_eq!(cache.get_consistent(&"b"), None);
+        assert_eq!(cache.get_consistent(&"a"), Some("alice"));
+    }
+
+    #[test]
+    fn basic_multi_threads() {
+        let num_threads = 4;
+        let cache = Cache::new(100);
+
+        let handles = (0..num_threads)
+            .map(|id| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    cache.insert(10, format!("{}-100", id));
+                    cache.get(&10);
+                    cache.insert(20, format!("{}-200", id));
+                    cache.invalidate(&10);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+
+        assert!(cache.get(&10).is_none());
+        assert!(cache.get(&20).is_some());
+    }
+
+    #[test]
+    fn remove() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(10))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.remove(&"a"), Some("alice"));
+        assert_eq!(cache.remove(&"a"), None);
+        assert_eq!(cache.get(&"a"), None);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 1);
+
+        // An expired value should not be returned.
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+        assert_eq!(cache.remove(&"b"), None);
+        cache.run_pending_tasks();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn compute() {
+        const NUM_THREADS: usize = 8;
+        const NUM_INCREMENTS: usize = 100;
+
+        let cache = Cache::new(100);
+
+        let handles = (0..NUM_THREADS)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..NUM_INCREMENTS {
+                        cache.compute("counter", |v| Some(v.map_or(1, |v| v + 1)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+
+        assert_eq!(cache.get(&"counter"), Some(NUM_THREADS * NUM_INCREMENTS));
+
+        assert_eq!(cache.compute("counter", |_v| None), None);
+        assert_eq!(cache.get(&"counter"), None);
+    }
+
+    #[test]
+    fn eviction_channel() {
+        use crate::{notification::ChannelFullPolicy, policy::EvictionPolicy};
+
+        let (mut cache, rcv) = CacheBuilder::new(10)
+            .eviction_policy(EvictionPolicy::Lru)
+            .eviction_channel(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..30 {
+            cache.insert(i, i * 10);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 10);
+
+        let mut events: Vec<_> = rcv.try_iter().map(|(k, v, c)| (*k, v, c)).collect();
+        events.sort_unstable_by_key(|(k, _, _)| *k);
+        let expected: Vec<_> = (0..20).map(|i| (i, i * 10, RemovalCause::Size)).collect();
+        assert_eq!(events, expected);
+        assert_eq!(cache.dropped_eviction_events(), 0);
+
+        // The events overflowing a small channel are dropped and counted.
+        let (mut cache, rcv) = CacheBuilder::new(10)
+            .eviction_policy(EvictionPolicy::Lru)
+            .eviction_channel_with_policy(5, ChannelFullPolicy::Drop);
+        cache.reconfigure_for_testing();
+        let cache = cache;
+
+        for i in 0..30 {
+            cache.insert(i, i * 10);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(rcv.try_iter().count(), 5);
+        assert_eq!(cache.dropped_eviction_events(), 15);
+    }
+
+    #[test]
+    fn eviction_listener() {
+        use parking_lot::Mutex;
+
+        let notifications = Arc::new(Mutex::new(Vec::new()));
+        let notifications1 = Arc::clone(&notifications);
+
+        let mut cache = CacheBuilder::new(3)
+            .time_to_live(Duration::from_secs(10))
+            .eviction_listener(move |k, v, cause| notifications1.lock().push((*k, v, cause)))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let mut expected = Vec::new();
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+        assert!(notifications.lock().is_empty());
+
+        cache.insert("a", "anna");
+        cache.run_pending_tasks();
+        expected.push(("a", "alice", RemovalCause::Replaced));
+        assert_eq!(*notifications.lock(), expected);
+
+        cache.invalidate(&"b");
+        cache.run_pending_tasks();
+        expected.push(("b", "bob", RemovalCause::Explicit));
+        assert_eq!(*notifications.lock(), expected);
+
+        // "d" will be admitted, but "e" will not because there is no room and its
+        // frequency is not higher than the victim's.
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        cache.insert("e", "emily");
+        cache.run_pending_tasks();
+        expected.push(("e", "emily", RemovalCause::Size));
+        assert_eq!(*notifications.lock(), expected);
+
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+        cache.run_pending_tasks();
+        expected.push(("c", "cindy", RemovalCause::Expired));
+        expected.push(("a", "anna", RemovalCause::Expired));
+        expected.push(("d", "david", RemovalCause::Expired));
+        assert_eq!(*notifications.lock(), expected);
+
+        cache.insert("f", "frank");
+        cache.run_pending_tasks();
+        mock.increment(Duration::from_secs(1)); // 11 secs.
+        cache.invalidate_all();
+        cache.run_pending_tasks();
+        expected.push(("f", "frank", RemovalCause::Explicit));
+        assert_eq!(*notifications.lock(), expected);
+    }
+
+    #[test]
+    fn write_channel_capacity() {
+        const CAPACITY: usize = 10_000;
+
+        let mut cache = CacheBuilder::new(CAPACITY)
+            .write_channel_capacity(CAPACITY)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Send the write ops without scheduling the housekeeper, so nothing drains
+        // the channel. None of them should hit the full channel.
+        for i in 0..CAPACITY {
+            let hash = cache.base.hash(&i);
+            let op = cache.base.do_insert_with_hash(Arc::new(i), hash, i, None);
+            assert!(cache.base.write_op_ch.try_send(op).is_ok());
+        }
+
+        let hash = cache.base.hash(&CAPACITY);
+        let op = cache
+            .base
+            .do_insert_with_hash(Arc::new(CAPACITY), hash, CAPACITY, None);
+        assert!(matches!(
+            cache.base.write_op_ch.try_send(op),
+            Err(TrySendError::Full(_))
+        ));
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&(CAPACITY - 1)), Some(CAPACITY - 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "write_channel_capacity must be greater than 0")]
+    fn write_channel_capacity_zero() {
+        let _cache: Cache<u32, u32> = CacheBuilder::new(100).write_channel_capacity(0).build();
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let cache = CacheBuilder::new(0)
+            .eviction_listener(move |k, _v, cause| tx.send((k, cause)).unwrap())
+            .build();
+
+        for i in 0..1_000 {
+            cache.insert(i % 10, i);
+            assert_eq!(cache.get(&(i % 10)), None);
+        }
+        assert!(!cache.contains_key(&0));
+        assert_eq!(cache.get_or_insert_with(0, || 5), 5);
+        assert_eq!(cache.get(&0), None);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+        let notifications = rx.try_iter().collect::<Vec<_>>();
+        assert!(notifications
+            .iter()
+            .any(|(_, cause)| *cause == RemovalCause::Size));
+    }
+
+    #[test]
+    fn housekeeper_shutdown_on_drop() {
+        use std::sync::atomic::Ordering;
+
+        for i in 0..1_000 {
+            let cache = if i % 2 == 0 {
+                Cache::new(10)
+            } else {
+                CacheBuilder::new(10)
+                    .support_invalidation_closures()
+                    .build()
+            };
+            cache.insert(i, i);
+            let cache2 = cache.clone();
+            let shutdown_flag = cache.base.housekeeper.as_ref().unwrap().shutdown_flag();
+            let inner = Arc::downgrade(&cache.base.inner);
+
+            std::mem::drop(cache);
+            assert!(!shutdown_flag.load(Ordering::Acquire));
+            assert_eq!(cache2.get(&i), Some(i));
+
+            // Dropping the last clone stops the housekeeper, and the internal data
+            // structures are not kept alive by the housekeeping job.
+            std::mem::drop(cache2);
+            assert!(shutdown_flag.load(Ordering::Acquire));
+            assert!(inner.upgrade().is_none());
+        }
+    }
+
+    #[test]
+    fn manual_housekeeping() {
+        let cache = CacheBuilder::new(3).manual_housekeeping().build();
+
+        // No automatic housekeeping even after many writes.
+        for i in 0..1_000 {
+            cache.insert(i % 10, i);
+        }
+        assert_eq!(cache.on_demand_sync_count(), 0);
+        assert_eq!(cache.entry_count(), 10);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 3);
+
+        // Writes more than the write channel capacity should not block forever.
+        let cache = CacheBuilder::new(10)
+            .manual_housekeeping()
+            .write_channel_capacity(16)
+            .build();
+        for i in 0..100 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 10);
+    }
+
+    #[test]
+    fn insert_with_report() {
+        use crate::policy::InsertReport;
+
+        let mut cache = CacheBuilder::new(3).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Admit: there is some room in the cache.
+        assert_eq!(
+            cache.insert_with_report("a", "alice"),
+            InsertReport::Admitted
+        );
+        assert_eq!(cache.insert_with_report("b", "bob"), InsertReport::Admitted);
+        assert_eq!(
+            cache.insert_with_report("c", "cindy"),
+            InsertReport::Admitted
+        );
+        cache.run_pending_tasks();
+        // counts: a -> 1, b -> 1, c -> 1
+        cache.get(&"a");
+        cache.get(&"b");
+        cache.get(&"c");
+        cache.run_pending_tasks();
+
+        // Replace: the key is already in the cache.
+        assert_eq!(
+            cache.insert_with_report("a", "anne"),
+            InsertReport::Replaced
+        );
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("anne"));
+        assert_eq!(cache.entry_count(), 3);
+
+        // Reject: the cache is full, and d is accessed less frequently than the
+        // victim.
+        assert_eq!(
+            cache.insert_with_report("d", "david"),
+            InsertReport::Rejected
+        );
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"d"));
+        assert_eq!(cache.entry_count(), 3);
+
+        // Admit: the cache is full, but e is accessed more frequently than the
+        // victim.
+        for _ in 0..3 {
+            cache.get(&"e");
+        }
+        cache.run_pending_tasks();
+        assert_eq!(
+            cache.insert_with_report("e", "emily"),
+            InsertReport::Admitted
+        );
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"e"), Some("emily"));
+        assert_eq!(cache.entry_count(), 3);
+    }
+
+    #[test]
+    fn try_insert_on_disconnected_channel() {
+        use crate::CacheError;
+
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+        assert_eq!(cache.try_insert("a", "alice"), Ok(()));
+
+        // Simulate a dead cache by replacing the write op channel with the one
+        // whose receiver has been dropped.
+        let (snd, _) = crossbeam_channel::bounded(1);
+        cache.base.write_op_ch = snd;
+
+        assert_eq!(
+            cache.try_insert("b", "bob"),
+            Err(CacheError::WriteChannelDisconnected)
+        );
+        assert_eq!(cache.get(&"a"), Some("alice"));
+    }
+
+    #[test]
+    fn insert_if_absent() {
+        use std::sync::Barrier;
+
+        let cache = Cache::new(100);
+        assert_eq!(cache.insert_if_absent("a", 1), Ok(()));
+        assert_eq!(cache.insert_if_absent("a", 2), Err(2));
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        // An invalidated entry is treated as absent.
+        cache.invalidate(&"a");
+        assert_eq!(cache.insert_if_absent("a", 3), Ok(()));
+        assert_eq!(cache.get(&"a"), Some(3));
+
+        // Many threads race to insert the same key. Exactly one succeeds.
+        const NUM_THREADS: usize = 16;
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+        let handles = (0..NUM_THREADS)
+            .map(|i| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    cache.insert_if_absent("b", i).is_ok()
+                })
+            })
+            .collect::<Vec<_>>();
+        let winners = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .enumerate()
+            .filter(|(_, ok)| *ok)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(winners.len(), 1);
+        assert_eq!(cache.get(&"b"), Some(winners[0]));
+    }
+
+    #[test]
+    fn replace() {
+        let cache = Cache::new(100);
+
+        // Replacing an absent key does not insert it.
+        assert_eq!(cache.replace("a", 1), None);
+        assert_eq!(cache.get(&"a"), None);
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&"a"));
+
+        cache.insert("a", 1);
+        assert_eq!(cache.replace("a", 2), Some(1));
+        assert_eq!(cache.get(&"a"), Some(2));
+
+        // An invalidated entry is treated as absent.
+        cache.invalidate(&"a");
+        assert_eq!(cache.replace("a", 3), None);
+        assert_eq!(cache.get(&"a"), None);
+    }
+
+    #[test]
+    fn insert_many() {
+        const NUM_ENTRIES: usize = 100_000;
+
+        let cache = Cache::new(NUM_ENTRIES);
+        for i in 0..NUM_ENTRIES {
+            cache.insert(i, i);
+        }
+        let looped = cache.on_demand_sync_count();
+
+        let mut cache = Cache::new(NUM_ENTRIES);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert_many((0..NUM_ENTRIES).map(|i| (i, i)));
+        let batched = cache.on_demand_sync_count();
+        assert!(batched < looped, "batched: {}, looped: {}", batched, looped);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), NUM_ENTRIES);
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&(NUM_ENTRIES - 1)), Some(NUM_ENTRIES - 1));
+    }
+
+    #[test]
+    fn get_with_refresh() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            thread::sleep,
+        };
+
+        let mut cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let reload_count = Arc::new(AtomicUsize::new(0));
+        let reload = |value: &'static str, delay_millis: u64| {
+            let count = Arc::clone(&reload_count);
+            move |_key: &&str| {
+                count.fetch_add(1, Ordering::AcqRel);
+                sleep(Duration::from_millis(delay_millis));
+                if value.is_empty() {
+                    Err("reload failed")
+                } else {
+                    Ok(value)
+                }
+            }
+        };
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+
+        // Not due for refresh yet.
+        assert_eq!(
+            cache.get_with_refresh(&"a", reload("anna", 0)),
+            Some("alice")
+        );
+        assert_eq!(cache.get_with_refresh(&"b", reload("bob", 0)), None);
+        sleep(Duration::from_millis(200));
+        assert_eq!(reload_count.load(Ordering::Acquire), 0);
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+
+        // The stale value is returned while the reload is in flight. Only one
+        // reload is started.
+        assert_eq!(
+            cache.get_with_refresh(&"a", reload("anna", 200)),
+            Some("alice")
+        );
+        assert_eq!(
+            cache.get_with_refresh(&"a", reload("amy", 200)),
+            Some("alice")
+        );
+        sleep(Duration::from_millis(500));
+        cache.run_pending_tasks();
+
+        assert_eq!(reload_count.load(Ordering::Acquire), 1);
+        assert_eq!(cache.get(&"a"), Some("anna"));
+
+        mock.increment(Duration::from_secs(10)); // 20 secs.
+
+        // The reload fails and the current value is retained.
+        assert_eq!(cache.get_with_refresh(&"a", reload("", 0)), Some("anna"));
+        sleep(Duration::from_millis(200));
+        cache.run_pending_tasks();
+
+        assert_eq!(reload_count.load(Ordering::Acquire), 2);
+        assert_eq!(cache.get(&"a"), Some("anna"));
+
+        // The entry is still due for refresh, so the next call will reload it.
+        assert_eq!(
+            cache.get_with_refresh(&"a", reload("alex", 0)),
+            Some("anna")
+        );
+        sleep(Duration::from_millis(200));
+        cache.run_pending_tasks();
+
+        assert_eq!(reload_count.load(Ordering::Acquire), 3);
+        assert_eq!(cache.get(&"a"), Some("alex"));
+    }
+
+    #[test]
+    fn get_with_refresh_reports_errors() {
+        use crate::RefreshError;
+        use std::thread::sleep;
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors1 = Arc::clone(&errors);
+        let mut cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .refresh_error_listener(move |key: Arc<u32>, error| {
+                let failed = matches!(error, RefreshError::Failed(_));
+                errors1.lock().push((*key, failed, error.to_string()));
+            })
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, 0);
+        cache.insert(1, 0);
+        cache.run_pending_tasks();
+        mock.increment(Duration::from_secs(11));
+
+        // The reload of key 0 fails, and the one of key 1 panics.
+        let value = cache.get_with_refresh(&0, |_| Err::<u32, _>("oops".to_string()));
+        assert_eq!(value, Some(0));
+        let value = cache.get_with_refresh(&1, |_| {
+            if true {
+                panic!("Panic during get_with_refresh_reports_errors");
+            }
+            Ok::<_, Infallible>(1)
+        });
+        assert_eq!(value, Some(0));
+
+        for _ in 0..100 {
+            if errors.lock().len() == 2 {
+                break;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        let mut reported = errors.lock().clone();
+        reported.sort();
+        assert_eq!(
+            reported,
+            vec![
+                (0, true, "the reload failed: oops".to_string()),
+                (1, false, "the reload panicked".to_string())
+            ]
+        );
+
+        // The current values are retained.
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), Some(0));
+    }
+
+    #[test]
+    fn get_with_refresh_retains_newer_value() {
+        use std::{sync::mpsc, thread::sleep};
+
+        let mut cache = CacheBuilder::new(100)
+            .refresh_after_write(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+        mock.increment(Duration::from_secs(10));
+
+        // Start the reloads, and update and invalidate the entries while the
+        // reloads are blocked.
+        let (tx, rx) = mpsc::channel::<()>();
+        let (tx1, rx1) = (tx.clone(), Arc::new(Mutex::new(rx)));
+        let rx2 = Arc::clone(&rx1);
+        let reload = move |rx: Arc<Mutex<mpsc::Receiver<()>>>, v| {
+            move |_key: &&str| {
+                rx.lock().recv().expect("Failed to receive");
+                Ok::<_, Infallible>(v)
+            }
+        };
+        assert_eq!(
+            cache.get_with_refresh(&"a", reload(rx1, "anna")),
+            Some("alice")
+        );
+        assert_eq!(
+            cache.get_with_refresh(&"b", reload(rx2, "bill")),
+            Some("bob")
+        );
+
+        cache.insert("a", "amy");
+        cache.invalidate(&"b");
+        tx.send(()).expect("Failed to send");
+        tx1.send(()).expect("Failed to send");
+        sleep(Duration::from_millis(200));
+        cache.run_pending_tasks();
+
+        // The reloaded values do not overwrite the newer value nor revive the
+        // invalidated entry.
+        assert_eq!(cache.get(&"a"), Some("amy"));
+        assert_eq!(cache.get(&"b"), None);
+    }
+
+    #[test]
+    fn entry() {
+        let cache: Cache<&str, u32> = Cache::new(100);
+
+        // and_modify on a missing key is a no-op.
+        let entry = cache.entry("a").and_modify(|v| *v += 1);
+        assert_eq!(entry.key(), &"a");
+        assert_eq!(cache.get(&"a"), None);
+
+        // or_default and or_insert_with insert the value only when empty.
+        assert_eq!(cache.entry("a").or_default(), 0);
+        assert_eq!(cache.entry("a").or_insert_with(|| unreachable!()), 0);
+        assert_eq!(cache.entry("b").or_insert_with(|| 5), 5);
+        assert_eq!(cache.entry("b").or_default(), 5);
+        assert_eq!(cache.entry("c").or_insert(7), 7);
+
+        // and_modify updates the existing value.
+        assert_eq!(cache.entry("a").and_modify(|v| *v += 10).or_default(), 10);
+        assert_eq!(cache.get(&"a"), Some(10));
+        assert_eq!(cache.entry("b").and_modify(|v| *v *= 2).or_insert(0), 10);
+        assert_eq!(cache.get(&"b"), Some(10));
+    }
+
+    #[test]
+    fn entry_or_insert_with_single_flight() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            thread::{sleep, spawn},
+        };
+
+        let cache: Cache<u32, u32> = Cache::new(100);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let threads = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = Arc::clone(&calls);
+                spawn(move || {
+                    cache.entry(0).or_insert_with(|| {
+                        calls.fetch_add(1, Ordering::AcqRel);
+                        sleep(Duration::from_millis(100));
+                        42
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for t in threads {
+            assert_eq!(t.join().expect("Thread failed"), 42);
+        }
+        assert_eq!(calls.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn stats() {
+        let mut cache = CacheBuilder::new(3).record_stats().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.get(&"a"), None); // miss
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("alice")); // hit
+        assert_eq!(cache.get(&"a"), Some("alice")); // hit
+        assert_eq!(cache.get(&"b"), None); // miss
+
+        // A miss and a successful load, then a hit.
+        assert_eq!(cache.get_or_insert_with("b", || "bob"), "bob");
+        assert_eq!(cache.get_or_insert_with("b", || unreachable!()), "bob");
+        // A miss and a failed load.
+        let res: Result<_, Arc<&str>> = cache.get_or_try_insert_with("c", || Err("err"));
+        assert!(res.is_err());
+        cache.run_pending_tasks();
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 3);
+        assert_eq!(stats.miss_count(), 4);
+        assert_eq!(stats.hit_rate(), 3.0 / 7.0);
+        assert_eq!(stats.load_success_count(), 1);
+        assert_eq!(stats.load_failure_count(), 1);
+        assert_eq!(stats.eviction_count(), 0);
+
+        // Fill the cache and evict "d" (which has lower frequency) twice.
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+        cache.insert("d", "david");
+        cache.run_pending_tasks();
+        cache.insert("d", "dennis");
+        cache.run_pending_tasks();
+        assert_eq!(cache.stats().eviction_count(), 2);
+
+        // Stats are not recorded by default.
+        let cache = Cache::new(3);
+        cache.insert("a", "alice");
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn insert_with_ttl() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_live(Duration::from_secs(30))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert_with_ttl("a", "alice", Duration::from_secs(10));
+        cache.insert_with_ttl("b", "bob", Duration::from_secs(20));
+        cache.insert_with_ttl("c", "cindy", Duration::from_secs(60));
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.table_size(), 2);
+
+        mock.increment(Duration::from_secs(10)); // 20 secs.
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+
+        // The cache-wide time-to-live is shorter than the per-entry one.
+        mock.increment(Duration::from_secs(10)); // 30 secs.
+        assert_eq!(cache.get(&"c"), None);
+
+        cache.run_pending_tasks();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn get_or_insert_with_ttl() {
+        use std::thread::{sleep, spawn};
+
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Load "a" and "b" concurrently with different time-to-lives.
+        let thread1 = {
+            let cache1 = cache.clone();
+            spawn(move || {
+                cache1.get_or_insert_with_ttl("a", || {
+                    sleep(Duration::from_millis(200));
+                    ("alice", Duration::from_secs(10))
+                })
+            })
+        };
+        let thread2 = {
+            let cache2 = cache.clone();
+            spawn(move || {
+                cache2.get_or_insert_with_ttl("b", || {
+                    sleep(Duration::from_millis(200));
+                    ("bob", Duration::from_secs(20))
+                })
+            })
+        };
+        // This call waits for the init closure of thread1, or gets the value
+        // inserted by it.
+        let thread3 = {
+            let cache3 = cache.clone();
+            spawn(move || {
+                sleep(Duration::from_millis(100));
+                cache3.get_or_insert_with_ttl("a", || unreachable!())
+            })
+        };
+
+        assert_eq!(thread1.join().expect("Failed to join"), "alice");
+        assert_eq!(thread2.join().expect("Failed to join"), "bob");
+        assert_eq!(thread3.join().expect("Failed to join"), "alice");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+
+        mock.increment(Duration::from_secs(10)); // 20 secs.
+        assert_eq!(cache.get(&"b"), None);
+
+        cache.run_pending_tasks();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn insert_with_ttl_out_of_order() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // "a" is older but lives longer than "b", so "b" cannot be found by
+        // scanning the write order queue from the front.
+        cache.insert_with_ttl("a", "alice", Duration::from_secs(3600));
+        cache.insert_with_ttl("b", "bob", Duration::from_secs(10));
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 2);
+
+        mock.increment(Duration::from_secs(11)); // 11 secs from the start.
+        cache.run_pending_tasks();
+        assert_eq!(cache.table_size(), 1);
+        assert_eq!(cache.entry_count(), 1);
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        // Updating "a" with a shorter time-to-live reschedules it.
+        cache.insert_with_ttl("a", "alex", Duration::from_secs(5));
+        cache.run_pending_tasks();
+        mock.increment(Duration::from_secs(5)); // 16 secs.
+        cache.run_pending_tasks();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn insert_with_ttl_keeps_one_timer_per_key() {
+        let mut cache = CacheBuilder::new(100).build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..100 {
+            cache.insert_with_ttl("a", i, Duration::from_secs(10));
+            cache.insert_with_ttl("b", i, Duration::from_secs(10));
+            cache.run_pending_tasks();
+        }
+        assert_eq!(cache.base.timer_count(), 2);
+
+        // Invalidating or updating without a time-to-live cancels the timer.
+        cache.invalidate(&"a");
+        cache.insert("b", 100);
+        cache.run_pending_tasks();
+        assert_eq!(cache.base.timer_count(), 0);
+
+        mock.increment(Duration::from_secs(11));
+        assert_eq!(cache.get(&"b"), Some(100));
+    }
+
+    #[test]
+    fn expire_after() {
+        use crate::policy::Expiry;
+
+        // The value is the max-age in seconds.
+        struct MaxAge;
+
+        impl Expiry<&str, u64> for MaxAge {
+            fn expire_after_create(&self, _key: &&str, value: &u64) -> Option<Duration> {
+                Some(Duration::from_secs(*value))
+            }
+
+            fn expire_after_read(&self, key: &&str, _value: &u64) -> Option<Duration> {
+                // Extend the life of "c" on every read.
+                if *key == "c" {
+                    Some(Duration::from_secs(10))
+                } else {
+                    None
+                }
+            }
+        }
+
+        let mut cache = CacheBuilder::new(100).expire_after(MaxAge).build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", 30);
+        cache.insert("b", 5);
+        cache.insert("c", 10);
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        assert_eq!(cache.get(&"a"), Some(30));
+        assert_eq!(cache.get(&"b"), None);
+        // Now "c" will expire at 15 secs.
+        assert_eq!(cache.get(&"c"), Some(10));
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.table_size(), 2);
+
+        // Updating "a" keeps its time-to-live of 30 secs, measured from now.
+        cache.insert("a", 300);
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(9)); // 14 secs.
+        assert_eq!(cache.get(&"c"), Some(10)); // Now expires at 24 secs.
+
+        mock.increment(Duration::from_secs(10)); // 24 secs.
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"a"), Some(300));
+
+        mock.increment(Duration::from_secs(11)); // 35 secs.
+        assert_eq!(cache.get(&"a"), None);
+
+        cache.run_pending_tasks();
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn weighted_capacity() {
+        let mut cache = CacheBuilder::new(500)
+            .weigher(|_k, v: &Vec<u8>| v.len() as u32)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, vec![0u8; 100]);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 5);
+        assert_eq!(cache.base.inner.weighted_size(), 500);
+
+        // A value heavier than the max capacity should never be admitted.
+        cache.insert(10, vec![0u8; 501]);
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&10));
+        assert_eq!(cache.entry_count(), 5);
+
+        // Making an entry heavier should evict other entries.
+        let key = (0..10).find(|k| cache.contains_key(k)).unwrap();
+        cache.insert(key, vec![0u8; 300]);
+        cache.run_pending_tasks();
+        assert!(cache.contains_key(&key));
+        assert_eq!(cache.entry_count(), 3);
+        assert_eq!(cache.base.inner.weighted_size(), 500);
     }
-}
 
-impl<K, V, S> ConcurrentCacheExt<K, V> for Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    fn sync(&self) {
-        self.base.inner.sync(MAX_SYNC_REPEATS);
+    #[test]
+    fn exact_entry_count() {
+        const NUM_THREADS: u32 = 4;
+        const NUM_KEYS: u32 = 300;
+
+        let cache = CacheBuilder::new(100).exact_entry_count().build();
+
+        // A replacement does not change the count.
+        cache.insert(0, 0);
+        cache.insert(0, 1);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 1);
+        cache.invalidate(&0);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+
+        // Concurrent inserts and invalidations, with evictions as there are more
+        // keys than the max capacity.
+        let handles = (0..NUM_THREADS)
+            .map(|t| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for i in 0..2_000 {
+                        let key = (i * 7 + t * 13) % NUM_KEYS;
+                        if i % 3 == 0 {
+                            cache.invalidate(&key);
+                        } else {
+                            cache.insert(key, i);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+
+        // Drain the pending writes, and iterate the entries. A `run_pending_tasks`
+        // call applies a bounded number of the writes, so call it a few times.
+        for _ in 0..10 {
+            cache.run_pending_tasks();
+        }
+        let ground_truth = cache.to_hash_map().len();
+        assert!(ground_truth > 0);
+        assert_eq!(cache.entry_count(), ground_truth);
     }
-}
 
-// private methods
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    #[inline]
-    fn schedule_write_op(
-        ch: &Sender<WriteOp<K, V>>,
-        op: WriteOp<K, V>,
-        housekeeper: Option<&HouseKeeperArc<K, V, S>>,
-    ) -> Result<(), TrySendError<WriteOp<K, V>>> {
-        let mut op = op;
+    #[test]
+    fn max_entry_weight() {
+        use crate::policy::InsertReport;
 
-        // NOTES:
-        // - This will block when the channel is full.
-        // - We are doing a busy-loop here. We were originally calling `ch.send(op)?`,
-        //   but we got a notable performance degradation.
-        loop {
-            BaseCache::apply_reads_writes_if_needed(ch, housekeeper);
-            match ch.try_send(op) {
-                Ok(()) => break,
-                Err(TrySendError::Full(op1)) => {
-                    op = op1;
-                    std::thread::sleep(Duration::from_micros(WRITE_RETRY_INTERVAL_MICROS));
-                }
-                Err(e @ TrySendError::Disconnected(_)) => return Err(e),
+        let mut cache = CacheBuilder::new(500)
+            .weigher(|_k, v: &Vec<u8>| v.len() as u32)
+            .max_entry_weight(100)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        // Entries not heavier than the max entry weight are stored.
+        cache.insert(0, vec![0u8; 50]);
+        cache.insert(1, vec![0u8; 100]);
+        cache.run_pending_tasks();
+        assert!(cache.contains_key(&0));
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.base.inner.weighted_size(), 150);
+
+        // A heavier entry is never stored although the cache has enough room.
+        cache.insert(2, vec![0u8; 101]);
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&2));
+        assert_eq!(cache.entry_count(), 2);
+        assert_eq!(cache.base.inner.weighted_size(), 150);
+
+        // Updating an entry with a heavier value removes the entry.
+        cache.insert(0, vec![0u8; 200]);
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&0));
+        assert_eq!(cache.entry_count(), 1);
+        assert_eq!(cache.base.inner.weighted_size(), 100);
+
+        // insert_with_report reports the heavier entry as rejected.
+        assert_eq!(
+            cache.insert_with_report(1, vec![0u8; 101]),
+            InsertReport::Rejected
+        );
+        assert_eq!(
+            cache.insert_with_report(3, vec![0u8; 10]),
+            InsertReport::Admitted
+        );
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&3));
+        assert_eq!(cache.base.inner.weighted_size(), 10);
+    }
+
+    #[test]
+    fn weighted_admission_with_multiple_victims() {
+        let mut cache = CacheBuilder::new(100)
+            .weigher(|_k, v: &Vec<u8>| v.len() as u32)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, vec![0u8; 10]);
+            cache.get(&i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), 100);
+        // counts: 0..10 -> 1 each
+
+        // Admitting the heavy entry needs three victims. Its frequency is lower
+        // than the total frequency of them, so it is not admitted.
+        const HEAVY: u32 = 100;
+        cache.get(&HEAVY);
+        cache.get(&HEAVY);
+        cache.insert(HEAVY, vec![0u8; 30]);
+        cache.run_pending_tasks();
+        assert!(!cache.contains_key(&HEAVY));
+        assert_eq!(cache.entry_count(), 10);
+        assert_eq!(cache.weighted_size(), 100);
+        // counts: HEAVY -> 2
+
+        // Now it is accessed more frequently than the three victims put together.
+        cache.get(&HEAVY);
+        cache.get(&HEAVY);
+        cache.insert(HEAVY, vec![0u8; 30]);
+        cache.run_pending_tasks();
+        assert!(cache.contains_key(&HEAVY));
+        assert_eq!(cache.entry_count(), 8);
+        assert_eq!(cache.weighted_size(), 100);
+
+        // The cache never exceeds the max capacity with mixed weights.
+        for i in 0..200 {
+            let weight = [1, 7, 25, 50, 3][i % 5];
+            let key = 1_000 + i as u32;
+            for _ in 0..(i % 4) {
+                cache.get(&key);
             }
+            cache.insert(key, vec![0u8; weight]);
+            cache.run_pending_tasks();
+            assert!(cache.weighted_size() <= 100);
         }
-        Ok(())
     }
-}
 
-// For unit tests.
-#[cfg(test)]
-impl<K, V, S> Cache<K, V, S>
-where
-    K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
-    S: BuildHasher + Clone + Send + Sync + 'static,
-{
-    pub(crate) fn is_table_empty(&self) -> bool {
-        self.table_size() == 0
+    #[test]
+    fn entry_count() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.entry_count(), 0);
+
+        for i in 0..10 {
+            cache.insert(i, format!("value-{}", i));
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 10);
+
+        cache.invalidate(&0);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 9);
     }
 
-    pub(crate) fn table_size(&self) -> usize {
-        self.base.table_size()
+    #[test]
+    fn set_max_capacity() {
+        let mut cache = CacheBuilder::new(100).build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..100 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 100);
+
+        // Shrink. The entries are evicted by the next housekeeping pass.
+        cache.set_max_capacity(10);
+        assert_eq!(cache.max_capacity(), 10);
+        assert_eq!(cache.entry_count(), 100);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 10);
+
+        // Grow. The cache accepts more entries.
+        cache.set_max_capacity(1_000);
+        for i in 100..300 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 210);
     }
 
-    pub(crate) fn invalidation_predicate_count(&self) -> usize {
-        self.base.invalidation_predicate_count()
+    #[test]
+    fn frequency_sketch_capacity() {
+        // Returns the hit ratio of the hot keys that are accessed among many more
+        // distinct cold keys.
+        fn hot_hit_ratio(sketch_capacity: Option<usize>) -> f64 {
+            const HOT_KEYS: u64 = 100;
+            let mut builder = Cache::builder().max_capacity(HOT_KEYS as usize);
+            if let Some(cap) = sketch_capacity {
+                builder = builder.frequency_sketch_capacity(cap);
+            }
+            let mut cache = builder.build();
+            cache.reconfigure_for_testing();
+            let cache = cache;
+
+            let mut cold_key = HOT_KEYS;
+            let (mut hits, mut reads) = (0, 0);
+            for round in 0..60 {
+                for hot_key in 0..HOT_KEYS {
+                    let hit = cache.get(&hot_key).is_some();
+                    if !hit {
+                        cache.insert(hot_key, ());
+                    }
+                    // Skip the warm-up rounds.
+                    if round >= 30 {
+                        reads += 1;
+                        hits += hit as usize;
+                    }
+                    for _ in 0..16 {
+                        cache.get(&cold_key);
+                        cache.insert(cold_key, ());
+                        cold_key += 1;
+                    }
+                }
+                cache.run_pending_tasks();
+            }
+            hits as f64 / reads as f64
+        }
+
+        // With the default sketch sized for the max capacity, the counters are
+        // aged so often that the hot keys are hardly told apart from the cold
+        // keys. A sketch sized for the key space keeps the hot keys cached.
+        let default_ratio = hot_hit_ratio(None);
+        let larger_ratio = hot_hit_ratio(Some(1 << 16));
+        assert!(default_ratio < 0.5, "default ratio: {}", default_ratio);
+        assert!(larger_ratio > 0.9, "larger ratio: {}", larger_ratio);
     }
 
-    pub(crate) fn reconfigure_for_testing(&mut self) {
-        self.base.reconfigure_for_testing();
+    #[test]
+    fn eviction_batch_size() {
+        let mut cache = CacheBuilder::new(100)
+            .eviction_batch_size(20)
+            .exact_entry_count()
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..100 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 100);
+
+        // Shrink drastically. Each housekeeping pass evicts at most 20 entries, so
+        // it takes (100 - 10) / 20 = 4.5, i.e. 5 passes to converge.
+        cache.set_max_capacity(10);
+        for expected in &[80, 60, 40, 20, 10, 10] {
+            cache.run_pending_tasks();
+            assert_eq!(cache.entry_count(), *expected);
+        }
     }
 
-    pub(crate) fn set_expiration_clock(&self, clock: Option<crate::common::time::Clock>) {
-        self.base.set_expiration_clock(clock);
+    #[test]
+    fn eviction_batch_size_with_entry_ttl() {
+        let mut cache = CacheBuilder::new(100)
+            .eviction_batch_size(20)
+            .exact_entry_count()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..50 {
+            cache.insert_with_ttl(i, i, Duration::from_secs(10));
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 50);
+
+        // In each housekeeping pass, the write order queue and the timer wheel
+        // remove at most 20 expired entries each. The other due timers stay in the
+        // wheel until the next pass.
+        mock.increment(Duration::from_secs(11));
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 10);
+        assert_eq!(cache.base.timer_count(), 10);
+
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.base.timer_count(), 0);
     }
-}
 
-// To see the debug prints, run test as `cargo test -- --nocapture`
-#[cfg(test)]
-mod tests {
-    use super::{Cache, ConcurrentCacheExt};
-    use crate::{common::time::Clock, sync::CacheBuilder};
+    #[test]
+    fn weighted_size() {
+        let weigher = |_k: &i32, v: &String| v.len() as u32;
+        let cache = CacheBuilder::new(100).weigher(weigher).build();
+
+        let values = ["a", "bb", "ccc", "dddd"];
+        for (i, v) in values.iter().enumerate() {
+            cache.insert(i as i32, v.to_string());
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), 10);
 
-    use std::{convert::Infallible, sync::Arc, time::Duration};
+        // Replace a value with a heavier one.
+        cache.insert(0, "eeeee".to_string());
+        cache.invalidate(&1);
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), 12);
+
+        // Without a weigher, every entry weighs one.
+        let cache = Cache::new(100);
+        for i in 0..10 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), 10);
+    }
 
     #[test]
-    fn basic_single_thread() {
-        let mut cache = Cache::new(3);
+    fn invalidate_all() {
+        let mut cache = Cache::new(100);
         cache.reconfigure_for_testing();
 
         // Make the cache exterior immutable.
@@ -678,92 +4372,210 @@ mod tests {
 
         cache.insert("a", "alice");
         cache.insert("b", "bob");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
-        // counts: a -> 1, b -> 1
-
         cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        // counts: a -> 1, b -> 1, c -> 1
-        cache.sync();
-
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
-        // counts: a -> 2, b -> 2, c -> 1
+        assert_eq!(cache.get(&"c"), Some("cindy"));
+        cache.run_pending_tasks();
 
-        // "d" should not be admitted because its frequency is too low.
-        cache.insert("d", "david"); //   count: d -> 0
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 1
+        cache.invalidate_all();
+        cache.run_pending_tasks();
 
         cache.insert("d", "david");
-        cache.sync();
-        assert_eq!(cache.get(&"d"), None); //   d -> 2
+        cache.run_pending_tasks();
 
-        // "d" should be admitted and "c" should be evicted
-        // because d's frequency is higher then c's.
-        cache.insert("d", "dennis");
-        cache.sync();
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+        assert_eq!(cache.get(&"d"), Some("david"));
+    }
+
+    #[test]
+    fn invalidate_all_under_continuous_inserts() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        const NUM_KEYS: usize = 10_000;
+
+        let cache = Cache::new(NUM_KEYS);
+        for i in 0..NUM_KEYS {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+
+        // Keep inserting new keys on another thread.
+        let is_done = Arc::new(AtomicBool::new(false));
+        let next_key = Arc::new(AtomicUsize::new(NUM_KEYS));
+        let writer = {
+            let cache = cache.clone();
+            let is_done = Arc::clone(&is_done);
+            let next_key = Arc::clone(&next_key);
+            std::thread::spawn(move || {
+                while !is_done.load(Ordering::Acquire) {
+                    let key = next_key.fetch_add(1, Ordering::Relaxed);
+                    cache.insert(key % (NUM_KEYS * 2), key);
+                }
+            })
+        };
+
+        for _ in 0..3 {
+            cache.invalidate_all();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let first_key_after = next_key.load(Ordering::Relaxed);
+
+        // The entries written before the last invalidate_all are removed, and the
+        // timestamp is cleared in a bounded number of cycles, even though the
+        // writer keeps inserting.
+        let mut cycles = 0;
+        while cache.base.is_invalidate_all_pending() {
+            cycles += 1;
+            assert!(cycles <= 1_000, "invalidate_all was not completed");
+            cache.run_pending_tasks();
+        }
+        assert!(next_key.load(Ordering::Relaxed) > first_key_after);
+
+        is_done.store(true, Ordering::Release);
+        writer.join().expect("Writer failed");
+        cache.run_pending_tasks();
+        assert!(!cache.base.is_invalidate_all_pending());
+    }
+
+    #[test]
+    fn invalidate_all_with_entry_behind_live_one() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        // Read "a" before invalidate_all, but do not apply the read yet.
         assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), None);
-        assert_eq!(cache.get(&"d"), Some("dennis"));
 
-        cache.invalidate(&"b");
-        assert_eq!(cache.get(&"b"), None);
+        mock.increment(Duration::from_secs(1));
+        cache.invalidate_all();
+
+        mock.increment(Duration::from_secs(1));
+        cache.insert("b", "bob");
+        cache.base.apply_pending_writes();
+
+        // Applying the read moves "a" behind "b" in the access order, with the
+        // timestamp of the read, which is before invalidate_all.
+        cache.run_pending_tasks();
+
+        assert!(!cache.base.is_invalidate_all_pending());
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.table_size(), 1);
+        assert_eq!(cache.entry_count(), 1);
     }
 
     #[test]
-    fn basic_multi_threads() {
-        let num_threads = 4;
-        let cache = Cache::new(100);
+    fn max_pending_invalidation_closures() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::PredicateError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        const MAX: usize = 3;
+
+        let mut cache = CacheBuilder::new(100)
+            .support_invalidation_closures()
+            .max_pending_invalidation_closures(MAX)
+            .build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        // Register more predicates than the limit. The predicates count how many
+        // times they are applied.
+        let applied = Arc::new(AtomicUsize::new(0));
+        for i in 0..MAX * 2 {
+            let applied = Arc::clone(&applied);
+            let result = cache.invalidate_entries_if(move |_k, _v| {
+                applied.fetch_add(1, Ordering::Relaxed);
+                false
+            });
+            if i < MAX {
+                assert!(result.is_ok());
+            } else {
+                assert!(matches!(
+                    result,
+                    Err(PredicateError::TooManyPending { max: MAX })
+                ));
+            }
+        }
+        assert_eq!(cache.invalidation_predicate_count(), MAX);
 
-        let handles = (0..num_threads)
-            .map(|id| {
-                let cache = cache.clone();
-                std::thread::spawn(move || {
-                    cache.insert(10, format!("{}-100", id));
-                    cache.get(&10);
-                    cache.insert(20, format!("{}-200", id));
-                    cache.invalidate(&10);
-                })
-            })
-            .collect::<Vec<_>>();
+        // A get applies at most MAX predicates.
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        assert_eq!(applied.load(Ordering::Relaxed), MAX);
 
-        handles.into_iter().for_each(|h| h.join().expect("Failed"));
+        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
+        cache.run_pending_tasks(); // To submit the invalidation task.
+        std::thread::sleep(Duration::from_millis(200));
+        cache.run_pending_tasks(); // To process the task result.
+        std::thread::sleep(Duration::from_millis(200));
 
-        assert!(cache.get(&10).is_none());
-        assert!(cache.get(&20).is_some());
+        // The applied predicates have been removed, so new ones can be registered.
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+        cache.invalidate_entries_if(|_k, &v| v == "alice")?;
+        assert!(cache.get(&"a").is_none());
+
+        Ok(())
     }
 
     #[test]
-    fn invalidate_all() {
-        let mut cache = Cache::new(100);
+    fn cancel_invalidation() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cache = CacheBuilder::new(100)
+            .support_invalidation_closures()
+            .build();
         cache.reconfigure_for_testing();
 
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
         // Make the cache exterior immutable.
         let cache = cache;
 
-        cache.insert("a", "alice");
-        cache.insert("b", "bob");
-        cache.insert("c", "cindy");
-        assert_eq!(cache.get(&"a"), Some("alice"));
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.get(&"c"), Some("cindy"));
-        cache.sync();
+        cache.insert(0, "alice");
+        cache.insert(1, "bob");
+        cache.insert(2, "alex");
+        cache.run_pending_tasks();
 
-        cache.invalidate_all();
-        cache.sync();
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
 
-        cache.insert("d", "david");
-        cache.sync();
+        let id = cache.invalidate_entries_if(|_k, &v| v.starts_with('a'))?;
+        assert_eq!(cache.invalidation_predicate_count(), 1);
 
-        assert!(cache.get(&"a").is_none());
-        assert!(cache.get(&"b").is_none());
-        assert!(cache.get(&"c").is_none());
-        assert_eq!(cache.get(&"d"), Some("david"));
+        // Cancel the predicate before it is applied.
+        assert!(cache.cancel_invalidation(id.clone()));
+        assert!(!cache.cancel_invalidation(id.clone()));
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        // The entries matching the cancelled predicate are still there.
+        assert_eq!(cache.get(&0), Some("alice"));
+        assert_eq!(cache.get(&2), Some("alex"));
+
+        // Run the pending tasks and wait. (TODO: Need a better way than sleeping)
+        cache.run_pending_tasks();
+        std::thread::sleep(Duration::from_millis(200));
+        cache.run_pending_tasks();
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(cache.get(&0), Some("alice"));
+        assert_eq!(cache.get(&1), Some("bob"));
+        assert_eq!(cache.get(&2), Some("alex"));
+        assert_eq!(cache.predicate_removed_count(&id), None);
+
+        Ok(())
     }
 
     #[test]
@@ -784,10 +4596,10 @@ mod tests {
         cache.insert(0, "alice");
         cache.insert(1, "bob");
         cache.insert(2, "alex");
-        cache.sync();
+        cache.run_pending_tasks();
 
         mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
+        cache.run_pending_tasks();
 
         assert_eq!(cache.get(&0), Some("alice"));
         assert_eq!(cache.get(&1), Some("bob"));
@@ -802,9 +4614,9 @@ mod tests {
         cache.insert(3, "alice");
 
         // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
+        cache.run_pending_tasks(); // To submit the invalidation task.
         std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
+        cache.run_pending_tasks(); // To process the task result.
         std::thread::sleep(Duration::from_millis(200));
 
         assert!(cache.get(&0).is_none());
@@ -822,9 +4634,9 @@ mod tests {
         assert_eq!(cache.invalidation_predicate_count(), 2);
 
         // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
+        cache.run_pending_tasks(); // To submit the invalidation task.
         std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
+        cache.run_pending_tasks(); // To process the task result.
         std::thread::sleep(Duration::from_millis(200));
 
         assert!(cache.get(&1).is_none());
@@ -835,6 +4647,102 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn invalidate_entries_with_meta_if() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cache = CacheBuilder::new(100)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert(0, "alice");
+        cache.insert(1, "bob");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(10)); // 10 secs from the start.
+
+        cache.insert(2, "cindy");
+        cache.insert(3, "david");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 15 secs from the start.
+
+        // Invalidate the entries older than 10 seconds, i.e. 0 and 1.
+        cache.invalidate_entries_with_meta_if(|_k, _v, meta| {
+            assert_eq!(meta.weight(), 1);
+            meta.age() >= Duration::from_secs(10)
+        })?;
+
+        // `get` applies the predicate too.
+        assert!(cache.get(&0).is_none());
+        assert_eq!(cache.get(&2), Some("cindy"));
+
+        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
+        cache.run_pending_tasks(); // To submit the invalidation task.
+        std::thread::sleep(Duration::from_millis(200));
+        cache.run_pending_tasks(); // To process the task result.
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.get(&3), Some("david"));
+        assert_eq!(cache.table_size(), 2);
+        assert_eq!(cache.invalidation_predicate_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn predicate_removed_count() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cache = CacheBuilder::new(100)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, i * 10);
+        }
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+
+        // Matches 0, 20, 40, 60 and 80.
+        let id1 = cache.invalidate_entries_if(|_k, v| v % 20 == 0)?;
+        // Matches 60 and 70.
+        let id2 = cache.invalidate_entries_if(|_k, v| *v >= 60 && *v < 80)?;
+        assert_eq!(cache.predicate_removed_count(&id1), Some(0));
+        assert_eq!(cache.predicate_removed_count(&id2), Some(0));
+        assert_eq!(cache.predicate_removed_count(&"unknown".to_string()), None);
+
+        // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
+        cache.run_pending_tasks(); // To submit the invalidation task.
+        std::thread::sleep(Duration::from_millis(200));
+
+        // The predicates are still registered, as the task result has not been
+        // processed yet.
+        assert_eq!(cache.predicate_removed_count(&id1), Some(5));
+        assert_eq!(cache.predicate_removed_count(&id2), Some(2));
+
+        cache.run_pending_tasks(); // To process the task result.
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(cache.table_size(), 4);
+        assert_eq!(cache.predicate_removed_count(&id1), None);
+        assert_eq!(cache.predicate_removed_count(&id2), None);
+
+        Ok(())
+    }
+
     #[test]
     fn time_to_live() {
         let mut cache = CacheBuilder::new(100)
@@ -850,51 +4758,265 @@ mod tests {
         let cache = cache;
 
         cache.insert("a", "alice");
-        cache.sync();
+        cache.run_pending_tasks();
 
         mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
+        cache.run_pending_tasks();
 
         cache.get(&"a");
 
         mock.increment(Duration::from_secs(5)); // 10 secs.
-        cache.sync();
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert!(cache.is_table_empty());
+
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.table_size(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.table_size(), 1);
+
+        cache.insert("b", "bill");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 20 secs
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"b"), Some("bill"));
+        assert_eq!(cache.table_size(), 1);
+
+        mock.increment(Duration::from_secs(5)); // 25 secs
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), None);
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn age_histogram() {
+        let mut cache = CacheBuilder::new(100).build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert!(cache.age_histogram().is_empty());
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(3)); // 3 secs from the start.
+        cache.insert("c", "cindy");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(7)); // 10 secs from the start.
+        cache.insert("d", "david");
+        cache.insert("e", "emily");
+        cache.insert("f", "fred");
+        cache.run_pending_tasks();
+
+        // Ages: a and b are 10 secs old, c is 7 secs old, and d, e and f are 0 sec.
+        assert_eq!(
+            cache.age_histogram(),
+            vec![
+                (Duration::from_secs(1), 3),
+                (Duration::from_secs(2), 0),
+                (Duration::from_secs(4), 0),
+                (Duration::from_secs(8), 1),
+                (Duration::from_secs(16), 2),
+            ]
+        );
+
+        // Updating a value resets its age, and invalidated entries are not counted.
+        cache.insert("a", "anne");
+        cache.invalidate(&"c");
+        cache.run_pending_tasks();
+        assert_eq!(
+            cache.age_histogram(),
+            vec![
+                (Duration::from_secs(1), 4),
+                (Duration::from_secs(2), 0),
+                (Duration::from_secs(4), 0),
+                (Duration::from_secs(8), 0),
+                (Duration::from_secs(16), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn time_to_idle() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), Some("alice"));
+
+        mock.increment(Duration::from_secs(5)); // 10 secs.
+        cache.run_pending_tasks();
+
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.table_size(), 2);
+
+        mock.increment(Duration::from_secs(5)); // 15 secs.
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some("bob"));
+        assert_eq!(cache.table_size(), 1);
+
+        mock.increment(Duration::from_secs(10)); // 25 secs
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), None);
+        assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn custom_time_source() {
+        use crate::time_source::TimeSource;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        #[derive(Default)]
+        struct FakeClock(AtomicU64);
+
+        impl TimeSource for FakeClock {
+            fn now(&self) -> Duration {
+                Duration::from_secs(self.0.load(Ordering::Acquire))
+            }
+        }
+
+        let clock = Arc::new(FakeClock::default());
+        let advance = |secs| clock.0.fetch_add(secs, Ordering::AcqRel);
+        let cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .clock(Arc::clone(&clock))
+            .build();
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        advance(5);
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.run_pending_tasks();
 
+        advance(5);
+        assert_eq!(cache.get(&"a"), Some("alice"));
+        cache.run_pending_tasks();
+
+        advance(10);
         assert_eq!(cache.get(&"a"), None);
+        cache.run_pending_tasks();
         assert!(cache.is_table_empty());
+    }
+
+    #[test]
+    fn time_to_idle_reset_by_writes() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .build();
+
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.run_pending_tasks();
+
+        // By default, updating the value resets the idle timer.
+        cache.insert("a", "anna");
+        cache.run_pending_tasks();
+
+        mock.increment(Duration::from_secs(7)); // 12 secs.
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.get(&"a"), Some("anna"));
+        assert_eq!(cache.table_size(), 1);
+    }
+
+    #[test]
+    fn time_to_idle_reads_only() {
+        let mut cache = CacheBuilder::new(100)
+            .time_to_idle(Duration::from_secs(10))
+            .time_to_idle_reads_only()
+            .build();
+
+        cache.reconfigure_for_testing();
 
-        cache.insert("b", "bob");
-        cache.sync();
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
 
-        assert_eq!(cache.table_size(), 1);
+        // Make the cache exterior immutable.
+        let cache = cache;
 
-        mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.sync();
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
 
-        assert_eq!(cache.get(&"b"), Some("bob"));
-        assert_eq!(cache.table_size(), 1);
+        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
+        cache.run_pending_tasks();
 
+        // Updating the values does not reset the idle timer, but reading does.
+        cache.insert("a", "anna");
         cache.insert("b", "bill");
-        cache.sync();
+        assert_eq!(cache.get(&"b"), Some("bill"));
+        cache.run_pending_tasks();
 
-        mock.increment(Duration::from_secs(5)); // 20 secs
-        cache.sync();
+        assert_eq!(cache.table_size(), 2);
+
+        mock.increment(Duration::from_secs(7)); // 12 secs.
+        cache.run_pending_tasks();
 
+        assert_eq!(cache.get(&"a"), None);
         assert_eq!(cache.get(&"b"), Some("bill"));
         assert_eq!(cache.table_size(), 1);
 
-        mock.increment(Duration::from_secs(5)); // 25 secs
-        cache.sync();
+        mock.increment(Duration::from_secs(10)); // 22 secs.
+        cache.run_pending_tasks();
 
-        assert_eq!(cache.get(&"a"), None);
         assert_eq!(cache.get(&"b"), None);
         assert!(cache.is_table_empty());
     }
 
     #[test]
-    fn time_to_idle() {
+    fn time_to_idle_reads_only_with_invalidate_all() {
         let mut cache = CacheBuilder::new(100)
             .time_to_idle(Duration::from_secs(10))
+            .time_to_idle_reads_only()
             .build();
 
         cache.reconfigure_for_testing();
@@ -906,36 +5028,127 @@ mod tests {
         let cache = cache;
 
         cache.insert("a", "alice");
-        cache.sync();
-
-        mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
-
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
         assert_eq!(cache.get(&"a"), Some("alice"));
 
-        mock.increment(Duration::from_secs(5)); // 10 secs.
-        cache.sync();
+        mock.increment(Duration::from_secs(2)); // 2 secs from the start.
+        cache.invalidate_all();
 
-        cache.insert("b", "bob");
-        cache.sync();
+        mock.increment(Duration::from_secs(1)); // 3 secs.
+        cache.insert("a", "anna");
+        cache.run_pending_tasks();
 
-        assert_eq!(cache.table_size(), 2);
+        // "a" has been inserted again after `invalidate_all`, so it is a new value.
+        assert_eq!(cache.get(&"a"), Some("anna"));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.table_size(), 1);
 
-        mock.increment(Duration::from_secs(5)); // 15 secs.
-        cache.sync();
+        mock.increment(Duration::from_secs(5)); // 8 secs.
+        cache.run_pending_tasks();
 
-        assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), Some("bob"));
+        // Updating the value does not reset the idle timer started by the read at 3
+        // secs.
+        cache.insert("a", "andy");
+        cache.run_pending_tasks();
         assert_eq!(cache.table_size(), 1);
 
-        mock.increment(Duration::from_secs(10)); // 25 secs
-        cache.sync();
+        mock.increment(Duration::from_secs(4)); // 12 secs.
+        cache.run_pending_tasks();
+        assert_eq!(cache.get(&"a"), Some("andy"));
+
+        mock.increment(Duration::from_secs(10)); // 22 secs.
+        cache.run_pending_tasks();
 
         assert_eq!(cache.get(&"a"), None);
-        assert_eq!(cache.get(&"b"), None);
         assert!(cache.is_table_empty());
     }
 
+    #[test]
+    fn get_or_optionally_insert_with() {
+        use std::thread::{sleep, spawn};
+
+        let cache: Cache<u32, &str> = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // Thread1 loads the value, but the loader declines to produce one.
+        let thread1 = {
+            let cache1 = cache.clone();
+            spawn(move || {
+                cache1.get_or_optionally_insert_with(KEY, || {
+                    sleep(Duration::from_millis(300));
+                    None
+                })
+            })
+        };
+
+        // Thread2 waits for thread1's load, and gets `None` without evaluating its
+        // own closure.
+        let thread2 = {
+            let cache2 = cache.clone();
+            spawn(move || {
+                sleep(Duration::from_millis(100));
+                cache2.get_or_optionally_insert_with(KEY, || unreachable!())
+            })
+        };
+
+        assert_eq!(thread1.join().expect("Thread1 failed"), None);
+        assert_eq!(thread2.join().expect("Thread2 failed"), None);
+        assert!(!cache.contains_key(&KEY));
+
+        // The next call evaluates its closure again.
+        let v = cache.get_or_optionally_insert_with(KEY, || Some("thread3"));
+        assert_eq!(v, Some("thread3"));
+        assert_eq!(cache.get(&KEY), Some("thread3"));
+    }
+
+    #[test]
+    fn get_with_if() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            thread::{sleep, spawn},
+        };
+
+        // The value is (version, data).
+        let cache: Cache<u32, (u32, &str)> = Cache::new(100);
+        let is_stale = |v: &(u32, &str)| v.0 < 2;
+
+        // Absent: the init closure is evaluated.
+        assert_eq!(cache.get_with_if(0, || (1, "v1"), is_stale), (1, "v1"));
+        assert_eq!(cache.get(&0), Some((1, "v1")));
+
+        // Present and fresh: the init closure is not evaluated.
+        cache.insert(1, (2, "v2"));
+        assert_eq!(cache.get_with_if(1, || unreachable!(), is_stale), (2, "v2"));
+
+        // Present and stale: the init closure is evaluated only once even if many
+        // threads find the stale value at the same time.
+        let init_count = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                let init_count = Arc::clone(&init_count);
+                spawn(move || {
+                    sleep(Duration::from_millis(i * 50));
+                    cache.get_with_if(
+                        0,
+                        || {
+                            init_count.fetch_add(1, Ordering::AcqRel);
+                            sleep(Duration::from_millis(300));
+                            (2, "v2")
+                        },
+                        is_stale,
+                    )
+                })
+            })
+            .collect();
+        for t in threads {
+            assert_eq!(t.join().expect("Thread failed"), (2, "v2"));
+        }
+        assert_eq!(init_count.load(Ordering::Acquire), 1);
+        assert_eq!(cache.get(&0), Some((2, "v2")));
+    }
+
     #[test]
     fn get_or_insert_with() {
         use std::thread::{sleep, spawn};
@@ -1018,6 +5231,118 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_or_insert_with_key() {
+        let cache = Cache::new(100);
+
+        let v = cache.get_or_insert_with_key(1, |key| format!("value{}", key));
+        assert_eq!(v, "value1");
+        let v = cache.get_or_insert_with_key(2, |key| {
+            assert_eq!(**key, 2);
+            format!("value{}", key)
+        });
+        assert_eq!(v, "value2");
+
+        // The init closure is not called when the value exists.
+        let v = cache.get_or_insert_with_key(1, |_key| unreachable!());
+        assert_eq!(v, "value1");
+        assert_eq!(cache.get(&2), Some("value2".to_string()));
+    }
+
+    #[test]
+    fn get_or_insert_with_status() {
+        use crate::policy::EntryStatus;
+        use std::{sync::Barrier, thread};
+
+        const NUM_THREADS: usize = 8;
+
+        let cache = Cache::new(100);
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        let handles: Vec<_> = (0..NUM_THREADS)
+            .map(|i| {
+                let (cache, barrier) = (cache.clone(), Arc::clone(&barrier));
+                thread::spawn(move || {
+                    barrier.wait();
+                    cache.get_or_insert_with_status(1, || {
+                        // Give the other threads time to wait for this closure.
+                        thread::sleep(Duration::from_millis(50));
+                        i
+                    })
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        let created: Vec<_> = results
+            .iter()
+            .filter(|(_, status)| *status == EntryStatus::Created)
+            .collect();
+        assert_eq!(created.len(), 1);
+        // All threads got the value of the thread that created it.
+        let v = created[0].0;
+        assert!(results.iter().all(|(value, _)| *value == v));
+        assert_eq!(cache.get(&1), Some(v));
+
+        assert_eq!(
+            cache.get_or_insert_with_status(1, || unreachable!()),
+            (v, EntryStatus::Existed)
+        );
+    }
+
+    #[test]
+    fn try_get_with() {
+        use std::thread::{sleep, spawn};
+
+        #[derive(Debug, PartialEq)]
+        enum LoadError {
+            NotFound(u32),
+            Unavailable,
+        }
+
+        let cache = Cache::new(100);
+        const KEY: u32 = 0;
+
+        // Thread1 evaluates its init closure, which fails after 300 ms.
+        let thread1 = {
+            let cache1 = cache.clone();
+            spawn(move || {
+                cache1.try_get_with(KEY, || {
+                    sleep(Duration::from_millis(300));
+                    Err(LoadError::NotFound(KEY))
+                })
+            })
+        };
+
+        // Thread2 waits for thread1's init closure and gets the same error.
+        let thread2 = {
+            let cache2 = cache.clone();
+            spawn(move || {
+                sleep(Duration::from_millis(100));
+                cache2.try_get_with(KEY, || -> Result<&str, _> { unreachable!() })
+            })
+        };
+
+        let r1 = thread1.join().expect("Thread1 failed");
+        let r2 = thread2.join().expect("Thread2 failed");
+
+        // Both callers get the same concrete error.
+        match (&r1, &r2) {
+            (Err(e1), Err(e2)) => {
+                assert!(Arc::ptr_eq(e1, e2));
+                assert!(matches!(**e1, LoadError::NotFound(KEY)));
+            }
+            _ => panic!("unexpected results: {:?}, {:?}", r1, r2),
+        }
+        assert_eq!(cache.get(&KEY), None);
+
+        // The failure was not cached, so the next call evaluates its closure.
+        let r3 = cache.try_get_with(KEY, || -> Result<_, LoadError> { Ok("value") });
+        assert_eq!(r3, Ok("value"));
+        let r4 = cache.try_get_with(KEY, || Err(LoadError::Unavailable));
+        assert_eq!(r4, Ok("value"));
+    }
+
     #[test]
     fn get_or_try_insert_with() {
         use std::{
@@ -1157,6 +5482,115 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cache_errors_for() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, PartialEq)]
+        struct LoadError;
+
+        let mut cache = CacheBuilder::new(100)
+            .cache_errors_for(Duration::from_secs(5))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let calls = AtomicUsize::new(0);
+        let load = |result: Result<&'static str, LoadError>| {
+            || {
+                calls.fetch_add(1, Ordering::AcqRel);
+                result
+            }
+        };
+
+        // Only the first call runs `init`. The others get the remembered error.
+        for _ in 0..10 {
+            let v = cache.get_or_try_insert_with("a", load(Err(LoadError)));
+            assert_eq!(v, Err(Arc::new(LoadError)));
+        }
+        assert_eq!(calls.load(Ordering::Acquire), 1);
+
+        mock.increment(Duration::from_secs(4)); // 4 secs.
+        let v = cache.get_or_try_insert_with("a", load(Ok("alice")));
+        assert_eq!(v, Err(Arc::new(LoadError)));
+        assert_eq!(calls.load(Ordering::Acquire), 1);
+
+        // After the window, the next call retries.
+        mock.increment(Duration::from_secs(1)); // 5 secs.
+        let v = cache.get_or_try_insert_with("a", load(Ok("alice")));
+        assert_eq!(v, Ok("alice"));
+        assert_eq!(calls.load(Ordering::Acquire), 2);
+        assert_eq!(cache.get(&"a"), Some("alice"));
+    }
+
+    #[test]
+    fn cache_errors_for_removes_expired_errors() {
+        #[derive(Debug, PartialEq)]
+        struct LoadError;
+
+        let mut cache = CacheBuilder::new(100)
+            .cache_errors_for(Duration::from_secs(5))
+            .build();
+        cache.reconfigure_for_testing();
+
+        let (clock, mock) = Clock::mock();
+        cache.set_expiration_clock(Some(clock));
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in &["a", "b"] {
+            let v = cache.get_or_try_insert_with(*key, || Err::<&str, _>(LoadError));
+            assert_eq!(v, Err(Arc::new(LoadError)));
+        }
+        assert_eq!(cache.value_initializer.waiter_count(), 2);
+
+        mock.increment(Duration::from_secs(4)); // 4 secs.
+        cache.run_pending_tasks();
+        assert_eq!(cache.value_initializer.waiter_count(), 2);
+
+        // The housekeeper removes the expired errors even though the keys are not
+        // requested again.
+        mock.increment(Duration::from_secs(1)); // 5 secs.
+        cache.run_pending_tasks();
+        assert_eq!(cache.value_initializer.waiter_count(), 0);
+    }
+
+    #[test]
+    fn reentrant_get_or_insert_with() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let cache = Cache::new(100);
+
+        // Run on another thread so that a deadlock would fail the test by the
+        // timeout rather than hang.
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let cache1 = cache.clone();
+        std::thread::spawn(move || {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                cache1.get_or_insert_with(1, || cache1.get_or_insert_with(1, || 5))
+            }));
+            tx.send(result.is_err()).unwrap();
+        });
+        let panicked = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("The re-entrant call was not detected");
+        assert!(panicked);
+
+        // The key can be loaded after the panic.
+        assert_eq!(cache.get_or_insert_with(1, || 10), 10);
+        // A nested call for a different key is fine.
+        assert_eq!(
+            cache.get_or_insert_with(2, || cache.get_or_insert_with(3, || 3)),
+            3
+        );
+    }
+
     #[test]
     // https://github.com/moka-rs/moka/issues/43
     fn handle_panic_in_get_or_insert_with() {
@@ -1180,6 +5614,292 @@ mod tests {
         assert_eq!(cache.get_or_insert_with(1, || 5), 5);
     }
 
+    #[test]
+    fn panic_in_get_or_insert_with_is_raised_only_to_initiating_caller() {
+        use std::{
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Barrier,
+            },
+            thread,
+        };
+
+        let cache = Cache::new(16);
+        let barrier = Arc::new(Barrier::new(2));
+
+        // Thread 1 starts the init closure, which will panic.
+        let thread1 = {
+            let cache_ref = cache.clone();
+            let barrier_ref = barrier.clone();
+            thread::spawn(move || {
+                cache_ref.get_or_insert_with(1, || {
+                    barrier_ref.wait();
+                    thread::sleep(Duration::from_millis(100));
+                    panic!("Panic during get_or_insert_with");
+                })
+            })
+        };
+
+        // Thread 2 waits for thread 1's init closure, and then should run its own.
+        let init2_called = Arc::new(AtomicBool::new(false));
+        let thread2 = {
+            let cache_ref = cache.clone();
+            let init2_called_ref = Arc::clone(&init2_called);
+            barrier.wait();
+            thread::spawn(move || {
+                cache_ref.get_or_insert_with(1, || {
+                    init2_called_ref.store(true, Ordering::Release);
+                    5
+                })
+            })
+        };
+
+        assert!(thread1.join().is_err());
+        assert_eq!(thread2.join().expect("Thread 2 should not panic"), 5);
+        assert!(init2_called.load(Ordering::Acquire));
+        assert_eq!(cache.get(&1), Some(5));
+    }
+
+    #[test]
+    fn init_panic_policy() {
+        use crate::{policy::InitPanicPolicy, InitPanicked};
+        use std::{
+            panic::{catch_unwind, AssertUnwindSafe},
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Barrier,
+            },
+            thread,
+        };
+
+        // Returns the result of the call waiting for the panicking init closure,
+        // and whether its own init closure was called.
+        let run = |policy| {
+            let cache = CacheBuilder::new(16).init_panic_policy(policy).build();
+            let barrier = Arc::new(Barrier::new(2));
+
+            let thread1 = {
+                let cache_ref = cache.clone();
+                let barrier_ref = barrier.clone();
+                thread::spawn(move || {
+                    cache_ref.get_or_insert_with(1, || {
+                        barrier_ref.wait();
+                        thread::sleep(Duration::from_millis(100));
+                        panic!("Panic during get_or_insert_with");
+                    })
+                })
+            };
+
+            barrier.wait();
+            let init2_called = AtomicBool::new(false);
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                cache.get_or_insert_with(1, || {
+                    init2_called.store(true, Ordering::Release);
+                    5
+                })
+            }));
+            assert!(thread1.join().is_err());
+            (cache, result, init2_called.load(Ordering::Acquire))
+        };
+
+        // Retry: the waiting call evaluates its own init closure.
+        let (cache, result, init2_called) = run(InitPanicPolicy::Retry);
+        assert_eq!(result.ok(), Some(5));
+        assert!(init2_called);
+        assert_eq!(cache.get(&1), Some(5));
+
+        // Fail: the waiting call fails without evaluating its init closure.
+        let (cache, result, init2_called) = run(InitPanicPolicy::Fail);
+        let payload = result.expect_err("The waiting call should fail");
+        assert!(payload.is::<InitPanicked>());
+        assert!(!init2_called);
+        assert_eq!(cache.get(&1), None);
+
+        // The later calls evaluate their init closures as usual.
+        assert_eq!(cache.get_or_insert_with(1, || 6), 6);
+    }
+
+    #[test]
+    fn get_or_insert_with_retrying() {
+        use crate::policy::InitPanicPolicy;
+        use std::{
+            panic::{catch_unwind, AssertUnwindSafe},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Barrier,
+            },
+            thread,
+        };
+
+        // Use the `Fail` policy to ensure the waiting call is not failed by the
+        // first panic of the loader.
+        let cache = CacheBuilder::new(16)
+            .init_panic_policy(InitPanicPolicy::Fail)
+            .build();
+        let barrier = Arc::new(Barrier::new(2));
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        // Thread 1 calls the loader, which panics on the first attempt.
+        let thread1 = {
+            let cache_ref = cache.clone();
+            let barrier_ref = barrier.clone();
+            let attempts_ref = Arc::clone(&attempts);
+            thread::spawn(move || {
+                cache_ref.get_or_insert_with_retrying(1, || {
+                    if attempts_ref.fetch_add(1, Ordering::AcqRel) == 0 {
+                        barrier_ref.wait();
+                        thread::sleep(Duration::from_millis(100));
+                        panic!("Panic during get_or_insert_with_retrying");
+                    }
+                    5
+                })
+            })
+        };
+
+        // The main thread waits for the loader while it is retried.
+        barrier.wait();
+        assert_eq!(cache.get_or_insert_with(1, || unreachable!()), 5);
+        assert_eq!(thread1.join().expect("Thread 1 should not panic"), 5);
+        assert_eq!(attempts.load(Ordering::Acquire), 2);
+        assert_eq!(cache.get(&1), Some(5));
+
+        // A loader that keeps panicking is called up to three times.
+        let attempts = AtomicUsize::new(0);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            cache.get_or_insert_with_retrying(2, || {
+                attempts.fetch_add(1, Ordering::AcqRel);
+                panic!("Panic during get_or_insert_with_retrying");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Acquire), 3);
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn get_or_insert_with_retrying_by_leader() {
+        use std::{
+            panic::{self, catch_unwind, AssertUnwindSafe},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Barrier, Mutex,
+            },
+            thread,
+        };
+
+        const MESSAGE: &str = "Panic during get_or_insert_with_retrying_by_leader";
+
+        // Count the panics of this test seen by the panic hook, and pass the other
+        // panics to the previous hook.
+        let hook_calls = Arc::new(AtomicUsize::new(0));
+        {
+            let hook_calls = Arc::clone(&hook_calls);
+            let prev_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                let payload = info.payload();
+                let message = match payload.downcast_ref::<String>() {
+                    Some(message) => Some(message.as_str()),
+                    None => payload.downcast_ref::<&str>().copied(),
+                };
+                if message == Some(MESSAGE) {
+                    hook_calls.fetch_add(1, Ordering::AcqRel);
+                } else {
+                    prev_hook(info);
+                }
+            }));
+        }
+
+        let cache = CacheBuilder::new(16).max_init_attempts(4).build();
+        let barrier = Arc::new(Barrier::new(2));
+        let callers = Arc::new(Mutex::new(Vec::new()));
+
+        // Thread 1 becomes the leader. Its loader panics on the first three
+        // attempts, and succeeds on the last one.
+        let thread1 = {
+            let cache_ref = cache.clone();
+            let barrier_ref = Arc::clone(&barrier);
+            let callers_ref = Arc::clone(&callers);
+            thread::spawn(move || {
+                cache_ref.get_or_insert_with_retrying(1, || {
+                    let mut callers = callers_ref.lock().unwrap();
+                    callers.push(thread::current().id());
+                    let attempts = callers.len();
+                    std::mem::drop(callers);
+                    if attempts == 1 {
+                        barrier_ref.wait();
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    if attempts < 4 {
+                        panic!("{}", MESSAGE);
+                    }
+                    5
+                })
+            })
+        };
+
+        // The main thread waits for the leader, and its own loader is never
+        // called, even though the leader's loader panics.
+        barrier.wait();
+        assert_eq!(cache.get_or_insert_with_retrying(1, || unreachable!()), 5);
+        let thread1_id = thread1.thread().id();
+        assert_eq!(thread1.join().expect("Thread 1 should not panic"), 5);
+
+        // All the attempts were made by the leader, and every swallowed panic ran
+        // the panic hook.
+        let callers = callers.lock().unwrap();
+        assert_eq!(callers.len(), 4);
+        assert!(callers.iter().all(|id| *id == thread1_id));
+        assert_eq!(hook_calls.load(Ordering::Acquire), 3);
+
+        // With one attempt, the first panic is propagated.
+        let cache = CacheBuilder::new(16).max_init_attempts(1).build();
+        let attempts = AtomicUsize::new(0);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            cache.get_or_insert_with_retrying(2, || -> u32 {
+                attempts.fetch_add(1, Ordering::AcqRel);
+                panic!("{}", MESSAGE);
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Acquire), 1);
+        assert_eq!(hook_calls.load(Ordering::Acquire), 4);
+    }
+
+    #[test]
+    fn get_or_insert() {
+        use std::{sync::Barrier, thread};
+
+        const NUM_THREADS: usize = 16;
+
+        let cache = Cache::new(100);
+        let barrier = Arc::new(Barrier::new(NUM_THREADS));
+
+        // Every thread tries to insert its own default value at the same time.
+        let threads = (0..NUM_THREADS)
+            .map(|i| {
+                let cache = cache.clone();
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    cache.get_or_insert("key", i)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let values = threads
+            .into_iter()
+            .map(|t| t.join().expect("Failed to join"))
+            .collect::<Vec<_>>();
+
+        // All threads observe the same winning value.
+        let winner = values[0];
+        assert!(values.iter().all(|v| *v == winner));
+        assert_eq!(cache.get(&"key"), Some(winner));
+
+        // The existing value is returned and the default is discarded.
+        assert_eq!(cache.get_or_insert("key", NUM_THREADS), winner);
+    }
+
     #[test]
     // https://github.com/moka-rs/moka/issues/43
     fn handle_panic_in_get_or_try_insert_with() {
@@ -0,0 +1,170 @@
+use super::Cache;
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+/// A read-only handle to a [`Cache`][cache-struct].
+///
+/// A `CacheView` can be obtained by calling the [`view`][view-method] method of a
+/// `Cache`. It shares the internal data structures with the cache, but it only
+/// exposes the methods to read the cache; `get`, `contains_key`, `peek` and
+/// `entry_count`. This is useful to pass the cache to the code that should read
+/// it but never modify it.
+///
+/// Note that `get` is still a cache read operation. It updates the historic
+/// popularity estimator and resets the idle timer for the key as usual. Use `peek`
+/// to read a value without affecting the cache policy.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::sync::{Cache, CacheView};
+///
+/// fn print_user(users: &CacheView<u32, String>, id: u32) {
+///     match users.get(&id) {
+///         Some(name) => println!("user {}: {}", id, name),
+///         None => println!("user {} not found", id),
+///     }
+/// }
+///
+/// let cache = Cache::new(100);
+/// cache.insert(1, "alice".to_string());
+/// print_user(&cache.view(), 1);
+/// ```
+///
+/// The methods to modify the cache are not available on a `CacheView`:
+///
+/// ```compile_fail
+/// use moka::sync::Cache;
+///
+/// let cache = Cache::new(100);
+/// let view = cache.view();
+/// view.insert(1, "alice".to_string());
+/// ```
+///
+/// ```compile_fail
+/// use moka::sync::Cache;
+///
+/// let cache: Cache<u32, String> = Cache::new(100);
+/// let view = cache.view();
+/// view.invalidate(&1);
+/// ```
+///
+/// [cache-struct]: ./struct.Cache.html
+/// [view-method]: ./struct.Cache.html#method.view
+pub struct CacheView<K, V, S = RandomState> {
+    cache: Cache<K, V, S>,
+}
+
+impl<K, V, S> Clone for CacheView<K, V, S> {
+    /// Makes a clone of this read-only handle.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<K, V, S> CacheView<K, V, S> {
+    pub(crate) fn new(cache: Cache<K, V, S>) -> Self {
+        Self { cache }
+    }
+}
+
+impl<K, V, S> CacheView<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    /// Returns a _clone_ of the value corresponding to the key.
+    ///
+    /// See [`Cache::get`][get] for details.
+    ///
+    /// [get]: ./struct.Cache.html#method.get
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.get(key)
+    }
+
+    /// Returns `true` if the cache contains a value for the key.
+    ///
+    /// See [`Cache::contains_key`][contains-key] for details.
+    ///
+    /// [contains-key]: ./struct.Cache.html#method.contains_key
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.contains_key(key)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, without affecting
+    /// the cache policy.
+    ///
+    /// See [`Cache::peek`][peek] for details.
+    ///
+    /// [peek]: ./struct.Cache.html#method.peek
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.peek(key)
+    }
+
+    /// Returns the approximate number of entries in the cache.
+    ///
+    /// See [`Cache::entry_count`][entry-count] for details.
+    ///
+    /// [entry-count]: ./struct.Cache.html#method.entry_count
+    pub fn entry_count(&self) -> usize {
+        self.cache.entry_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CacheView;
+    use crate::sync::Cache;
+
+    fn read_view(
+        view: &CacheView<&'static str, &'static str>,
+    ) -> (Option<&'static str>, bool, usize) {
+        (view.peek(&"a"), view.contains_key(&"b"), view.entry_count())
+    }
+
+    #[test]
+    fn read_through_view() {
+        let mut cache = Cache::new(100);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let view = cache.view();
+        assert_eq!(read_view(&view), (None, false, 0));
+
+        // The view sees the writes to the cache.
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+        assert_eq!(view.get(&"a"), Some("alice"));
+        assert_eq!(read_view(&view.clone()), (Some("alice"), false, 1));
+
+        cache.invalidate(&"a");
+        cache.run_pending_tasks();
+        assert_eq!(view.get(&"a"), None);
+        assert_eq!(read_view(&view), (None, false, 0));
+    }
+}
@@ -0,0 +1,160 @@
+use super::{segment::segment_capacity, spill::SpillStoreRef, Weigher};
+use crate::{
+    notification::{EvictionListener, MaintenanceListener, RefreshErrorListener},
+    policy::{EvictionPolicy, Expiry, InitPanicPolicy},
+    time_source::TimeSource,
+};
+
+#[cfg(feature = "future")]
+use crate::{future::Runtime, notification::AsyncEvictionListener};
+
+use std::{sync::Arc, time::Duration};
+
+/// The configuration knobs of a cache, except the max capacity and the
+/// segmentation.
+///
+/// The cache builders hold one, and pass it by value to the cache and its
+/// internal structures at the construction time. So adding a knob only needs a
+/// new field here and a setter in the builders.
+pub(crate) struct CacheConfig<K, V> {
+    pub(crate) initial_capacity: Option<usize>,
+    pub(crate) spill_store: Option<SpillStoreRef<K, V>>,
+    pub(crate) weigher: Option<Weigher<K, V>>,
+    pub(crate) eviction_listener: Option<EvictionListener<K, V>>,
+    #[cfg(feature = "future")]
+    pub(crate) async_eviction_listener: Option<AsyncEvictionListener<K, V>>,
+    #[cfg(feature = "future")]
+    pub(crate) eviction_concurrency: usize,
+    pub(crate) time_to_live: Option<Duration>,
+    pub(crate) time_to_idle: Option<Duration>,
+    pub(crate) time_to_idle_reads_only: bool,
+    pub(crate) refresh_after_write: Option<Duration>,
+    pub(crate) record_stats: bool,
+    pub(crate) eviction_policy: EvictionPolicy,
+    pub(crate) init_panic_policy: InitPanicPolicy,
+    pub(crate) max_init_attempts: Option<usize>,
+    pub(crate) maintenance_listener: Option<MaintenanceListener>,
+    pub(crate) expiry: Option<Arc<dyn Expiry<K, V>>>,
+    pub(crate) time_source: Option<Arc<dyn TimeSource>>,
+    pub(crate) write_channel_capacity: Option<usize>,
+    pub(crate) read_buffer_flush_threshold: Option<usize>,
+    pub(crate) manual_housekeeping: bool,
+    pub(crate) invalidator_enabled: bool,
+    pub(crate) max_pending_invalidation_closures: Option<usize>,
+    pub(crate) max_entry_weight: Option<u32>,
+    pub(crate) exact_entry_count: bool,
+    pub(crate) admit_on_insert: bool,
+    pub(crate) eviction_batch_size: Option<usize>,
+    pub(crate) frequency_sketch_capacity: Option<usize>,
+    pub(crate) cache_errors_for: Option<Duration>,
+    #[cfg(feature = "future")]
+    pub(crate) load_timeout: Option<Duration>,
+    #[cfg(feature = "future")]
+    pub(crate) refresh_concurrency: usize,
+    pub(crate) refresh_error_listener: Option<RefreshErrorListener<K>>,
+    #[cfg(feature = "future")]
+    pub(crate) runtime: Option<Arc<dyn Runtime>>,
+}
+
+impl<K, V> Default for CacheConfig<K, V> {
+    fn default() -> Self {
+        Self {
+            initial_capacity: None,
+            spill_store: None,
+            weigher: None,
+            eviction_listener: None,
+            #[cfg(feature = "future")]
+            async_eviction_listener: None,
+            #[cfg(feature = "future")]
+            eviction_concurrency: 1,
+            time_to_live: None,
+            time_to_idle: None,
+            time_to_idle_reads_only: false,
+            refresh_after_write: None,
+            record_stats: false,
+            eviction_policy: EvictionPolicy::TinyLfu,
+            init_panic_policy: InitPanicPolicy::Retry,
+            max_init_attempts: None,
+            maintenance_listener: None,
+            expiry: None,
+            time_source: None,
+            write_channel_capacity: None,
+            read_buffer_flush_threshold: None,
+            manual_housekeeping: false,
+            invalidator_enabled: false,
+            max_pending_invalidation_closures: None,
+            max_entry_weight: None,
+            exact_entry_count: false,
+            admit_on_insert: false,
+            eviction_batch_size: None,
+            frequency_sketch_capacity: None,
+            cache_errors_for: None,
+            #[cfg(feature = "future")]
+            load_timeout: None,
+            #[cfg(feature = "future")]
+            refresh_concurrency: 1,
+            refresh_error_listener: None,
+            #[cfg(feature = "future")]
+            runtime: None,
+        }
+    }
+}
+
+// `#[derive(Clone)]` would require `K: Clone` and `V: Clone`.
+impl<K, V> Clone for CacheConfig<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            initial_capacity: self.initial_capacity,
+            spill_store: self.spill_store.as_ref().map(Arc::clone),
+            weigher: self.weigher.as_ref().map(Arc::clone),
+            eviction_listener: self.eviction_listener.as_ref().map(Arc::clone),
+            #[cfg(feature = "future")]
+            async_eviction_listener: self.async_eviction_listener.as_ref().map(Arc::clone),
+            #[cfg(feature = "future")]
+            eviction_concurrency: self.eviction_concurrency,
+            time_to_live: self.time_to_live,
+            time_to_idle: self.time_to_idle,
+            time_to_idle_reads_only: self.time_to_idle_reads_only,
+            refresh_after_write: self.refresh_after_write,
+            record_stats: self.record_stats,
+            eviction_policy: self.eviction_policy,
+            init_panic_policy: self.init_panic_policy,
+            max_init_attempts: self.max_init_attempts,
+            maintenance_listener: self.maintenance_listener.as_ref().map(Arc::clone),
+            expiry: self.expiry.as_ref().map(Arc::clone),
+            time_source: self.time_source.as_ref().map(Arc::clone),
+            write_channel_capacity: self.write_channel_capacity,
+            read_buffer_flush_threshold: self.read_buffer_flush_threshold,
+            manual_housekeeping: self.manual_housekeeping,
+            invalidator_enabled: self.invalidator_enabled,
+            max_pending_invalidation_closures: self.max_pending_invalidation_closures,
+            max_entry_weight: self.max_entry_weight,
+            exact_entry_count: self.exact_entry_count,
+            admit_on_insert: self.admit_on_insert,
+            eviction_batch_size: self.eviction_batch_size,
+            frequency_sketch_capacity: self.frequency_sketch_capacity,
+            cache_errors_for: self.cache_errors_for,
+            #[cfg(feature = "future")]
+            load_timeout: self.load_timeout,
+            #[cfg(feature = "future")]
+            refresh_concurrency: self.refresh_concurrency,
+            refresh_error_listener: self.refresh_error_listener.as_ref().map(Arc::clone),
+            #[cfg(feature = "future")]
+            runtime: self.runtime.as_ref().map(Arc::clone),
+        }
+    }
+}
+
+impl<K, V> CacheConfig<K, V> {
+    /// Returns the configuration of the segment at `index` of a segmented cache,
+    /// which has a share of the capacities.
+    pub(crate) fn for_segment(&self, num_segments: usize, index: usize) -> Self {
+        Self {
+            initial_capacity: self.initial_capacity.map(|cap| cap / num_segments),
+            frequency_sketch_capacity: self
+                .frequency_sketch_capacity
+                .map(|cap| segment_capacity(cap, num_segments, index)),
+            ..self.clone()
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use super::{KeyDate, KeyHashDate, ValueEntry};
-use crate::common::deque::{CacheRegion, DeqNode, Deque};
+use crate::common::{
+    deque::{CacheRegion, DeqNode, Deque},
+    timer_wheel::TimerWheel,
+};
 
 use std::{ptr::NonNull, sync::Arc};
 
@@ -8,6 +11,8 @@ pub(crate) struct Deques<K> {
     pub(crate) probation: Deque<KeyHashDate<K>>,
     pub(crate) protected: Deque<KeyHashDate<K>>, // Not used yet.
     pub(crate) write_order: Deque<KeyDate<K>>,
+    /// Deadlines of the entries with their own time-to-live.
+    pub(crate) timer_wheel: TimerWheel<K>,
 }
 
 #[cfg(feature = "future")]
@@ -25,6 +30,7 @@ impl<K> Default for Deques<K> {
             probation: Deque::new(CacheRegion::MainProbation),
             protected: Deque::new(CacheRegion::MainProtected),
             write_order: Deque::new(CacheRegion::WriteOrder),
+            timer_wheel: TimerWheel::default(),
         }
     }
 }
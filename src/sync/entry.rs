@@ -0,0 +1,79 @@
+use super::Cache;
+
+use std::{
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
+
+/// A view into a single entry in a cache, which may either be vacant or occupied.
+///
+/// This `struct` is constructed from the `entry` method on `Cache` and
+/// `SegmentedCache`.
+///
+/// Unlike `std::collections::hash_map::Entry`, this does not hold any lock on the
+/// cache across the method calls. Other threads may update or remove the entry
+/// between the calls. Each method coordinates with the other threads only for its
+/// own operation; `or_insert_with` has the same guarantee as
+/// `Cache::get_or_insert_with`, and `and_modify` as `Cache::compute`.
+pub struct Entry<'a, K, V, S> {
+    cache: &'a Cache<K, V, S>,
+    key: Arc<K>,
+    hash: u64,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    pub(crate) fn new(cache: &'a Cache<K, V, S>, key: Arc<K>, hash: u64) -> Self {
+        Self { cache, key, hash }
+    }
+
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the `init`
+    /// closure if empty, and returns a _clone_ of the value.
+    ///
+    /// Like `Cache::get_or_insert_with`, only one of the concurrent calls on the
+    /// same key evaluates its closure, and other calls wait for it to complete.
+    pub fn or_insert_with(self, init: impl FnOnce() -> V) -> V {
+        self.cache
+            .get_or_insert_with_hash_and_fun(self.key, self.hash, init)
+    }
+
+    /// Ensures a value is in the entry by inserting the `default` if empty, and
+    /// returns a _clone_ of the value.
+    pub fn or_insert(self, default: V) -> V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensures a value is in the entry by inserting the default value if empty, and
+    /// returns a _clone_ of the value.
+    pub fn or_default(self) -> V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Provides in-place mutable access to a _clone_ of the value in the entry, and
+    /// inserts the modified value to the cache. Does nothing if the entry is empty.
+    ///
+    /// The calls of this method (and `Cache::compute`) on the same key are
+    /// serialized.
+    pub fn and_modify(self, f: impl FnOnce(&mut V)) -> Self {
+        self.cache
+            .compute_with_hash(Arc::clone(&self.key), self.hash, |current| {
+                current.cloned().map(|mut v| {
+                    f(&mut v);
+                    v
+                })
+            });
+        self
+    }
+}
@@ -0,0 +1,55 @@
+use super::ValueEntry;
+
+use std::{fmt, ops::Deref, sync::Arc};
+
+/// A guard giving a reference to the value of a cache entry without cloning the
+/// value.
+///
+/// This `struct` is returned by the `get_ref` method of the caches.
+///
+/// The guard holds the entry, so the value stays alive while the guard exists,
+/// even if the entry is evicted, invalidated or updated in the meantime. In that
+/// case, the guard keeps giving the old value, and the memory of the value is
+/// released when the last guard holding it is dropped. So holding many guards for
+/// a long time can grow the memory usage beyond what the `max_capacity` of the
+/// cache suggests.
+pub struct EntryGuard<K, V> {
+    inner: GuardInner<K, V>,
+}
+
+enum GuardInner<K, V> {
+    Entry(Arc<ValueEntry<K, V>>),
+    // The value read from the spill store, which is not held by the cache.
+    Spilled(V),
+}
+
+impl<K, V> EntryGuard<K, V> {
+    pub(crate) fn new(entry: Arc<ValueEntry<K, V>>) -> Self {
+        Self {
+            inner: GuardInner::Entry(entry),
+        }
+    }
+
+    pub(crate) fn spilled(value: V) -> Self {
+        Self {
+            inner: GuardInner::Spilled(value),
+        }
+    }
+}
+
+impl<K, V> Deref for EntryGuard<K, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        match &self.inner {
+            GuardInner::Entry(entry) => &entry.value,
+            GuardInner::Spilled(value) => value,
+        }
+    }
+}
+
+impl<K, V: fmt::Debug> fmt::Debug for EntryGuard<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EntryGuard").field(&**self).finish()
+    }
+}
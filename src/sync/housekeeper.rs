@@ -12,7 +12,7 @@ use scheduled_thread_pool::JobHandle;
 use std::{
     marker::PhantomData,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, Weak,
     },
     time::Duration,
@@ -45,6 +45,8 @@ pub(crate) struct Housekeeper<T> {
     periodical_sync_job: Mutex<Option<JobHandle>>,
     periodical_sync_running: Arc<Mutex<()>>,
     on_demand_sync_scheduled: Arc<AtomicBool>,
+    on_demand_sync_count: AtomicUsize,
+    is_manual: bool,
     _marker: PhantomData<T>,
 }
 
@@ -75,7 +77,9 @@ impl<T> Drop for Housekeeper<T> {
 
 // functions/methods used by Cache
 impl<T: InnerSync> Housekeeper<T> {
-    pub(crate) fn new(inner: Weak<T>) -> Self {
+    /// Creates a housekeeper. If `is_manual` is `true`, it will not run the sync
+    /// jobs automatically; the client is responsible for calling `sync`.
+    pub(crate) fn new(inner: Weak<T>, is_manual: bool) -> Self {
         use crate::common::thread_pool::PoolName;
 
         let thread_pool = ThreadPoolRegistry::acquire_pool(PoolName::Housekeeper);
@@ -83,20 +87,26 @@ impl<T: InnerSync> Housekeeper<T> {
         let is_shutting_down = Arc::new(AtomicBool::new(false));
         let periodical_sync_running = Arc::new(Mutex::new(()));
 
-        let sync_job = Self::start_periodical_sync_job(
-            &thread_pool,
-            Arc::clone(&inner_ptr),
-            Arc::clone(&is_shutting_down),
-            Arc::clone(&periodical_sync_running),
-        );
+        let sync_job = if is_manual {
+            None
+        } else {
+            Some(Self::start_periodical_sync_job(
+                &thread_pool,
+                Arc::clone(&inner_ptr),
+                Arc::clone(&is_shutting_down),
+                Arc::clone(&periodical_sync_running),
+            ))
+        };
 
         Self {
             inner: inner_ptr,
             thread_pool,
             is_shutting_down,
-            periodical_sync_job: Mutex::new(Some(sync_job)),
+            periodical_sync_job: Mutex::new(sync_job),
             periodical_sync_running,
             on_demand_sync_scheduled: Arc::new(AtomicBool::new(false)),
+            on_demand_sync_count: AtomicUsize::new(0),
+            is_manual,
             _marker: PhantomData::default(),
         }
     }
@@ -135,8 +145,8 @@ impl<T: InnerSync> Housekeeper<T> {
     pub(crate) fn try_schedule_sync(&self) -> bool {
         // TODO: Check if these `Orderings` are correct.
 
-        // If shutting down, do not schedule the task.
-        if self.is_shutting_down.load(Ordering::Acquire) {
+        // If shutting down or in the manual mode, do not schedule the task.
+        if self.is_manual || self.is_shutting_down.load(Ordering::Acquire) {
             return false;
         }
 
@@ -148,6 +158,7 @@ impl<T: InnerSync> Housekeeper<T> {
             Ordering::Relaxed,
         ) {
             Ok(_) => {
+                self.on_demand_sync_count.fetch_add(1, Ordering::Relaxed);
                 let unsafe_weak_ptr = Arc::clone(&self.inner);
                 let sync_scheduled = Arc::clone(&self.on_demand_sync_scheduled);
                 // Execute a task in a worker thread.
@@ -161,10 +172,29 @@ impl<T: InnerSync> Housekeeper<T> {
         }
     }
 
+    /// Called by a writer when the write op channel is full. In the manual mode,
+    /// nobody else will drain the channel, so run the sync job on the calling
+    /// thread.
+    pub(crate) fn on_write_channel_full(&self) {
+        if self.is_manual && !self.is_shutting_down.load(Ordering::Acquire) {
+            Self::call_sync(&self.inner);
+        }
+    }
+
     #[cfg(test)]
     pub(crate) fn periodical_sync_job(&self) -> &Mutex<Option<JobHandle>> {
         &self.periodical_sync_job
     }
+
+    #[cfg(test)]
+    pub(crate) fn shutdown_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.is_shutting_down)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn on_demand_sync_count(&self) -> usize {
+        self.on_demand_sync_count.load(Ordering::Relaxed)
+    }
 }
 
 // private functions/methods
@@ -7,10 +7,11 @@ use crate::{
         unsafe_weak_pointer::UnsafeWeakPointer,
         AccessTime,
     },
+    policy::EntryMeta,
     PredicateError,
 };
 
-use super::{base_cache::Inner, PredicateId, PredicateIdStr, ValueEntry};
+use super::{base_cache::Inner, KvEntry, PredicateId, PredicateIdStr, ValueEntry};
 
 use parking_lot::{Mutex, RwLock};
 use std::{
@@ -21,11 +22,12 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc, Weak,
     },
+    task::{Context, Poll, Waker},
     time::Duration,
 };
 use uuid::Uuid;
 
-pub(crate) type PredicateFun<K, V> = Arc<dyn Fn(&K, &V) -> bool + Send + Sync + 'static>;
+pub(crate) type PredicateFun<K, V> = Arc<dyn Fn(&K, &V, EntryMeta) -> bool + Send + Sync + 'static>;
 
 pub(crate) trait GetOrRemoveEntry<K, V> {
     fn get_value_entry(&self, key: &Arc<K>) -> Option<Arc<ValueEntry<K, V>>>;
@@ -35,6 +37,59 @@ pub(crate) trait GetOrRemoveEntry<K, V> {
         F: FnMut(&Arc<K>, &Arc<ValueEntry<K, V>>) -> bool;
 }
 
+/// Signals the completion of the invalidation by a predicate, i.e. when the
+/// predicate has been applied to all entries that were inserted before it was
+/// registered.
+#[derive(Default)]
+pub(crate) struct PredicateCompletion {
+    state: Mutex<CompletionState>,
+}
+
+#[derive(Default)]
+struct CompletionState {
+    is_complete: bool,
+    // The number of the entries removed by the invalidation task because they
+    // matched the predicate.
+    removed_count: u64,
+    wakers: Vec<Waker>,
+}
+
+impl PredicateCompletion {
+    pub(crate) fn is_complete(&self) -> bool {
+        self.state.lock().is_complete
+    }
+
+    pub(crate) fn removed_count(&self) -> u64 {
+        self.state.lock().removed_count
+    }
+
+    fn record_removal(&self) {
+        let mut state = self.state.lock();
+        state.removed_count = state.removed_count.saturating_add(1);
+    }
+
+    pub(crate) fn poll_complete(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock();
+        if state.is_complete {
+            Poll::Ready(())
+        } else {
+            if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+
+    fn complete(&self) {
+        let wakers = {
+            let mut state = self.state.lock();
+            state.is_complete = true;
+            std::mem::take(&mut state.wakers)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
 pub(crate) struct KeyDateLite<K> {
     key: Arc<K>,
     timestamp: Instant,
@@ -59,12 +114,12 @@ impl<K> KeyDateLite<K> {
 }
 
 pub(crate) struct InvalidationResult<K, V> {
-    pub(crate) invalidated: Vec<Arc<ValueEntry<K, V>>>,
+    pub(crate) invalidated: Vec<KvEntry<K, V>>,
     pub(crate) is_done: bool,
 }
 
 impl<K, V> InvalidationResult<K, V> {
-    fn new(invalidated: Vec<Arc<ValueEntry<K, V>>>, is_done: bool) -> Self {
+    fn new(invalidated: Vec<KvEntry<K, V>>, is_done: bool) -> Self {
         Self {
             invalidated,
             is_done,
@@ -77,6 +132,8 @@ pub(crate) struct Invalidator<K, V, S> {
     is_empty: AtomicBool,
     scan_context: Arc<ScanContext<K, V, S>>,
     thread_pool: Arc<ThreadPool>,
+    // The max number of the predicates that are registered but not applied yet.
+    max_pending: Option<usize>,
 }
 
 impl<K, V, S> Drop for Invalidator<K, V, S> {
@@ -91,6 +148,12 @@ impl<K, V, S> Drop for Invalidator<K, V, S> {
         }
 
         ThreadPoolRegistry::release_pool(&self.thread_pool);
+
+        // Nobody will apply the remaining predicates. Wake up the waiters for them.
+        self.predicates
+            .read()
+            .values()
+            .for_each(|p| p.completion.complete());
     }
 }
 
@@ -98,13 +161,14 @@ impl<K, V, S> Drop for Invalidator<K, V, S> {
 // Crate public methods.
 //
 impl<K, V, S> Invalidator<K, V, S> {
-    pub(crate) fn new(cache: Weak<Inner<K, V, S>>) -> Self {
+    pub(crate) fn new(cache: Weak<Inner<K, V, S>>, max_pending: Option<usize>) -> Self {
         let thread_pool = ThreadPoolRegistry::acquire_pool(PoolName::Invalidator);
         Self {
             predicates: RwLock::new(HashMap::new()),
             is_empty: AtomicBool::new(true),
             scan_context: Arc::new(ScanContext::new(cache)),
             thread_pool,
+            max_pending,
         }
     }
 
@@ -123,7 +187,9 @@ impl<K, V, S> Invalidator<K, V, S> {
             .collect::<Vec<_>>();
 
         for id in removing_ids {
-            pred_map.remove(&id);
+            if let Some(pred) = pred_map.remove(&id) {
+                pred.completion.complete();
+            }
         }
 
         if pred_map.is_empty() {
@@ -140,6 +206,12 @@ impl<K, V, S> Invalidator<K, V, S> {
         let mut tries = 0;
         let mut preds = self.predicates.write();
 
+        if let Some(max) = self.max_pending {
+            if preds.len() >= max {
+                return Err(PredicateError::TooManyPending { max });
+            }
+        }
+
         while tries < MAX_RETRY {
             let id = Uuid::new_v4().to_hyphenated().to_string();
             if preds.contains_key(&id) {
@@ -160,13 +232,49 @@ impl<K, V, S> Invalidator<K, V, S> {
         panic!("Cannot assign a new PredicateId to a predicate");
     }
 
+    /// Removes the predicate so that it will not be applied anymore. Returns
+    /// `false` if the predicate has already been removed (completed or cancelled).
+    ///
+    /// If an invalidation task is running, this waits for it to finish the
+    /// current batch.
+    pub(crate) fn cancel_predicate(&self, id: PredicateIdStr<'_>) -> bool {
+        // Lock the scan context first, in the same order as `submit_task` and
+        // `remove_finished_predicates`.
+        let mut scanning = self.scan_context.predicates.lock();
+        scanning.retain(|p| p.id() != id);
+
+        let mut pred_map = self.predicates.write();
+        let removed = pred_map.remove(id);
+        if pred_map.is_empty() {
+            self.is_empty.store(true, Ordering::Release);
+        }
+
+        match removed {
+            Some(pred) => {
+                // Wake up the waiters for the predicate.
+                pred.completion.complete();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the completion of the predicate, or `None` if the predicate has
+    /// already been removed (completed).
+    pub(crate) fn completion(&self, id: PredicateIdStr<'_>) -> Option<Arc<PredicateCompletion>> {
+        self.predicates
+            .read()
+            .get(id)
+            .map(|p| Arc::clone(&p.completion))
+    }
+
     // This method will be called by the get method of Cache.
     #[inline]
     pub(crate) fn apply_predicates(&self, key: &Arc<K>, entry: &Arc<ValueEntry<K, V>>) -> bool {
         if self.is_empty() {
             false
         } else if let Some(ts) = entry.last_modified() {
-            Self::do_apply_predicates(self.predicates.read().values(), key, &entry.value, ts)
+            Self::do_apply_predicates(self.predicates.read().values(), key, entry, ts)
         } else {
             false
         }
@@ -226,12 +334,17 @@ impl<K, V, S> Invalidator<K, V, S> {
 //
 impl<K, V, S> Invalidator<K, V, S> {
     #[inline]
-    fn do_apply_predicates<'a, I>(predicates: I, key: &'a K, value: &'a V, ts: Instant) -> bool
+    fn do_apply_predicates<'a, I>(
+        predicates: I,
+        key: &'a K,
+        entry: &'a Arc<ValueEntry<K, V>>,
+        ts: Instant,
+    ) -> bool
     where
         I: Iterator<Item = &'a Predicate<K, V>>,
     {
         for predicate in predicates {
-            if predicate.is_applicable(ts) && predicate.apply(key, value) {
+            if predicate.is_applicable(ts) && predicate.apply(key, entry) {
                 return true;
             }
         }
@@ -261,7 +374,9 @@ impl<K, V, S> Invalidator<K, V, S> {
     fn remove_predicates(&self, predicates: &[Predicate<K, V>]) {
         let mut pred_map = self.predicates.write();
         predicates.iter().for_each(|p| {
-            pred_map.remove(p.id());
+            if pred_map.remove(p.id()).is_some() {
+                p.completion.complete();
+            }
         });
         if pred_map.is_empty() {
             self.is_empty.store(true, Ordering::Release);
@@ -305,6 +420,7 @@ struct Predicate<K, V> {
     id: PredicateId,
     f: PredicateFun<K, V>,
     registered_at: Instant,
+    completion: Arc<PredicateCompletion>,
 }
 
 impl<K, V> Clone for Predicate<K, V> {
@@ -313,6 +429,7 @@ impl<K, V> Clone for Predicate<K, V> {
             id: self.id.clone(),
             f: Arc::clone(&self.f),
             registered_at: self.registered_at,
+            completion: Arc::clone(&self.completion),
         }
     }
 }
@@ -323,6 +440,7 @@ impl<K, V> Predicate<K, V> {
             id: id.to_string(),
             f,
             registered_at,
+            completion: Arc::default(),
         }
     }
 
@@ -334,8 +452,23 @@ impl<K, V> Predicate<K, V> {
         last_modified <= self.registered_at
     }
 
-    fn apply(&self, key: &K, value: &V) -> bool {
-        (self.f)(key, value)
+    fn apply(&self, key: &K, entry: &Arc<ValueEntry<K, V>>) -> bool {
+        (self.f)(key, &entry.value, self.entry_meta(entry))
+    }
+
+    /// Returns the metadata of the entry, measured at the time when this predicate
+    /// was registered.
+    fn entry_meta(&self, entry: &Arc<ValueEntry<K, V>>) -> EntryMeta {
+        let elapsed = |ts: Option<Instant>| {
+            ts.map_or(Duration::default(), |ts| {
+                self.registered_at.saturating_duration_since(ts)
+            })
+        };
+        EntryMeta::new(
+            elapsed(entry.last_modified()),
+            elapsed(entry.last_accessed()),
+            entry.weight(),
+        )
     }
 }
 
@@ -397,9 +530,11 @@ where
         for candidate in &self.candidates {
             let key = &candidate.key;
             let ts = candidate.timestamp;
-            if Self::apply(&predicates, cache, key, ts) {
+            let matched = Self::apply(&predicates, cache, key, ts);
+            if !matched.is_empty() {
                 if let Some(entry) = Self::invalidate(cache, key, ts) {
-                    invalidated.push(entry)
+                    matched.iter().for_each(|p| p.completion.record_removal());
+                    invalidated.push(KvEntry::new(Arc::clone(key), entry))
                 }
             }
             newest_timestamp = Some(ts);
@@ -412,24 +547,28 @@ where
         }
     }
 
-    fn apply<C>(predicates: &[Predicate<K, V>], cache: &Arc<C>, key: &Arc<K>, ts: Instant) -> bool
+    /// Returns the predicates matching the entry of the key.
+    fn apply<'p, C>(
+        predicates: &'p [Predicate<K, V>],
+        cache: &Arc<C>,
+        key: &Arc<K>,
+        ts: Instant,
+    ) -> Vec<&'p Predicate<K, V>>
     where
         Arc<C>: GetOrRemoveEntry<K, V>,
     {
         if let Some(entry) = cache.get_value_entry(key) {
             if let Some(lm) = entry.last_modified() {
                 if lm == ts {
-                    return Invalidator::<_, _, S>::do_apply_predicates(
-                        predicates.iter(),
-                        key,
-                        &entry.value,
-                        lm,
-                    );
+                    return predicates
+                        .iter()
+                        .filter(|p| p.is_applicable(lm) && p.apply(key, &entry))
+                        .collect();
                 }
             }
         }
 
-        false
+        Vec::default()
     }
 
     fn invalidate<C>(cache: &Arc<C>, key: &Arc<K>, ts: Instant) -> Option<Arc<ValueEntry<K, V>>>
@@ -447,7 +586,7 @@ where
 }
 
 struct ScanResult<K, V> {
-    invalidated: Vec<Arc<ValueEntry<K, V>>>,
+    invalidated: Vec<KvEntry<K, V>>,
     is_truncated: bool,
     newest_timestamp: Option<Instant>,
 }
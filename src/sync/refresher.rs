@@ -0,0 +1,31 @@
+use crate::common::thread_pool::{PoolName, ThreadPool, ThreadPoolRegistry};
+
+use std::sync::Arc;
+
+/// Runs the background refreshes of a cache on the refresher thread pool.
+///
+/// The pool is shared by all the caches and has as many threads as the CPU cores,
+/// so up to that many reloads run concurrently, and the other reloads wait in the
+/// queue of the pool. Only one reload per key is queued at a time, so the queue
+/// does not grow beyond the number of the keys due for refresh.
+pub(crate) struct Refresher {
+    thread_pool: Arc<ThreadPool>,
+}
+
+impl Drop for Refresher {
+    fn drop(&mut self) {
+        ThreadPoolRegistry::release_pool(&self.thread_pool);
+    }
+}
+
+impl Refresher {
+    pub(crate) fn new() -> Self {
+        Self {
+            thread_pool: ThreadPoolRegistry::acquire_pool(PoolName::Refresher),
+        }
+    }
+
+    pub(crate) fn schedule(&self, refresh: impl FnOnce() + Send + 'static) {
+        self.thread_pool.pool.execute(refresh);
+    }
+}
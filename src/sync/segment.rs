@@ -1,12 +1,23 @@
-use super::{cache::Cache, ConcurrentCacheExt};
-use crate::PredicateError;
+use super::{
+    cache::Cache, config::CacheConfig, invalidator::PredicateFun, CacheBuilder, ConcurrentCacheExt,
+    Entry, EntryGuard, SegmentKeyFn,
+};
+use crate::{
+    policy::{EntryMeta, EntryStatus, EvictionPolicy, InsertReport},
+    stats::{CacheStats, SegmentStat},
+    CacheError, PredicateError,
+};
 
 use std::{
     borrow::Borrow,
-    collections::hash_map::RandomState,
+    collections::{hash_map::RandomState, HashMap},
     error::Error,
+    fmt,
     hash::{BuildHasher, Hash, Hasher},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
@@ -54,6 +65,25 @@ impl<K, V, S> Clone for SegmentedCache<K, V, S> {
     }
 }
 
+impl<K, V, S> fmt::Debug for SegmentedCache<K, V, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Prints the configuration and the approximate size of this cache. The
+    /// entries are not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SegmentedCache")
+            .field("max_capacity", &self.max_capacity())
+            .field("num_segments", &self.num_segments())
+            .field("time_to_live", &self.time_to_live())
+            .field("time_to_idle", &self.time_to_idle())
+            .field("entry_count", &self.entry_count())
+            .finish()
+    }
+}
+
 impl<K, V> SegmentedCache<K, V, RandomState>
 where
     K: Hash + Eq + Send + Sync + 'static,
@@ -62,6 +92,12 @@ where
     /// Constructs a new `SegmentedCache<K, V>` that has multiple internal
     /// segments and will store up to the `max_capacity` entries.
     ///
+    /// `num_segments` is rounded up to the next power of two, and `max_capacity`
+    /// is distributed across the segments.
+    ///
+    /// If `max_capacity` is `0`, the cache does not store any entry; values passed
+    /// to `insert` are evicted immediately, and `get` always returns `None`.
+    ///
     /// To adjust various configuration knobs such as `initial_capacity` or
     /// `time_to_live`, use the [`CacheBuilder`][builder-struct].
     ///
@@ -74,14 +110,34 @@ where
         let build_hasher = RandomState::default();
         Self::with_everything(
             max_capacity,
-            None,
             num_segments,
-            build_hasher,
-            None,
             None,
-            false,
+            build_hasher,
+            CacheConfig::default(),
         )
     }
+
+    /// Returns a [`CacheBuilder`][builder-struct], which can build a
+    /// `SegmentedCache` with `num_segments` segments and various configuration
+    /// knobs.
+    ///
+    /// The max capacity must be set by the `max_capacity` method of the builder;
+    /// otherwise `build` panics.
+    ///
+    /// [builder-struct]: ./struct.CacheBuilder.html
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::SegmentedCache;
+    ///
+    /// let cache = SegmentedCache::builder(4).max_capacity(1_000).build();
+    /// cache.insert("a", "alice");
+    /// assert_eq!(cache.num_segments(), 4);
+    /// ```
+    pub fn builder(num_segments: usize) -> CacheBuilder<K, V, SegmentedCache<K, V, RandomState>> {
+        CacheBuilder::default().segments(num_segments)
+    }
 }
 
 impl<K, V, S> SegmentedCache<K, V, S>
@@ -95,22 +151,18 @@ where
     /// Panics if `num_segments` is 0.
     pub(crate) fn with_everything(
         max_capacity: usize,
-        initial_capacity: Option<usize>,
         num_segments: usize,
+        segment_key_fn: Option<SegmentKeyFn<K>>,
         build_hasher: S,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        config: CacheConfig<K, V>,
     ) -> Self {
         Self {
             inner: Arc::new(Inner::new(
                 max_capacity,
-                initial_capacity,
                 num_segments,
+                segment_key_fn,
                 build_hasher,
-                time_to_live,
-                time_to_idle,
-                invalidator_enabled,
+                config,
             )),
         }
     }
@@ -131,7 +183,211 @@ where
         Q: Hash + Eq + ?Sized,
     {
         let hash = self.inner.hash(key);
-        self.inner.select(hash).get_with_hash(key, hash)
+        self.inner
+            .select_borrowed(key, hash)
+            .get_with_hash(key, hash)
+    }
+
+    /// Returns a guard giving a reference to the value corresponding to the key,
+    /// rather than a _clone_ of the value.
+    ///
+    /// This avoids the cost of cloning a large value on every read, without
+    /// wrapping the values by `Arc`. The guard keeps the value alive even if the
+    /// entry is evicted or invalidated while the guard is held; see
+    /// [`EntryGuard`][entry-guard] for the memory implications of holding many
+    /// guards.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    ///
+    /// [entry-guard]: ./struct.EntryGuard.html
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<EntryGuard<K, V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).get_ref(key)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, after applying the
+    /// pending writes to the cache.
+    ///
+    /// `insert` and `invalidate` update the hash table immediately, but their
+    /// effects on the cache policy, such as the admission of a new entry, are
+    /// applied later by the housekeeper. So `get` may return a value that is going
+    /// to be rejected by the admission policy. This method applies all the writes
+    /// scheduled to the segment of the key before the call, so the result reflects
+    /// them. This is useful for read-your-writes scenarios and for tests.
+    ///
+    /// **NOTE**: This method is much slower than `get`, as it takes the lock
+    /// shared with the housekeeper and applies the pending writes on the calling
+    /// thread. Do not call it on a hot path.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get_consistent<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).get_consistent(key)
+    }
+
+    /// Returns _clones_ of the values corresponding to the keys, in the same order
+    /// as the keys. The element is `None` if the key does not exist in the cache.
+    ///
+    /// The keys are grouped by the segments, and looked up one segment at a
+    /// time. There is no global lock, so the result is a weakly-consistent
+    /// snapshot; it may not reflect the concurrent updates made while this method
+    /// is running.
+    pub fn get_all<'a, Q, I>(&self, keys: I) -> Vec<Option<V>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let mut keys = keys
+            .into_iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let hash = self.inner.hash(key);
+                (self.inner.segment_index_borrowed(key, hash), i, key, hash)
+            })
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|(seg, i, ..)| (*seg, *i));
+
+        let mut values = vec![None; keys.len()];
+        for (seg, i, key, hash) in keys {
+            values[i] = self.inner.segments[seg].get_with_hash(key, hash);
+        }
+        values
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, and reloads the
+    /// value in background if the entry is older than the `refresh_after_write`
+    /// period of the cache.
+    ///
+    /// See [`Cache::get_with_refresh`][get-with-refresh] for details.
+    ///
+    /// [get-with-refresh]: ./struct.Cache.html#method.get_with_refresh
+    pub fn get_with_refresh<Q, F, E>(&self, key: &Q, reload: F) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&K) -> Result<V, E> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync + 'static>> + 'static,
+    {
+        let hash = self.inner.hash(key);
+        self.inner
+            .select_borrowed(key, hash)
+            .get_with_refresh(key, reload)
+    }
+
+    /// Returns `true` if the cache contains a value for the key.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation, so it does not update the historic popularity estimator or reset
+    /// the idle timer for the key. It also does not clone the value.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).contains_key(key)
+    }
+
+    /// Resets the idle timer of the entry for the key without reading its value,
+    /// and returns `true` if the cache contains a value for the key.
+    ///
+    /// See [`Cache::touch`][touch] for details.
+    ///
+    /// [touch]: ./struct.Cache.html#method.touch
+    pub fn touch<Q>(&self, key: &Q, reset_ttl: bool) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).touch(key, reset_ttl)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, without affecting
+    /// the cache policy.
+    ///
+    /// Unlike the `get` method, this method is not considered a cache read
+    /// operation, so it does not update the historic popularity estimator, the
+    /// recency of the entry, or reset the idle timer for the key. It is not
+    /// counted in the hit or miss statistics either. This is useful for monitoring
+    /// or debugging tools that should not perturb the eviction decisions.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).peek(key)
+    }
+
+    /// Returns a snapshot of the entries in the cache, e.g. to persist them on
+    /// shutdown.
+    ///
+    /// The snapshot is weakly consistent; it reflects the entries whose writes have
+    /// been applied by the housekeeper, so it may not include the entries being
+    /// inserted concurrently. The expired and invalidated entries are not included.
+    /// Like `peek`, this method does not affect the cache policy.
+    ///
+    /// The snapshot does not carry the access or frequency history of the entries,
+    /// so the entries loaded from it (e.g. by [`CacheBuilder::build_with_entries`]
+    /// [build-with-entries]) will start cold, and their time-to-live and
+    /// time-to-idle will start from the loading time.
+    ///
+    /// [build-with-entries]: ./struct.CacheBuilder.html#method.build_with_entries
+    pub fn to_hash_map(&self) -> HashMap<Arc<K>, V> {
+        let mut map = HashMap::new();
+        for segment in self.inner.segments.iter() {
+            map.extend(segment.to_hash_map());
+        }
+        map
+    }
+
+    /// Returns a histogram of the ages of the entries in the cache, to help
+    /// choosing the time-to-live and time-to-idle empirically.
+    ///
+    /// The age of an entry is the time elapsed since its current value was
+    /// inserted. Each element of the returned `Vec` is a pair of the (exclusive)
+    /// upper bound of the ages in the bucket and the number of the entries in it.
+    /// The first bucket holds the ages shorter than one second, and the upper
+    /// bounds double in the following buckets (2 seconds, 4 seconds, 8 seconds,
+    /// and so on). The buckets after the one holding the oldest entry are omitted,
+    /// so an empty cache returns an empty `Vec`.
+    ///
+    /// Like `to_hash_map`, the histogram is weakly consistent; it only counts the
+    /// entries whose writes have been applied by the housekeeper, and it does not
+    /// count the expired and invalidated entries.
+    ///
+    /// **NOTE**: This method is a diagnostic; it scans all the entries in the
+    /// cache, so it takes O(n) time. Do not call it on a hot path.
+    pub fn age_histogram(&self) -> Vec<(Duration, usize)> {
+        // The buckets of the segments have the same bounds at the same indices.
+        let mut histogram: Vec<(Duration, usize)> = Vec::new();
+        for segment in self.inner.segments.iter() {
+            for (i, (bound, count)) in segment.age_histogram().into_iter().enumerate() {
+                match histogram.get_mut(i) {
+                    Some((_, total)) => *total += count,
+                    None => histogram.push((bound, count)),
+                }
+            }
+        }
+        histogram
     }
 
     /// Ensures the value of the key exists by inserting the result of the init
@@ -145,10 +401,130 @@ where
         let hash = self.inner.hash(&key);
         let key = Arc::new(key);
         self.inner
-            .select(hash)
+            .select(&key, hash)
             .get_or_insert_with_hash_and_fun(key, hash, init)
     }
 
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// `default` value if not exist and returns a clone of it.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// with an init closure returning `default`. When many threads call this method
+    /// concurrently for the same absent key, only one of the `default` values is
+    /// inserted, and all the calls get that value.
+    pub fn get_or_insert(&self, key: K, default: V) -> V {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .get_or_insert_with_hash_and_fun(key, hash, move || default)
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but also returns
+    /// whether the value was inserted by this call (`EntryStatus::Created`), or
+    /// already existed (`EntryStatus::Existed`).
+    pub fn get_or_insert_with_status(&self, key: K, init: impl FnOnce() -> V) -> (V, EntryStatus) {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .get_or_insert_with_status_hash_and_fun(key, hash, init)
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but the init
+    /// closure returns the time-to-live of the entry along with the value.
+    ///
+    /// See [`Cache::get_or_insert_with_ttl`][get-or-insert-with-ttl] for details.
+    ///
+    /// [get-or-insert-with-ttl]: ./struct.Cache.html#method.get_or_insert_with_ttl
+    pub fn get_or_insert_with_ttl(&self, key: K, init: impl FnOnce() -> (V, Duration)) -> V {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .get_or_insert_with_ttl_hash_and_fun(key, hash, init)
+    }
+
+    /// Like [`get_or_insert_with`](#method.get_or_insert_with), but the init
+    /// closure is called again when it panics.
+    ///
+    /// See [`Cache::get_or_insert_with_retrying`][get-or-insert-with-retrying] for
+    /// details.
+    ///
+    /// [get-or-insert-with-retrying]: ./struct.Cache.html#method.get_or_insert_with_retrying
+    pub fn get_or_insert_with_retrying(&self, key: K, init: impl Fn() -> V) -> V {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .get_or_insert_with_retrying_hash_and_fun(key, hash, init)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value returned by the init closure if not exist. The init closure takes the
+    /// key, which is convenient when the value is loaded by the key.
+    ///
+    /// This is the same as [`get_or_insert_with`](#method.get_or_insert_with)
+    /// except the argument of the init closure.
+    pub fn get_or_insert_with_key(&self, key: K, init: impl FnOnce(&Arc<K>) -> V) -> V {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        let init_key = Arc::clone(&key);
+        self.inner
+            .select(&key, hash)
+            .get_or_insert_with_hash_and_fun(key, hash, move || init(&init_key))
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value returned by the init closure if not exist or if `should_recompute`
+    /// returns `true` on the existing value.
+    ///
+    /// See [`Cache::get_with_if`][get-with-if] for details.
+    ///
+    /// [get-with-if]: ./struct.Cache.html#method.get_with_if
+    pub fn get_with_if(
+        &self,
+        key: K,
+        init: impl FnOnce() -> V,
+        should_recompute: impl Fn(&V) -> bool,
+    ) -> V {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .get_with_if_hash_and_fun(key, hash, init, should_recompute)
+    }
+
+    /// Returns a _clone_ of the value corresponding to the key, or inserts the
+    /// value returned by the init closure if not exist. If the init closure
+    /// returns `None`, nothing is inserted and `None` is returned.
+    ///
+    /// See [`Cache::get_or_optionally_insert_with`][get-or-optionally] for details.
+    ///
+    /// [get-or-optionally]: ./struct.Cache.html#method.get_or_optionally_insert_with
+    pub fn get_or_optionally_insert_with(
+        &self,
+        key: K,
+        init: impl FnOnce() -> Option<V>,
+    ) -> Option<V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .get_or_optionally_insert_with_hash_and_fun(key, hash, init)
+    }
+
+    /// Returns the [`Entry`][entry-struct] of the key for in-place manipulation.
+    ///
+    /// See [`Cache::entry`][cache-entry] for details.
+    ///
+    /// [entry-struct]: ./struct.Entry.html
+    /// [cache-entry]: ./struct.Cache.html#method.entry
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, S> {
+        let hash = self.inner.hash(&key);
+        Entry::new(self.inner.select(&key, hash), Arc::new(key), hash)
+    }
+
     /// Try to ensure the value of the key exists by inserting an `Ok` result of the
     /// init closure if not exist, and returns a _clone_ of the value or the `Err`
     /// returned by the closure.
@@ -165,7 +541,27 @@ where
         let hash = self.inner.hash(&key);
         let key = Arc::new(key);
         self.inner
-            .select(hash)
+            .select(&key, hash)
+            .get_or_try_insert_with_hash_and_fun(key, hash, init)
+    }
+
+    /// Try to ensure the value of the key exists by inserting an `Ok` result of the
+    /// init closure if not exist, and returns a _clone_ of the value or the `Err`
+    /// returned by the closure.
+    ///
+    /// Unlike `get_or_try_insert_with`, the error type does not have to implement
+    /// `std::error::Error`. See [`Cache::try_get_with`][try-get-with] for details.
+    ///
+    /// [try-get-with]: ./struct.Cache.html#method.try_get_with
+    pub fn try_get_with<F, E>(&self, key: K, init: F) -> Result<V, Arc<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+        E: Send + Sync + 'static,
+    {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
             .get_or_try_insert_with_hash_and_fun(key, hash, init)
     }
 
@@ -175,7 +571,183 @@ where
     pub fn insert(&self, key: K, value: V) {
         let hash = self.inner.hash(&key);
         let key = Arc::new(key);
-        self.inner.select(hash).insert_with_hash(key, hash, value);
+        self.inner
+            .select(&key, hash)
+            .insert_with_hash(key, hash, value);
+    }
+
+    /// Inserts a key-value pair into the cache, returning an error instead of
+    /// panicking when the write cannot be recorded.
+    ///
+    /// See [`Cache::try_insert`][try-insert] for details.
+    ///
+    /// [try-insert]: ./struct.Cache.html#method.try_insert
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), CacheError> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .try_insert_with_hash(key, hash, value)
+    }
+
+    /// Inserts a key-value pair into the cache, and reports whether the key was
+    /// newly added or its value was replaced, and whether the new entry is expected
+    /// to be admitted. See [`InsertReport`][insert-report] for details.
+    ///
+    /// This is useful to apply back pressure when the cache is saturated and the
+    /// inserts cause evictions or get rejected. Unlike `insert`, this method may
+    /// wait for the housekeeper to finish applying the pending writes, as it
+    /// needs the eviction deques to predict the admission.
+    ///
+    /// [insert-report]: ../policy/enum.InsertReport.html
+    pub fn insert_with_report(&self, key: K, value: V) -> InsertReport {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .insert_with_hash_and_report(key, hash, value)
+    }
+
+    /// Inserts a key-value pair into the cache only when the cache does not have a
+    /// value for the key.
+    ///
+    /// See [`Cache::insert_if_absent`][insert-if-absent] for details.
+    ///
+    /// [insert-if-absent]: ./struct.Cache.html#method.insert_if_absent
+    pub fn insert_if_absent(&self, key: K, value: V) -> Result<(), V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .insert_if_absent_with_hash(key, hash, value)
+    }
+
+    /// Replaces the value of the key only when the cache has a value for the key,
+    /// and returns the previous value.
+    ///
+    /// See [`Cache::replace`][replace] for details.
+    ///
+    /// [replace]: ./struct.Cache.html#method.replace
+    pub fn replace(&self, key: K, value: V) -> Option<V> {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .replace_with_hash(key, hash, value)
+    }
+
+    /// Inserts the key-value pairs into the cache.
+    ///
+    /// This is equivalent to calling `insert` for each pair, but has less overhead
+    /// when inserting many entries at once. See [`Cache::insert_many`][insert-many]
+    /// for details.
+    ///
+    /// [insert-many]: ./struct.Cache.html#method.insert_many
+    pub fn insert_many(&self, entries: impl IntoIterator<Item = (K, V)>) {
+        self.insert_many_with_arc_keys(entries.into_iter().map(|(k, v)| (Arc::new(k), v)))
+    }
+
+    pub(crate) fn insert_many_with_arc_keys(&self, entries: impl IntoIterator<Item = (Arc<K>, V)>) {
+        let mut batches: Vec<Vec<(Arc<K>, V)>> =
+            (0..self.inner.segments.len()).map(|_| Vec::new()).collect();
+        for (key, value) in entries {
+            let hash = self.inner.hash(&key);
+            batches[self.inner.segment_index(&key, hash)].push((key, value));
+        }
+        for (segment, batch) in self.inner.segments.iter().zip(batches) {
+            if !batch.is_empty() {
+                segment.insert_many_with_arc_keys(batch);
+            }
+        }
+    }
+
+    /// Discards any cached values for the keys, and returns the number of the
+    /// entries removed.
+    ///
+    /// The keys are grouped by their segments, and each group is invalidated in a
+    /// batch. See [`Cache::invalidate_keys_in`][invalidate-keys-in] for details.
+    ///
+    /// [invalidate-keys-in]: ./struct.Cache.html#method.invalidate_keys_in
+    pub fn invalidate_keys_in(&self, keys: impl IntoIterator<Item = K>) -> usize {
+        let mut batches: Vec<Vec<K>> = (0..self.inner.segments.len()).map(|_| Vec::new()).collect();
+        for key in keys {
+            let hash = self.inner.hash(&key);
+            batches[self.inner.segment_index(&key, hash)].push(key);
+        }
+        self.inner
+            .segments
+            .iter()
+            .zip(batches)
+            .filter(|(_, batch)| !batch.is_empty())
+            .map(|(segment, batch)| segment.invalidate_keys_in(batch))
+            .sum()
+    }
+
+    /// Inserts a key-value pair into the cache with the time-to-live for the entry.
+    ///
+    /// The entry will be expired after the specified duration past from this
+    /// insert, or after the cache-wide `time_to_live` (if set) if that is shorter.
+    /// If the cache has this key present, the value and its time-to-live are
+    /// updated. A later `insert` of the same key without a time-to-live resets the
+    /// entry to the cache-wide `time_to_live`.
+    ///
+    /// An expired entry will never be returned by `get`. However, it may remain in
+    /// the cache until the entries written before it have been evicted.
+    pub fn insert_with_ttl(&self, key: K, value: V, time_to_live: Duration) {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner.select(&key, hash).insert_with_hash_and_ttl(
+            key,
+            hash,
+            value,
+            Some(time_to_live),
+        );
+    }
+
+    /// Computes a new value for the key from the current one (if any) with the
+    /// closure, and returns a _clone_ of the new value.
+    ///
+    /// The closure takes `Some(&V)` if the cache has a value for the key, or `None`
+    /// otherwise. If it returns `Some(V)`, the value is inserted to the cache. If it
+    /// returns `None`, the current value (if any) is discarded.
+    ///
+    /// The calls of this method on the same key are serialized; while a thread is
+    /// evaluating its closure, other threads calling this method with the same key
+    /// will wait for it to complete, and then evaluate their closures with the new
+    /// value. Note that `insert` and `invalidate` do not wait, so the value written
+    /// by them while a closure is being evaluated may be overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use moka::sync::SegmentedCache;
+    ///
+    /// let cache = SegmentedCache::new(100, 4);
+    ///
+    /// // Increment the counter.
+    /// let new_value = cache.compute("counter", |v| Some(v.map_or(1, |v| v + 1)));
+    /// assert_eq!(new_value, Some(1));
+    /// let new_value = cache.compute("counter", |v| Some(v.map_or(1, |v| v + 1)));
+    /// assert_eq!(new_value, Some(2));
+    ///
+    /// // Remove the counter.
+    /// assert_eq!(cache.compute("counter", |_v| None), None);
+    /// assert_eq!(cache.get(&"counter"), None);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This method panics when the closure has been panicked. Other threads waiting
+    /// for the closure will go on and evaluate their own closures.
+    pub fn compute<F>(&self, key: K, f: F) -> Option<V>
+    where
+        F: FnOnce(Option<&V>) -> Option<V>,
+    {
+        let hash = self.inner.hash(&key);
+        let key = Arc::new(key);
+        self.inner
+            .select(&key, hash)
+            .compute_with_hash(key, hash, f)
     }
 
     /// Discards any cached value for the key.
@@ -188,22 +760,89 @@ where
         Q: Hash + Eq + ?Sized,
     {
         let hash = self.inner.hash(key);
-        self.inner.select(hash).invalidate(key);
+        self.inner.select_borrowed(key, hash).invalidate(key);
+    }
+
+    /// Discards any cached value for the key, and returns a _clone_ of the value.
+    ///
+    /// Returns `None` if the cache did not have a value for the key, or the value
+    /// has been expired or invalidated.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).remove(key)
+    }
+
+    /// Discards all cached values.
+    ///
+    /// This method returns immediately and a background thread will evict all the
+    /// cached values inserted before the time when this method was called. It is
+    /// guaranteed that the `get` method must not return these invalidated values
+    /// even if they have not been evicted.
+    ///
+    /// Like the `invalidate` method, this method does not clear the historic
+    /// popularity estimator of keys so that it retains the client activities of
+    /// trying to retrieve an item. Use the `reset` method to clear it as well.
+    pub fn invalidate_all(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_all();
+        }
+    }
+
+    /// Discards all cached values, and clears the historic popularity estimator of
+    /// keys.
+    ///
+    /// See [`Cache::reset`][reset] for details.
+    ///
+    /// [reset]: ./struct.Cache.html#method.reset
+    pub fn reset(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.reset();
+        }
+    }
+
+    /// Records an access to the key in the historic popularity estimator of keys,
+    /// without reading or inserting the value.
+    ///
+    /// See [`Cache::record_access`][record-access] for details.
+    ///
+    /// [record-access]: ./struct.Cache.html#method.record_access
+    pub fn record_access<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash(key);
+        self.inner.select_borrowed(key, hash).record_access(key);
+    }
+
+    /// Discards all cached values in all the segments, and waits for them to be
+    /// removed from the cache.
+    ///
+    /// See [`Cache::clear`][clear] for details.
+    ///
+    /// [clear]: ./struct.Cache.html#method.clear
+    pub fn clear(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.clear();
+        }
     }
 
-    /// Discards all cached values.
+    /// Retains only the entries specified by the predicate, and waits for the
+    /// other entries to be removed from the cache.
     ///
-    /// This method returns immediately and a background thread will evict all the
-    /// cached values inserted before the time when this method was called. It is
-    /// guaranteed that the `get` method must not return these invalidated values
-    /// even if they have not been evicted.
+    /// See [`Cache::retain`][retain] for details.
     ///
-    /// Like the `invalidate` method, this method does not clear the historic
-    /// popularity estimator of keys so that it retains the client activities of
-    /// trying to retrieve an item.
-    pub fn invalidate_all(&self) {
+    /// [retain]: ./struct.Cache.html#method.retain
+    pub fn retain(&self, mut f: impl FnMut(&K, &V) -> bool) {
         for segment in self.inner.segments.iter() {
-            segment.invalidate_all();
+            segment.retain(&mut f);
         }
     }
 
@@ -226,26 +865,195 @@ where
     /// fail with a
     /// [`PredicateError::InvalidationClosuresDisabled`][invalidation-disabled-error].
     ///
+    /// If the cache is built with
+    /// [`CacheBuilder::max_pending_invalidation_closures`][max-pending], this
+    /// method fails with
+    /// [`PredicateError::TooManyPending`][too-many-pending-error] when that many
+    /// closures have not been applied yet.
+    ///
     /// Like the `invalidate` method, this method does not clear the historic
     /// popularity estimator of keys so that it retains the client activities of
     /// trying to retrieve an item.
     ///
     /// [support-invalidation-closures]: ./struct.CacheBuilder.html#method.support_invalidation_closures
     /// [invalidation-disabled-error]: ../enum.PredicateError.html#variant.InvalidationClosuresDisabled
+    /// [max-pending]: ./struct.CacheBuilder.html#method.max_pending_invalidation_closures
+    /// [too-many-pending-error]: ../enum.PredicateError.html#variant.TooManyPending
     pub fn invalidate_entries_if<F>(&self, predicate: F) -> Result<(), PredicateError>
     where
         F: Fn(&K, &V) -> bool + Send + Sync + 'static,
     {
-        let pred = Arc::new(predicate);
+        let pred: PredicateFun<K, V> = Arc::new(move |k, v, _meta| predicate(k, v));
+        for segment in self.inner.segments.iter() {
+            segment.invalidate_entries_with_arc_fun(Arc::clone(&pred))?;
+        }
+        Ok(())
+    }
+
+    /// Discards cached values that satisfy a predicate on the entry metadata as
+    /// well as the key and value.
+    ///
+    /// See the document of
+    /// [`Cache::invalidate_entries_with_meta_if`][cache-invalidate-with-meta-if]
+    /// for details.
+    ///
+    /// [cache-invalidate-with-meta-if]: ./struct.Cache.html#method.invalidate_entries_with_meta_if
+    pub fn invalidate_entries_with_meta_if<F>(&self, predicate: F) -> Result<(), PredicateError>
+    where
+        F: Fn(&K, &V, EntryMeta) -> bool + Send + Sync + 'static,
+    {
+        let pred: PredicateFun<K, V> = Arc::new(predicate);
         for segment in self.inner.segments.iter() {
             segment.invalidate_entries_with_arc_fun(Arc::clone(&pred))?;
         }
         Ok(())
     }
 
+    /// Discards cached values that satisfy a predicate, only in the segment of the
+    /// given segment key.
+    ///
+    /// This works like `invalidate_entries_if`, but the closure is applied only to
+    /// the entries in one segment rather than to the entries in all the segments.
+    /// The segment key is the output of the function set by
+    /// [`CacheBuilder::segment_key_fn`][segment-key-fn]. Therefore, all the entries
+    /// whose keys have the given segment key are in that segment, along with the
+    /// entries of the other segment keys that share the segment, so the closure
+    /// should still check the keys.
+    ///
+    /// [segment-key-fn]: ./struct.CacheBuilder.html#method.segment_key_fn
+    pub fn invalidate_entries_with_segment_key_if<F>(
+        &self,
+        segment_key: u64,
+        predicate: F,
+    ) -> Result<(), PredicateError>
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        let index = self.inner.segment_index_from_hash(segment_key);
+        self.inner.segments[index]
+            .invalidate_entries_with_arc_fun(Arc::new(move |k, v, _meta| predicate(k, v)))?;
+        Ok(())
+    }
+
+    /// Modifies the values of all cached entries in place by applying the closure.
+    ///
+    /// See the document of [`Cache::modify_all`][cache-modify-all] for details.
+    ///
+    /// [cache-modify-all]: ./struct.Cache.html#method.modify_all
+    pub fn modify_all(&self, f: impl Fn(&K, &mut V)) {
+        for segment in self.inner.segments.iter() {
+            segment.modify_all(&f);
+        }
+    }
+
+    /// Returns the approximate number of entries in this cache.
+    ///
+    /// The returned value is the sum of the entry counts of all segments.
+    ///
+    /// The value is approximate because `insert`, `invalidate` and eviction are
+    /// applied to the internal hash table asynchronously via the write operation
+    /// channel. Entries that have been expired or scheduled for eviction may still
+    /// be counted until the housekeeper processes them.
+    ///
+    /// If the cache is built with
+    /// [`CacheBuilder::exact_entry_count`][exact-entry-count], this returns the
+    /// exact number of the entries admitted by the housekeeper instead.
+    ///
+    /// [exact-entry-count]: ./struct.CacheBuilder.html#method.exact_entry_count
+    pub fn entry_count(&self) -> usize {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.entry_count())
+            .sum()
+    }
+
+    /// Returns the approximate total weight of the entries in this cache.
+    ///
+    /// See [`Cache::weighted_size`][weighted-size] for details.
+    ///
+    /// [weighted-size]: ./struct.Cache.html#method.weighted_size
+    pub fn weighted_size(&self) -> u64 {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| seg.weighted_size())
+            .sum()
+    }
+
+    /// Performs the pending maintenance tasks of the cache on the calling thread.
+    ///
+    /// This applies the buffered reads and writes to the cache policy, and runs
+    /// the eviction of the expired and invalidated entries, and of the entries
+    /// exceeding the `max_capacity`. After this method returns, `entry_count`,
+    /// `weighted_size` and `stats` reflect all the writes made before the call.
+    ///
+    /// Normally a background housekeeper runs these tasks periodically, or when
+    /// enough reads and writes have been buffered, so you do not need to call this
+    /// method. Call it when you need an up-to-date view of the cache, e.g. in tests.
+    /// If the cache is built with
+    /// [`CacheBuilder::manual_housekeeping`][manual-housekeeping], the tasks are
+    /// not run automatically, and you need to call this method periodically.
+    ///
+    /// [manual-housekeeping]: ./struct.CacheBuilder.html#method.manual_housekeeping
+    pub fn run_pending_tasks(&self) {
+        for segment in self.inner.segments.iter() {
+            segment.run_pending_tasks();
+        }
+    }
+
+    /// Returns a snapshot of the statistics of this cache, aggregated across
+    /// the segments.
+    ///
+    /// The statistics are recorded only when the cache is built with
+    /// `CacheBuilder::record_stats`; otherwise all of the counters are zero.
+    pub fn stats(&self) -> CacheStats {
+        self.inner
+            .segments
+            .iter()
+            .fold(CacheStats::default(), |mut stats, seg| {
+                stats.merge(&seg.stats());
+                stats
+            })
+    }
+
+    /// Returns a snapshot of the entry count, the weighted size and the statistics
+    /// of each segment, in the order of the segments.
+    ///
+    /// This is useful to diagnose the imbalance of the segments. The statistics
+    /// are recorded only when the cache is built with `CacheBuilder::record_stats`;
+    /// otherwise all of the counters are zero.
+    pub fn segment_stats(&self) -> Vec<SegmentStat> {
+        self.inner
+            .segments
+            .iter()
+            .map(|seg| SegmentStat::new(seg.entry_count(), seg.weighted_size(), seg.stats()))
+            .collect()
+    }
+
     /// Returns the `max_capacity` of this cache.
     pub fn max_capacity(&self) -> usize {
-        self.inner.desired_capacity
+        self.inner.desired_capacity.load(Ordering::Acquire)
+    }
+
+    /// Changes the `max_capacity` of this cache, e.g. in response to memory
+    /// pressure.
+    ///
+    /// The change is applied asynchronously. When shrinking, the entries exceeding
+    /// the new capacity are evicted by the next housekeeping pass (or by
+    /// `run_pending_tasks`), so the cache may stay above the new capacity for a
+    /// while. When growing, the cache simply accepts more entries.
+    ///
+    /// The new capacity is distributed across the segments in the same way as at
+    /// the creation time.
+    pub fn set_max_capacity(&self, max_capacity: usize) {
+        self.inner
+            .desired_capacity
+            .store(max_capacity, Ordering::Release);
+        let num_segments = self.inner.segments.len();
+        for (i, segment) in self.inner.segments.iter().enumerate() {
+            segment.set_max_capacity(segment_capacity(max_capacity, num_segments, i));
+        }
     }
 
     /// Returns the `time_to_live` of this cache.
@@ -258,6 +1066,22 @@ where
         self.inner.segments[0].time_to_idle()
     }
 
+    /// Returns the `initial_capacity` of this cache, or `None` if it was not set.
+    pub fn initial_capacity(&self) -> Option<usize> {
+        self.inner.desired_initial_capacity
+    }
+
+    /// Returns the eviction policy of this cache.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.inner.segments[0].eviction_policy()
+    }
+
+    /// Returns `true` if this cache supports the invalidation closures, which is
+    /// enabled by the `support_invalidation_closures` method of the builder.
+    pub fn invalidation_closures_enabled(&self) -> bool {
+        self.inner.segments[0].invalidation_closures_enabled()
+    }
+
     /// Returns the number of internal segments of this cache.
     pub fn num_segments(&self) -> usize {
         self.inner.segments.len()
@@ -276,13 +1100,11 @@ where
 impl<K, V, S> ConcurrentCacheExt<K, V> for SegmentedCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     fn sync(&self) {
-        for segment in self.inner.segments.iter() {
-            segment.sync();
-        }
+        self.run_pending_tasks();
     }
 }
 
@@ -291,7 +1113,7 @@ where
 impl<K, V, S> SegmentedCache<K, V, S>
 where
     K: Hash + Eq + Send + Sync + 'static,
-    V: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
     S: BuildHasher + Clone + Send + Sync + 'static,
 {
     fn table_size(&self) -> usize {
@@ -345,10 +1167,13 @@ impl MockExpirationClock {
 }
 
 struct Inner<K, V, S> {
-    desired_capacity: usize,
+    // This can be updated by `set_max_capacity`.
+    desired_capacity: AtomicUsize,
+    desired_initial_capacity: Option<usize>,
     segments: Box<[Cache<K, V, S>]>,
     build_hasher: S,
     segment_shift: u32,
+    segment_key_fn: Option<SegmentKeyFn<K>>,
 }
 
 impl<K, V, S> Inner<K, V, S>
@@ -362,40 +1187,34 @@ where
     /// Panics if `num_segments` is 0.
     fn new(
         max_capacity: usize,
-        initial_capacity: Option<usize>,
         num_segments: usize,
+        segment_key_fn: Option<SegmentKeyFn<K>>,
         build_hasher: S,
-        time_to_live: Option<Duration>,
-        time_to_idle: Option<Duration>,
-        invalidator_enabled: bool,
+        config: CacheConfig<K, V>,
     ) -> Self {
         assert!(num_segments > 0);
 
         let actual_num_segments = num_segments.next_power_of_two();
         let segment_shift = 64 - actual_num_segments.trailing_zeros();
-        // TODO: Round up.
-        let seg_capacity = max_capacity / actual_num_segments;
-        let seg_init_capacity = initial_capacity.map(|cap| cap / actual_num_segments);
         // NOTE: We cannot initialize the segments as `vec![cache; actual_num_segments]`
         // because Cache::clone() does not clone its inner but shares the same inner.
-        let segments = (0..num_segments)
-            .map(|_| {
+        let segments = (0..actual_num_segments)
+            .map(|i| {
                 Cache::with_everything(
-                    seg_capacity,
-                    seg_init_capacity,
-                    build_hasher.clone(),
-                    time_to_live,
-                    time_to_idle,
-                    invalidator_enabled,
+                    segment_capacity(max_capacity, actual_num_segments, i),
+                    || build_hasher.clone(),
+                    config.for_segment(actual_num_segments, i),
                 )
             })
             .collect::<Vec<_>>();
 
         Self {
-            desired_capacity: max_capacity,
+            desired_capacity: AtomicUsize::new(max_capacity),
+            desired_initial_capacity: config.initial_capacity,
             segments: segments.into_boxed_slice(),
             build_hasher,
             segment_shift,
+            segment_key_fn,
         }
     }
 
@@ -410,10 +1229,47 @@ where
         hasher.finish()
     }
 
+    /// Selects the segment for the key. `hash` is the hash of the key.
+    #[inline]
+    fn select(&self, key: &K, hash: u64) -> &Cache<K, V, S> {
+        &self.segments[self.segment_index(key, hash)]
+    }
+
+    /// Selects the segment for a borrowed form of the key. `hash` is the hash of
+    /// the key.
+    #[inline]
+    fn select_borrowed<Q>(&self, key: &Q, hash: u64) -> &Cache<K, V, S>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        &self.segments[self.segment_index_borrowed(key, hash)]
+    }
+
+    #[inline]
+    fn segment_index(&self, key: &K, hash: u64) -> usize {
+        match &self.segment_key_fn {
+            Some(f) => self.segment_index_from_hash(f(key)),
+            None => self.segment_index_from_hash(hash),
+        }
+    }
+
     #[inline]
-    fn select(&self, hash: u64) -> &Cache<K, V, S> {
-        let index = self.segment_index_from_hash(hash);
-        &self.segments[index]
+    fn segment_index_borrowed<Q>(&self, key: &Q, hash: u64) -> usize
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.segment_key_fn.is_none() {
+            return self.segment_index_from_hash(hash);
+        }
+        // The segment key cannot be computed from a borrowed form of the key, so
+        // look for the segment having the key. If none has it, any segment will do
+        // as the key will not be found anyway.
+        self.segments
+            .iter()
+            .position(|seg| seg.has_entry(key))
+            .unwrap_or_else(|| self.segment_index_from_hash(hash))
     }
 
     #[inline]
@@ -421,17 +1277,56 @@ where
         if self.segment_shift == 64 {
             0
         } else {
-            (hash >> self.segment_shift) as usize
+            (mix_hash(hash) >> self.segment_shift) as usize
         }
     }
 }
 
+/// Mixes the bits of the hash, so that the high bits used to select a segment
+/// will be uniformly distributed even if the user-provided hasher concentrates
+/// the entropy in the low bits. This is the finalizer of MurmurHash3.
+#[inline]
+pub(crate) fn mix_hash(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
+/// Returns the capacity of the `index`-th segment. The remainder of the capacity
+/// is distributed across the first segments, so that the sum of the segment
+/// capacities equals to `max_capacity`.
+pub(crate) fn segment_capacity(max_capacity: usize, num_segments: usize, index: usize) -> usize {
+    let capacity = max_capacity / num_segments;
+    if index < max_capacity % num_segments {
+        capacity + 1
+    } else {
+        capacity
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{ConcurrentCacheExt, SegmentedCache};
+    use super::SegmentedCache;
     use crate::sync::CacheBuilder;
     use std::time::Duration;
 
+    #[test]
+    fn debug_format() {
+        let cache = SegmentedCache::new(100, 4);
+        cache.insert("a", "alice");
+        cache.run_pending_tasks();
+
+        let s = format!("{:?}", cache);
+        assert!(s.starts_with("SegmentedCache {"));
+        assert!(s.contains("max_capacity: 100"));
+        assert!(s.contains("num_segments: 4"));
+        assert!(s.contains("entry_count: 1"));
+        assert!(!s.contains("alice"));
+    }
+
     #[test]
     fn basic_single_thread() {
         let mut cache = SegmentedCache::new(3, 1);
@@ -444,32 +1339,32 @@ mod tests {
         cache.insert("b", "bob");
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
+        cache.run_pending_tasks();
         // counts: a -> 1, b -> 1
 
         cache.insert("c", "cindy");
         assert_eq!(cache.get(&"c"), Some("cindy"));
         // counts: a -> 1, b -> 1, c -> 1
-        cache.sync();
+        cache.run_pending_tasks();
 
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
-        cache.sync();
+        cache.run_pending_tasks();
         // counts: a -> 2, b -> 2, c -> 1
 
         // "d" should not be admitted because its frequency is too low.
         cache.insert("d", "david"); //   count: d -> 0
-        cache.sync();
+        cache.run_pending_tasks();
         assert_eq!(cache.get(&"d"), None); //   d -> 1
 
         cache.insert("d", "david");
-        cache.sync();
+        cache.run_pending_tasks();
         assert_eq!(cache.get(&"d"), None); //   d -> 2
 
         // "d" should be admitted and "c" should be evicted
         // because d's frequency is higher then c's.
         cache.insert("d", "dennis");
-        cache.sync();
+        cache.run_pending_tasks();
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.get(&"c"), None);
@@ -494,7 +1389,7 @@ mod tests {
                 std::thread::spawn(move || {
                     cache.insert(10, format!("{}-100", id));
                     cache.get(&10);
-                    cache.sync();
+                    cache.run_pending_tasks();
                     cache.insert(20, format!("{}-200", id));
                     cache.invalidate(&10);
                 })
@@ -503,12 +1398,317 @@ mod tests {
 
         handles.into_iter().for_each(|h| h.join().expect("Failed"));
 
-        cache.sync();
+        cache.run_pending_tasks();
 
         assert!(cache.get(&10).is_none());
         assert!(cache.get(&20).is_some());
     }
 
+    #[test]
+    fn segment_distribution_with_low_entropy_hasher() {
+        use std::hash::{BuildHasher, Hasher};
+
+        // A hasher returning the key as is, so the high bits of the hashes of small
+        // keys are all zero.
+        #[derive(Default)]
+        struct IdentityHasher(u64);
+
+        impl Hasher for IdentityHasher {
+            fn finish(&self) -> u64 {
+                self.0
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                for &b in bytes {
+                    self.0 = (self.0 << 8) | b as u64;
+                }
+            }
+
+            fn write_u64(&mut self, n: u64) {
+                self.0 = n;
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct BuildIdentityHasher;
+
+        impl BuildHasher for BuildIdentityHasher {
+            type Hasher = IdentityHasher;
+
+            fn build_hasher(&self) -> Self::Hasher {
+                IdentityHasher::default()
+            }
+        }
+
+        const NUM_SEGMENTS: usize = 16;
+        const NUM_KEYS: u64 = 16_000;
+
+        let mut cache = CacheBuilder::new(NUM_KEYS as usize * 2)
+            .segments(NUM_SEGMENTS)
+            .build_with_hasher(BuildIdentityHasher);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for key in 0..NUM_KEYS {
+            cache.insert(key, key);
+        }
+        cache.run_pending_tasks();
+
+        // Every segment should have roughly 1,000 entries.
+        let expected = NUM_KEYS as usize / NUM_SEGMENTS;
+        for segment in cache.inner.segments.iter() {
+            let len = segment.table_size();
+            assert!(
+                len > expected * 8 / 10 && len < expected * 12 / 10,
+                "unbalanced segment: {}",
+                len
+            );
+        }
+        assert_eq!(cache.entry_count(), NUM_KEYS as usize);
+    }
+
+    #[test]
+    fn segment_capacities() {
+        for &(max_capacity, num_segments) in &[(10, 4), (10, 3), (7, 8), (100, 16), (0, 2)] {
+            let cache: SegmentedCache<u32, u32> = SegmentedCache::new(max_capacity, num_segments);
+            let segments = &cache.inner.segments;
+
+            assert_eq!(cache.num_segments(), num_segments.next_power_of_two());
+            assert_eq!(cache.max_capacity(), max_capacity);
+            assert_eq!(
+                segments.iter().map(|seg| seg.max_capacity()).sum::<usize>(),
+                max_capacity
+            );
+
+            // The segment capacities differ at most by one.
+            let min = segments.iter().map(|seg| seg.max_capacity()).min().unwrap();
+            let max = segments.iter().map(|seg| seg.max_capacity()).max().unwrap();
+            assert!(max - min <= 1);
+        }
+    }
+
+    #[test]
+    fn set_max_capacity() {
+        let mut cache = SegmentedCache::new(1_000, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..100 {
+            cache.insert(i, i);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 100);
+
+        // The new capacity is distributed across the segments.
+        cache.set_max_capacity(10);
+        assert_eq!(cache.max_capacity(), 10);
+        let segments = &cache.inner.segments;
+        assert_eq!(
+            segments
+                .iter()
+                .map(|seg| seg.max_capacity())
+                .collect::<Vec<_>>(),
+            vec![3, 3, 2, 2]
+        );
+
+        // Each segment evicts its entries down to its new capacity.
+        cache.run_pending_tasks();
+        for segment in segments.iter() {
+            assert!(segment.entry_count() <= segment.max_capacity());
+        }
+    }
+
+    #[test]
+    fn entry_count() {
+        let mut cache = SegmentedCache::new(100, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        assert_eq!(cache.entry_count(), 0);
+
+        for i in 0..10 {
+            cache.insert(i, format!("value-{}", i));
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 10);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let cache = SegmentedCache::new(0, 4);
+        for i in 0..1_000 {
+            cache.insert(i % 10, i);
+            assert_eq!(cache.get(&(i % 10)), None);
+        }
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.get(&0), None);
+    }
+
+    #[test]
+    fn stats() {
+        let mut cache = CacheBuilder::new(100).segments(4).record_stats().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for i in 0..10 {
+            cache.insert(i, format!("value-{}", i));
+        }
+        cache.run_pending_tasks();
+
+        // 10 hits and 5 misses spread across the segments.
+        for i in 0..15 {
+            let _ = cache.get(&i);
+        }
+        assert_eq!(
+            cache.get_or_insert_with(20, || "value-20".into()),
+            "value-20"
+        );
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_count(), 10);
+        assert_eq!(stats.miss_count(), 6);
+        assert_eq!(stats.load_success_count(), 1);
+    }
+
+    #[test]
+    fn segment_stats() {
+        let mut cache = CacheBuilder::new(100).segments(4).record_stats().build();
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        let segment_of = |k: &u32| cache.inner.segment_index(k, cache.inner.hash(k));
+        let hot_segment = segment_of(&0);
+
+        // A skewed key distribution: 20 keys in the hot segment and one key in
+        // each of the other segments.
+        let mut hot_keys = Vec::new();
+        let mut cold_keys = [None; 4];
+        for k in 0u32.. {
+            let seg = segment_of(&k);
+            if seg == hot_segment {
+                if hot_keys.len() < 20 {
+                    hot_keys.push(k);
+                }
+            } else if cold_keys[seg].is_none() {
+                cold_keys[seg] = Some(k);
+            }
+            // The slot of the hot segment in `cold_keys` is always `None`.
+            if hot_keys.len() == 20 && cold_keys.iter().filter(|k| k.is_none()).count() == 1 {
+                break;
+            }
+        }
+        for &k in hot_keys.iter().chain(cold_keys.iter().flatten()) {
+            cache.insert(k, k);
+        }
+        cache.run_pending_tasks();
+
+        // Hit every key in the hot segment twice, and miss once.
+        for &k in &hot_keys {
+            assert_eq!(cache.get(&k), Some(k));
+            assert_eq!(cache.get(&k), Some(k));
+        }
+        let missing = (1000u32..).find(|k| segment_of(k) == hot_segment).unwrap();
+        assert_eq!(cache.get(&missing), None);
+
+        let stats = cache.segment_stats();
+        assert_eq!(stats.len(), 4);
+        for (i, stat) in stats.iter().enumerate() {
+            if i == hot_segment {
+                assert_eq!(stat.entry_count(), 20);
+                assert_eq!(stat.weighted_size(), 20);
+                assert_eq!(stat.stats().hit_count(), 40);
+                assert_eq!(stat.stats().miss_count(), 1);
+            } else {
+                assert_eq!(stat.entry_count(), 1);
+                assert_eq!(stat.weighted_size(), 1);
+                assert_eq!(stat.stats().request_count(), 0);
+            }
+        }
+        assert_eq!(
+            stats.iter().map(|s| s.entry_count()).sum::<usize>(),
+            cache.entry_count()
+        );
+    }
+
+    #[test]
+    fn get_all() {
+        let cache = SegmentedCache::new(100, 4);
+        for i in 0..50 {
+            cache.insert(i * 2, i);
+        }
+
+        let keys = (0..20).rev().collect::<Vec<_>>();
+        let expected = keys
+            .iter()
+            .map(|k| if k % 2 == 0 { Some(k / 2) } else { None })
+            .collect::<Vec<_>>();
+        assert_eq!(cache.get_all(&keys), expected);
+        assert!(cache.get_all(&Vec::<i32>::new()).is_empty());
+    }
+
+    #[test]
+    fn insert_many() {
+        let mut cache = SegmentedCache::new(100, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert_many((0..10).map(|i| (i, format!("value-{}", i))));
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.entry_count(), 10);
+        for i in 0..10 {
+            assert_eq!(cache.get(&i), Some(format!("value-{}", i)));
+        }
+    }
+
+    #[test]
+    fn invalidate_keys_in() {
+        use std::collections::HashSet;
+
+        let mut cache = SegmentedCache::new(100, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert_many((0..40).map(|i| (i, i)));
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 40);
+
+        // Invalidate the even keys, and some keys that are not cached.
+        let keys = (0..40).filter(|i| i % 2 == 0).chain(100..105);
+        let segments = keys
+            .clone()
+            .map(|k| cache.inner.segment_index(&k, cache.inner.hash(&k)))
+            .collect::<HashSet<_>>();
+        assert!(segments.len() > 1);
+
+        assert_eq!(cache.invalidate_keys_in(keys), 20);
+        cache.run_pending_tasks();
+
+        assert_eq!(cache.entry_count(), 20);
+        for i in 0..40 {
+            if i % 2 == 0 {
+                assert_eq!(cache.get(&i), None);
+            } else {
+                assert_eq!(cache.get(&i), Some(i));
+            }
+        }
+    }
+
     #[test]
     fn invalidate_all() {
         let mut cache = SegmentedCache::new(100, 4);
@@ -523,13 +1723,13 @@ mod tests {
         assert_eq!(cache.get(&"a"), Some("alice"));
         assert_eq!(cache.get(&"b"), Some("bob"));
         assert_eq!(cache.get(&"c"), Some("cindy"));
-        cache.sync();
+        cache.run_pending_tasks();
 
         cache.invalidate_all();
-        cache.sync();
+        cache.run_pending_tasks();
 
         cache.insert("d", "david");
-        cache.sync();
+        cache.run_pending_tasks();
 
         assert!(cache.get(&"a").is_none());
         assert!(cache.get(&"b").is_none());
@@ -537,6 +1737,79 @@ mod tests {
         assert_eq!(cache.get(&"d"), Some("david"));
     }
 
+    #[test]
+    fn clear() {
+        let mut cache = SegmentedCache::new(100, 4);
+        cache.reconfigure_for_testing();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        cache.insert("a", "alice");
+        cache.insert("b", "bob");
+        cache.run_pending_tasks();
+        cache.insert("c", "cindy");
+
+        cache.clear();
+        assert_eq!(cache.entry_count(), 0);
+        assert!(cache.get(&"a").is_none());
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"c").is_none());
+    }
+
+    #[test]
+    fn segment_key_fn() -> Result<(), Box<dyn std::error::Error>> {
+        const TENANTS: u32 = 8;
+        const KEYS_PER_TENANT: u32 = 10;
+
+        // The key is (tenant ID, item ID).
+        let mut cache = CacheBuilder::new(1000)
+            .segments(16)
+            .segment_key_fn(|&(tenant, _): &(u32, u32)| tenant as u64)
+            .support_invalidation_closures()
+            .build();
+        cache.reconfigure_for_testing();
+
+        let mut mock = cache.create_mock_expiration_clock();
+
+        // Make the cache exterior immutable.
+        let cache = cache;
+
+        for tenant in 0..TENANTS {
+            for item in 0..KEYS_PER_TENANT {
+                cache.insert((tenant, item), item);
+            }
+        }
+        cache.run_pending_tasks();
+
+        // All the keys of a tenant are in the same segment.
+        for tenant in 0..TENANTS {
+            let hash = |key| cache.inner.hash(&key);
+            let index = cache.inner.segment_index(&(tenant, 0), hash((tenant, 0)));
+            for item in 0..KEYS_PER_TENANT {
+                let key = (tenant, item);
+                assert_eq!(cache.inner.segment_index(&key, hash(key)), index);
+                assert!(cache.inner.segments[index].has_entry(&key));
+                assert_eq!(cache.get(&key), Some(item));
+            }
+        }
+
+        cache.invalidate(&(0, 0));
+        assert!(cache.get(&(0, 0)).is_none());
+
+        mock.increment(Duration::from_secs(5));
+
+        // Only the segment of tenant 1 gets the predicate.
+        cache.invalidate_entries_with_segment_key_if(1, |&(tenant, _), _| tenant == 1)?;
+        assert_eq!(cache.invalidation_predicate_count(), 1);
+        for item in 0..KEYS_PER_TENANT {
+            assert!(cache.get(&(1, item)).is_none());
+            assert_eq!(cache.get(&(2, item)), Some(item));
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn invalidate_entries_if() -> Result<(), Box<dyn std::error::Error>> {
         use std::collections::HashSet;
@@ -557,9 +1830,9 @@ mod tests {
         cache.insert(0, "alice");
         cache.insert(1, "bob");
         cache.insert(2, "alex");
-        cache.sync();
+        cache.run_pending_tasks();
         mock.increment(Duration::from_secs(5)); // 5 secs from the start.
-        cache.sync();
+        cache.run_pending_tasks();
 
         assert_eq!(cache.get(&0), Some("alice"));
         assert_eq!(cache.get(&1), Some("bob"));
@@ -574,9 +1847,9 @@ mod tests {
         cache.insert(3, "alice");
 
         // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
+        cache.run_pending_tasks(); // To submit the invalidation task.
         std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
+        cache.run_pending_tasks(); // To process the task result.
         std::thread::sleep(Duration::from_millis(200));
 
         assert!(cache.get(&0).is_none());
@@ -594,9 +1867,9 @@ mod tests {
         assert_eq!(cache.invalidation_predicate_count(), SEGMENTS * 2);
 
         // Run the invalidation task and wait for it to finish. (TODO: Need a better way than sleeping)
-        cache.sync(); // To submit the invalidation task.
+        cache.run_pending_tasks(); // To submit the invalidation task.
         std::thread::sleep(Duration::from_millis(200));
-        cache.sync(); // To process the task result.
+        cache.run_pending_tasks(); // To process the task result.
         std::thread::sleep(Duration::from_millis(200));
 
         assert!(cache.get(&1).is_none());
@@ -0,0 +1,189 @@
+use parking_lot::{Mutex, MutexGuard};
+use std::{borrow::Borrow, hash::Hash, sync::Arc};
+
+/// A secondary store that holds entries spilled over from a cache.
+///
+/// When a cache is configured with a spill store via
+/// [`CacheBuilder::spill_to`][spill-to], entries evicted from the cache by the
+/// capacity constraint are written to the store with `put`, and `get` calls
+/// missing the in-memory cache consult the store with `get` before reporting the
+/// absence of the key. This is useful to keep hot entries in memory while cold ones
+/// are kept in a larger but slower store such as a file on a disk.
+///
+/// # Looking up the Keys
+///
+/// The cache does not keep the spilled keys in memory, so it consults the store
+/// on every miss, and on every update and removal of a key. The store should
+/// answer the absent keys cheaply, e.g. by its own index or a filter.
+///
+/// The cache is looked up by any borrowed form of the key, which the store cannot
+/// hash or compare by itself. So the methods of the store take the `hash` of the
+/// key, computed by the cache, and `get` and `remove` take a closure `is_key`
+/// telling whether a stored key is the one being looked up. A store can index the
+/// entries by the hash, and call `is_key` with the keys of the same hash. The hash
+/// is only stable for the lifetime of the cache, so the store should not be
+/// reused by another cache.
+///
+/// # Thread Safety
+///
+/// The methods of a spill store will be called from the client threads _and_ the
+/// housekeeping thread of the cache, so the store must be `Send` and `Sync`. The
+/// cache does not call the methods concurrently for the same key, except `clear`,
+/// which is never called concurrently with the others.
+///
+/// # Consistency
+///
+/// - The cache spills an entry while it removes the entry from the hash table,
+///   and removes the spilled entry while it removes or updates the key in the
+///   hash table. So an invalidated or updated value is never read back from the
+///   store.
+/// - Entries expired by `time_to_live` or `time_to_idle`, or invalidated by
+///   `invalidate_all`, are not spilled. Spilled entries are never expired by the
+///   cache.
+/// - A value read from the store is not moved back to the cache. It will be
+///   removed from the store when the key is inserted again to the cache.
+/// - `invalidate` and `invalidate_all` remove the spilled entries from the store,
+///   but `invalidate_entries_if` does not apply its predicate to them.
+/// - The updates of a key are applied to the store by the housekeeping thread,
+///   so the store may briefly hold an older value of a key that has been updated
+///   in the cache. `get` never reads it, as it finds the newer value in the
+///   cache first.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::sync::{CacheBuilder, SpillStore};
+/// use std::{
+///     collections::HashMap,
+///     sync::{Arc, Mutex},
+/// };
+///
+/// // A store indexing the entries by the hashes of the keys. (A real store would
+/// // write the values to a disk.)
+/// #[derive(Default)]
+/// struct MapStore(Mutex<HashMap<u64, Vec<(Arc<String>, String)>>>);
+///
+/// impl SpillStore<String, String> for MapStore {
+///     fn get(&self, hash: u64, is_key: &dyn Fn(&Arc<String>) -> bool) -> Option<String> {
+///         let map = self.0.lock().unwrap();
+///         let entries = map.get(&hash)?;
+///         entries.iter().find(|(k, _)| is_key(k)).map(|(_, v)| v.clone())
+///     }
+///
+///     fn put(&self, hash: u64, key: &Arc<String>, value: &String) {
+///         let mut map = self.0.lock().unwrap();
+///         let entries = map.entry(hash).or_default();
+///         entries.retain(|(k, _)| k != key);
+///         entries.push((Arc::clone(key), value.clone()));
+///     }
+///
+///     fn remove(&self, hash: u64, is_key: &dyn Fn(&Arc<String>) -> bool) {
+///         let mut map = self.0.lock().unwrap();
+///         if let Some(entries) = map.get_mut(&hash) {
+///             entries.retain(|(k, _)| !is_key(k));
+///             if entries.is_empty() {
+///                 map.remove(&hash);
+///             }
+///         }
+///     }
+///
+///     fn clear(&self) {
+///         self.0.lock().unwrap().clear();
+///     }
+/// }
+///
+/// let cache = CacheBuilder::new(100).spill_to(MapStore::default()).build();
+/// let key = "key".to_string();
+/// cache.insert(key.clone(), "value".to_string());
+/// assert_eq!(cache.get(&key), Some("value".to_string()));
+/// ```
+///
+/// [spill-to]: ./struct.CacheBuilder.html#method.spill_to
+pub trait SpillStore<K, V>: Send + Sync {
+    /// Returns the value of the key if the store has it.
+    ///
+    /// The key is the stored one with the `hash` and for which `is_key` returns
+    /// `true`.
+    fn get(&self, hash: u64, is_key: &dyn Fn(&Arc<K>) -> bool) -> Option<V>;
+
+    /// Stores the value for the key with the `hash`, overwriting the existing one
+    /// if any.
+    fn put(&self, hash: u64, key: &Arc<K>, value: &V);
+
+    /// Removes the value of the key if the store has it.
+    ///
+    /// The key is the stored one with the `hash` and for which `is_key` returns
+    /// `true`.
+    fn remove(&self, hash: u64, is_key: &dyn Fn(&Arc<K>) -> bool);
+
+    /// Removes all the values.
+    fn clear(&self);
+}
+
+pub(crate) type SpillStoreRef<K, V> = Arc<dyn SpillStore<K, V> + 'static>;
+
+// The number of the locks guarding the keys of a spill store. A power of two.
+const NUM_LOCKS: usize = 64;
+
+pub(crate) struct Spill<K, V> {
+    store: SpillStoreRef<K, V>,
+    // The striped locks serializing the spilling and the removal of a key, with
+    // its removal from the hash table of the cache.
+    locks: Box<[Mutex<()>]>,
+}
+
+impl<K, V> Spill<K, V>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn new(store: SpillStoreRef<K, V>) -> Self {
+        Self {
+            store,
+            locks: (0..NUM_LOCKS).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    /// Locks the keys of the hash. The caller must hold the lock while it removes
+    /// the key from the hash table of the cache and calls `put` or `remove`.
+    pub(crate) fn lock(&self, hash: u64) -> MutexGuard<'_, ()> {
+        self.locks[hash as usize & (NUM_LOCKS - 1)].lock()
+    }
+
+    pub(crate) fn get<Q>(&self, key: &Q, hash: u64) -> Option<V>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // Wait for the key being spilled, if any, so that it can be read.
+        let _guard = self.lock(hash);
+        self.store.get(hash, &|k| k.borrow() == key)
+    }
+
+    pub(crate) fn contains_key<Q>(&self, key: &Q, hash: u64) -> bool
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key, hash).is_some()
+    }
+
+    /// Spills the value. The caller must hold the lock of the hash.
+    pub(crate) fn put(&self, key: &Arc<K>, hash: u64, value: &V) {
+        self.store.put(hash, key, value);
+    }
+
+    /// Removes the spilled value. The caller must hold the lock of the hash.
+    pub(crate) fn remove<Q>(&self, key: &Q, hash: u64)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.remove(hash, &|k| k.borrow() == key);
+    }
+
+    pub(crate) fn clear(&self) {
+        // Take all the locks in order, so that no key is being spilled.
+        let _guards = self.locks.iter().map(Mutex::lock).collect::<Vec<_>>();
+        self.store.clear();
+    }
+}
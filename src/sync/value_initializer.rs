@@ -1,26 +1,94 @@
-use parking_lot::RwLock;
+use crate::{common::time::Instant, policy::InitPanicPolicy};
+
+use parking_lot::{Mutex, RwLock};
 use std::{
     any::{Any, TypeId},
+    cell::RefCell,
+    collections::VecDeque,
+    convert::Infallible,
     hash::{BuildHasher, Hash},
     sync::Arc,
+    time::Duration,
 };
 
 type ErrorObject = Arc<dyn Any + Send + Sync + 'static>;
-type WaiterValue<V> = Option<Result<V, ErrorObject>>;
+type WaiterValue<V> = Option<InitValue<V>>;
 type Waiter<V> = Arc<RwLock<WaiterValue<V>>>;
 
+/// The outcome of the init closure, which is shared with the other callers waiting
+/// for the same waiter.
+enum InitValue<V> {
+    Value(V),
+    // The init closure of `optionally_init_or_read` returned `None`.
+    NoValue,
+    Err(ErrorObject),
+    // The init closure of `try_init_or_read` returned an error, which is remembered
+    // until the instant (`cache_errors_for`).
+    CachedErr(ErrorObject, Instant),
+    // The init closure panicked, and the waiting callers should not retry
+    // (`InitPanicPolicy::Fail`).
+    Panicked,
+}
+
+/// A waiter holding `CachedErr`, which is removed by `remove_expired_errors` after
+/// the error expires.
+struct CachedError<K, V> {
+    key: Arc<K>,
+    type_id: TypeId,
+    waiter: Waiter<V>,
+    expires_at: Instant,
+}
+
 pub(crate) enum InitResult<V, E> {
     Initialized(V),
     ReadExisting(V),
     InitErr(Arc<E>),
+    // The init closure returned `None` (on this call or on a concurrent call).
+    InitNone,
+    // The init closure of a concurrent call panicked (`InitPanicPolicy::Fail`).
+    InitPanicked,
+}
+
+thread_local! {
+    // The addresses of the waiters whose init closures are being evaluated on the
+    // current thread. Used to detect the re-entrant calls on the same key, which
+    // would otherwise deadlock.
+    //
+    // A `const` initializer is not available in the MSRV.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static INITIALIZING_WAITERS: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+}
+
+fn waiter_addr<V>(waiter: &Waiter<V>) -> usize {
+    Arc::as_ptr(waiter) as *const () as usize
+}
+
+fn is_initializing_on_current_thread<V>(waiter: &Waiter<V>) -> bool {
+    let addr = waiter_addr(waiter);
+    INITIALIZING_WAITERS.with(|ws| ws.borrow().contains(&addr))
 }
 
+// The marker types to make the waiters for serialize() and the refreshes distinct
+// from the ones for init_or_read() and try_init_or_read().
+struct Serialize;
+struct Refresh;
+
+/// The marker type to make the waiters for `optionally_init_or_read` distinct from
+/// the ones for `init_or_read` and `try_init_or_read`.
+pub(crate) struct OptionallyInit;
+
 pub(crate) struct ValueInitializer<K, V, S> {
     // TypeId is the type ID of the concrete error type of generic type E in
     // try_init_or_read(). We use the type ID as a part of the key to ensure that
     // we can always downcast the trait object ErrorObject (in Waiter<V>) into
     // its concrete type.
     waiters: moka_cht::SegmentedHashMap<(Arc<K>, TypeId), Waiter<V>, S>,
+    init_panic_policy: InitPanicPolicy,
+    // How long an error returned by the init closure of `try_init_or_read` is
+    // remembered.
+    error_ttl: Option<Duration>,
+    // The waiters holding `CachedErr`, in the order that the errors were returned.
+    cached_errors: Mutex<VecDeque<CachedError<K, V>>>,
 }
 
 impl<K, V, S> ValueInitializer<K, V, S>
@@ -29,29 +97,74 @@ where
     V: Clone,
     S: BuildHasher,
 {
-    pub(crate) fn with_hasher(hasher: S) -> Self {
+    pub(crate) fn with_hasher(
+        hasher: S,
+        init_panic_policy: InitPanicPolicy,
+        error_ttl: Option<Duration>,
+    ) -> Self {
         Self {
             waiters: moka_cht::SegmentedHashMap::with_num_segments_and_hasher(16, hasher),
+            init_panic_policy,
+            error_ttl,
+            cached_errors: Mutex::default(),
         }
     }
 
     /// # Panics
-    /// Panics if the `init` future has been panicked.
-    pub(crate) fn init_or_read(&self, key: Arc<K>, init: impl FnOnce() -> V) -> InitResult<V, ()> {
+    /// Panics if the `init` closure has been panicked.
+    pub(crate) fn init_or_read(
+        &self,
+        key: Arc<K>,
+        init: impl FnOnce() -> V,
+    ) -> InitResult<V, Infallible> {
         // This closure will be called after the init closure has returned a value.
         // It will convert the returned value (from init) into an InitResult.
         let post_init = |_key, value: V, lock: &mut WaiterValue<V>| {
-            *lock = Some(Ok(value.clone()));
+            *lock = Some(InitValue::Value(value.clone()));
             InitResult::Initialized(value)
         };
 
         let type_id = TypeId::of::<()>();
-        self.do_try_init(&key, type_id, init, post_init)
+        self.do_try_init(&key, type_id, None, init, post_init)
     }
 
+    /// Like `init_or_read`, but the `init` closure may return `None`, in which case
+    /// `InitNone` is returned to this and the waiting callers.
+    ///
     /// # Panics
-    /// Panics if the `init` future has been panicked.
-    pub(crate) fn try_init_or_read<F, E>(&self, key: Arc<K>, init: F) -> InitResult<V, E>
+    /// Panics if the `init` closure has been panicked.
+    pub(crate) fn optionally_init_or_read(
+        &self,
+        key: Arc<K>,
+        init: impl FnOnce() -> Option<V>,
+    ) -> InitResult<V, Infallible> {
+        let type_id = TypeId::of::<OptionallyInit>();
+
+        // This closure will be called after the init closure has returned a value.
+        // It will convert the returned value (from init) into an InitResult.
+        let post_init = |key, value: Option<V>, lock: &mut WaiterValue<V>| match value {
+            Some(value) => {
+                *lock = Some(InitValue::Value(value.clone()));
+                InitResult::Initialized(value)
+            }
+            None => {
+                *lock = Some(InitValue::NoValue);
+                self.remove_waiter(key, type_id);
+                InitResult::InitNone
+            }
+        };
+
+        self.do_try_init(&key, type_id, None, init, post_init)
+    }
+
+    /// # Panics
+    /// Panics if the `init` closure has been panicked.
+    pub(crate) fn try_init_or_read<F, E>(
+        &self,
+        key: Arc<K>,
+        now: Instant,
+        init: F,
+    ) -> InitResult<V, E>
     where
         F: FnOnce() -> Result<V, E>,
         E: Send + Sync + 'static,
@@ -62,26 +175,37 @@ where
         // It will convert the returned value (from init) into an InitResult.
         let post_init = |key, value: Result<V, E>, lock: &mut WaiterValue<V>| match value {
             Ok(value) => {
-                *lock = Some(Ok(value.clone()));
+                *lock = Some(InitValue::Value(value.clone()));
                 InitResult::Initialized(value)
             }
             Err(e) => {
                 let err: ErrorObject = Arc::new(e);
-                *lock = Some(Err(Arc::clone(&err)));
-                self.remove_waiter(key, type_id);
+                match self.error_ttl {
+                    // Keep the waiter so that the later calls will get the error
+                    // until it expires.
+                    Some(ttl) => *lock = Some(InitValue::CachedErr(Arc::clone(&err), now + ttl)),
+                    None => {
+                        *lock = Some(InitValue::Err(Arc::clone(&err)));
+                        self.remove_waiter(key, type_id);
+                    }
+                }
                 InitResult::InitErr(err.downcast().unwrap())
             }
         };
 
-        self.do_try_init(&key, type_id, init, post_init)
+        self.do_try_init(&key, type_id, Some(now), init, post_init)
     }
 
     /// # Panics
-    /// Panics if the `init` future has been panicked.
+    /// Panics if the `init` closure has been panicked, or if the `init` closure
+    /// called this method for the same key.
     fn do_try_init<'a, F, O, C, E>(
         &self,
         key: &'a Arc<K>,
         type_id: TypeId,
+        // The current time to check the expiration of `CachedErr`. `None` for the
+        // waiters that never have `CachedErr`.
+        now: Option<Instant>,
         init: F,
         mut post_init: C,
     ) -> InitResult<V, E>
@@ -104,13 +228,37 @@ where
                 None => {
                     // Our waiter was inserted. Let's resolve the init future.
                     // Catching panic is safe here as we do not try to resolve the future again.
-                    match catch_unwind(AssertUnwindSafe(init)) {
+                    let addr = waiter_addr(&waiter);
+                    INITIALIZING_WAITERS.with(|ws| ws.borrow_mut().push(addr));
+                    let result = catch_unwind(AssertUnwindSafe(init));
+                    INITIALIZING_WAITERS.with(|ws| {
+                        let mut ws = ws.borrow_mut();
+                        if let Some(pos) = ws.iter().rposition(|a| *a == addr) {
+                            ws.remove(pos);
+                        }
+                    });
+                    match result {
                         // Resolved.
-                        Ok(value) => return post_init(key, value, &mut lock),
+                        Ok(value) => {
+                            let result = post_init(key, value, &mut lock);
+                            if let Some(InitValue::CachedErr(_, expires_at)) = &*lock {
+                                self.cached_errors.lock().push_back(CachedError {
+                                    key: Arc::clone(key),
+                                    type_id,
+                                    waiter: Arc::clone(&waiter),
+                                    expires_at: *expires_at,
+                                });
+                            }
+                            return result;
+                        }
                         // Panicked.
                         Err(payload) => {
-                            *lock = None;
-                            // Remove the waiter so that others can retry.
+                            *lock = match self.init_panic_policy {
+                                InitPanicPolicy::Retry => None,
+                                InitPanicPolicy::Fail => Some(InitValue::Panicked),
+                            };
+                            // Remove the waiter so that the later calls will not
+                            // find it, and the waiting ones can retry if allowed.
                             self.remove_waiter(key, type_id);
                             resume_unwind(payload);
                         } // The write lock will be unlocked here.
@@ -120,9 +268,31 @@ where
                     // Somebody else's waiter already exists. Drop our write lock and wait
                     // for a read lock to become available.
                     std::mem::drop(lock);
+                    if is_initializing_on_current_thread(&res) {
+                        // The waiter is held by the init closure running on this
+                        // thread. Waiting for it would deadlock.
+                        panic!(
+                            "Detected a re-entrant call for the same key from the `init` \
+                            closure of `get_or_insert_with` or `get_or_try_insert_with`. \
+                            This would cause a deadlock."
+                        );
+                    }
                     match &*res.read() {
-                        Some(Ok(value)) => return ReadExisting(value.clone()),
-                        Some(Err(e)) => return InitErr(Arc::clone(e).downcast().unwrap()),
+                        Some(InitValue::Value(value)) => return ReadExisting(value.clone()),
+                        Some(InitValue::NoValue) => return InitNone,
+                        Some(InitValue::Panicked) => return InitPanicked,
+                        Some(InitValue::Err(e)) => {
+                            return InitErr(Arc::clone(e).downcast().unwrap())
+                        }
+                        Some(InitValue::CachedErr(e, expires_at)) => match now {
+                            Some(now) if now >= *expires_at => {
+                                // The error has expired. Remove the waiter (unless
+                                // somebody else has already replaced it) and retry.
+                                self.remove_waiter_if_same(key, type_id, &res);
+                                continue;
+                            }
+                            _ => return InitErr(Arc::clone(e).downcast().unwrap()),
+                        },
                         // None means somebody else's init closure has been panicked.
                         None => {
                             retries += 1;
@@ -143,12 +313,92 @@ where
         }
     }
 
+    /// Removes the waiters of the errors remembered by `cache_errors_for` that have
+    /// expired at `now`, so that they do not stay in the waiter map until their
+    /// keys are requested again.
+    pub(crate) fn remove_expired_errors(&self, now: Instant) {
+        let mut errors = self.cached_errors.lock();
+        // The errors expire roughly in the order that they were returned. An error
+        // whose init closure ran shorter than the ones before it waits for them.
+        while let Some(error) = errors.front() {
+            if error.expires_at > now {
+                break;
+            }
+            if let Some(error) = errors.pop_front() {
+                self.remove_waiter_if_same(&error.key, error.type_id, &error.waiter);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn waiter_count(&self) -> usize {
+        self.waiters.len()
+    }
+
+    /// Tries to mark the key as being refreshed. Returns `false` if another refresh
+    /// of the key is already in flight.
+    pub(crate) fn try_start_refresh(&self, key: &Arc<K>) -> bool {
+        let waiter = Arc::new(RwLock::new(None));
+        self.try_insert_waiter(key, TypeId::of::<Refresh>(), &waiter)
+            .is_none()
+    }
+
+    pub(crate) fn finish_refresh(&self, key: &Arc<K>) {
+        self.remove_waiter(key, TypeId::of::<Refresh>());
+    }
+
+    /// Runs the closure while holding the waiter of the key, so that the calls of
+    /// this method on the same key are serialized.
+    ///
+    /// # Panics
+    /// Panics if the closure has been panicked.
+    pub(crate) fn serialize<F, O>(&self, key: &Arc<K>, f: F) -> O
+    where
+        F: FnOnce() -> O,
+    {
+        use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+        let type_id = TypeId::of::<Serialize>();
+
+        loop {
+            let waiter = Arc::new(RwLock::new(None));
+            let lock = waiter.write();
+
+            match self.try_insert_waiter(key, type_id, &waiter) {
+                None => {
+                    // Our waiter was inserted. Let's call the closure.
+                    let result = catch_unwind(AssertUnwindSafe(f));
+                    // Remove the waiter before unlocking it, so that others will
+                    // not find it when they retry.
+                    self.remove_waiter(key, type_id);
+                    std::mem::drop(lock);
+                    match result {
+                        Ok(value) => return value,
+                        Err(payload) => resume_unwind(payload),
+                    }
+                }
+                Some(res) => {
+                    // Somebody else's waiter already exists. Drop our write lock and
+                    // wait for the other call to complete, then retry.
+                    std::mem::drop(lock);
+                    std::mem::drop(res.read());
+                }
+            }
+        }
+    }
+
     #[inline]
     pub(crate) fn remove_waiter(&self, key: &Arc<K>, type_id: TypeId) {
         let key = Arc::clone(key);
         self.waiters.remove(&(key, type_id));
     }
 
+    fn remove_waiter_if_same(&self, key: &Arc<K>, type_id: TypeId, waiter: &Waiter<V>) {
+        let key = Arc::clone(key);
+        self.waiters
+            .remove_if(&(key, type_id), |_, w| Arc::ptr_eq(w, waiter));
+    }
+
     #[inline]
     fn try_insert_waiter(
         &self,
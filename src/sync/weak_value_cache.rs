@@ -0,0 +1,240 @@
+use super::{Cache, CacheBuilder};
+
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+const DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A thread-safe concurrent cache holding weak references to its values.
+///
+/// `WeakValueCache` is built on a [`Cache`][cache-struct] of `Weak<T>` values, so
+/// it does not keep the values alive. This is useful for interning; a value is
+/// shared while someone else holds an `Arc` to it, and dropped as usual when the
+/// last `Arc` is dropped.
+///
+/// An entry whose value has been dropped is _dead_. `get` treats a dead entry as
+/// absent and removes it. The housekeeper also sweeps the dead entries in the
+/// background once per _sweep interval_ (60 seconds by default; see
+/// [`with_sweep_interval`](#method.with_sweep_interval)), so they will not count
+/// against the max capacity for long. A sweep scans all the entries, and while it
+/// is in progress, `get` checks the value against it too.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::sync::WeakValueCache;
+/// use std::sync::Arc;
+///
+/// let cache = WeakValueCache::new(100);
+///
+/// let value = Arc::new("alice".to_string());
+/// cache.insert(1, &value);
+/// assert_eq!(cache.get(&1), Some(Arc::clone(&value)));
+///
+/// // Drop the last `Arc` to the value.
+/// drop(value);
+/// assert_eq!(cache.get(&1), None);
+/// ```
+///
+/// [cache-struct]: ./struct.Cache.html
+pub struct WeakValueCache<K, T, S = RandomState> {
+    cache: Cache<K, Weak<T>, S>,
+}
+
+impl<K, T, S> Clone for WeakValueCache<K, T, S>
+where
+    K: Clone,
+    S: Clone,
+{
+    /// Makes a clone of this shared cache.
+    ///
+    /// This operation is cheap as it only creates thread-safe reference counted
+    /// pointers to the shared internal data structures.
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<K, T, S> fmt::Debug for WeakValueCache<K, T, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakValueCache")
+            .field("entry_count", &self.cache.entry_count())
+            .finish()
+    }
+}
+
+impl<K, T> WeakValueCache<K, T, RandomState>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+{
+    /// Constructs a new `WeakValueCache<K, T>` that will store up to the
+    /// `max_capacity` entries.
+    pub fn new(max_capacity: usize) -> Self {
+        Self::with_sweep_interval(max_capacity, DEFAULT_SWEEP_INTERVAL)
+    }
+
+    /// Constructs a new `WeakValueCache<K, T>` that will store up to the
+    /// `max_capacity` entries, and sweeps the dead entries once per `interval`.
+    ///
+    /// A shorter interval removes the dead entries sooner, but scans all the
+    /// entries more often.
+    pub fn with_sweep_interval(max_capacity: usize, interval: Duration) -> Self {
+        let cache = CacheBuilder::new(max_capacity)
+            .support_invalidation_closures()
+            .build();
+        cache.set_sweep_predicate(
+            Arc::new(|_k, v: &Weak<T>, _meta| v.strong_count() == 0),
+            interval,
+        );
+        Self { cache }
+    }
+}
+
+impl<K, T, S> WeakValueCache<K, T, S>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Returns an `Arc` to the value corresponding to the key, or `None` if the
+    /// cache does not have the key or the value has been dropped.
+    ///
+    /// If the value has been dropped, this method removes the dead entry.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn get<Q>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let value = self.cache.get(key).and_then(|v| v.upgrade());
+        if value.is_none() {
+            // The cache's `get` also misses a dead entry once the background sweep
+            // has started, so try to remove it anyway. Check the value again not to
+            // remove a live value inserted by another thread after the `get`.
+            self.cache.invalidate_if(key, |v| v.strong_count() == 0);
+        }
+        value
+    }
+
+    /// Inserts a weak reference to the value into the cache.
+    ///
+    /// If the cache has this key present, the value is updated.
+    pub fn insert(&self, key: K, value: &Arc<T>) {
+        self.cache.insert(key, Arc::downgrade(value));
+    }
+
+    /// Discards any cached value for the key.
+    ///
+    /// The key may be any borrowed form of the cache's key type, but `Hash` and `Eq`
+    /// on the borrowed form _must_ match those for the key type.
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        Arc<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.cache.invalidate(key);
+    }
+
+    /// Discards all cached values.
+    ///
+    /// See the document of [`Cache::invalidate_all`][cache-invalidate-all] for
+    /// details.
+    ///
+    /// [cache-invalidate-all]: ./struct.Cache.html#method.invalidate_all
+    pub fn invalidate_all(&self) {
+        self.cache.invalidate_all();
+    }
+
+    /// Returns the approximate number of entries in this cache, including the dead
+    /// entries that have not been removed yet.
+    pub fn entry_count(&self) -> usize {
+        self.cache.entry_count()
+    }
+
+    /// Performs any pending maintenance operations needed by the cache, including
+    /// removing the dead entries found by the background sweep.
+    pub fn run_pending_tasks(&self) {
+        self.cache.run_pending_tasks();
+    }
+
+    /// Returns the `max_capacity` of this cache.
+    pub fn max_capacity(&self) -> usize {
+        self.cache.max_capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeakValueCache;
+    use crate::common::time::Clock;
+    use std::{sync::Arc, time::Duration};
+
+    // Runs the pending tasks including the background sweep, and waits for them to
+    // finish. (TODO: Need a better way than sleeping)
+    fn run_sweep<T: Send + Sync + 'static>(cache: &WeakValueCache<u32, T>) {
+        cache.run_pending_tasks(); // To submit the invalidation task.
+        std::thread::sleep(Duration::from_millis(200));
+        cache.run_pending_tasks(); // To process the task result.
+        std::thread::sleep(Duration::from_millis(200));
+        cache.run_pending_tasks();
+    }
+
+    #[test]
+    fn prune_dead_entries() {
+        let cache = WeakValueCache::with_sweep_interval(100, Duration::from_secs(10));
+        let (clock, mock) = Clock::mock();
+        cache.cache.set_expiration_clock(Some(clock));
+
+        let alice = Arc::new("alice");
+        let bob = Arc::new("bob");
+        cache.insert(0, &alice);
+        cache.insert(1, &bob);
+        // Let the first sweep finish.
+        run_sweep(&cache);
+        assert_eq!(cache.get(&0), Some(Arc::clone(&alice)));
+        assert_eq!(cache.get(&1), Some(Arc::clone(&bob)));
+        assert_eq!(cache.entry_count(), 2);
+
+        // `get` removes the dead entry.
+        drop(alice);
+        assert_eq!(cache.get(&0), None);
+        cache.run_pending_tasks();
+        assert_eq!(cache.entry_count(), 1);
+
+        // The next sweep will not run until the interval passes.
+        drop(bob);
+        run_sweep(&cache);
+        assert_eq!(cache.entry_count(), 1);
+        assert_eq!(cache.cache.invalidation_predicate_count(), 0);
+
+        // The housekeeper sweeps the dead entry without `get`.
+        mock.increment(Duration::from_secs(10));
+        run_sweep(&cache);
+        assert_eq!(cache.entry_count(), 0);
+        assert_eq!(cache.get(&1), None);
+
+        // A live value is not removed.
+        let cindy = Arc::new("cindy");
+        cache.insert(2, &cindy);
+        mock.increment(Duration::from_secs(10));
+        run_sweep(&cache);
+        assert_eq!(cache.get(&2), Some(cindy));
+        assert_eq!(cache.entry_count(), 1);
+    }
+}
@@ -0,0 +1,58 @@
+//! Time sources for the caches.
+
+use std::time::Duration;
+
+/// A source of the current time, used by a cache to determine the expiration of
+/// its entries.
+///
+/// By default, a cache reads the time from a monotonic clock of the system. You can
+/// set a custom time source with the `clock` method of the `CacheBuilder`, e.g. to
+/// drive the expiration by a virtual clock in tests or simulations.
+///
+/// # Example
+///
+/// ```rust
+/// use moka::{sync::CacheBuilder, time_source::TimeSource};
+/// use std::{
+///     sync::{
+///         atomic::{AtomicU64, Ordering},
+///         Arc,
+///     },
+///     time::Duration,
+/// };
+///
+/// #[derive(Default)]
+/// struct FakeClock(AtomicU64);
+///
+/// impl TimeSource for FakeClock {
+///     fn now(&self) -> Duration {
+///         Duration::from_secs(self.0.load(Ordering::Acquire))
+///     }
+/// }
+///
+/// let clock = Arc::new(FakeClock::default());
+/// let cache = CacheBuilder::new(100)
+///     .time_to_live(Duration::from_secs(10))
+///     .clock(Arc::clone(&clock))
+///     .build();
+///
+/// cache.insert("a", "alice");
+/// cache.run_pending_tasks();
+/// assert_eq!(cache.get(&"a"), Some("alice"));
+///
+/// // Advance the clock by 10 seconds.
+/// clock.0.fetch_add(10, Ordering::AcqRel);
+/// assert_eq!(cache.get(&"a"), None);
+/// ```
+pub trait TimeSource: Send + Sync {
+    /// Returns the time elapsed since an arbitrary, fixed point in time.
+    ///
+    /// The returned value must be monotonically non-decreasing.
+    fn now(&self) -> Duration;
+}
+
+impl<T: TimeSource + ?Sized> TimeSource for std::sync::Arc<T> {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
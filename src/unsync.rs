@@ -4,13 +4,24 @@ mod builder;
 mod cache;
 mod deques;
 
-use std::{ptr::NonNull, rc::Rc};
+use alloc::rc::Rc;
+use core::ptr::NonNull;
 
 pub use builder::CacheBuilder;
 pub use cache::Cache;
 
 use crate::common::{deque::DeqNode, time::Instant, AccessTime};
 
+#[cfg(feature = "std")]
+pub(crate) use std::collections::hash_map::RandomState;
+
+// Without `std`, there is no source of random keys, so the default hasher is
+// SipHash 1-3 with fixed keys. (`SipHasher` is deprecated only in favor of
+// `std::collections::hash_map::DefaultHasher`.)
+#[cfg(not(feature = "std"))]
+#[allow(deprecated)]
+pub(crate) type RandomState = core::hash::BuildHasherDefault<core::hash::SipHasher>;
+
 pub(crate) struct KeyDate<K> {
     pub(crate) key: Rc<K>,
     pub(crate) timestamp: Option<Instant>,
@@ -1,7 +1,6 @@
-use super::Cache;
+use super::{Cache, RandomState};
 
-use std::{
-    collections::hash_map::RandomState,
+use core::{
     hash::{BuildHasher, Hash},
     marker::PhantomData,
     time::Duration,
@@ -100,6 +99,9 @@ impl<C> CacheBuilder<C> {
     ///
     /// A cached entry will be expired after the specified duration past from
     /// `insert`.
+    ///
+    /// This method requires the "std" crate feature, which provides the clock.
+    #[cfg(feature = "std")]
     pub fn time_to_live(self, duration: Duration) -> Self {
         Self {
             time_to_live: Some(duration),
@@ -111,6 +113,9 @@ impl<C> CacheBuilder<C> {
     ///
     /// A cached entry will be expired after the specified duration past from `get`
     /// or `insert`.
+    ///
+    /// This method requires the "std" crate feature, which provides the clock.
+    #[cfg(feature = "std")]
     pub fn time_to_idle(self, duration: Duration) -> Self {
         Self {
             time_to_idle: Some(duration),
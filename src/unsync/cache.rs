@@ -1,4 +1,4 @@
-use super::{deques::Deques, KeyDate, KeyHashDate, ValueEntry};
+use super::{deques::Deques, KeyDate, KeyHashDate, RandomState, ValueEntry};
 use crate::common::{
     deque::{CacheRegion, DeqNode, Deque},
     frequency_sketch::FrequencySketch,
@@ -6,22 +6,28 @@ use crate::common::{
     AccessTime,
 };
 
-use std::{
+use alloc::{rc::Rc, vec::Vec};
+use core::{
     borrow::Borrow,
-    collections::{hash_map::RandomState, HashMap},
     convert::TryInto,
     hash::{BuildHasher, Hash, Hasher},
     ptr::NonNull,
-    rc::Rc,
     time::Duration,
 };
 
-type CacheStore<K, V, S> = std::collections::HashMap<Rc<K>, ValueEntry<K, V>, S>;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+type CacheStore<K, V, S> = HashMap<Rc<K>, ValueEntry<K, V>, S>;
 
 /// An in-memory cache that is _not_ thread-safe.
 ///
 /// `Cache` utilizes a hash table `std::collections::HashMap` from the standard
-/// library for the central key-value storage. `Cache` performs a best-effort
+/// library for the central key-value storage. (Without the "std" crate feature,
+/// it uses the `HashMap` of the [hashbrown][hashbrown-crate] crate instead; see
+/// the [`no_std` support][no-std-support] section of the crate documentation.) `Cache` performs a best-effort
 /// bounding of the map using an entry replacement algorithm to determine which
 /// entries to evict when the capacity is exceeded.
 ///
@@ -85,7 +91,7 @@ type CacheStore<K, V, S> = std::collections::HashMap<Rc<K>, ValueEntry<K, V>, S>
 ///   past from `get` or `insert`.
 ///
 /// See the [`CacheBuilder`][builder-struct]'s doc for how to configure a cache
-/// with them.
+/// with them. They require the "std" crate feature, which provides the clock.
 ///
 /// [builder-struct]: ./struct.CacheBuilder.html
 ///
@@ -95,6 +101,11 @@ type CacheStore<K, V, S> = std::collections::HashMap<Rc<K>, ValueEntry<K, V>, S>
 /// against HashDoS attacks. It will the same one used by
 /// `std::collections::HashMap`, which is currently SipHash 1-3.
 ///
+/// Without the "std" crate feature, there is no source of random keys, so the
+/// default hasher is SipHash 1-3 with fixed keys, which does _not_ protect against
+/// HashDoS attacks. Use `build_with_hasher` with a randomly seeded hasher if the
+/// keys may come from an untrusted source.
+///
 /// While SipHash's performance is very competitive for medium sized keys, other
 /// hashing algorithms will outperform it for small keys such as integers as well as
 /// large keys such as long strings. However those algorithms will typically not
@@ -107,6 +118,8 @@ type CacheStore<K, V, S> = std::collections::HashMap<Rc<K>, ValueEntry<K, V>, S>
 ///
 /// [build-with-hasher-method]: ./struct.CacheBuilder.html#method.build_with_hasher
 /// [ahash-crate]: https://crates.io/crates/ahash
+/// [hashbrown-crate]: https://crates.io/crates/hashbrown
+/// [no-std-support]: ../index.html#no_std-support
 ///
 pub struct Cache<K, V, S = RandomState> {
     max_capacity: usize,
@@ -1,7 +1,8 @@
 use super::{KeyDate, KeyHashDate, ValueEntry};
 use crate::common::deque::{CacheRegion, DeqNode, Deque};
 
-use std::ptr::NonNull;
+use alloc::boxed::Box;
+use core::ptr::NonNull;
 
 pub(crate) struct Deques<K> {
     pub(crate) window: Deque<KeyHashDate<K>>, //    Not used yet.
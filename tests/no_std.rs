@@ -0,0 +1,58 @@
+// Tests `unsync::Cache` of a `no_std` build. Run this test with:
+//
+// cargo test --no-default-features --features alloc --test no_std
+//
+// This test crate itself uses `std` for the test harness, but it only uses the API
+// that is available without the "std" crate feature.
+
+use moka::unsync::{Cache, CacheBuilder};
+
+#[test]
+fn basic_single_thread() {
+    let mut cache = Cache::new(3);
+
+    cache.insert("a", "alice");
+    cache.insert("b", "bob");
+    assert_eq!(cache.get(&"a"), Some(&"alice"));
+    assert_eq!(cache.get(&"b"), Some(&"bob"));
+
+    cache.insert("c", "cindy");
+    assert_eq!(cache.get(&"c"), Some(&"cindy"));
+    assert_eq!(cache.get(&"a"), Some(&"alice"));
+    assert_eq!(cache.get(&"b"), Some(&"bob"));
+    // counts: a -> 2, b -> 2, c -> 1
+
+    // "d" should not be admitted because its frequency is too low.
+    cache.insert("d", "david");
+    assert_eq!(cache.get(&"d"), None);
+    cache.insert("d", "david");
+    assert_eq!(cache.get(&"d"), None);
+
+    // "d" should be admitted and "c" should be evicted.
+    cache.insert("d", "dennis");
+    assert_eq!(cache.get(&"c"), None);
+    assert_eq!(cache.get(&"d"), Some(&"dennis"));
+
+    cache.invalidate(&"b");
+    assert_eq!(cache.get(&"b"), None);
+
+    cache.invalidate_entries_if(|_key, value| value.starts_with('a'));
+    assert_eq!(cache.get(&"a"), None);
+    assert_eq!(cache.get(&"d"), Some(&"dennis"));
+
+    cache.invalidate_all();
+    assert_eq!(cache.get(&"d"), None);
+}
+
+#[test]
+fn build_with_initial_capacity() {
+    let mut cache = CacheBuilder::new(100).initial_capacity(10).build();
+    assert_eq!(cache.max_capacity(), 100);
+    assert_eq!(cache.time_to_live(), None);
+    assert_eq!(cache.time_to_idle(), None);
+
+    for key in 0..200u32 {
+        cache.insert(key, key * 10);
+    }
+    assert!((0..200u32).filter(|key| cache.get(key).is_some()).count() <= 101);
+}